@@ -1,6 +1,27 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use reqwest::header::{HeaderMap, HeaderValue};
+use std::sync::OnceLock;
+use std::time::Duration;
+use crate::zatca::ZatcaInvoice;
+
+/// How long downloading a job's image or PDF may take
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Download a job's image or PDF. HTTP error statuses fail the download
+/// rather than handing an error page to the decoder.
+pub async fn download(url: &str) -> Result<Vec<u8>> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    let client = CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(DOWNLOAD_TIMEOUT)
+            .build()
+            .expect("Failed to create HTTP client")
+    });
+
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
 
 /// API Client for VopecsPOS backend
 pub struct ApiClient {
@@ -81,14 +102,30 @@ pub struct PrintJob {
     pub image: Option<String>,
     #[serde(default)]
     pub image_path: Option<String>,
+    /// PDF as a URL, base64 or data URL
     #[serde(default)]
     pub pdf: Option<String>,
+    /// Pages to print, e.g. "1-2"; the printer profile's setting when unset
+    #[serde(default)]
+    pub pdf_pages: Option<String>,
+    /// Fraction of the printable width the PDF is scaled to
+    #[serde(default)]
+    pub pdf_scale: Option<f32>,
     #[serde(default)]
     pub content: Option<String>,
     #[serde(default)]
     pub html: Option<String>,
     #[serde(default)]
     pub url: Option<String>,
+    /// Structured receipt, see [`crate::document::Document`]
+    #[serde(default)]
+    pub document: Option<serde_json::Value>,
+    /// ZATCA invoice fields; the QR code is printed at the end of the receipt
+    #[serde(default)]
+    pub zatca: Option<ZatcaInvoice>,
+    /// Key of a logo stored on the printer, printed at the top of the receipt
+    #[serde(default)]
+    pub logo: Option<String>,
     #[serde(default)]
     pub copies: Option<i32>,
     #[serde(default)]
@@ -114,7 +151,9 @@ impl PrintJob {
     }
 
     pub fn get_job_type(&self) -> String {
-        if self.image.is_some() {
+        if self.document.is_some() {
+            "document".to_string()
+        } else if self.image.is_some() {
             "image".to_string()
         } else if self.image_path.is_some() {
             "image_url".to_string()
@@ -126,6 +165,8 @@ impl PrintJob {
             "url".to_string()
         } else if self.content.is_some() {
             "content".to_string()
+        } else if self.zatca.is_some() {
+            "zatca".to_string()
         } else {
             self.job_type.clone().unwrap_or_else(|| "unknown".to_string())
         }
@@ -379,3 +420,26 @@ impl ApiClient {
         Ok(bytes.to_vec())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::FailureClass;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[tokio::test]
+    async fn server_errors_are_download_failures() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/receipt.png", server.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut socket, _) = server.accept().unwrap();
+            let _ = socket.read(&mut [0; 1024]);
+            let body = "<html>Bad gateway</html>";
+            let _ = write!(socket, "HTTP/1.1 502 Bad Gateway\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+        });
+
+        let error = download(&url).await.unwrap_err();
+        assert_eq!(FailureClass::classify(&error), FailureClass::DownloadTimeout);
+    }
+}
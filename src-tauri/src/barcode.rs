@@ -0,0 +1,568 @@
+use anyhow::{anyhow, bail, Result};
+use image::GrayImage;
+use qrcode::{Color, EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+use crate::config::PrinterProfile;
+use crate::dither::MonoBitmap;
+use crate::escpos::Justify;
+use crate::text::{TextOptions, TextRasterizer};
+
+/// Whether barcodes and QR codes are drawn by the printer or sent as images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BarcodeRendering {
+    /// Printer commands (`GS k`, `GS ( k`)
+    #[default]
+    Native,
+    /// Bitmaps drawn here, for printers without barcode or QR support
+    Raster,
+}
+
+/// 1D barcode symbology
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Symbology {
+    #[default]
+    Code128,
+    Ean13,
+    #[serde(alias = "upca")]
+    UpcA,
+    Code39,
+    /// Interleaved 2 of 5
+    Itf,
+}
+
+/// QR error correction level, recovering about 7, 15, 25 or 30% of the symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCorrection {
+    #[serde(alias = "l")]
+    Low,
+    #[default]
+    #[serde(alias = "m")]
+    Medium,
+    #[serde(alias = "q")]
+    Quartile,
+    #[serde(alias = "h")]
+    High,
+}
+
+/// Size and placement of a QR code
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct QrOptions {
+    /// QR model 1 or 2. Raster QR codes are always model 2.
+    pub model: u8,
+    /// Dots per module, 1-16
+    #[serde(rename = "size")]
+    pub module_size: u8,
+    pub error_correction: ErrorCorrection,
+    pub justify: Justify,
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        Self {
+            model: 2,
+            module_size: 6,
+            error_correction: ErrorCorrection::default(),
+            justify: Justify::default(),
+        }
+    }
+}
+
+/// Size and placement of a 1D barcode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BarcodeOptions {
+    pub symbology: Symbology,
+    /// Bar height in dots, 1-255
+    pub height: u8,
+    /// Narrow bar width in dots, 1-6
+    #[serde(rename = "width")]
+    pub module_width: u8,
+    /// Print the human readable text below the bars
+    pub hri: bool,
+    pub justify: Justify,
+}
+
+impl Default for BarcodeOptions {
+    fn default() -> Self {
+        Self {
+            symbology: Symbology::default(),
+            height: 80,
+            module_width: 2,
+            hri: true,
+            justify: Justify::default(),
+        }
+    }
+}
+
+/// Validated barcode content, with check digits added where the symbology has them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Barcode {
+    symbology: Symbology,
+    data: String,
+}
+
+impl Barcode {
+    /// Validate `data` for `symbology`. EAN-13 and UPC-A take the digits with
+    /// or without their check digit; ITF data of odd length gets a leading zero;
+    /// CODE39 is upper-cased.
+    pub fn new(symbology: Symbology, data: &str) -> Result<Self> {
+        let data = data.trim();
+        let digits = || {
+            if !data.bytes().all(|b| b.is_ascii_digit()) {
+                bail!("{:?} barcodes can only contain digits: {:?}", symbology, data);
+            }
+            Ok(())
+        };
+
+        let data = match symbology {
+            Symbology::Code128 => {
+                if let Some(c) = data.chars().find(|c| !(' '..='~').contains(c)) {
+                    bail!("CODE128 can't encode {:?}", c);
+                }
+                data.to_string()
+            }
+            Symbology::Ean13 | Symbology::UpcA => {
+                digits()?;
+                let length = if symbology == Symbology::Ean13 { 13 } else { 12 };
+                // UPC-A is EAN-13 with a leading zero
+                let padded = if symbology == Symbology::UpcA { format!("0{}", data) } else { data.to_string() };
+                let check = ean_check_digit(&padded[..padded.len().min(12)]);
+                match data.len() {
+                    n if n == length - 1 => format!("{}{}", data, check),
+                    n if n == length => {
+                        if data.as_bytes()[n - 1] != b'0' + check {
+                            bail!("Wrong check digit in {:?}, expected {}", data, check);
+                        }
+                        data.to_string()
+                    }
+                    _ => bail!("{:?} needs {} or {} digits, got {:?}", symbology, length - 1, length, data),
+                }
+            }
+            Symbology::Code39 => {
+                let data = data.to_ascii_uppercase();
+                if let Some(c) = data.chars().find(|&c| c == '*' || code39_pattern(c).is_none()) {
+                    bail!("CODE39 can't encode {:?}", c);
+                }
+                data
+            }
+            Symbology::Itf => {
+                digits()?;
+                if data.len() % 2 == 1 { format!("0{}", data) } else { data.to_string() }
+            }
+        };
+
+        if data.is_empty() || data.len() > 253 {
+            bail!("Barcode data must be 1-253 characters");
+        }
+        Ok(Self { symbology, data })
+    }
+
+    /// The encoded text, as printed below the bars
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    /// `GS k` function B symbology number and data
+    pub fn native(&self) -> (u8, Vec<u8>) {
+        match self.symbology {
+            Symbology::UpcA => (65, self.data.as_bytes().to_vec()),
+            Symbology::Ean13 => (67, self.data.as_bytes().to_vec()),
+            Symbology::Code39 => (69, self.data.as_bytes().to_vec()),
+            Symbology::Itf => (70, self.data.as_bytes().to_vec()),
+            Symbology::Code128 => {
+                let mut payload = Vec::with_capacity(self.data.len() + 2);
+                match code128_pairs(&self.data) {
+                    // Code set C packs two digits per symbol
+                    Some(pairs) => {
+                        payload.extend_from_slice(b"{C");
+                        payload.extend(pairs);
+                    }
+                    None => {
+                        payload.extend_from_slice(b"{B");
+                        payload.extend_from_slice(self.data.as_bytes());
+                    }
+                }
+                (73, payload)
+            }
+        }
+    }
+
+    /// Bar pattern in narrow modules, `true` = bar. Wide elements are three modules.
+    pub fn modules(&self) -> Vec<bool> {
+        let mut modules = Vec::new();
+        let mut push = |width: u8, bar: bool| modules.extend(std::iter::repeat(bar).take(width as usize));
+
+        match self.symbology {
+            Symbology::Code128 => {
+                let (start, values): (u8, Vec<u8>) = match code128_pairs(&self.data) {
+                    Some(pairs) => (105, pairs),
+                    None => (104, self.data.bytes().map(|b| b - 32).collect()),
+                };
+                let checksum = values
+                    .iter()
+                    .enumerate()
+                    .fold(start as usize, |sum, (i, &v)| sum + (i + 1) * v as usize)
+                    % 103;
+
+                let symbols = std::iter::once(start)
+                    .chain(values)
+                    .chain([checksum as u8, 106]);
+                for symbol in symbols {
+                    for (i, width) in CODE128[symbol as usize].bytes().enumerate() {
+                        push(width - b'0', i % 2 == 0);
+                    }
+                }
+            }
+            Symbology::Ean13 | Symbology::UpcA => {
+                let digits: Vec<usize> = if self.symbology == Symbology::UpcA {
+                    std::iter::once(0).chain(self.data.bytes().map(|b| (b - b'0') as usize)).collect()
+                } else {
+                    self.data.bytes().map(|b| (b - b'0') as usize).collect()
+                };
+                let mut bits = |pattern: &str| {
+                    for bit in pattern.bytes() {
+                        push(1, bit == b'1');
+                    }
+                };
+
+                bits("101");
+                for (i, &digit) in digits[1..7].iter().enumerate() {
+                    let even = EAN_PARITY[digits[0]].as_bytes()[i] == b'G';
+                    bits(if even { EAN_G[digit] } else { EAN_L[digit] });
+                }
+                bits("01010");
+                for &digit in &digits[7..] {
+                    bits(EAN_R[digit]);
+                }
+                bits("101");
+            }
+            Symbology::Code39 => {
+                let text = format!("*{}*", self.data);
+                for (n, c) in text.chars().enumerate() {
+                    if n > 0 {
+                        push(1, false); // gap between characters
+                    }
+                    let pattern = code39_pattern(c).unwrap_or(0);
+                    for i in 0..9 {
+                        let wide = pattern & (1 << (8 - i)) != 0;
+                        push(if wide { 3 } else { 1 }, i % 2 == 0);
+                    }
+                }
+            }
+            Symbology::Itf => {
+                for bar in [true, false, true, false] {
+                    push(1, bar);
+                }
+                for pair in self.data.as_bytes().chunks(2) {
+                    let bars = ITF[(pair[0] - b'0') as usize].as_bytes();
+                    let spaces = ITF[(pair[1] - b'0') as usize].as_bytes();
+                    for i in 0..5 {
+                        push(if bars[i] == b'1' { 3 } else { 1 }, true);
+                        push(if spaces[i] == b'1' { 3 } else { 1 }, false);
+                    }
+                }
+                push(3, true);
+                push(1, false);
+                push(1, true);
+            }
+        }
+
+        modules
+    }
+}
+
+/// All-digit CODE128 data of even length as code set C values
+fn code128_pairs(data: &str) -> Option<Vec<u8>> {
+    if data.len() < 4 || data.len() % 2 == 1 || !data.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(data.as_bytes().chunks(2).map(|p| (p[0] - b'0') * 10 + (p[1] - b'0')).collect())
+}
+
+/// Check digit of the first 12 digits of an EAN-13
+fn ean_check_digit(digits: &str) -> u8 {
+    let sum: u32 = digits
+        .bytes()
+        .enumerate()
+        .map(|(i, b)| (b - b'0') as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+/// Render a barcode as a bitmap `profile.dot_width` wide, with its text below
+/// when `hri` is set. The module width is reduced if the symbol doesn't fit.
+pub fn render_barcode(barcode: &Barcode, options: &BarcodeOptions, profile: &PrinterProfile) -> Result<MonoBitmap> {
+    // Ten modules of quiet zone on each side
+    let modules = barcode.modules();
+    let needed = modules.len() as u32 + 20;
+    let module = (options.module_width.clamp(1, 6) as u32).min(profile.dot_width / needed);
+    if module == 0 {
+        bail!("Barcode {:?} is too wide for {} dots", barcode.data(), profile.dot_width);
+    }
+
+    let bars_height = options.height.max(1) as u32;
+    let label = options.hri.then(|| {
+        TextRasterizer::new(TextOptions {
+            justify: Some(Justify::Center),
+            ..TextOptions::from_profile(profile)
+        })
+        .render(barcode.data(), modules.len() as u32 * module)
+    });
+    let height = bars_height + label.as_ref().map_or(0, |l| l.height() + 2);
+
+    let width = modules.len() as u32 * module;
+    let left = offset(options.justify, profile.dot_width, width);
+    let mut bitmap = MonoBitmap::new(profile.dot_width, height);
+    for (i, _) in modules.iter().enumerate().filter(|(_, &bar)| bar) {
+        for x in 0..module {
+            for y in 0..bars_height {
+                bitmap.set(left + i as u32 * module + x, y, true);
+            }
+        }
+    }
+    if let Some(label) = label {
+        draw_gray(&mut bitmap, &label, left, bars_height + 2);
+    }
+
+    Ok(bitmap)
+}
+
+/// Render a QR code as a bitmap `profile.dot_width` wide
+pub fn render_qr(data: &str, options: &QrOptions, profile: &PrinterProfile) -> Result<MonoBitmap> {
+    let level = match options.error_correction {
+        ErrorCorrection::Low => EcLevel::L,
+        ErrorCorrection::Medium => EcLevel::M,
+        ErrorCorrection::Quartile => EcLevel::Q,
+        ErrorCorrection::High => EcLevel::H,
+    };
+    let code = QrCode::with_error_correction_level(data, level).map_err(|e| anyhow!("Can't encode QR code: {}", e))?;
+
+    let modules = code.width() as u32;
+    let size = (options.module_size.clamp(1, 16) as u32).min(profile.dot_width / modules);
+    if size == 0 {
+        bail!("QR code with {} modules is too wide for {} dots", modules, profile.dot_width);
+    }
+
+    let left = offset(options.justify, profile.dot_width, modules * size);
+    let mut bitmap = MonoBitmap::new(profile.dot_width, modules * size);
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color != Color::Dark {
+            continue;
+        }
+        let (mx, my) = (i as u32 % modules, i as u32 / modules);
+        for y in 0..size {
+            for x in 0..size {
+                bitmap.set(left + mx * size + x, my * size + y, true);
+            }
+        }
+    }
+
+    Ok(bitmap)
+}
+
+/// Left edge of something `width` dots wide placed on a line `page` dots wide
+pub(crate) fn offset(justify: Justify, page: u32, width: u32) -> u32 {
+    match justify {
+        Justify::Left => 0,
+        Justify::Center => (page - width) / 2,
+        Justify::Right => page - width,
+    }
+}
+
+fn draw_gray(bitmap: &mut MonoBitmap, image: &GrayImage, left: u32, top: u32) {
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[0] < 128 && left + x < bitmap.width() && top + y < bitmap.height() {
+            bitmap.set(left + x, top + y, true);
+        }
+    }
+}
+
+/// CODE128 symbols as bar/space widths, values 0-102, start A/B/C and stop
+const CODE128: [&str; 107] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212", "221213",
+    "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221", "223211", "221132",
+    "221231", "213212", "223112", "312131", "311222", "321122", "321221", "312212", "322112", "322211",
+    "212123", "212321", "232121", "111323", "131123", "131321", "112313", "132113", "132311", "211313",
+    "231113", "231311", "112133", "112331", "132131", "113123", "113321", "133121", "313121", "211331",
+    "231131", "213113", "213311", "213131", "311123", "311321", "331121", "312113", "312311", "332111",
+    "314111", "221411", "431111", "111224", "111422", "121124", "121421", "141122", "141221", "112214",
+    "112412", "122114", "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111",
+    "111242", "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311", "113141",
+    "114131", "311141", "411131", "211412", "211214", "211232", "2331112",
+];
+
+const EAN_L: [&str; 10] = [
+    "0001101", "0011001", "0010011", "0111101", "0100011", "0110001", "0101111", "0111011", "0110111", "0001011",
+];
+const EAN_G: [&str; 10] = [
+    "0100111", "0110011", "0011011", "0100001", "0011101", "0111001", "0000101", "0010001", "0001001", "0010111",
+];
+const EAN_R: [&str; 10] = [
+    "1110010", "1100110", "1101100", "1000010", "1011100", "1001110", "1010000", "1000100", "1001000", "1110100",
+];
+
+/// Left-half parity of EAN-13, selected by the first digit
+const EAN_PARITY: [&str; 10] = [
+    "LLLLLL", "LLGLGG", "LLGGLG", "LLGGGL", "LGLLGG", "LGGLLG", "LGGGLL", "LGLGLG", "LGLGGL", "LGGLGL",
+];
+
+/// ITF digits as five elements, `1` = wide
+const ITF: [&str; 10] = ["00110", "10001", "01001", "11000", "00101", "10100", "01100", "00011", "10010", "01010"];
+
+/// CODE39 character as nine elements (bar, space, ...), a set bit = wide
+fn code39_pattern(c: char) -> Option<u16> {
+    Some(match c {
+        '0' => 0x034,
+        '1' => 0x121,
+        '2' => 0x061,
+        '3' => 0x160,
+        '4' => 0x031,
+        '5' => 0x130,
+        '6' => 0x070,
+        '7' => 0x025,
+        '8' => 0x124,
+        '9' => 0x064,
+        'A' => 0x109,
+        'B' => 0x049,
+        'C' => 0x148,
+        'D' => 0x019,
+        'E' => 0x118,
+        'F' => 0x058,
+        'G' => 0x00D,
+        'H' => 0x10C,
+        'I' => 0x04C,
+        'J' => 0x01C,
+        'K' => 0x103,
+        'L' => 0x043,
+        'M' => 0x142,
+        'N' => 0x013,
+        'O' => 0x112,
+        'P' => 0x052,
+        'Q' => 0x007,
+        'R' => 0x106,
+        'S' => 0x046,
+        'T' => 0x016,
+        'U' => 0x181,
+        'V' => 0x0C1,
+        'W' => 0x1C0,
+        'X' => 0x091,
+        'Y' => 0x190,
+        'Z' => 0x0D0,
+        '-' => 0x085,
+        '.' => 0x184,
+        ' ' => 0x0C4,
+        '$' => 0x0A8,
+        '/' => 0x0A2,
+        '+' => 0x08A,
+        '%' => 0x02A,
+        '*' => 0x094,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Widths of alternating bar/space runs
+    fn runs(modules: &[bool]) -> Vec<usize> {
+        let mut runs = vec![1];
+        for pair in modules.windows(2) {
+            if pair[0] == pair[1] {
+                *runs.last_mut().unwrap() += 1;
+            } else {
+                runs.push(1);
+            }
+        }
+        runs
+    }
+
+    #[test]
+    fn ean_and_upc_check_digits() {
+        assert_eq!(Barcode::new(Symbology::Ean13, "400638133393").unwrap().data(), "4006381333931");
+        assert!(Barcode::new(Symbology::Ean13, "4006381333931").is_ok());
+        assert!(Barcode::new(Symbology::Ean13, "4006381333932").is_err());
+        assert_eq!(Barcode::new(Symbology::UpcA, "03600029145").unwrap().data(), "036000291452");
+        assert!(Barcode::new(Symbology::UpcA, "12AB").is_err());
+    }
+
+    #[test]
+    fn ean13_pattern() {
+        let modules = Barcode::new(Symbology::Ean13, "4006381333931").unwrap().modules();
+        assert_eq!(modules.len(), 95);
+        // Guard bars, then the first left digit (0 with parity L)
+        let bits: String = modules[..10].iter().map(|&b| if b { '1' } else { '0' }).collect();
+        assert_eq!(bits, "1010001101");
+
+        // UPC-A draws the same bars as the EAN-13 with a leading zero
+        let upc = Barcode::new(Symbology::UpcA, "036000291452").unwrap().modules();
+        assert_eq!(upc, Barcode::new(Symbology::Ean13, "0036000291452").unwrap().modules());
+    }
+
+    #[test]
+    fn code128_uses_set_c_for_digits() {
+        let digits = Barcode::new(Symbology::Code128, "123456").unwrap();
+        assert_eq!(digits.native(), (73, vec![b'{', b'C', 12, 34, 56]));
+        // Start C, three pairs, checksum, stop
+        assert_eq!(digits.modules().len(), 11 * 5 + 13);
+
+        let text = Barcode::new(Symbology::Code128, "ORDER-42").unwrap();
+        assert_eq!(text.native(), (73, b"{BORDER-42".to_vec()));
+        let runs = runs(&text.modules());
+        assert_eq!(&runs[..6], &[2, 1, 1, 2, 1, 4]); // start B
+        assert_eq!(&runs[runs.len() - 7..], &[2, 3, 3, 1, 1, 1, 2]); // stop
+        assert!(Barcode::new(Symbology::Code128, "é").is_err());
+    }
+
+    #[test]
+    fn code39_and_itf_patterns() {
+        for c in "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ-. $/+%*".chars() {
+            assert_eq!(code39_pattern(c).unwrap().count_ones(), 3, "{}", c);
+        }
+        let code39 = Barcode::new(Symbology::Code39, "ab1").unwrap();
+        assert_eq!(code39.data(), "AB1");
+        // 5 characters with start/stop, 6 narrow and 3 wide elements each, 4 gaps
+        assert_eq!(code39.modules().len(), 5 * 15 + 4);
+        assert!(Barcode::new(Symbology::Code39, "a*b").is_err());
+
+        let itf = Barcode::new(Symbology::Itf, "123").unwrap();
+        assert_eq!(itf.data(), "0123");
+        assert_eq!(itf.native(), (70, b"0123".to_vec()));
+        assert_eq!(itf.modules().len(), 4 + 2 * 18 + 5);
+    }
+
+    #[test]
+    fn raster_qr_and_barcode_fit_the_page() {
+        let profile = PrinterProfile {
+            dot_width: 384,
+            ..PrinterProfile::default()
+        };
+
+        let options = QrOptions {
+            justify: Justify::Center,
+            ..QrOptions::default()
+        };
+        let qr = render_qr("https://example.com/r/42", &options, &profile).unwrap();
+        // Version 2 is 25 modules
+        assert_eq!((qr.width(), qr.height()), (384, 25 * 6));
+        assert!(qr.get((384 - 150) / 2, 0));
+        assert!(!qr.get((384 - 150) / 2 - 1, 0));
+
+        let barcode = Barcode::new(Symbology::Code128, "ORDER-42").unwrap();
+        let options = BarcodeOptions {
+            module_width: 6,
+            hri: false,
+            ..BarcodeOptions::default()
+        };
+        let bitmap = render_barcode(&barcode, &options, &profile).unwrap();
+        assert_eq!(bitmap.height(), 80);
+        // 123 modules don't fit at 6 dots, so they are drawn at 2
+        let ink = (0..384).filter(|&x| bitmap.get(x, 0)).count();
+        assert_eq!(ink, barcode.modules().iter().filter(|&&b| b).count() * 2);
+    }
+}
@@ -0,0 +1,412 @@
+use serde::{Deserialize, Serialize};
+use unicode_bidi::BidiInfo;
+
+/// Character table used for printer text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodePage {
+    /// US English and box drawing, the power-on default of most printers
+    #[default]
+    Cp437,
+    /// Western European with the euro sign
+    Cp858,
+    /// Arabic presentation forms
+    Cp864,
+    /// Windows Arabic
+    Wpc1256,
+}
+
+impl CodePage {
+    /// `ESC t` table number on Epson-compatible printers
+    pub fn table(self) -> u8 {
+        match self {
+            CodePage::Cp437 => 0,
+            CodePage::Cp858 => 19,
+            CodePage::Cp864 => 37,
+            CodePage::Wpc1256 => 50,
+        }
+    }
+
+    /// Code page with an Epson `ESC t` table number
+    pub fn from_table(table: u8) -> Option<Self> {
+        [CodePage::Cp437, CodePage::Cp858, CodePage::Cp864, CodePage::Wpc1256]
+            .into_iter()
+            .find(|c| c.table() == table)
+    }
+
+    /// Character printed for a byte; undefined bytes decode to `None`
+    pub fn decode(self, byte: u8) -> Option<char> {
+        if byte < 0x80 {
+            return Some(byte as char);
+        }
+        match self.upper_half()[byte as usize - 0x80] {
+            0 => None,
+            code => char::from_u32(code as u32),
+        }
+    }
+
+    /// Unicode code points of bytes 0x80-0xFF, 0 where undefined
+    fn upper_half(self) -> &'static [u16; 128] {
+        match self {
+            CodePage::Cp437 => &CP437,
+            CodePage::Cp858 => &CP858,
+            CodePage::Cp864 => &CP864,
+            CodePage::Wpc1256 => &WPC1256,
+        }
+    }
+
+    /// Byte for a single character, if the table has it
+    pub fn encode_char(self, c: char) -> Option<u8> {
+        if c.is_ascii() {
+            return Some(c as u8);
+        }
+        if self == CodePage::Cp864 && c == '\u{066A}' {
+            // Arabic percent sign sits on the ASCII '%' position
+            return Some(b'%');
+        }
+
+        let code = u16::try_from(c as u32).ok()?;
+        self.upper_half()
+            .iter()
+            .position(|&v| v == code)
+            .map(|i| 0x80 + i as u8)
+    }
+
+    /// Encode text, or `None` if a character is missing from the table
+    pub fn encode(self, text: &str) -> Option<Vec<u8>> {
+        self.prepare(text).chars().map(|c| self.encode_form(c)).collect()
+    }
+
+    /// Encode text, printing `replacement` for characters missing from the table
+    pub fn encode_lossy(self, text: &str, replacement: char) -> Vec<u8> {
+        let fallback = self.encode_char(replacement).unwrap_or(b'?');
+        self.prepare(text)
+            .chars()
+            .map(|c| self.encode_form(c).unwrap_or(fallback))
+            .collect()
+    }
+
+    /// Whether every character of `text` can be printed with this table
+    pub fn can_encode(self, text: &str) -> bool {
+        self.encode(text).is_some()
+    }
+
+    /// Printers draw code page text left to right with one glyph per byte, so
+    /// Arabic has to be put in visual order, and joined into presentation
+    /// forms on CP864 which has no contextual shaping of its own.
+    fn prepare(self, text: &str) -> String {
+        if !matches!(self, CodePage::Cp864 | CodePage::Wpc1256) || !text.chars().any(is_arabic) {
+            return text.to_string();
+        }
+
+        let text = if self == CodePage::Cp864 {
+            shape_arabic(text)
+        } else {
+            text.to_string()
+        };
+        visual_order(&text)
+    }
+
+    /// Like `encode_char`, but falls back to a nearby presentation form:
+    /// CP864 lacks most medial and final forms
+    fn encode_form(self, c: char) -> Option<u8> {
+        self.encode_char(c).or_else(|| {
+            let (isolated, form) = presentation_form(c)?;
+            let candidates: &[u32] = match form {
+                MEDIAL => &[INITIAL, ISOLATED],
+                INITIAL | FINAL => &[ISOLATED],
+                _ => &[],
+            };
+            candidates
+                .iter()
+                .filter_map(|&f| char::from_u32(isolated + f))
+                .find_map(|f| self.encode_char(f))
+        })
+    }
+}
+
+/// Offsets of the contextual forms from a letter's isolated form
+const ISOLATED: u32 = 0;
+const FINAL: u32 = 1;
+const INITIAL: u32 = 2;
+const MEDIAL: u32 = 3;
+
+const TATWEEL: char = '\u{0640}';
+const LAM: char = '\u{0644}';
+
+/// Arabic letters with their isolated presentation form and whether they join
+/// on both sides (true) or only to the preceding letter (false)
+const ARABIC_LETTERS: [(char, u32, bool); 36] = [
+    ('\u{0621}', 0xFE80, false),
+    ('\u{0622}', 0xFE81, false),
+    ('\u{0623}', 0xFE83, false),
+    ('\u{0624}', 0xFE85, false),
+    ('\u{0625}', 0xFE87, false),
+    ('\u{0626}', 0xFE89, true),
+    ('\u{0627}', 0xFE8D, false),
+    ('\u{0628}', 0xFE8F, true),
+    ('\u{0629}', 0xFE93, false),
+    ('\u{062A}', 0xFE95, true),
+    ('\u{062B}', 0xFE99, true),
+    ('\u{062C}', 0xFE9D, true),
+    ('\u{062D}', 0xFEA1, true),
+    ('\u{062E}', 0xFEA5, true),
+    ('\u{062F}', 0xFEA9, false),
+    ('\u{0630}', 0xFEAB, false),
+    ('\u{0631}', 0xFEAD, false),
+    ('\u{0632}', 0xFEAF, false),
+    ('\u{0633}', 0xFEB1, true),
+    ('\u{0634}', 0xFEB5, true),
+    ('\u{0635}', 0xFEB9, true),
+    ('\u{0636}', 0xFEBD, true),
+    ('\u{0637}', 0xFEC1, true),
+    ('\u{0638}', 0xFEC5, true),
+    ('\u{0639}', 0xFEC9, true),
+    ('\u{063A}', 0xFECD, true),
+    ('\u{0641}', 0xFED1, true),
+    ('\u{0642}', 0xFED5, true),
+    ('\u{0643}', 0xFED9, true),
+    ('\u{0644}', 0xFEDD, true),
+    ('\u{0645}', 0xFEE1, true),
+    ('\u{0646}', 0xFEE5, true),
+    ('\u{0647}', 0xFEE9, true),
+    ('\u{0648}', 0xFEED, false),
+    ('\u{0649}', 0xFEEF, false),
+    ('\u{064A}', 0xFEF1, true),
+];
+
+/// Isolated lam-alef ligature for each alef that follows a lam
+const LAM_ALEF: [(char, u32); 4] = [
+    ('\u{0622}', 0xFEF5),
+    ('\u{0623}', 0xFEF7),
+    ('\u{0625}', 0xFEF9),
+    ('\u{0627}', 0xFEFB),
+];
+
+fn is_arabic(c: char) -> bool {
+    matches!(c, '\u{0600}'..='\u{06FF}' | '\u{FE70}'..='\u{FEFF}')
+}
+
+/// Harakat and other marks that don't break joining
+fn is_transparent(c: char) -> bool {
+    matches!(c, '\u{064B}'..='\u{0652}' | '\u{0670}')
+}
+
+fn letter(c: char) -> Option<(u32, bool)> {
+    ARABIC_LETTERS
+        .iter()
+        .find(|(base, _, _)| *base == c)
+        .map(|&(_, isolated, dual)| (isolated, dual))
+}
+
+fn joins_next(c: char) -> bool {
+    c == TATWEEL || letter(c).is_some_and(|(_, dual)| dual)
+}
+
+fn joins_previous(c: char) -> bool {
+    // Hamza never joins
+    c == TATWEEL || (letter(c).is_some() && c != '\u{0621}')
+}
+
+/// Isolated form and form offset of a presentation form character
+fn presentation_form(c: char) -> Option<(u32, u32)> {
+    let code = c as u32;
+    if (0xFEF5..=0xFEFC).contains(&code) {
+        return Some((code - (code - 0xFEF5) % 2, (code - 0xFEF5) % 2));
+    }
+
+    ARABIC_LETTERS.iter().find_map(|&(_, isolated, dual)| {
+        let forms = if dual { 4 } else { 2 };
+        if (isolated..isolated + forms).contains(&code) {
+            Some((isolated, code - isolated))
+        } else {
+            None
+        }
+    })
+}
+
+/// Replace Arabic letters with their contextual presentation forms
+fn shape_arabic(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let neighbour = |range: &mut dyn Iterator<Item = usize>| {
+        range.map(|j| (j, chars[j])).find(|(_, c)| !is_transparent(*c))
+    };
+
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let Some((isolated, _)) = letter(c) else {
+            out.push(c);
+            i += 1;
+            continue;
+        };
+
+        let previous = neighbour(&mut (0..i).rev());
+        let next = neighbour(&mut (i + 1..chars.len()));
+        let after_joiner = joins_previous(c) && previous.is_some_and(|(_, p)| joins_next(p));
+
+        // Lam followed by alef becomes a single ligature
+        if c == LAM {
+            if let Some((j, ligature)) = next.and_then(|(j, n)| {
+                LAM_ALEF.iter().find(|(alef, _)| *alef == n).map(|&(_, l)| (j, l))
+            }) {
+                let form = if after_joiner { FINAL } else { ISOLATED };
+                out.extend(char::from_u32(ligature + form));
+                out.extend(&chars[i + 1..j]);
+                i = j + 1;
+                continue;
+            }
+        }
+
+        let before_joiner = joins_next(c) && next.is_some_and(|(_, n)| joins_previous(n));
+        let form = match (after_joiner, before_joiner) {
+            (false, false) => ISOLATED,
+            (true, false) => FINAL,
+            (false, true) => INITIAL,
+            (true, true) => MEDIAL,
+        };
+        out.extend(char::from_u32(isolated + form));
+        i += 1;
+    }
+
+    out
+}
+
+/// Reorder each line from logical to visual (left to right) order
+fn visual_order(text: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            let bidi = BidiInfo::new(line, None);
+            match bidi.paragraphs.first() {
+                Some(paragraph) => bidi.reorder_line(paragraph, paragraph.range.clone()).into_owned(),
+                None => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Upper halves (bytes 0x80-0xFF) of each table
+
+const CP437: [u16; 128] = [
+    0x00C7, 0x00FC, 0x00E9, 0x00E2, 0x00E4, 0x00E0, 0x00E5, 0x00E7,
+    0x00EA, 0x00EB, 0x00E8, 0x00EF, 0x00EE, 0x00EC, 0x00C4, 0x00C5,
+    0x00C9, 0x00E6, 0x00C6, 0x00F4, 0x00F6, 0x00F2, 0x00FB, 0x00F9,
+    0x00FF, 0x00D6, 0x00DC, 0x00A2, 0x00A3, 0x00A5, 0x20A7, 0x0192,
+    0x00E1, 0x00ED, 0x00F3, 0x00FA, 0x00F1, 0x00D1, 0x00AA, 0x00BA,
+    0x00BF, 0x2310, 0x00AC, 0x00BD, 0x00BC, 0x00A1, 0x00AB, 0x00BB,
+    0x2591, 0x2592, 0x2593, 0x2502, 0x2524, 0x2561, 0x2562, 0x2556,
+    0x2555, 0x2563, 0x2551, 0x2557, 0x255D, 0x255C, 0x255B, 0x2510,
+    0x2514, 0x2534, 0x252C, 0x251C, 0x2500, 0x253C, 0x255E, 0x255F,
+    0x255A, 0x2554, 0x2569, 0x2566, 0x2560, 0x2550, 0x256C, 0x2567,
+    0x2568, 0x2564, 0x2565, 0x2559, 0x2558, 0x2552, 0x2553, 0x256B,
+    0x256A, 0x2518, 0x250C, 0x2588, 0x2584, 0x258C, 0x2590, 0x2580,
+    0x03B1, 0x00DF, 0x0393, 0x03C0, 0x03A3, 0x03C3, 0x00B5, 0x03C4,
+    0x03A6, 0x0398, 0x03A9, 0x03B4, 0x221E, 0x03C6, 0x03B5, 0x2229,
+    0x2261, 0x00B1, 0x2265, 0x2264, 0x2320, 0x2321, 0x00F7, 0x2248,
+    0x00B0, 0x2219, 0x00B7, 0x221A, 0x207F, 0x00B2, 0x25A0, 0x00A0,
+];
+
+const CP858: [u16; 128] = [
+    0x00C7, 0x00FC, 0x00E9, 0x00E2, 0x00E4, 0x00E0, 0x00E5, 0x00E7,
+    0x00EA, 0x00EB, 0x00E8, 0x00EF, 0x00EE, 0x00EC, 0x00C4, 0x00C5,
+    0x00C9, 0x00E6, 0x00C6, 0x00F4, 0x00F6, 0x00F2, 0x00FB, 0x00F9,
+    0x00FF, 0x00D6, 0x00DC, 0x00F8, 0x00A3, 0x00D8, 0x00D7, 0x0192,
+    0x00E1, 0x00ED, 0x00F3, 0x00FA, 0x00F1, 0x00D1, 0x00AA, 0x00BA,
+    0x00BF, 0x00AE, 0x00AC, 0x00BD, 0x00BC, 0x00A1, 0x00AB, 0x00BB,
+    0x2591, 0x2592, 0x2593, 0x2502, 0x2524, 0x00C1, 0x00C2, 0x00C0,
+    0x00A9, 0x2563, 0x2551, 0x2557, 0x255D, 0x00A2, 0x00A5, 0x2510,
+    0x2514, 0x2534, 0x252C, 0x251C, 0x2500, 0x253C, 0x00E3, 0x00C3,
+    0x255A, 0x2554, 0x2569, 0x2566, 0x2560, 0x2550, 0x256C, 0x00A4,
+    0x00F0, 0x00D0, 0x00CA, 0x00CB, 0x00C8, 0x20AC, 0x00CD, 0x00CE,
+    0x00CF, 0x2518, 0x250C, 0x2588, 0x2584, 0x00A6, 0x00CC, 0x2580,
+    0x00D3, 0x00DF, 0x00D4, 0x00D2, 0x00F5, 0x00D5, 0x00B5, 0x00FE,
+    0x00DE, 0x00DA, 0x00DB, 0x00D9, 0x00FD, 0x00DD, 0x00AF, 0x00B4,
+    0x00AD, 0x00B1, 0x2017, 0x00BE, 0x00B6, 0x00A7, 0x00F7, 0x00B8,
+    0x00B0, 0x00A8, 0x00B7, 0x00B9, 0x00B3, 0x00B2, 0x25A0, 0x00A0,
+];
+
+const CP864: [u16; 128] = [
+    0x00B0, 0x00B7, 0x2219, 0x221A, 0x2592, 0x2500, 0x2502, 0x253C,
+    0x2524, 0x252C, 0x251C, 0x2534, 0x2510, 0x250C, 0x2514, 0x2518,
+    0x03B2, 0x221E, 0x03C6, 0x00B1, 0x00BD, 0x00BC, 0x2248, 0x00AB,
+    0x00BB, 0xFEF7, 0xFEF8, 0x0000, 0x0000, 0xFEFB, 0xFEFC, 0x0000,
+    0x00A0, 0x00AD, 0xFE82, 0x00A3, 0x00A4, 0xFE84, 0x0000, 0x0000,
+    0xFE8E, 0xFE8F, 0xFE95, 0xFE99, 0x060C, 0xFE9D, 0xFEA1, 0xFEA5,
+    0x0660, 0x0661, 0x0662, 0x0663, 0x0664, 0x0665, 0x0666, 0x0667,
+    0x0668, 0x0669, 0xFED1, 0x061B, 0xFEB1, 0xFEB5, 0xFEB9, 0x061F,
+    0x00A2, 0xFE80, 0xFE81, 0xFE83, 0xFE85, 0xFECA, 0xFE8B, 0xFE8D,
+    0xFE91, 0xFE93, 0xFE97, 0xFE9B, 0xFE9F, 0xFEA3, 0xFEA7, 0xFEA9,
+    0xFEAB, 0xFEAD, 0xFEAF, 0xFEB3, 0xFEB7, 0xFEBB, 0xFEBF, 0xFEC1,
+    0xFEC5, 0xFECB, 0xFECF, 0x00A6, 0x00AC, 0x00F7, 0x00D7, 0xFEC9,
+    0x0640, 0xFED3, 0xFED7, 0xFEDB, 0xFEDF, 0xFEE3, 0xFEE7, 0xFEEB,
+    0xFEED, 0xFEEF, 0xFEF3, 0xFEBD, 0xFECC, 0xFECE, 0xFECD, 0xFEE1,
+    0xFE7D, 0x0651, 0xFEE5, 0xFEE9, 0xFEEC, 0xFEF0, 0xFEF2, 0xFED0,
+    0xFED5, 0xFEF5, 0xFEF6, 0xFEDD, 0xFED9, 0xFEF1, 0x25A0, 0x0000,
+];
+
+const WPC1256: [u16; 128] = [
+    0x20AC, 0x067E, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021,
+    0x02C6, 0x2030, 0x0679, 0x2039, 0x0152, 0x0686, 0x0698, 0x0688,
+    0x06AF, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014,
+    0x06A9, 0x2122, 0x0691, 0x203A, 0x0153, 0x200C, 0x200D, 0x06BA,
+    0x00A0, 0x060C, 0x00A2, 0x00A3, 0x00A4, 0x00A5, 0x00A6, 0x00A7,
+    0x00A8, 0x00A9, 0x06BE, 0x00AB, 0x00AC, 0x00AD, 0x00AE, 0x00AF,
+    0x00B0, 0x00B1, 0x00B2, 0x00B3, 0x00B4, 0x00B5, 0x00B6, 0x00B7,
+    0x00B8, 0x00B9, 0x061B, 0x00BB, 0x00BC, 0x00BD, 0x00BE, 0x061F,
+    0x06C1, 0x0621, 0x0622, 0x0623, 0x0624, 0x0625, 0x0626, 0x0627,
+    0x0628, 0x0629, 0x062A, 0x062B, 0x062C, 0x062D, 0x062E, 0x062F,
+    0x0630, 0x0631, 0x0632, 0x0633, 0x0634, 0x0635, 0x0636, 0x00D7,
+    0x0637, 0x0638, 0x0639, 0x063A, 0x0640, 0x0641, 0x0642, 0x0643,
+    0x00E0, 0x0644, 0x00E2, 0x0645, 0x0646, 0x0647, 0x0648, 0x00E7,
+    0x00E8, 0x00E9, 0x00EA, 0x00EB, 0x0649, 0x064A, 0x00EE, 0x00EF,
+    0x064B, 0x064C, 0x064D, 0x064E, 0x00F4, 0x064F, 0x0650, 0x00F7,
+    0x0651, 0x00F9, 0x0652, 0x00FB, 0x00FC, 0x200E, 0x200F, 0x06D2,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin_characters_use_table_bytes() {
+        assert_eq!(CodePage::Cp437.encode("Café ñ"), Some(b"Caf\x82 \xA4".to_vec()));
+        assert_eq!(CodePage::Cp858.encode("5€"), Some(b"5\xD5".to_vec()));
+        assert_eq!(CodePage::Cp437.encode("5€"), None);
+    }
+
+    #[test]
+    fn decodes_table_bytes() {
+        assert_eq!(CodePage::from_table(19), Some(CodePage::Cp858));
+        assert_eq!(CodePage::from_table(99), None);
+        let text: Option<String> = b"Caf\x82 \xD5".iter().map(|&b| CodePage::Cp858.decode(b)).collect();
+        assert_eq!(text.as_deref(), Some("Café €"));
+    }
+
+    #[test]
+    fn lossy_encoding_uses_replacement() {
+        assert_eq!(CodePage::Cp437.encode_lossy("5€", '*'), b"5*".to_vec());
+        // Replacement missing from the table falls back to '?'
+        assert_eq!(CodePage::Cp437.encode_lossy("5€", '€'), b"5?".to_vec());
+    }
+
+    #[test]
+    fn wpc1256_reverses_arabic() {
+        assert_eq!(CodePage::Wpc1256.encode("سلام"), Some(vec![0xE3, 0xC7, 0xE1, 0xD3]));
+    }
+
+    #[test]
+    fn cp864_shapes_and_reverses_arabic() {
+        // beh (initial) + yeh (medial, printed as initial) + teh (final, printed as isolated)
+        assert_eq!(CodePage::Cp864.encode("بيت"), Some(vec![0xAA, 0xEA, 0xC8]));
+        // lam + alef ligature
+        assert_eq!(CodePage::Cp864.encode("لا"), Some(vec![0x9D]));
+    }
+
+    #[test]
+    fn mixed_lines_keep_numbers_left_to_right() {
+        let encoded = CodePage::Wpc1256.encode("سلام 12").unwrap();
+        assert_eq!(encoded, vec![b'1', b'2', b' ', 0xE3, 0xC7, 0xE1, 0xD3]);
+    }
+}
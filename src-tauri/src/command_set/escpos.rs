@@ -0,0 +1,100 @@
+use anyhow::Result;
+use crate::config::{CutOptions, CutStyle, DrawerOptions, PrinterProfile};
+use crate::dither::MonoBitmap;
+use crate::escpos::{
+    encode_column, encode_graphics, encode_graphics_planes, encode_raster, EscPos, EscPosBuilder, Font, ImageMode, InkColor,
+    Justify,
+};
+use super::CommandSet;
+
+/// Epson ESC/POS
+pub struct EscPosCommands;
+
+impl CommandSet for EscPosCommands {
+    /// Reset, then the profile's code page and density
+    fn setup(&self, profile: &PrinterProfile) -> Vec<u8> {
+        let mut builder = EscPosBuilder::new();
+        builder.init().code_page(profile.code_page, profile.code_table);
+        if let Some(density) = profile.density {
+            builder.raw(&EscPos::density(density));
+        }
+        builder.build()
+    }
+
+    /// Encode with the profile's image mode
+    fn image(&self, bitmap: &MonoBitmap, profile: &PrinterProfile) -> Vec<u8> {
+        match profile.image_mode {
+            ImageMode::Column => encode_column(bitmap),
+            ImageMode::Raster => encode_raster(bitmap, profile.max_band_height),
+            ImageMode::Graphics => encode_graphics(bitmap, profile.max_band_height),
+        }
+    }
+
+    fn two_color_image(&self, black: &MonoBitmap, red: &MonoBitmap, profile: &PrinterProfile) -> Vec<u8> {
+        if red.is_blank() {
+            return self.image(black, profile);
+        }
+        // Only the graphics buffer takes a second colour plane
+        encode_graphics_planes(&[black, red], profile.max_band_height)
+    }
+
+    fn cut(&self, cut: &CutOptions) -> Vec<u8> {
+        let mut data = Vec::new();
+        for _ in 0..cut.feed_lines {
+            data.extend_from_slice(EscPos::LF);
+        }
+        match cut.style {
+            CutStyle::Full => data.extend_from_slice(EscPos::CUT_FULL),
+            CutStyle::Partial => data.extend_from_slice(EscPos::CUT_PARTIAL),
+            CutStyle::None => {}
+        }
+        data
+    }
+
+    fn drawer(&self, pin: u8, drawer: &DrawerOptions) -> Result<Vec<u8>> {
+        Ok(EscPos::drawer_pulse(drawer.pin.unwrap_or(pin), drawer.pulse_on_ms, drawer.pulse_off_ms))
+    }
+
+    fn feed(&self, lines: u8) -> Vec<u8> {
+        EscPos::feed(lines)
+    }
+
+    fn line_spacing(&self, dots: Option<u8>) -> Vec<u8> {
+        match dots {
+            Some(dots) => EscPos::line_spacing(dots),
+            None => EscPos::LINE_SPACING_DEFAULT.to_vec(),
+        }
+    }
+
+    fn justify(&self, justify: Justify) -> Vec<u8> {
+        EscPos::justify(justify)
+    }
+
+    fn font(&self, font: Font) -> Vec<u8> {
+        EscPos::font(font)
+    }
+
+    fn size(&self, width: u8, height: u8) -> Vec<u8> {
+        EscPos::char_size(width, height)
+    }
+
+    fn bold(&self, on: bool) -> Vec<u8> {
+        EscPos::bold(on)
+    }
+
+    fn underline(&self, weight: u8) -> Vec<u8> {
+        EscPos::underline(weight)
+    }
+
+    fn inverse(&self, on: bool) -> Vec<u8> {
+        EscPos::inverse(on)
+    }
+
+    fn color(&self, color: InkColor) -> Vec<u8> {
+        EscPos::color(color)
+    }
+
+    fn beep(&self, times: u8, duration: u8) -> Result<Vec<u8>> {
+        Ok(EscPos::beep(times, duration))
+    }
+}
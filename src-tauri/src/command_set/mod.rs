@@ -0,0 +1,110 @@
+//! Printer command languages.
+//!
+//! Receipts are built as ESC/POS, but the commands that differ most between
+//! vendors — job setup, images, cuts and drawer kicks — go through a
+//! [`CommandSet`] chosen by the profile's `emulation`:
+//!
+//! | Emulation  | Printers                                 |
+//! |------------|------------------------------------------|
+//! | `escPos`   | Epson and compatible printers (default)  |
+//! | `starLine` | Star TSP100/TSP650 in Star Line / StarPRNT mode |
+//! | `zpl`      | Zebra label printers, see [`crate::label`] |
+//! | `tspl`     | TSC label printers, see [`crate::label`]   |
+
+mod escpos;
+mod star;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::config::{CutOptions, DrawerOptions, PrinterProfile};
+use crate::dither::MonoBitmap;
+use crate::escpos::{Font, InkColor, Justify};
+use crate::label::{LabelCommands, LabelLanguage, Tspl, Zpl};
+
+pub use escpos::EscPosCommands;
+pub use star::StarLineCommands;
+
+/// Command language a printer is set to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Emulation {
+    #[default]
+    EscPos,
+    /// Star Line Mode and StarPRNT, images sent in Star raster mode
+    StarLine,
+    /// Zebra Programming Language labels
+    Zpl,
+    /// TSC Printer Language labels
+    Tspl,
+}
+
+impl Emulation {
+    /// Encoder for this language
+    pub fn commands(self) -> &'static dyn CommandSet {
+        match self {
+            Emulation::EscPos => &EscPosCommands,
+            Emulation::StarLine => &StarLineCommands,
+            Emulation::Zpl => &LabelCommands(&Zpl),
+            Emulation::Tspl => &LabelCommands(&Tspl),
+        }
+    }
+
+    /// Field encoder of label printer languages
+    pub fn label_language(self) -> Option<&'static dyn LabelLanguage> {
+        match self {
+            Emulation::Zpl => Some(&Zpl),
+            Emulation::Tspl => Some(&Tspl),
+            Emulation::EscPos | Emulation::StarLine => None,
+        }
+    }
+
+    /// Whether the printer prints labels rather than receipts
+    pub fn is_label(self) -> bool {
+        self.label_language().is_some()
+    }
+}
+
+/// Encodes the printer commands that differ between command languages
+pub trait CommandSet: Sync {
+    /// Commands sent at the start of every job
+    fn setup(&self, profile: &PrinterProfile) -> Vec<u8>;
+
+    /// Print a 1-bit image
+    fn image(&self, bitmap: &MonoBitmap, profile: &PrinterProfile) -> Vec<u8>;
+
+    /// Print a black and a red plane of the same size on two-colour paper
+    fn two_color_image(&self, black: &MonoBitmap, red: &MonoBitmap, profile: &PrinterProfile) -> Vec<u8>;
+
+    /// Feed past the cutter, then cut as configured
+    fn cut(&self, cut: &CutOptions) -> Vec<u8>;
+
+    /// Kick the cash drawer on `pin` unless the drawer options set one
+    fn drawer(&self, pin: u8, drawer: &DrawerOptions) -> Result<Vec<u8>>;
+
+    /// Print and feed `lines` lines
+    fn feed(&self, lines: u8) -> Vec<u8>;
+
+    /// Line spacing in dots, or the printer default when `None`
+    fn line_spacing(&self, dots: Option<u8>) -> Vec<u8>;
+
+    fn justify(&self, justify: Justify) -> Vec<u8>;
+
+    fn font(&self, font: Font) -> Vec<u8>;
+
+    /// Character width and height multipliers
+    fn size(&self, width: u8, height: u8) -> Vec<u8>;
+
+    fn bold(&self, on: bool) -> Vec<u8>;
+
+    /// Underline off (0), thin (1) or thick (2)
+    fn underline(&self, weight: u8) -> Vec<u8>;
+
+    /// White-on-black text
+    fn inverse(&self, on: bool) -> Vec<u8>;
+
+    /// Black or red ink on two-colour printers
+    fn color(&self, color: InkColor) -> Vec<u8>;
+
+    /// Sound the buzzer `times` times for `duration` x 50 ms each
+    fn beep(&self, times: u8, duration: u8) -> Result<Vec<u8>>;
+}
@@ -0,0 +1,222 @@
+use anyhow::Result;
+use crate::config::{CutOptions, CutStyle, DrawerOptions, PrinterProfile};
+use crate::dither::MonoBitmap;
+use crate::escpos::{ink_widths, pack_row_prefix, Font, InkColor, Justify};
+use super::CommandSet;
+
+/// Star Line Mode / StarPRNT: images go through Star raster mode, which
+/// TSP100 printers without a character generator also understand
+pub struct StarLineCommands;
+
+impl StarLineCommands {
+    /// Initialize the printer (ESC @)
+    const INIT: &'static [u8] = &[0x1B, 0x40];
+    /// Enter raster mode (ESC * r A)
+    const RASTER_ENTER: &'static [u8] = &[0x1B, 0x2A, 0x72, 0x41];
+    /// Continuous paper, no page length (ESC * r P 0 NUL)
+    const RASTER_CONTINUOUS: &'static [u8] = &[0x1B, 0x2A, 0x72, 0x50, b'0', 0x00];
+    /// Quit raster mode (ESC * r B)
+    const RASTER_QUIT: &'static [u8] = &[0x1B, 0x2A, 0x72, 0x42];
+    /// Kick drawer 1 (BEL) and drawer 2 (SUB)
+    const DRAWER_1: u8 = 0x07;
+    const DRAWER_2: u8 = 0x1A;
+    /// Default 4 mm line spacing (ESC z 1)
+    const LINE_SPACING_DEFAULT: &'static [u8] = &[0x1B, 0x7A, 0x01];
+}
+
+impl CommandSet for StarLineCommands {
+    fn setup(&self, _profile: &PrinterProfile) -> Vec<u8> {
+        Self::INIT.to_vec()
+    }
+
+    /// One `b n1 n2` row per line of dots, blank runs skipped with `ESC * r Y`
+    fn image(&self, bitmap: &MonoBitmap, _profile: &PrinterProfile) -> Vec<u8> {
+        let mut data = Self::RASTER_ENTER.to_vec();
+        data.extend_from_slice(Self::RASTER_CONTINUOUS);
+
+        let mut blank = 0;
+        for (y, width) in ink_widths(bitmap).into_iter().enumerate() {
+            if width == 0 {
+                blank += 1;
+                continue;
+            }
+            raster_feed(&mut data, blank);
+            blank = 0;
+
+            let byte_width = (width + 7) / 8;
+            data.push(b'b');
+            data.extend_from_slice(&(byte_width as u16).to_le_bytes());
+            pack_row_prefix(bitmap, y as u32, byte_width, &mut data);
+        }
+        raster_feed(&mut data, blank);
+
+        data.extend_from_slice(Self::RASTER_QUIT);
+        data
+    }
+
+    /// Red is not supported in raster mode, both planes print black
+    fn two_color_image(&self, black: &MonoBitmap, red: &MonoBitmap, profile: &PrinterProfile) -> Vec<u8> {
+        let mut merged = black.clone();
+        merged.overlay(red);
+        self.image(&merged, profile)
+    }
+
+    /// Line feeds, then ESC d 0 (full) or ESC d 1 (partial) at the current position
+    fn cut(&self, cut: &CutOptions) -> Vec<u8> {
+        let mut data = vec![0x0A; cut.feed_lines as usize];
+        match cut.style {
+            CutStyle::Full => data.extend_from_slice(&[0x1B, 0x64, 0]),
+            CutStyle::Partial => data.extend_from_slice(&[0x1B, 0x64, 1]),
+            CutStyle::None => {}
+        }
+        data
+    }
+
+    /// Pins are numbered as for ESC/POS: 0 or 2 is drawer 1, which takes its
+    /// pulse width from ESC BEL n1 n2 in 10 ms units; 1 or 5 is drawer 2,
+    /// which always uses the printer's own
+    fn drawer(&self, pin: u8, drawer: &DrawerOptions) -> Result<Vec<u8>> {
+        Ok(match drawer.pin.unwrap_or(pin) {
+            0 | 2 => {
+                let units = |ms: u16| (ms / 10).clamp(1, 255) as u8;
+                vec![0x1B, 0x07, units(drawer.pulse_on_ms), units(drawer.pulse_off_ms), Self::DRAWER_1]
+            }
+            1 | 5 => vec![Self::DRAWER_2],
+            pin => anyhow::bail!("Drawer pin {} does not exist on Star printers, use 2 or 5", pin),
+        })
+    }
+
+    /// ESC a n; ESC d n would cut the paper here
+    fn feed(&self, lines: u8) -> Vec<u8> {
+        if lines == 0 {
+            return Vec::new();
+        }
+        vec![0x1B, 0x61, lines.min(127)]
+    }
+
+    /// ESC 3 n sets the spacing in 1/4 mm, two dots at 203 dpi
+    fn line_spacing(&self, dots: Option<u8>) -> Vec<u8> {
+        match dots {
+            Some(dots) => vec![0x1B, 0x33, (dots / 2).max(1)],
+            None => Self::LINE_SPACING_DEFAULT.to_vec(),
+        }
+    }
+
+    /// ESC GS a n
+    fn justify(&self, justify: Justify) -> Vec<u8> {
+        let n = match justify {
+            Justify::Left => 0,
+            Justify::Center => 1,
+            Justify::Right => 2,
+        };
+        vec![0x1B, 0x1D, 0x61, n]
+    }
+
+    /// ESC RS F n: font A is 12 x 24, font B 9 x 24
+    fn font(&self, font: Font) -> Vec<u8> {
+        let n = match font {
+            Font::A => 0,
+            Font::B => 1,
+        };
+        vec![0x1B, 0x1E, 0x46, n]
+    }
+
+    /// ESC i n1 n2, height first; Star expands up to 6 times
+    fn size(&self, width: u8, height: u8) -> Vec<u8> {
+        vec![0x1B, 0x69, height.clamp(1, 6) - 1, width.clamp(1, 6) - 1]
+    }
+
+    /// ESC E / ESC F
+    fn bold(&self, on: bool) -> Vec<u8> {
+        vec![0x1B, if on { 0x45 } else { 0x46 }]
+    }
+
+    /// ESC - n, Star only has one weight
+    fn underline(&self, weight: u8) -> Vec<u8> {
+        vec![0x1B, 0x2D, weight.min(1)]
+    }
+
+    /// Highlight (ESC 4 / ESC 5), white on black on single-colour printers
+    fn inverse(&self, on: bool) -> Vec<u8> {
+        vec![0x1B, if on { 0x34 } else { 0x35 }]
+    }
+
+    /// Everything prints black, as with images
+    fn color(&self, _color: InkColor) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn beep(&self, _times: u8, _duration: u8) -> Result<Vec<u8>> {
+        anyhow::bail!("Beeping is not supported on Star printers")
+    }
+}
+
+/// Move the paper `dots` in raster mode (ESC * r Y n NUL, `n` in ASCII digits)
+fn raster_feed(data: &mut Vec<u8>, mut dots: u32) {
+    while dots > 0 {
+        let n = dots.min(255);
+        data.extend_from_slice(&[0x1B, 0x2A, 0x72, 0x59]);
+        data.extend_from_slice(n.to_string().as_bytes());
+        data.push(0x00);
+        dots -= n;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_set::Emulation;
+
+    #[test]
+    fn raster_rows_skip_blank_lines() {
+        let mut bitmap = MonoBitmap::new(24, 4);
+        bitmap.set(0, 0, true);
+        bitmap.set(9, 3, true);
+
+        let profile = PrinterProfile {
+            emulation: Emulation::StarLine,
+            ..PrinterProfile::default()
+        };
+        let data = profile.emulation.commands().image(&bitmap, &profile);
+        assert_eq!(
+            data,
+            [
+                &[0x1B, 0x2A, 0x72, 0x41, 0x1B, 0x2A, 0x72, 0x50, b'0', 0][..],
+                &[b'b', 1, 0, 0x80],
+                &[0x1B, 0x2A, 0x72, 0x59, b'2', 0],
+                &[b'b', 2, 0, 0x00, 0x40],
+                &[0x1B, 0x2A, 0x72, 0x42],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn cuts_and_drawers() {
+        let star = Emulation::StarLine.commands();
+        let cut = CutOptions {
+            style: CutStyle::Partial,
+            feed_lines: 2,
+        };
+        assert_eq!(star.cut(&cut), vec![0x0A, 0x0A, 0x1B, 0x64, 1]);
+
+        let drawer = DrawerOptions::default();
+        assert_eq!(star.drawer(0, &drawer).unwrap(), vec![0x1B, 0x07, 5, 50, 0x07]);
+        assert_eq!(star.drawer(1, &drawer).unwrap(), vec![0x1A]);
+    }
+
+    #[test]
+    fn drawer_pins_match_escpos() {
+        let star = Emulation::StarLine.commands();
+        let drawer = DrawerOptions::default();
+        assert_eq!(star.drawer(2, &drawer).unwrap(), vec![0x1B, 0x07, 5, 50, 0x07]);
+        assert_eq!(star.drawer(5, &drawer).unwrap(), vec![0x1A]);
+
+        let pin_5 = DrawerOptions {
+            pin: Some(5),
+            ..DrawerOptions::default()
+        };
+        assert_eq!(star.drawer(2, &pin_5).unwrap(), vec![0x1A]);
+        assert!(star.drawer(3, &drawer).is_err());
+    }
+}
@@ -1,8 +1,15 @@
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Manager, State};
 use tauri_plugin_autostart::ManagerExt;
-use crate::{config, printer, api, escpos::ThermalImage};
+use crate::{config, printer, api, escpos::ThermalImage, transport::{PrinterStatus, TransportStatus}};
+use crate::config::PrinterProfile;
+use crate::document::Document;
+use crate::journal::{Journal, JournalEntry};
+use crate::logo::StoredLogo;
+use crate::printer::PrintPreview;
+use crate::retry::RetryPolicies;
+use crate::service::{self, PrintService, PrinterHealth, ServiceStatus};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -15,6 +22,8 @@ pub struct ConfigResponse {
     pub drawer_pin: u8,
     pub polling_interval: u64,
     pub auto_start: bool,
+    pub retry_policies: RetryPolicies,
+    pub printer_profiles: HashMap<String, PrinterProfile>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +37,12 @@ pub struct SaveConfigRequest {
     pub drawer_pin: u8,
     pub polling_interval: u64,
     pub auto_start: bool,
+    #[serde(default)]
+    pub retry_policies: RetryPolicies,
+    /// Replaces the saved profiles when given. Logos stay as recorded by
+    /// `store_printer_logo` either way, the webview's copy may be stale.
+    #[serde(default)]
+    pub printer_profiles: Option<HashMap<String, PrinterProfile>>,
 }
 
 // ============ Config Commands ============
@@ -45,11 +60,27 @@ pub fn get_config() -> Result<ConfigResponse, String> {
         drawer_pin: cfg.drawer_pin,
         polling_interval: cfg.polling_interval,
         auto_start: cfg.auto_start,
+        retry_policies: cfg.retry_policies,
+        printer_profiles: cfg.printer_profiles,
     })
 }
 
 #[tauri::command]
 pub fn save_config(config_data: SaveConfigRequest) -> Result<(), String> {
+    let saved = config::load_config().map_err(|e| e.to_string())?;
+    let printer_profiles = match config_data.printer_profiles {
+        None => saved.printer_profiles,
+        Some(mut profiles) => {
+            for (printer, profile) in profiles.iter_mut() {
+                profile.logos = saved.printer_profiles
+                    .get(printer)
+                    .map(|saved| saved.logos.clone())
+                    .unwrap_or_default();
+            }
+            profiles
+        }
+    };
+
     let cfg = config::Config {
         domain_url: config_data.domain_url,
         key: config_data.key,
@@ -59,6 +90,8 @@ pub fn save_config(config_data: SaveConfigRequest) -> Result<(), String> {
         drawer_pin: config_data.drawer_pin,
         polling_interval: config_data.polling_interval,
         auto_start: config_data.auto_start,
+        retry_policies: config_data.retry_policies,
+        printer_profiles,
     };
 
     config::save_config(&cfg).map_err(|e| e.to_string())?;
@@ -71,11 +104,66 @@ pub async fn test_connection(domain_url: String, key: String) -> Result<bool, St
     client.test_connection().await.map_err(|e| e.to_string())
 }
 
+// ============ Service Commands ============
+
+#[tauri::command]
+pub fn start_service(app: AppHandle, service: State<'_, PrintService>) -> Result<ServiceStatus, String> {
+    service.start(app).map_err(|e| e.to_string())?;
+    Ok(service.status())
+}
+
+#[tauri::command]
+pub async fn stop_service(app: AppHandle, service: State<'_, PrintService>) -> Result<ServiceStatus, String> {
+    service.stop(&app).await;
+    Ok(service.status())
+}
+
+#[tauri::command]
+pub fn get_service_status(service: State<'_, PrintService>) -> ServiceStatus {
+    service.status()
+}
+
+/// Live state of the printers watched with Automatic Status Back
+#[tauri::command]
+pub fn get_printer_health(service: State<'_, PrintService>) -> Vec<PrinterHealth> {
+    service.health()
+}
+
+#[tauri::command]
+pub fn get_dead_letters() -> Result<Vec<JournalEntry>, String> {
+    let journal = Journal::open().map_err(|e| e.to_string())?;
+    journal.dead_letters().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reprint_dead_letter(job_id: i64, printer_name: Option<String>) -> Result<(), String> {
+    service::reprint_dead_letter(job_id, printer_name.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn discard_dead_letter(job_id: i64) -> Result<(), String> {
+    let journal = Journal::open().map_err(|e| e.to_string())?;
+    journal.discard(job_id).map_err(|e| e.to_string())
+}
+
 // ============ Printer Commands ============
 
 #[tauri::command]
-pub fn get_system_printers() -> Result<Vec<String>, String> {
-    printer::get_system_printers().map_err(|e| e.to_string())
+pub async fn get_system_printers() -> Result<Vec<String>, String> {
+    blocking(printer::get_system_printers).await
+}
+
+#[tauri::command]
+pub async fn get_printer_status(printer_name: String) -> Result<TransportStatus, String> {
+    blocking(move || printer::printer_status(&printer_name)).await
+}
+
+/// Paper, cover and error status reported by the printer itself
+#[tauri::command]
+pub async fn query_printer_status(printer_name: String) -> Result<Option<PrinterStatus>, String> {
+    blocking(move || printer::query_printer_status(&printer_name)).await
 }
 
 #[tauri::command]
@@ -100,121 +188,127 @@ pub async fn update_job_status(job_id: i64, status: String, reason: Option<Strin
 }
 
 #[tauri::command]
-pub fn test_print(printer_name: String) -> Result<(), String> {
-    printer::print_test_page(&printer_name).map_err(|e| e.to_string())
+pub async fn test_print(printer_name: String) -> Result<(), String> {
+    blocking(move || printer::print_test_page(&printer_name)).await
 }
 
 // ============ Thermal Printing Commands ============
 
 #[tauri::command]
-pub fn print_image_to_thermal(printer_name: String, image_data: Vec<u8>) -> Result<(), String> {
+pub async fn print_image_to_thermal(printer_name: String, image_data: Vec<u8>) -> Result<(), String> {
     let img = image::load_from_memory(&image_data)
         .map_err(|e| format!("Failed to load image: {}", e))?;
 
-    let escpos_data = ThermalImage::to_escpos_bitmap(&img, ThermalImage::MAX_WIDTH_80MM)
+    let profile = config::load_printer_profile(&printer_name)
+        .map_err(|e| e.to_string())?;
+    let escpos_data = ThermalImage::image_to_escpos(&img, &profile)
         .map_err(|e| e.to_string())?;
 
-    printer::print_raw(&printer_name, &escpos_data).map_err(|e| e.to_string())
+    blocking(move || printer::print_raw(&printer_name, &escpos_data)).await
 }
 
 #[tauri::command]
-pub fn print_base64_to_thermal(printer_name: String, base64_image: String) -> Result<(), String> {
-    printer::print_base64_image(&printer_name, &base64_image, ThermalImage::MAX_WIDTH_80MM)
-        .map_err(|e| e.to_string())
+pub async fn print_base64_to_thermal(printer_name: String, base64_image: String) -> Result<(), String> {
+    blocking(move || printer::print_base64_image(&printer_name, &base64_image)).await
 }
 
 #[tauri::command]
 pub async fn print_image_from_url(printer_name: String, url: String) -> Result<(), String> {
-    printer::print_url_image(&printer_name, &url, ThermalImage::MAX_WIDTH_80MM)
+    printer::print_url_image(&printer_name, &url)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub fn print_to_80mm_fast(printer_name: String, base64_image: String) -> Result<(), String> {
+pub async fn print_to_80mm_fast(printer_name: String, base64_image: String) -> Result<(), String> {
     // Use streaming approach for fast 80mm printers
-    printer::print_base64_image(&printer_name, &base64_image, ThermalImage::MAX_WIDTH_80MM)
-        .map_err(|e| e.to_string())
+    blocking(move || printer::print_base64_image(&printer_name, &base64_image)).await
 }
 
 #[tauri::command]
-pub async fn print_pdf_to_thermal(printer_name: String, pdf_url: String) -> Result<(), String> {
-    // Download PDF and convert to image for thermal printing
-    printer::print_pdf_url(&printer_name, &pdf_url, ThermalImage::MAX_WIDTH_80MM)
+pub async fn print_pdf_to_thermal(
+    printer_name: String,
+    pdf_url: String,
+    pages: Option<String>,
+    scale: Option<f32>,
+) -> Result<(), String> {
+    // `pdf_url` may also be base64 or a data URL; pages are rasterized for thermal printing
+    printer::print_pdf(&printer_name, &pdf_url, pages, scale)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub fn print_text_content(printer_name: String, content: String) -> Result<(), String> {
-    printer::print_text(&printer_name, &content)
-        .map_err(|e| e.to_string())
+pub async fn print_text_content(printer_name: String, content: String) -> Result<(), String> {
+    blocking(move || printer::print_text(&printer_name, &content)).await
+}
+
+#[tauri::command]
+pub async fn print_document(printer_name: String, document: Document) -> Result<(), String> {
+    blocking(move || printer::print_document(&printer_name, &document)).await
+}
+
+#[tauri::command]
+pub async fn print_html_content(printer_name: String, html: String) -> Result<(), String> {
+    blocking(move || printer::print_html(&printer_name, &html)).await
 }
 
 #[tauri::command]
-pub fn print_html_content(printer_name: String, html: String) -> Result<(), String> {
-    // For HTML, we extract text and print it (thermal printers can't render HTML)
-    // This is a simplified approach - TableTrack likely converts HTML to image
-    let text = html_to_text(&html);
-    printer::print_text(&printer_name, &text)
+pub async fn preview_print_job(
+    job: api::PrintJob,
+    printer_name: Option<String>,
+    profile: Option<PrinterProfile>,
+) -> Result<PrintPreview, String> {
+    // An explicit profile wins so unsaved profile edits can be previewed
+    let profile = match (profile, printer_name) {
+        (Some(profile), _) => profile,
+        (None, Some(name)) => config::load_printer_profile(&name).map_err(|e| e.to_string())?,
+        (None, None) => PrinterProfile::default(),
+    };
+    printer::preview_job(&job, &profile)
+        .await
         .map_err(|e| e.to_string())
 }
 
-/// Simple HTML to text extraction
-fn html_to_text(html: &str) -> String {
-    // Remove HTML tags and decode entities
-    let mut text = html.to_string();
-
-    // Replace common HTML entities
-    text = text.replace("&nbsp;", " ");
-    text = text.replace("&amp;", "&");
-    text = text.replace("&lt;", "<");
-    text = text.replace("&gt;", ">");
-    text = text.replace("&quot;", "\"");
-    text = text.replace("<br>", "\n");
-    text = text.replace("<br/>", "\n");
-    text = text.replace("<br />", "\n");
-    text = text.replace("</p>", "\n\n");
-    text = text.replace("</div>", "\n");
-    text = text.replace("</tr>", "\n");
-    text = text.replace("</td>", "\t");
-    text = text.replace("</th>", "\t");
-
-    // Remove all remaining HTML tags
-    let mut result = String::new();
-    let mut in_tag = false;
-    for c in text.chars() {
-        if c == '<' {
-            in_tag = true;
-        } else if c == '>' {
-            in_tag = false;
-        } else if !in_tag {
-            result.push(c);
-        }
-    }
+#[tauri::command]
+pub async fn store_printer_logo(printer_name: String, key: String, image: String) -> Result<StoredLogo, String> {
+    blocking(move || printer::store_logo(&printer_name, &key, &image)).await
+}
+
+#[tauri::command]
+pub async fn delete_printer_logo(printer_name: String, key: String) -> Result<(), String> {
+    blocking(move || printer::delete_logo(&printer_name, &key)).await
+}
 
-    // Clean up extra whitespace
-    result.split('\n')
-        .map(|line| line.trim())
-        .collect::<Vec<_>>()
-        .join("\n")
+#[tauri::command]
+pub fn get_printer_logos(printer_name: String) -> Result<BTreeMap<String, StoredLogo>, String> {
+    printer::list_logos(&printer_name).map_err(|e| e.to_string())
 }
 
 // ============ Printer Control Commands ============
 
 #[tauri::command]
-pub fn cut_paper(printer_name: String) -> Result<(), String> {
-    printer::cut_paper(&printer_name).map_err(|e| e.to_string())
+pub async fn cut_paper(printer_name: String) -> Result<(), String> {
+    blocking(move || printer::cut_paper(&printer_name)).await
 }
 
 #[tauri::command]
-pub fn open_drawer(printer_name: String, pin: u8) -> Result<(), String> {
-    printer::open_drawer(&printer_name, pin).map_err(|e| e.to_string())
+pub async fn open_drawer(printer_name: String, pin: u8) -> Result<(), String> {
+    blocking(move || printer::open_drawer(&printer_name, pin)).await
 }
 
 #[tauri::command]
-pub fn clear_printer_jobs(printer_name: String) -> Result<(), String> {
-    printer::clear_print_jobs(&printer_name).map_err(|e| e.to_string())
+pub async fn clear_printer_jobs(printer_name: String) -> Result<(), String> {
+    blocking(move || printer::clear_print_jobs(&printer_name)).await
+}
+
+/// Run printer I/O on a blocking thread. Status queries and confirmed sends
+/// can take many seconds, which would freeze the UI on the main thread.
+async fn blocking<T: Send + 'static>(io: impl FnOnce() -> anyhow::Result<T> + Send + 'static) -> Result<T, String> {
+    tokio::task::spawn_blocking(io)
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
 }
 
 // ============ System Commands ============
@@ -1,8 +1,18 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::PathBuf;
+use crate::barcode::BarcodeRendering;
+use crate::codepage::CodePage;
+use crate::command_set::Emulation;
+use crate::dither::DitherOptions;
+use crate::escpos::{ColorOptions, ImageMode, ImageOptions, ThermalImage};
+use crate::label::LabelOptions;
+use crate::logo::{LogoMemory, StoredLogo};
+use crate::pdf::PdfOptions;
+use crate::retry::RetryPolicies;
+use crate::text::TextRendering;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -30,6 +40,143 @@ pub struct Config {
 
     #[serde(default)]
     pub auto_start: bool,
+
+    #[serde(default)]
+    pub retry_policies: RetryPolicies,
+
+    #[serde(default)]
+    pub printer_profiles: HashMap<String, PrinterProfile>,
+}
+
+/// Hardware and rendering settings for one local printer, keyed by its target in `printer_profiles`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PrinterProfile {
+    /// Command language: ESC/POS or Star Line Mode
+    pub emulation: Emulation,
+    /// Printable width in dots: 384 (58 mm), 512 or 576 (80 mm), 832 (112 mm)
+    pub dot_width: u32,
+    /// Print resolution in dots per inch
+    pub dpi: u32,
+    pub dithering: DitherOptions,
+    /// Command used to send images
+    pub image_mode: ImageMode,
+    /// Trimming, blank-run collapsing and placement of receipt images
+    pub image: ImageOptions,
+    /// Red/black paper
+    pub color: ColorOptions,
+    /// Maximum rows per raster/graphics block, 0 = as many as the command allows.
+    /// Lower it for printers with small receive buffers.
+    pub max_band_height: u32,
+    pub cut: CutOptions,
+    pub drawer: DrawerOptions,
+    /// Character table printer text is encoded with
+    pub code_page: CodePage,
+    /// `ESC t` number of `code_page` when the printer doesn't use the Epson numbering
+    pub code_table: Option<u8>,
+    /// Printed for characters missing from the code page when text is not rasterized
+    pub replacement: char,
+    /// Print density from -6 (lightest) to 6 (darkest); printer default when unset
+    pub density: Option<i8>,
+    /// When text is rasterized with the bundled font instead of sent as printer text
+    pub text_rendering: TextRendering,
+    /// Line height of rasterized text in dots
+    pub font_size: f32,
+    /// Whether barcodes and QR codes use the printer's commands or are sent as images
+    pub barcode_rendering: BarcodeRendering,
+    /// Page range and scale for PDF jobs that don't set their own
+    pub pdf: PdfOptions,
+    /// Ask ESC/POS printers on socket and device targets for their status
+    /// before and after every job, holding jobs while they can't print
+    pub status_check: bool,
+    /// Keep a connection open to this `tcp://` ESC/POS printer with Automatic
+    /// Status Back, following its paper and cover state live
+    pub asb: bool,
+    /// Label size, gap and darkness of ZPL and TSPL printers
+    pub label: LabelOptions,
+    /// Which NV memory logos are stored in
+    pub logo_memory: LogoMemory,
+    /// Logos uploaded to the printer, by key
+    pub logos: BTreeMap<String, StoredLogo>,
+}
+
+impl Default for PrinterProfile {
+    fn default() -> Self {
+        Self {
+            emulation: Emulation::default(),
+            dot_width: ThermalImage::MAX_WIDTH_80MM,
+            dpi: 203,
+            dithering: DitherOptions::default(),
+            image_mode: ImageMode::default(),
+            image: ImageOptions::default(),
+            color: ColorOptions::default(),
+            max_band_height: 0,
+            cut: CutOptions::default(),
+            drawer: DrawerOptions::default(),
+            code_page: CodePage::default(),
+            code_table: None,
+            replacement: '?',
+            density: None,
+            text_rendering: TextRendering::default(),
+            font_size: 24.0,
+            barcode_rendering: BarcodeRendering::default(),
+            pdf: PdfOptions::default(),
+            status_check: false,
+            asb: false,
+            label: LabelOptions::default(),
+            logo_memory: LogoMemory::default(),
+            logos: BTreeMap::new(),
+        }
+    }
+}
+
+/// How the paper is cut at the end of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CutStyle {
+    #[default]
+    Full,
+    Partial,
+    /// No cutter: only feed so the receipt can be torn off
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CutOptions {
+    pub style: CutStyle,
+    /// Lines fed before cutting so the last printed line clears the cutter
+    pub feed_lines: u8,
+}
+
+impl Default for CutOptions {
+    fn default() -> Self {
+        Self {
+            style: CutStyle::Full,
+            feed_lines: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DrawerOptions {
+    /// Drawer pin, same values as `drawer_pin`; falls back to it when unset
+    pub pin: Option<u8>,
+    /// Length of the kick pulse
+    pub pulse_on_ms: u16,
+    /// Pause after the pulse
+    pub pulse_off_ms: u16,
+}
+
+impl Default for DrawerOptions {
+    fn default() -> Self {
+        Self {
+            pin: None,
+            pulse_on_ms: 50,
+            pulse_off_ms: 500,
+        }
+    }
 }
 
 impl Config {
@@ -43,6 +190,8 @@ impl Config {
             drawer_pin: 0,
             polling_interval: 5000,
             auto_start: false,
+            retry_policies: RetryPolicies::default(),
+            printer_profiles: HashMap::new(),
         }
     }
 }
@@ -105,3 +254,14 @@ pub fn save_config(config: &Config) -> Result<()> {
 pub fn get_mapped_printer(config: &Config, api_printer_name: &str) -> Option<String> {
     config.printer_mappings.get(api_printer_name).cloned()
 }
+
+/// Get the profile for a local printer, falling back to defaults
+pub fn get_printer_profile(config: &Config, local_printer: &str) -> PrinterProfile {
+    config.printer_profiles.get(local_printer).cloned().unwrap_or_default()
+}
+
+/// Load the config and get the profile for a local printer. A config file
+/// that can't be read or parsed is an error rather than a default profile.
+pub fn load_printer_profile(local_printer: &str) -> Result<PrinterProfile> {
+    Ok(get_printer_profile(&load_config()?, local_printer))
+}
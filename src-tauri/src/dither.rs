@@ -0,0 +1,322 @@
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+/// How grayscale pixels are reduced to black and white dots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DitherAlgorithm {
+    /// Fixed threshold, best for text-only receipts
+    #[default]
+    Threshold,
+    /// Error diffusion, smooth gradients for photos
+    FloydSteinberg,
+    /// Error diffusion that drops part of the error, keeps contrast on logos
+    Atkinson,
+    /// 8x8 Bayer matrix, regular pattern that survives low print density
+    Bayer,
+}
+
+/// Dithering settings for a printer profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DitherOptions {
+    pub algorithm: DitherAlgorithm,
+    /// Pixels darker than this are printed (threshold mode only)
+    pub threshold: u8,
+    /// Gamma applied before dithering; > 1.0 darkens mid-tones
+    pub gamma: f32,
+    /// Contrast multiplier around mid-gray; 1.0 leaves the image unchanged
+    pub contrast: f32,
+}
+
+impl Default for DitherOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: DitherAlgorithm::Threshold,
+            threshold: 128,
+            gamma: 1.0,
+            contrast: 1.0,
+        }
+    }
+}
+
+/// 1-bit image where `true` is a printed (black) dot
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonoBitmap {
+    width: u32,
+    height: u32,
+    dots: Vec<bool>,
+}
+
+impl MonoBitmap {
+    /// Create an all-white bitmap
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            dots: vec![false; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Whether the dot at (x, y) is black; out of range dots are white
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.dots[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, black: bool) {
+        if x < self.width && y < self.height {
+            self.dots[(y * self.width + x) as usize] = black;
+        }
+    }
+
+    /// Whether no dot is printed
+    pub fn is_blank(&self) -> bool {
+        !self.dots.contains(&true)
+    }
+
+    /// Also print every dot of `other`, which is no larger than this bitmap
+    pub fn overlay(&mut self, other: &MonoBitmap) {
+        for y in 0..other.height.min(self.height) {
+            for x in (0..other.width.min(self.width)).filter(|&x| other.get(x, y)) {
+                self.set(x, y, true);
+            }
+        }
+    }
+
+    /// Append row `y` packed 8 dots per byte, MSB first, padded with white
+    pub fn pack_row(&self, y: u32, out: &mut Vec<u8>) {
+        for x_start in (0..self.width).step_by(8) {
+            let mut byte: u8 = 0;
+            for bit in 0..8 {
+                if self.get(x_start + bit, y) {
+                    byte |= 0x80 >> bit;
+                }
+            }
+            out.push(byte);
+        }
+    }
+}
+
+/// 8x8 Bayer index matrix
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Apply gamma and contrast to a grayscale image in place
+pub fn adjust(gray: &mut GrayImage, gamma: f32, contrast: f32) {
+    if (gamma - 1.0).abs() < f32::EPSILON && (contrast - 1.0).abs() < f32::EPSILON {
+        return;
+    }
+
+    let gamma = if gamma > 0.0 { gamma } else { 1.0 };
+    let lut: Vec<u8> = (0..=255u32)
+        .map(|v| {
+            let v = (v as f32 / 255.0).powf(gamma);
+            let v = (v - 0.5) * contrast + 0.5;
+            (v.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect();
+
+    for pixel in gray.pixels_mut() {
+        pixel[0] = lut[pixel[0] as usize];
+    }
+}
+
+/// Convert a grayscale image to a 1-bit bitmap
+pub fn dither(gray: &GrayImage, options: &DitherOptions) -> MonoBitmap {
+    let mut gray = gray.clone();
+    adjust(&mut gray, options.gamma, options.contrast);
+
+    match options.algorithm {
+        DitherAlgorithm::Threshold => threshold(&gray, options.threshold),
+        DitherAlgorithm::FloydSteinberg => diffuse(
+            &gray,
+            &[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)],
+            16,
+        ),
+        DitherAlgorithm::Atkinson => diffuse(
+            &gray,
+            &[(1, 0, 1), (2, 0, 1), (-1, 1, 1), (0, 1, 1), (1, 1, 1), (0, 2, 1)],
+            8,
+        ),
+        DitherAlgorithm::Bayer => bayer(&gray),
+    }
+}
+
+fn threshold(gray: &GrayImage, level: u8) -> MonoBitmap {
+    let (width, height) = gray.dimensions();
+    let mut bitmap = MonoBitmap::new(width, height);
+
+    for (x, y, pixel) in gray.enumerate_pixels() {
+        bitmap.set(x, y, pixel[0] < level);
+    }
+
+    bitmap
+}
+
+/// Error diffusion with a kernel of (dx, dy, weight) taps divided by `divisor`
+fn diffuse(gray: &GrayImage, kernel: &[(i32, i32, i32)], divisor: i32) -> MonoBitmap {
+    let (width, height) = gray.dimensions();
+    let mut bitmap = MonoBitmap::new(width, height);
+    let mut levels: Vec<i32> = gray.pixels().map(|p| p[0] as i32).collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) as usize;
+            let old = levels[index];
+            let black = old < 128;
+            bitmap.set(x, y, black);
+
+            let error = old - if black { 0 } else { 255 };
+            if error == 0 {
+                continue;
+            }
+
+            for &(dx, dy, weight) in kernel {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+                if nx < 0 || nx >= width as i32 || ny >= height as i32 {
+                    continue;
+                }
+                let n = (ny as u32 * width + nx as u32) as usize;
+                levels[n] += error * weight / divisor;
+            }
+        }
+    }
+
+    bitmap
+}
+
+fn bayer(gray: &GrayImage) -> MonoBitmap {
+    let (width, height) = gray.dimensions();
+    let mut bitmap = MonoBitmap::new(width, height);
+
+    for (x, y, pixel) in gray.enumerate_pixels() {
+        // Scale the 0..63 index to a threshold in the middle of its 4-level band
+        let threshold = BAYER_8X8[(y % 8) as usize][(x % 8) as usize] as u32 * 4 + 2;
+        bitmap.set(x, y, (pixel[0] as u32) < threshold);
+    }
+
+    bitmap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// Horizontal ramp from black to white, `width` x `height`
+    fn ramp(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, _| {
+            Luma([(x * 255 / (width - 1)) as u8])
+        })
+    }
+
+    /// Render a bitmap as rows of '#' (black) and '.' (white)
+    fn pattern(bitmap: &MonoBitmap) -> Vec<String> {
+        (0..bitmap.height())
+            .map(|y| {
+                (0..bitmap.width())
+                    .map(|x| if bitmap.get(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn options(algorithm: DitherAlgorithm) -> DitherOptions {
+        DitherOptions {
+            algorithm,
+            ..DitherOptions::default()
+        }
+    }
+
+    #[test]
+    fn threshold_splits_ramp_at_level() {
+        let bitmap = dither(&ramp(8, 2), &options(DitherAlgorithm::Threshold));
+        assert_eq!(pattern(&bitmap), vec!["####....", "####...."]);
+
+        let dark = DitherOptions {
+            threshold: 200,
+            ..DitherOptions::default()
+        };
+        assert_eq!(pattern(&dither(&ramp(8, 1), &dark)), vec!["######.."]);
+    }
+
+    #[test]
+    fn floyd_steinberg_golden() {
+        let gray = GrayImage::from_pixel(8, 4, Luma([128]));
+        let bitmap = dither(&gray, &options(DitherAlgorithm::FloydSteinberg));
+        assert_eq!(
+            pattern(&bitmap),
+            vec![".#.#.#.#", "#.#.#.#.", ".#.#.#.#", "#.#.#.#."]
+        );
+    }
+
+    #[test]
+    fn atkinson_golden() {
+        let gray = GrayImage::from_pixel(8, 4, Luma([64]));
+        let bitmap = dither(&gray, &options(DitherAlgorithm::Atkinson));
+        assert_eq!(
+            pattern(&bitmap),
+            vec!["########", "########", "##.##.##", "#######."]
+        );
+    }
+
+    #[test]
+    fn bayer_golden() {
+        let gray = GrayImage::from_pixel(8, 8, Luma([128]));
+        let bitmap = dither(&gray, &options(DitherAlgorithm::Bayer));
+        assert_eq!(
+            pattern(&bitmap),
+            vec![
+                ".#.#.#.#", "#.#.#.#.", ".#.#.#.#", "#.#.#.#.",
+                ".#.#.#.#", "#.#.#.#.", ".#.#.#.#", "#.#.#.#.",
+            ]
+        );
+    }
+
+    #[test]
+    fn solid_colors_stay_solid() {
+        let black = GrayImage::from_pixel(4, 4, Luma([0]));
+        let white = GrayImage::from_pixel(4, 4, Luma([255]));
+
+        for algorithm in [
+            DitherAlgorithm::Threshold,
+            DitherAlgorithm::FloydSteinberg,
+            DitherAlgorithm::Atkinson,
+            DitherAlgorithm::Bayer,
+        ] {
+            let opts = options(algorithm);
+            assert!(pattern(&dither(&black, &opts)).iter().all(|r| r == "####"), "{:?}", algorithm);
+            assert!(pattern(&dither(&white, &opts)).iter().all(|r| r == "...."), "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn gamma_and_contrast_adjust_levels() {
+        let mut gray = GrayImage::from_pixel(1, 1, Luma([128]));
+        adjust(&mut gray, 2.0, 1.0);
+        assert_eq!(gray.get_pixel(0, 0)[0], 64);
+
+        let mut gray = GrayImage::from_pixel(2, 1, Luma([96]));
+        gray.put_pixel(1, 0, Luma([160]));
+        adjust(&mut gray, 1.0, 2.0);
+        assert_eq!(gray.get_pixel(0, 0)[0], 65);
+        assert_eq!(gray.get_pixel(1, 0)[0], 193);
+    }
+}
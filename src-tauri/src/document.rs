@@ -0,0 +1,408 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::barcode::{Barcode, BarcodeOptions, QrOptions};
+use crate::config::{CutOptions, CutStyle, DrawerOptions, PrinterProfile};
+use crate::dither::MonoBitmap;
+use crate::label;
+use crate::escpos::{EscPosBuilder, InkColor, Justify, TextStyle, ThermalImage};
+use crate::text::{self, TextOptions, TextRasterizer};
+use crate::zatca::ZatcaInvoice;
+use image::DynamicImage;
+
+/// A receipt described as a list of elements, sent in a job's `document` field
+/// and rendered with [`EscPosBuilder`].
+///
+/// ```json
+/// { "elements": [
+///     { "type": "text", "text": "ORDER 42", "justify": "center", "width": 2, "height": 2 },
+///     { "type": "text", "text": "1x Shawarma   12.00" },
+///     { "type": "qr", "data": "https://example.com/r/42" },
+///     { "type": "cut" }
+/// ] }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    pub elements: Vec<Element>,
+}
+
+/// One block of a [`Document`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Element {
+    /// One or more lines of styled text; the style is reset afterwards
+    Text {
+        text: String,
+        #[serde(flatten)]
+        style: TextStyle,
+    },
+    /// Feed blank lines
+    Feed {
+        #[serde(default = "default_lines")]
+        lines: u8,
+    },
+    /// Line spacing in dots, printer default when omitted
+    LineSpacing {
+        #[serde(default)]
+        dots: Option<u8>,
+    },
+    /// Cut with the profile's settings, optionally overriding the style
+    Cut {
+        #[serde(default)]
+        style: Option<CutStyle>,
+    },
+    /// Kick the cash drawer, on the profile's pin unless given
+    Drawer {
+        #[serde(default)]
+        pin: Option<u8>,
+    },
+    /// Sound the buzzer
+    Beep {
+        #[serde(default = "default_times")]
+        times: u8,
+        #[serde(default = "default_beep_duration")]
+        duration: u8,
+    },
+    /// 1D barcode, CODE128 unless `symbology` says otherwise
+    Barcode {
+        data: String,
+        #[serde(flatten)]
+        options: BarcodeOptions,
+    },
+    /// QR code
+    Qr {
+        data: String,
+        #[serde(flatten)]
+        options: QrOptions,
+    },
+    /// ZATCA phase-1 QR code of a Saudi simplified tax invoice
+    Zatca {
+        #[serde(flatten)]
+        invoice: ZatcaInvoice,
+        #[serde(flatten)]
+        options: QrOptions,
+    },
+    /// Base64 or data URL image, fitted and dithered with the profile settings
+    Image { image: String },
+    /// Logo stored in the printer's NV memory under `key`
+    Logo {
+        key: String,
+        #[serde(default = "default_logo_justify")]
+        justify: Justify,
+    },
+}
+
+fn default_lines() -> u8 {
+    1
+}
+
+fn default_logo_justify() -> Justify {
+    Justify::Center
+}
+
+fn default_times() -> u8 {
+    1
+}
+
+fn default_beep_duration() -> u8 {
+    2
+}
+
+impl Document {
+    /// Parse a document from a job's JSON field
+    pub fn from_json(value: &serde_json::Value) -> Result<Self> {
+        serde_json::from_value(value.clone()).context("Invalid document")
+    }
+
+    /// Render to ESC/POS data, cutting at the end unless the last element is a cut.
+    /// Label printers get ZPL or TSPL labels instead, see [`label::render`].
+    pub fn render(&self, profile: &PrinterProfile) -> Result<Vec<u8>> {
+        if profile.emulation.is_label() {
+            return label::render(&self.elements, profile);
+        }
+
+        let mut builder = EscPosBuilder::with_profile(profile);
+
+        for element in &self.elements {
+            match element {
+                Element::Text { text, style } if text::needs_raster(text, profile) => {
+                    let bitmap = rasterize_text(text, style, profile);
+                    if style.color == InkColor::Red && profile.color.two_color {
+                        let black = MonoBitmap::new(bitmap.width(), bitmap.height());
+                        builder.raw(&profile.emulation.commands().two_color_image(&black, &bitmap, profile));
+                    } else {
+                        builder.image(&bitmap, profile);
+                    }
+                }
+                Element::Text { text, style } => {
+                    // Red text prints black on single-colour paper
+                    let color = if profile.color.two_color { style.color } else { InkColor::Black };
+                    builder.style(&TextStyle { color, ..style.clone() });
+                    for line in text.lines() {
+                        builder.line(line);
+                    }
+                    builder.reset_style();
+                }
+                Element::Feed { lines } => {
+                    builder.feed(*lines);
+                }
+                Element::LineSpacing { dots } => {
+                    builder.line_spacing(*dots);
+                }
+                Element::Cut { style } => {
+                    let cut = CutOptions {
+                        style: style.unwrap_or(profile.cut.style),
+                        ..profile.cut.clone()
+                    };
+                    builder.cut(&cut);
+                }
+                Element::Drawer { pin } => {
+                    let drawer = DrawerOptions {
+                        pin: pin.or(profile.drawer.pin),
+                        ..profile.drawer.clone()
+                    };
+                    builder.drawer(0, &drawer)?;
+                }
+                Element::Beep { times, duration } => {
+                    builder.beep(*times, *duration)?;
+                }
+                Element::Barcode { data, options } => {
+                    let barcode = Barcode::new(options.symbology, data)?;
+                    builder.barcode_for(&barcode, options, profile)?;
+                }
+                Element::Qr { data, options } => {
+                    builder.qr_for(data, options, profile)?;
+                }
+                Element::Zatca { invoice, options } => {
+                    builder.qr_for(&invoice.qr_payload()?, options, profile)?;
+                }
+                Element::Image { image } => {
+                    let img = ThermalImage::decode_base64(image)?;
+                    if profile.color.two_color {
+                        let (black, red) = ThermalImage::fit_two_color(&img, profile);
+                        builder.raw(&profile.emulation.commands().two_color_image(&black, &red, profile));
+                    } else {
+                        builder.image(&ThermalImage::fit(&img, profile), profile);
+                    }
+                }
+                Element::Logo { key, justify } => {
+                    builder.justify(*justify);
+                    builder.logo(key, profile)?;
+                    builder.justify(Justify::Left);
+                }
+            }
+        }
+
+        if !matches!(self.elements.last(), Some(Element::Cut { .. })) {
+            builder.cut(&profile.cut);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+/// Draw styled text with the bundled font, for text the printer can't print itself
+pub(crate) fn rasterize_text(text: &str, style: &TextStyle, profile: &PrinterProfile) -> MonoBitmap {
+    let options = TextOptions {
+        size: profile.font_size * style.height.max(1) as f32,
+        stretch: style.width.max(1) as f32 / style.height.max(1) as f32,
+        bold: style.bold,
+        monospace: false,
+        // Left is the default, let Arabic paragraphs fall back to the right
+        justify: Some(style.justify).filter(|j| *j != Justify::Left),
+    };
+    let image = TextRasterizer::new(options).render(text, profile.dot_width);
+    ThermalImage::to_mono(&DynamicImage::ImageLuma8(image), profile.dot_width, &profile.dithering)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_json_document() {
+        let json = serde_json::json!({
+            "elements": [
+                { "type": "text", "text": "A", "bold": true, "justify": "center" },
+                { "type": "feed", "lines": 2 },
+                { "type": "cut", "style": "partial" }
+            ]
+        });
+
+        let profile = PrinterProfile::default();
+        let data = Document::from_json(&json).unwrap().render(&profile).unwrap();
+
+        let mut expected = EscPosBuilder::with_profile(&profile);
+        expected
+            .style(&TextStyle {
+                bold: true,
+                justify: crate::escpos::Justify::Center,
+                ..TextStyle::default()
+            })
+            .line("A")
+            .reset_style()
+            .feed(2)
+            .cut(&CutOptions {
+                style: CutStyle::Partial,
+                ..CutOptions::default()
+            });
+        assert_eq!(data, expected.build());
+    }
+
+    #[test]
+    fn star_documents_feed_without_cutting() {
+        let json = serde_json::json!({ "elements": [
+            { "type": "text", "text": "A", "bold": true, "justify": "center" },
+            { "type": "feed", "lines": 2 },
+            { "type": "qr", "data": "42" },
+            { "type": "cut" }
+        ] });
+        let profile = PrinterProfile {
+            emulation: crate::command_set::Emulation::StarLine,
+            ..PrinterProfile::default()
+        };
+        let data = Document::from_json(&json).unwrap().render(&profile).unwrap();
+
+        let text = [
+            &[0x1B, 0x40][..],
+            &[0x1B, 0x1E, 0x46, 0, 0x1B, 0x1D, 0x61, 1, 0x1B, 0x69, 0, 0, 0x1B, 0x45, 0x1B, 0x2D, 0, 0x1B, 0x35],
+            b"A\n",
+            &[0x1B, 0x1E, 0x46, 0, 0x1B, 0x1D, 0x61, 0, 0x1B, 0x69, 0, 0, 0x1B, 0x46, 0x1B, 0x2D, 0, 0x1B, 0x35],
+            &[0x1B, 0x61, 2],
+        ]
+        .concat();
+        assert!(data.starts_with(&text));
+        // The QR code is a raster image, the only ESC d is the final cut
+        assert!(data.windows(4).any(|w| w == [0x1B, 0x2A, 0x72, 0x41]));
+        assert!(!data.windows(3).any(|w| w == [0x1D, 0x28, 0x6B]));
+        assert_eq!(data.windows(2).filter(|w| *w == [0x1B, 0x64]).count(), 1);
+        assert!(data.ends_with(&[0x0A, 0x0A, 0x0A, 0x1B, 0x64, 0]));
+
+        let beep = serde_json::json!({ "elements": [{ "type": "beep" }] });
+        assert!(Document::from_json(&beep).unwrap().render(&profile).is_err());
+    }
+
+    #[test]
+    fn appends_profile_cut_when_missing() {
+        let json = serde_json::json!({ "elements": [{ "type": "text", "text": "A" }] });
+        let data = Document::from_json(&json).unwrap().render(&PrinterProfile::default()).unwrap();
+        assert!(data.ends_with(&[0x0A, 0x0A, 0x0A, 0x1D, 0x56, 0x00]));
+    }
+
+    #[test]
+    fn barcodes_follow_profile_rendering() {
+        let json = serde_json::json!({ "elements": [
+            { "type": "barcode", "data": "400638133393", "symbology": "ean13", "hri": false },
+            { "type": "qr", "data": "42", "errorCorrection": "h" }
+        ] });
+        let document = Document::from_json(&json).unwrap();
+
+        let native = document.render(&PrinterProfile::default()).unwrap();
+        let gs_k = [&[0x1D, 0x6B, 67, 13][..], b"4006381333931"].concat();
+        assert!(native.windows(gs_k.len()).any(|w| w == gs_k));
+        assert!(native.windows(3).any(|w| w == [0x31, 0x45, 0x33]));
+
+        let profile = PrinterProfile {
+            barcode_rendering: crate::barcode::BarcodeRendering::Raster,
+            ..PrinterProfile::default()
+        };
+        let raster = document.render(&profile).unwrap();
+        assert!(!raster.windows(2).any(|w| w == [0x1D, 0x6B]));
+        assert!(!raster.windows(3).any(|w| w == [0x1D, 0x28, 0x6B]));
+        assert!(raster.len() > native.len());
+
+        let invalid = serde_json::json!({ "elements": [{ "type": "barcode", "data": "12", "symbology": "ean13" }] });
+        assert!(Document::from_json(&invalid).unwrap().render(&profile).is_err());
+    }
+
+    #[test]
+    fn zatca_element_prints_tlv_qr() {
+        let json = serde_json::json!({ "elements": [{
+            "type": "zatca",
+            "sellerName": "Bobs Records",
+            "vatNumber": "310122393500003",
+            "timestamp": "2022-04-25T15:30:00Z",
+            "total": "1000.00",
+            "vatTotal": "150.00",
+            "size": 4
+        }] });
+        let data = Document::from_json(&json).unwrap().render(&PrinterProfile::default()).unwrap();
+
+        let payload = b"AQxCb2JzIFJlY29yZHMCDzMxMDEyMjM5MzUwMDAwMwMUMjAyMi0wNC0yNVQxNTozMDowMFoEBzEwMDAuMDAFBjE1MC4wMA==";
+        assert!(data.windows(payload.len()).any(|w| w == payload));
+        assert!(data.windows(3).any(|w| w == [0x31, 0x43, 4]));
+    }
+
+    #[test]
+    fn logos_print_from_printer_memory() {
+        let json = serde_json::json!({ "elements": [{ "type": "logo", "key": "LG" }] });
+        let document = Document::from_json(&json).unwrap();
+        assert!(document.render(&PrinterProfile::default()).is_err());
+
+        let stored = crate::logo::StoredLogo {
+            width: 200,
+            height: 80,
+            number: Some(2),
+            stored_at: "2024-01-01T00:00:00Z".to_string(),
+        };
+        let mut profile = PrinterProfile::default();
+        profile.logos.insert("LG".to_string(), stored);
+
+        let data = document.render(&profile).unwrap();
+        let fn69 = [0x1D, 0x28, 0x4C, 6, 0, 48, 69, b'L', b'G', 1, 1];
+        assert!(data.windows(fn69.len()).any(|w| w == fn69));
+        assert!(data.windows(3).any(|w| w == [0x1B, 0x61, 1]));
+
+        profile.logo_memory = crate::logo::LogoMemory::Legacy;
+        let data = document.render(&profile).unwrap();
+        assert!(data.windows(4).any(|w| w == [0x1C, 0x70, 2, 0]));
+    }
+
+    #[test]
+    fn images_follow_profile_image_options() {
+        // Black square on the left, red square on the right of a wide white margin
+        let image = image::RgbImage::from_fn(64, 32, |x, y| match (x / 16, y / 16) {
+            (1, 0) => image::Rgb([0, 0, 0]),
+            (2, 0) => image::Rgb([220, 20, 30]),
+            _ => image::Rgb([255, 255, 255]),
+        });
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        use base64::Engine;
+        let data_url = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(png.into_inner())
+        );
+        let document = Document::from_json(&serde_json::json!({
+            "elements": [{ "type": "image", "image": data_url }]
+        }))
+        .unwrap();
+
+        let image = DynamicImage::ImageRgb8(image);
+        let mut profile = PrinterProfile {
+            dot_width: 96,
+            ..PrinterProfile::default()
+        };
+        profile.image.trim = true;
+        profile.image.align = Justify::Center;
+
+        let mut expected = EscPosBuilder::with_profile(&profile);
+        expected.image(&ThermalImage::fit(&image, &profile), &profile).cut(&profile.cut);
+        assert_eq!(document.render(&profile).unwrap(), expected.build());
+
+        profile.color.two_color = true;
+        let (black, red) = ThermalImage::fit_two_color(&image, &profile);
+        assert!(red.get(48, 0) && !black.get(48, 0));
+        let mut expected = EscPosBuilder::with_profile(&profile);
+        expected
+            .raw(&profile.emulation.commands().two_color_image(&black, &red, &profile))
+            .cut(&profile.cut);
+        assert_eq!(document.render(&profile).unwrap(), expected.build());
+    }
+
+    #[test]
+    fn rejects_unknown_elements() {
+        let json = serde_json::json!({ "elements": [{ "type": "hologram" }] });
+        assert!(Document::from_json(&json).is_err());
+    }
+}
@@ -0,0 +1,1124 @@
+use anyhow::{Context, Result};
+use image::{GrayImage, Luma};
+use serde::Serialize;
+use std::collections::HashMap;
+use crate::barcode::{self, Barcode, BarcodeOptions, ErrorCorrection, QrOptions, Symbology};
+use crate::codepage::CodePage;
+use crate::config::PrinterProfile;
+use crate::dither::MonoBitmap;
+use crate::escpos::{Font, Justify};
+use crate::text::{TextOptions, TextRasterizer};
+
+/// Line spacing after `ESC @` and `ESC 2`, in dots
+const DEFAULT_LINE_SPACING: u32 = 30;
+
+/// Grey level of the dashed line drawn where the paper is cut
+const CUT_MARK: u8 = 160;
+
+/// Grey level of dots printed in red on two-colour paper
+const RED_INK: u8 = 96;
+
+/// Something a printer does besides putting dots on paper
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Effect {
+    /// `ESC @`
+    Init,
+    /// Paper cut at row `y` of the image
+    Cut { partial: bool, y: u32 },
+    /// Drawer kick on connector pin 2 or 5
+    #[serde(rename_all = "camelCase")]
+    Drawer { pin: u8, on_ms: u16, off_ms: u16 },
+    /// Buzzer, `duration` in 50 ms units
+    Beep { times: u8, duration: u8 },
+    /// `ESC t` character table
+    CodePage { table: u8 },
+    /// `GS ( K` print density
+    Density { level: i8 },
+    /// Logo printed from NV memory: a `GS ( L` key or an `FS p` image number.
+    /// It is only drawn when stored earlier in the same stream.
+    Logo { key: String },
+    /// A command that was skipped, at byte `offset` of the stream
+    Unsupported { offset: usize, command: String },
+}
+
+/// What a print job would look like on paper
+#[derive(Debug, Clone)]
+pub struct Printout {
+    /// The receipt, black dots on white; red dots are dark grey and cuts are
+    /// marked with a light grey dashed line
+    pub image: GrayImage,
+    pub effects: Vec<Effect>,
+}
+
+impl Printout {
+    /// The image as PNG
+    pub fn png(&self) -> Result<Vec<u8>> {
+        let mut png = Vec::new();
+        self.image
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .context("Failed to encode preview PNG")?;
+        Ok(png)
+    }
+}
+
+/// Interpret an ESC/POS byte stream like a printer `width` dots wide would.
+///
+/// Handles printer text (code page, font, size, bold, underline, inverse,
+/// justification), feeds, `ESC *`, `GS v 0` and `GS ( L` images, `GS k`
+/// barcodes, `GS ( k` QR codes, cuts, drawer pulses and beeps. Anything else
+/// is skipped and listed as [`Effect::Unsupported`].
+pub fn render(data: &[u8], width: u32) -> Printout {
+    let mut printer = VirtualPrinter::new(width.max(8));
+    printer.run(data);
+    printer.finish()
+}
+
+/// Character formatting in effect for a printed character
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CharStyle {
+    font: Font,
+    bold: bool,
+    underline: u8,
+    inverse: bool,
+    width: u8,
+    height: u8,
+    /// `ESC r 1` on two-colour paper
+    red: bool,
+}
+
+impl Default for CharStyle {
+    fn default() -> Self {
+        Self {
+            font: Font::A,
+            bold: false,
+            underline: 0,
+            inverse: false,
+            width: 1,
+            height: 1,
+            red: false,
+        }
+    }
+}
+
+impl CharStyle {
+    /// Character cell in dots
+    fn cell(&self) -> (u32, u32) {
+        let (width, height) = match self.font {
+            Font::A => (12, 24),
+            Font::B => (9, 17),
+        };
+        (width * self.width as u32, height * self.height as u32)
+    }
+}
+
+/// Printer settings changed by commands and reset by `ESC @`
+#[derive(Debug, Clone)]
+struct State {
+    style: CharStyle,
+    justify: Justify,
+    line_spacing: u32,
+    char_spacing: u32,
+    code_page: CodePage,
+    barcode: BarcodeOptions,
+    qr: QrOptions,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            style: CharStyle::default(),
+            justify: Justify::Left,
+            line_spacing: DEFAULT_LINE_SPACING,
+            char_spacing: 0,
+            code_page: CodePage::default(),
+            barcode: BarcodeOptions {
+                height: 162,
+                module_width: 3,
+                hri: false,
+                ..BarcodeOptions::default()
+            },
+            qr: QrOptions {
+                module_size: 3,
+                ..QrOptions::default()
+            },
+        }
+    }
+}
+
+/// Content of the line being assembled, printed by the next feed
+enum Piece {
+    Char { c: char, x: u32, style: CharStyle },
+    Bits { bitmap: MonoBitmap, x: u32, scale_x: u32, scale_y: u32 },
+}
+
+impl Piece {
+    fn height(&self) -> u32 {
+        match self {
+            Piece::Char { style, .. } => style.cell().1,
+            Piece::Bits { bitmap, scale_y, .. } => bitmap.height() * scale_y,
+        }
+    }
+}
+
+struct VirtualPrinter {
+    width: u32,
+    paper: GrayImage,
+    /// Top of the next line
+    y: u32,
+    /// Lowest row drawn so far
+    bottom: u32,
+    state: State,
+    line: Vec<Piece>,
+    /// Horizontal position in the line being assembled
+    x: u32,
+    qr_data: Vec<u8>,
+    /// `GS ( L` graphics buffer: one plane per colour with its horizontal and
+    /// vertical scale, and whether it prints red
+    graphics: Vec<(MonoBitmap, u32, u32, bool)>,
+    /// NV graphics stored by key with `GS ( L` function 67
+    nv_graphics: HashMap<[u8; 2], MonoBitmap>,
+    /// NV bit images stored with `FS q`, numbered from 1
+    nv_images: Vec<MonoBitmap>,
+    fonts: HashMap<(u32, u32, bool, bool), (TextRasterizer, f32)>,
+    effects: Vec<Effect>,
+}
+
+impl VirtualPrinter {
+    fn new(width: u32) -> Self {
+        Self {
+            width,
+            paper: GrayImage::from_pixel(width, 256, Luma([255])),
+            y: 0,
+            bottom: 0,
+            state: State::default(),
+            line: Vec::new(),
+            x: 0,
+            qr_data: Vec::new(),
+            graphics: Vec::new(),
+            nv_graphics: HashMap::new(),
+            nv_images: Vec::new(),
+            fonts: HashMap::new(),
+            effects: Vec::new(),
+        }
+    }
+
+    fn finish(mut self) -> Printout {
+        self.print_line();
+        let height = self.y.max(self.bottom).max(1);
+        self.grow(height);
+        let image = image::imageops::crop_imm(&self.paper, 0, 0, self.width, height).to_image();
+        Printout {
+            image,
+            effects: self.effects,
+        }
+    }
+
+    fn run(&mut self, data: &[u8]) {
+        let mut i = 0;
+        while i < data.len() {
+            let rest = &data[i..];
+            let consumed = match rest[0] {
+                0x0A => {
+                    let height = self.print_line();
+                    self.y += height.max(self.state.line_spacing);
+                    1
+                }
+                0x09 => {
+                    let tab = self.state.style.cell().0 * 8;
+                    self.x = (self.x / tab + 1) * tab;
+                    1
+                }
+                0x10 => self.dle(rest),
+                0x1B => self.esc(rest, i),
+                0x1C => self.fs(rest, i),
+                0x1D => self.gs(rest, i),
+                byte if byte >= 0x20 => {
+                    self.character(byte);
+                    1
+                }
+                _ => 1,
+            };
+            // Truncated commands consume the rest of the stream
+            i += consumed.clamp(1, rest.len());
+        }
+    }
+
+    fn unsupported(&mut self, offset: usize, command: &[u8]) {
+        let name = command
+            .iter()
+            .map(|b| match b {
+                0x10 => "DLE".to_string(),
+                0x1B => "ESC".to_string(),
+                0x1C => "FS".to_string(),
+                0x1D => "GS".to_string(),
+                0x21..=0x7E => (*b as char).to_string(),
+                _ => format!("0x{:02X}", b),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.effects.push(Effect::Unsupported { offset, command: name });
+    }
+
+    fn dle(&mut self, cmd: &[u8]) -> usize {
+        match cmd.get(1) {
+            // Real-time status and recovery requests don't print
+            Some(0x04) | Some(0x05) => 3,
+            // DLE DC4 fn m t: real-time drawer pulse, t x 100 ms
+            Some(0x14) => {
+                if cmd.get(2) == Some(&1) {
+                    let pin = if cmd.get(3).copied().unwrap_or(0) % 48 == 1 { 5 } else { 2 };
+                    let ms = cmd.get(4).copied().unwrap_or(1) as u16 * 100;
+                    self.effects.push(Effect::Drawer { pin, on_ms: ms, off_ms: ms });
+                }
+                5
+            }
+            _ => 1,
+        }
+    }
+
+    fn esc(&mut self, cmd: &[u8], offset: usize) -> usize {
+        let Some(&op) = cmd.get(1) else {
+            return 1;
+        };
+        let n = cmd.get(2).copied().unwrap_or(0);
+        let style = &mut self.state.style;
+
+        match op {
+            b'@' => {
+                // The print buffer is discarded, not printed
+                self.line.clear();
+                self.x = 0;
+                self.state = State::default();
+                self.effects.push(Effect::Init);
+                2
+            }
+            b'!' => {
+                style.font = if n & 0x01 != 0 { Font::B } else { Font::A };
+                style.bold = n & 0x08 != 0;
+                style.height = if n & 0x10 != 0 { 2 } else { 1 };
+                style.width = if n & 0x20 != 0 { 2 } else { 1 };
+                style.underline = if n & 0x80 != 0 { 1 } else { 0 };
+                3
+            }
+            b' ' => {
+                self.state.char_spacing = n as u32;
+                3
+            }
+            b'-' => {
+                style.underline = n & 0x03;
+                3
+            }
+            b'E' | b'G' => {
+                style.bold = n & 0x01 != 0;
+                3
+            }
+            b'M' => {
+                style.font = if n % 48 == 1 { Font::B } else { Font::A };
+                3
+            }
+            b'2' => {
+                self.state.line_spacing = DEFAULT_LINE_SPACING;
+                2
+            }
+            b'3' => {
+                self.state.line_spacing = n as u32;
+                3
+            }
+            b'a' => {
+                self.state.justify = match n % 48 {
+                    1 => Justify::Center,
+                    2 => Justify::Right,
+                    _ => Justify::Left,
+                };
+                3
+            }
+            b't' => {
+                if let Some(code_page) = CodePage::from_table(n) {
+                    self.state.code_page = code_page;
+                }
+                self.effects.push(Effect::CodePage { table: n });
+                3
+            }
+            b'J' => {
+                self.print_line();
+                self.y += n as u32;
+                3
+            }
+            b'd' => {
+                // Like LF for the buffered line, then the remaining lines
+                let height = self.print_line();
+                let spacing = self.state.line_spacing;
+                self.y += if height > 0 {
+                    height.max(spacing) + (n as u32).saturating_sub(1) * spacing
+                } else {
+                    n as u32 * spacing
+                };
+                3
+            }
+            b'*' => self.bit_image(cmd),
+            b'$' => {
+                self.x = u16::from_le_bytes([n, cmd.get(3).copied().unwrap_or(0)]) as u32;
+                4
+            }
+            b'\\' => {
+                let dx = i16::from_le_bytes([n, cmd.get(3).copied().unwrap_or(0)]);
+                self.x = (self.x as i32 + dx as i32).max(0) as u32;
+                4
+            }
+            b'p' => {
+                let pin = if n % 48 == 1 { 5 } else { 2 };
+                let on_ms = cmd.get(3).copied().unwrap_or(0) as u16 * 2;
+                let off_ms = cmd.get(4).copied().unwrap_or(0) as u16 * 2;
+                self.effects.push(Effect::Drawer { pin, on_ms, off_ms });
+                5
+            }
+            b'B' => {
+                let duration = cmd.get(3).copied().unwrap_or(0);
+                self.effects.push(Effect::Beep { times: n, duration });
+                4
+            }
+            b'i' | b'm' => {
+                self.cut(op == b'm');
+                2
+            }
+            b'D' => {
+                // Tab positions, NUL terminated
+                cmd[2..].iter().position(|&b| b == 0).map_or(cmd.len(), |end| end + 3)
+            }
+            b'c' => 4,
+            b'L' | b'S' | b'<' => 2,
+            b'r' => {
+                style.red = n & 0x01 != 0;
+                3
+            }
+            b'%' | b'=' | b'?' | b'R' | b'T' | b'U' | b'V' | b'{' | b'K' | b'e' => {
+                self.unsupported(offset, &cmd[..3.min(cmd.len())]);
+                3
+            }
+            _ => {
+                self.unsupported(offset, &cmd[..2]);
+                2
+            }
+        }
+    }
+
+    fn gs(&mut self, cmd: &[u8], offset: usize) -> usize {
+        let Some(&op) = cmd.get(1) else {
+            return 1;
+        };
+        let n = cmd.get(2).copied().unwrap_or(0);
+
+        match op {
+            b'!' => {
+                self.state.style.width = (n >> 4 & 0x07) + 1;
+                self.state.style.height = (n & 0x07) + 1;
+                3
+            }
+            b'B' => {
+                self.state.style.inverse = n & 0x01 != 0;
+                3
+            }
+            b'V' => {
+                let partial = matches!(n, 1 | 49 | 66);
+                if n == 65 || n == 66 {
+                    self.print_line();
+                    self.y += cmd.get(3).copied().unwrap_or(0) as u32;
+                    self.cut(partial);
+                    4
+                } else {
+                    self.cut(partial);
+                    3
+                }
+            }
+            b'H' => {
+                self.state.barcode.hri = n % 48 != 0;
+                3
+            }
+            b'h' => {
+                self.state.barcode.height = n;
+                3
+            }
+            b'w' => {
+                self.state.barcode.module_width = n;
+                3
+            }
+            b'k' => self.barcode(cmd, offset),
+            b'v' if n == b'0' => self.raster(cmd),
+            b'(' => {
+                let length = 5 + u16::from_le_bytes([
+                    cmd.get(3).copied().unwrap_or(0),
+                    cmd.get(4).copied().unwrap_or(0),
+                ]) as usize;
+                let body = &cmd[5.min(cmd.len())..length.min(cmd.len())];
+                match n {
+                    b'L' => self.graphics(body, &cmd[..5.min(cmd.len())], offset),
+                    b'k' => self.qr(body, offset),
+                    b'K' if body.first() == Some(&0x31) => {
+                        let level = body.get(1).copied().unwrap_or(0) as i8;
+                        self.effects.push(Effect::Density { level });
+                    }
+                    _ => self.unsupported(offset, &cmd[..3.min(cmd.len())]),
+                }
+                length
+            }
+            b'8' if n == b'L' => {
+                // GS 8 L p1 p2 p3 p4: GS ( L with a 32-bit length
+                let Some(&[p1, p2, p3, p4]) = cmd.get(3..7) else {
+                    return cmd.len();
+                };
+                let length = 7 + u32::from_le_bytes([p1, p2, p3, p4]) as usize;
+                let body = &cmd[7..length.min(cmd.len())];
+                self.graphics(body, &cmd[..3], offset);
+                length
+            }
+            b'*' => {
+                let (x, y) = (n as usize, cmd.get(3).copied().unwrap_or(0) as usize);
+                self.unsupported(offset, &cmd[..2]);
+                4 + x * y * 8
+            }
+            b'a' | b'r' | b'I' | b'b' | b'f' | b'/' | b'E' => 3,
+            b'L' | b'W' | b'P' | b'$' | b'\\' => {
+                self.unsupported(offset, &cmd[..2]);
+                4
+            }
+            _ => {
+                self.unsupported(offset, &cmd[..2]);
+                2
+            }
+        }
+    }
+
+    fn fs(&mut self, cmd: &[u8], offset: usize) -> usize {
+        let Some(&op) = cmd.get(1) else {
+            return 1;
+        };
+
+        match op {
+            b'p' => {
+                // FS p n m: bit 0 of m doubles the width, bit 1 the height
+                let (n, m) = (cmd.get(2).copied().unwrap_or(0), cmd.get(3).copied().unwrap_or(0) % 48);
+                self.effects.push(Effect::Logo { key: n.to_string() });
+                if let Some(bitmap) = self.nv_images.get((n as usize).wrapping_sub(1)).cloned() {
+                    self.print_line();
+                    self.place(&bitmap, 1 + (m & 1) as u32, 1 + (m >> 1 & 1) as u32);
+                }
+                4
+            }
+            b'q' => {
+                // FS q n [xL xH yL yH d1...dk]1...[...]n, k = x * y * 8, column by column
+                let mut end = 3;
+                let mut images = Vec::new();
+                for _ in 0..cmd.get(2).copied().unwrap_or(0) {
+                    let Some(header) = cmd.get(end..end + 4) else {
+                        return cmd.len();
+                    };
+                    let x = u16::from_le_bytes([header[0], header[1]]) as u32;
+                    let y = u16::from_le_bytes([header[2], header[3]]) as u32;
+                    let Some(data) = cmd.get(end + 4..end + 4 + (x * y * 8) as usize) else {
+                        return cmd.len();
+                    };
+
+                    let mut bitmap = MonoBitmap::new(x * 8, y * 8);
+                    for (i, &byte) in data.iter().enumerate() {
+                        let (column, row) = (i as u32 / y, i as u32 % y);
+                        for bit in (0..8).filter(|bit| byte & (0x80 >> bit) != 0) {
+                            bitmap.set(column, row * 8 + bit, true);
+                        }
+                    }
+                    images.push(bitmap);
+                    end += 4 + data.len();
+                }
+                self.nv_images = images;
+                end
+            }
+            b'(' => {
+                self.unsupported(offset, &cmd[..3.min(cmd.len())]);
+                5 + u16::from_le_bytes([cmd.get(3).copied().unwrap_or(0), cmd.get(4).copied().unwrap_or(0)]) as usize
+            }
+            b'C' | b'!' | b'-' | b'W' => {
+                self.unsupported(offset, &cmd[..2]);
+                3
+            }
+            _ => {
+                self.unsupported(offset, &cmd[..2]);
+                2
+            }
+        }
+    }
+
+    /// Queue a printable byte on the current line, wrapping at the paper edge
+    fn character(&mut self, byte: u8) {
+        let c = self.state.code_page.decode(byte).unwrap_or('?');
+        let style = self.state.style;
+        let advance = style.cell().0 + self.state.char_spacing * style.width as u32;
+        if self.x + style.cell().0 > self.width {
+            let height = self.print_line();
+            self.y += height.max(self.state.line_spacing);
+        }
+
+        self.line.push(Piece::Char { c, x: self.x, style });
+        self.x += advance;
+    }
+
+    /// `ESC * m nL nH d1...dk`: one row of 8- or 24-dot columns
+    fn bit_image(&mut self, cmd: &[u8]) -> usize {
+        let Some(&[m, low, high]) = cmd.get(2..5) else {
+            return cmd.len();
+        };
+        let columns = u16::from_le_bytes([low, high]) as u32;
+        let bytes = if m >= 32 { 3 } else { 1 };
+        let length = 5 + (columns * bytes) as usize;
+        let Some(data) = cmd.get(5..length) else {
+            return cmd.len();
+        };
+
+        let mut bitmap = MonoBitmap::new(columns, bytes * 8);
+        for x in 0..columns {
+            for k in 0..bytes {
+                let byte = data[(x * bytes + k) as usize];
+                for bit in 0..8 {
+                    if byte & (0x80 >> bit) != 0 {
+                        bitmap.set(x, k * 8 + bit, true);
+                    }
+                }
+            }
+        }
+
+        // Single density doubles every column; 8-dot modes are a third of the vertical resolution
+        let scale_x = if m == 0 || m == 32 { 2 } else { 1 };
+        let scale_y = if m < 32 { 3 } else { 1 };
+        self.line.push(Piece::Bits { bitmap, x: self.x, scale_x, scale_y });
+        self.x += columns * scale_x;
+        length
+    }
+
+    /// `GS v 0 m xL xH yL yH d1...dk`: raster image printed at once
+    fn raster(&mut self, cmd: &[u8]) -> usize {
+        let Some(header) = cmd.get(3..8) else {
+            return cmd.len();
+        };
+        let mode = header[0] % 48;
+        let byte_width = u16::from_le_bytes([header[1], header[2]]) as u32;
+        let height = u16::from_le_bytes([header[3], header[4]]) as u32;
+        let length = 8 + (byte_width * height) as usize;
+        let Some(data) = cmd.get(8..length) else {
+            return cmd.len();
+        };
+
+        let bitmap = unpack(data, byte_width, height);
+        let scale_x = if mode & 1 != 0 { 2 } else { 1 };
+        let scale_y = if mode & 2 != 0 { 2 } else { 1 };
+        self.print_line();
+        self.place(&bitmap, scale_x, scale_y);
+        length
+    }
+
+    /// `GS ( L`: store raster graphics (function 112) and print them (function 50)
+    fn graphics(&mut self, body: &[u8], header: &[u8], offset: usize) {
+        match body.get(1) {
+            Some(112) => {
+                let Some(&[_, _, _, bx, by, c, xl, xh, yl, yh]) = body.get(..10) else {
+                    return;
+                };
+                let width = u16::from_le_bytes([xl, xh]) as u32;
+                let height = u16::from_le_bytes([yl, yh]) as u32;
+                let bitmap = unpack(&body[10..], (width + 7) / 8, height);
+                // Colour 1 (49) is black, colour 2 (50) red
+                self.graphics.push((bitmap, bx.max(1) as u32, by.max(1) as u32, c == 50));
+            }
+            Some(50) | Some(2) => {
+                let planes = std::mem::take(&mut self.graphics);
+                if planes.is_empty() {
+                    return;
+                }
+                self.print_line();
+                let top = self.y;
+                // Black last, so it wins where both colours have a dot
+                for (bitmap, scale_x, scale_y, red) in planes.iter().filter(|p| p.3).chain(planes.iter().filter(|p| !p.3)) {
+                    let x = justify_offset(self.state.justify, self.width, bitmap.width() * scale_x);
+                    let ink = if *red { RED_INK } else { 0 };
+                    self.paint(bitmap, x, top, *scale_x, *scale_y, ink);
+                }
+                self.y = top + planes.iter().map(|(b, _, sy, _)| b.height() * sy).max().unwrap_or(0);
+            }
+            Some(67) => {
+                let Some(&[_, _, _, k1, k2, _, xl, xh, yl, yh, _]) = body.get(..11) else {
+                    return;
+                };
+                let width = u16::from_le_bytes([xl, xh]) as u32;
+                let height = u16::from_le_bytes([yl, yh]) as u32;
+                self.nv_graphics.insert([k1, k2], unpack(&body[11..], (width + 7) / 8, height));
+            }
+            Some(69) => {
+                let Some(&[_, _, k1, k2, bx, by]) = body.get(..6) else {
+                    return;
+                };
+                self.effects.push(Effect::Logo {
+                    key: String::from_utf8_lossy(&[k1, k2]).into_owned(),
+                });
+                if let Some(bitmap) = self.nv_graphics.get(&[k1, k2]).cloned() {
+                    self.print_line();
+                    self.place(&bitmap, bx.clamp(1, 2) as u32, by.clamp(1, 2) as u32);
+                }
+            }
+            Some(66) => {
+                if let Some(&[_, _, k1, k2]) = body.get(..4) {
+                    self.nv_graphics.remove(&[k1, k2]);
+                }
+            }
+            _ => self.unsupported(offset, header),
+        }
+    }
+
+    /// `GS k`: barcode in function A (NUL terminated) or B (length prefixed) form
+    fn barcode(&mut self, cmd: &[u8], offset: usize) -> usize {
+        let Some(&m) = cmd.get(2) else {
+            return cmd.len();
+        };
+        let (data, length) = if m <= 6 {
+            let end = cmd[3..].iter().position(|&b| b == 0).map_or(cmd.len(), |p| p + 3);
+            (&cmd[3..end], end + 1)
+        } else {
+            let n = cmd.get(3).copied().unwrap_or(0) as usize;
+            (&cmd[4.min(cmd.len())..(4 + n).min(cmd.len())], 4 + n)
+        };
+
+        let (symbology, text) = match m {
+            0 | 65 => (Symbology::UpcA, String::from_utf8_lossy(data).into_owned()),
+            2 | 67 => (Symbology::Ean13, String::from_utf8_lossy(data).into_owned()),
+            4 | 69 => (Symbology::Code39, String::from_utf8_lossy(data).trim_matches('*').to_string()),
+            5 | 70 => (Symbology::Itf, String::from_utf8_lossy(data).into_owned()),
+            73 => (Symbology::Code128, code128_text(data)),
+            _ => {
+                self.unsupported(offset, &cmd[..3]);
+                return length;
+            }
+        };
+
+        let options = BarcodeOptions {
+            symbology,
+            justify: self.state.justify,
+            ..self.state.barcode.clone()
+        };
+        let bitmap = Barcode::new(symbology, &text)
+            .and_then(|barcode| barcode::render_barcode(&barcode, &options, &self.profile()));
+        match bitmap {
+            Ok(bitmap) => {
+                self.print_line();
+                self.place(&bitmap, 1, 1);
+            }
+            Err(e) => self.effects.push(Effect::Unsupported {
+                offset,
+                command: format!("GS k: {}", e),
+            }),
+        }
+        length
+    }
+
+    /// `GS ( k` with cn 49: QR code settings, store and print
+    fn qr(&mut self, body: &[u8], offset: usize) {
+        let (Some(&cn), Some(&function)) = (body.first(), body.get(1)) else {
+            return;
+        };
+        if cn != 49 {
+            self.unsupported(offset, &[0x1D, b'(', b'k']);
+            return;
+        }
+
+        let n = body.get(2).copied().unwrap_or(0);
+        match function {
+            65 => self.state.qr.model = if n == 49 { 1 } else { 2 },
+            67 => self.state.qr.module_size = n,
+            69 => {
+                self.state.qr.error_correction = match n {
+                    48 => ErrorCorrection::Low,
+                    50 => ErrorCorrection::Quartile,
+                    51 => ErrorCorrection::High,
+                    _ => ErrorCorrection::Medium,
+                }
+            }
+            80 => self.qr_data = body.get(3..).unwrap_or_default().to_vec(),
+            81 => {
+                let options = QrOptions {
+                    justify: self.state.justify,
+                    ..self.state.qr.clone()
+                };
+                let data = String::from_utf8_lossy(&self.qr_data).into_owned();
+                match barcode::render_qr(&data, &options, &self.profile()) {
+                    Ok(bitmap) => {
+                        self.print_line();
+                        self.place(&bitmap, 1, 1);
+                    }
+                    Err(e) => self.effects.push(Effect::Unsupported {
+                        offset,
+                        command: format!("GS ( k: {}", e),
+                    }),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Profile used to draw barcodes and QR codes at this paper width
+    fn profile(&self) -> PrinterProfile {
+        PrinterProfile {
+            dot_width: self.width,
+            ..PrinterProfile::default()
+        }
+    }
+
+    fn cut(&mut self, partial: bool) {
+        self.print_line();
+        self.effects.push(Effect::Cut { partial, y: self.y });
+
+        self.grow(self.y + 1);
+        for x in (0..self.width).filter(|x| x % 8 < 4) {
+            let pixel = self.paper.get_pixel_mut(x, self.y);
+            if pixel[0] == 255 {
+                *pixel = Luma([CUT_MARK]);
+            }
+        }
+        self.bottom = self.bottom.max(self.y + 1);
+    }
+
+    /// Draw a full-height image at `y` with the current justification and move below it
+    fn place(&mut self, bitmap: &MonoBitmap, scale_x: u32, scale_y: u32) {
+        let width = bitmap.width() * scale_x;
+        let x = justify_offset(self.state.justify, self.width, width);
+        let y = self.y;
+        self.paint(bitmap, x, y, scale_x, scale_y, 0);
+        self.y += bitmap.height() * scale_y;
+    }
+
+    fn paint(&mut self, bitmap: &MonoBitmap, x: u32, y: u32, scale_x: u32, scale_y: u32, ink: u8) {
+        let bottom = y + bitmap.height() * scale_y;
+        self.grow(bottom);
+        self.bottom = self.bottom.max(bottom);
+
+        for by in 0..bitmap.height() {
+            for bx in 0..bitmap.width() {
+                if !bitmap.get(bx, by) {
+                    continue;
+                }
+                for dy in 0..scale_y {
+                    for dx in 0..scale_x {
+                        let px = x + bx * scale_x + dx;
+                        if px < self.width {
+                            self.paper.put_pixel(px, y + by * scale_y + dy, Luma([ink]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Print the buffered line at `y` without feeding, returning its height
+    fn print_line(&mut self) -> u32 {
+        let line = std::mem::take(&mut self.line);
+        let end = std::mem::take(&mut self.x);
+        let Some(height) = line.iter().map(Piece::height).max() else {
+            return 0;
+        };
+
+        let shift = justify_offset(self.state.justify, self.width, end.min(self.width));
+        let top = self.y;
+        self.grow(top + height);
+        self.bottom = self.bottom.max(top + height);
+
+        for piece in line {
+            // Everything on a line sits on the same bottom edge
+            let y = top + height - piece.height();
+            match piece {
+                Piece::Char { c, x, style } => self.draw_char(c, x + shift, y, style),
+                Piece::Bits { bitmap, x, scale_x, scale_y } => self.paint(&bitmap, x + shift, y, scale_x, scale_y, 0),
+            }
+        }
+        height
+    }
+
+    fn draw_char(&mut self, c: char, x: u32, y: u32, style: CharStyle) {
+        let (width, height) = style.cell();
+        let mut cell = GrayImage::from_pixel(width, height, Luma([255]));
+
+        if c != ' ' {
+            // Glyphs the fixed-pitch font lacks, like Arabic presentation forms, come from the regular font
+            let (rasterizer, ascent) = self.cell_font(width, height, style.bold, true);
+            let monospace = rasterizer.has_glyph(c);
+            let (rasterizer, ascent) = if monospace {
+                (rasterizer, ascent)
+            } else {
+                self.cell_font(width, height, style.bold, false)
+            };
+            rasterizer.draw(&mut cell, &c.to_string(), 0.0, ascent);
+        }
+        for row in 0..(style.underline as u32).min(height) {
+            for px in 0..width {
+                cell.put_pixel(px, height - 1 - row, Luma([0]));
+            }
+        }
+
+        let ink = if style.red { RED_INK } else { 0 };
+        for (cx, cy, pixel) in cell.enumerate_pixels() {
+            let black = (pixel[0] < 128) != style.inverse;
+            if black && x + cx < self.width {
+                self.paper.put_pixel(x + cx, y + cy, Luma([ink]));
+            }
+        }
+    }
+
+    /// Rasterizer filling a `width` x `height` character cell, and its baseline
+    fn cell_font(&mut self, width: u32, height: u32, bold: bool, monospace: bool) -> (&TextRasterizer, f32) {
+        let (rasterizer, ascent) = self.fonts.entry((width, height, bold, monospace)).or_insert_with(|| {
+            let options = |size: f32, stretch: f32| TextOptions {
+                size,
+                stretch,
+                bold,
+                monospace,
+                justify: None,
+            };
+            // Size the font so its line height is the cell height and a digit the cell width
+            let probe = TextRasterizer::new(options(height as f32, 1.0));
+            let size = height as f32 * height as f32 / probe.line_height() as f32;
+            let advance = TextRasterizer::new(TextOptions {
+                monospace: true,
+                ..options(size, 1.0)
+            })
+            .measure("0");
+            let rasterizer = TextRasterizer::new(options(size, width as f32 / advance.max(1.0)));
+            let ascent = rasterizer.ascent();
+            (rasterizer, ascent)
+        });
+        (rasterizer, *ascent)
+    }
+
+    /// Make the paper at least `height` rows long
+    fn grow(&mut self, height: u32) {
+        if height <= self.paper.height() {
+            return;
+        }
+        let mut paper = GrayImage::from_pixel(self.width, height.max(self.paper.height() * 2), Luma([255]));
+        image::imageops::replace(&mut paper, &self.paper, 0, 0);
+        self.paper = paper;
+    }
+}
+
+fn justify_offset(justify: Justify, page: u32, width: u32) -> u32 {
+    let free = page.saturating_sub(width);
+    match justify {
+        Justify::Left => 0,
+        Justify::Center => free / 2,
+        Justify::Right => free,
+    }
+}
+
+/// Unpack rows of `byte_width` bytes, most significant bit leftmost
+fn unpack(data: &[u8], byte_width: u32, height: u32) -> MonoBitmap {
+    let mut bitmap = MonoBitmap::new(byte_width * 8, height);
+    for (i, &byte) in data.iter().take((byte_width * height) as usize).enumerate() {
+        let (row, column) = (i as u32 / byte_width.max(1), i as u32 % byte_width.max(1));
+        for bit in 0..8 {
+            if byte & (0x80 >> bit) != 0 {
+                bitmap.set(column * 8 + bit, row, true);
+            }
+        }
+    }
+    bitmap
+}
+
+/// Text of `GS k` CODE128 data, following `{A`, `{B` and `{C` code set switches
+fn code128_text(data: &[u8]) -> String {
+    let mut text = String::new();
+    let mut set = b'B';
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'{' && i + 1 < data.len() {
+            match data[i + 1] {
+                code @ (b'A' | b'B' | b'C') => set = code,
+                b'{' => text.push('{'),
+                // Shift and function codes have no text
+                _ => {}
+            }
+            i += 2;
+            continue;
+        }
+        if set == b'C' {
+            text.push_str(&format!("{:02}", data[i]));
+        } else {
+            text.push(data[i] as char);
+        }
+        i += 1;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CutOptions;
+    use crate::escpos::{encode_column, encode_graphics, encode_raster, EscPosBuilder, TextStyle};
+    use std::path::{Path, PathBuf};
+
+    fn black(image: &GrayImage) -> usize {
+        image.pixels().filter(|p| p[0] == 0).count()
+    }
+
+    /// Compare against `testdata/emulator/<name>.png`; set `UPDATE_GOLDEN=1` to rewrite it
+    fn assert_golden(name: &str, printout: &Printout) {
+        let path: PathBuf = Path::new(file!())
+            .parent()
+            .unwrap()
+            .join("../testdata/emulator")
+            .join(format!("{}.png", name));
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            printout.image.save(&path).unwrap();
+        }
+        let golden = image::open(&path)
+            .unwrap_or_else(|e| panic!("Missing golden file {}: {}", path.display(), e))
+            .to_luma8();
+        assert!(
+            golden == printout.image,
+            "{} differs from {}; rerun with UPDATE_GOLDEN=1 if the change is intended",
+            name,
+            path.display()
+        );
+    }
+
+    #[test]
+    fn image_modes_print_the_same_dots() {
+        let mut bitmap = MonoBitmap::new(64, 48);
+        for y in 0..48 {
+            for x in 0..64 {
+                bitmap.set(x, y, (x / 8 + y / 8) % 2 == 0 || x == y);
+            }
+        }
+
+        let column = render(&encode_column(&bitmap), 64);
+        let raster = render(&encode_raster(&bitmap, 256), 64);
+        let graphics = render(&encode_graphics(&bitmap, 256), 64);
+
+        assert_eq!(raster.image.dimensions(), (64, 48));
+        assert!(column.image == raster.image);
+        assert!(graphics.image == raster.image);
+        for y in 0..48 {
+            for x in 0..64 {
+                assert_eq!(raster.image.get_pixel(x, y)[0] == 0, bitmap.get(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn records_side_effects() {
+        let profile = PrinterProfile::default();
+        let data = EscPosBuilder::with_profile(&profile)
+            .line("A")
+            .beep(2, 3)
+            .unwrap()
+            .drawer(1, &profile.drawer)
+            .unwrap()
+            .cut(&CutOptions::default())
+            .raw(&[0x1B, 0x52, 0x00])
+            .build();
+        let printout = render(&data, 576);
+
+        assert_eq!(
+            printout.effects,
+            vec![
+                Effect::Init,
+                Effect::CodePage { table: 0 },
+                Effect::Beep { times: 2, duration: 3 },
+                Effect::Drawer { pin: 5, on_ms: 50, off_ms: 500 },
+                Effect::Cut { partial: false, y: 4 * DEFAULT_LINE_SPACING },
+                Effect::Unsupported { offset: 22, command: "ESC R 0x00".to_string() },
+            ]
+        );
+        assert_eq!(printout.image.height(), 4 * DEFAULT_LINE_SPACING + 1);
+    }
+
+    #[test]
+    fn logos_stored_and_printed_from_memory() {
+        let mut bitmap = MonoBitmap::new(16, 8);
+        for x in 0..16 {
+            bitmap.set(x, x / 2, true);
+        }
+
+        let mut data = crate::logo::store_command(*b"LG", &bitmap);
+        data.extend(crate::logo::print_command(*b"LG"));
+        data.extend(crate::logo::print_command(*b"XX"));
+        data.extend(crate::logo::legacy_store_command(&[&bitmap]));
+        data.extend(crate::logo::legacy_print_command(1));
+        let printout = render(&data, 64);
+
+        assert_eq!(
+            printout.effects,
+            vec![
+                Effect::Logo { key: "LG".to_string() },
+                Effect::Logo { key: "XX".to_string() },
+                Effect::Logo { key: "1".to_string() },
+            ]
+        );
+        // Only stored logos are drawn, both the same way
+        assert_eq!(printout.image.height(), 16);
+        for y in 0..8 {
+            for x in 0..16 {
+                assert_eq!(printout.image.get_pixel(x, y)[0] == 0, bitmap.get(x, y));
+                assert_eq!(printout.image.get_pixel(x, y + 8)[0] == 0, bitmap.get(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn text_styles_and_wrapping() {
+        let plain = render(b"HELLO\n", 576);
+        let bold = render(b"\x1bE\x01HELLO\n", 576);
+        assert!(black(&bold.image) > black(&plain.image));
+
+        // Double size text is twice as tall; centered text starts further right
+        let big = render(b"\x1d!\x11\x1ba\x01HELLO\n", 576);
+        let columns: Vec<u32> = (0..576).filter(|&x| (0..48).any(|y| big.image.get_pixel(x, y)[0] == 0)).collect();
+        assert!(columns[0] > 200 && *columns.last().unwrap() < 400);
+        assert!((25..48).any(|y| (0..576).any(|x| big.image.get_pixel(x, y)[0] == 0)));
+
+        // 48 font A characters fit on 576 dots, the 49th wraps
+        let wrapped = render(&[b'X'; 49], 576);
+        assert_eq!(wrapped.image.height(), DEFAULT_LINE_SPACING + 24);
+    }
+
+    #[test]
+    fn styled_receipt_golden() {
+        let data = EscPosBuilder::new()
+            .init()
+            .style(&TextStyle {
+                justify: Justify::Center,
+                width: 2,
+                height: 2,
+                bold: true,
+                ..TextStyle::default()
+            })
+            .line("VOPECS")
+            .reset_style()
+            .underline(1)
+            .line("Order 42")
+            .underline(0)
+            .inverse(true)
+            .line(" PAID ")
+            .inverse(false)
+            .font(Font::B)
+            .line("2x Tea        6.00")
+            .font(Font::A)
+            .qr_with(
+                "https://example.com/r/42",
+                &QrOptions {
+                    module_size: 4,
+                    justify: Justify::Center,
+                    ..QrOptions::default()
+                },
+            )
+            .unwrap()
+            .cut(&CutOptions::default())
+            .build();
+        assert_golden("styled_receipt", &render(&data, 384));
+    }
+}
@@ -1,5 +1,13 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use crate::api;
+use crate::barcode::{self, Barcode, BarcodeOptions, BarcodeRendering, ErrorCorrection, QrOptions};
+use crate::codepage::CodePage;
+use crate::command_set::Emulation;
+use crate::config::{CutOptions, DrawerOptions, PrinterProfile};
+use crate::dither::{self, DitherOptions, MonoBitmap};
+use crate::logo::{self, LogoMemory};
 
 /// ESC/POS Commands
 pub struct EscPos;
@@ -20,12 +28,6 @@ impl EscPos {
     /// Cut paper (partial cut)
     pub const CUT_PARTIAL: &'static [u8] = &[0x1D, 0x56, 0x01];
 
-    /// Open cash drawer (pin 2)
-    pub const DRAWER_PIN2: &'static [u8] = &[0x1B, 0x70, 0x00, 0x19, 0xFA];
-
-    /// Open cash drawer (pin 5)
-    pub const DRAWER_PIN5: &'static [u8] = &[0x1B, 0x70, 0x01, 0x19, 0xFA];
-
     /// Select bit image mode
     pub const SELECT_BIT_IMAGE: &'static [u8] = &[0x1B, 0x2A];
 
@@ -37,16 +39,211 @@ impl EscPos {
     /// Reset line spacing to default
     pub const LINE_SPACING_DEFAULT: &'static [u8] = &[0x1B, 0x32];
 
-    /// Open drawer command based on pin number
-    pub fn open_drawer(pin: u8) -> &'static [u8] {
-        match pin {
-            0 | 2 => Self::DRAWER_PIN2,
-            1 | 5 => Self::DRAWER_PIN5,
-            _ => Self::DRAWER_PIN2,
+    /// Select character code table `n` (ESC t n)
+    pub fn code_page(n: u8) -> Vec<u8> {
+        vec![0x1B, 0x74, n]
+    }
+
+    /// Set print density from -6 (lightest) to 6 (darkest) (GS ( K fn 49)
+    pub fn density(level: i8) -> Vec<u8> {
+        vec![0x1D, 0x28, 0x4B, 0x02, 0x00, 0x31, level.clamp(-6, 6) as u8]
+    }
+
+    /// Pulse a drawer pin (ESC p m t1 t2); 0/2 = pin 2, 1/5 = pin 5.
+    /// Timings are sent in 2 ms units.
+    pub fn drawer_pulse(pin: u8, on_ms: u16, off_ms: u16) -> Vec<u8> {
+        let m = match pin {
+            1 | 5 => 1,
+            _ => 0,
+        };
+        let units = |ms: u16| (ms / 2).clamp(1, 255) as u8;
+        vec![0x1B, 0x70, m, units(on_ms), units(off_ms)]
+    }
+
+    /// Emphasized (bold) text on/off (ESC E n)
+    pub fn bold(on: bool) -> Vec<u8> {
+        vec![0x1B, 0x45, on as u8]
+    }
+
+    /// Underline off (0), thin (1) or thick (2) (ESC - n)
+    pub fn underline(weight: u8) -> Vec<u8> {
+        vec![0x1B, 0x2D, weight.min(2)]
+    }
+
+    /// White-on-black text on/off (GS B n)
+    pub fn inverse(on: bool) -> Vec<u8> {
+        vec![0x1D, 0x42, on as u8]
+    }
+
+    /// Select black or red ink on two-colour printers (ESC r n)
+    pub fn color(color: InkColor) -> Vec<u8> {
+        vec![0x1B, 0x72, (color == InkColor::Red) as u8]
+    }
+
+    /// Character size as width and height multipliers 1-8 (GS ! n)
+    pub fn char_size(width: u8, height: u8) -> Vec<u8> {
+        let width = width.clamp(1, 8) - 1;
+        let height = height.clamp(1, 8) - 1;
+        vec![0x1D, 0x21, (width << 4) | height]
+    }
+
+    /// Justification (ESC a n)
+    pub fn justify(justify: Justify) -> Vec<u8> {
+        let n = match justify {
+            Justify::Left => 0,
+            Justify::Center => 1,
+            Justify::Right => 2,
+        };
+        vec![0x1B, 0x61, n]
+    }
+
+    /// Character font (ESC M n)
+    pub fn font(font: Font) -> Vec<u8> {
+        let n = match font {
+            Font::A => 0,
+            Font::B => 1,
+        };
+        vec![0x1B, 0x4D, n]
+    }
+
+    /// Print and feed `n` lines (ESC d n)
+    pub fn feed(lines: u8) -> Vec<u8> {
+        vec![0x1B, 0x64, lines]
+    }
+
+    /// Sound the buzzer `times` times for `duration` x 50 ms each (ESC B n t)
+    pub fn beep(times: u8, duration: u8) -> Vec<u8> {
+        vec![0x1B, 0x42, times.clamp(1, 9), duration.clamp(1, 9)]
+    }
+}
+
+/// Text justification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Justify {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Built-in character font
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Font {
+    /// 12x24, 48 columns on 80 mm paper
+    #[default]
+    A,
+    /// 9x17, 64 columns on 80 mm paper
+    B,
+}
+
+/// Ink of two-colour paper
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InkColor {
+    #[default]
+    Black,
+    Red,
+}
+
+/// Character formatting applied to a run of text
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TextStyle {
+    pub bold: bool,
+    /// 0 = off, 1 = thin, 2 = thick
+    pub underline: u8,
+    pub inverse: bool,
+    /// Width multiplier 1-8 (0 is treated as 1)
+    pub width: u8,
+    /// Height multiplier 1-8 (0 is treated as 1)
+    pub height: u8,
+    pub justify: Justify,
+    pub font: Font,
+    /// Red only prints on two-colour printers
+    pub color: InkColor,
+}
+
+/// ESC/POS command used to send images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImageMode {
+    /// `ESC *` 24-dot column strips, understood by nearly every printer
+    #[default]
+    Column,
+    /// `GS v 0` raster blocks, faster and without gaps between strips
+    Raster,
+    /// `GS ( L` graphics buffer, for printers that dropped `GS v 0`
+    Graphics,
+}
+
+/// Cropping and placement of receipt images, applied before dithering
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImageOptions {
+    /// Crop borders the colour of the top-left pixel
+    pub trim: bool,
+    /// Blank runs longer than this many rows are shortened to it; 0 keeps them
+    pub max_blank_rows: u32,
+    /// Placement of images narrower than the printable width
+    pub align: Justify,
+    /// Scale narrower images up to the printable width
+    pub upscale: bool,
+    /// Largest upscale factor, so small logos don't turn into blocks
+    pub max_upscale: f32,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            trim: false,
+            max_blank_rows: 0,
+            align: Justify::Left,
+            upscale: false,
+            max_upscale: 2.0,
         }
     }
 }
 
+/// Two-colour (black/red) printing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ColorOptions {
+    /// The printer has red/black paper: red text is sent with `ESC r` and
+    /// images are split into a black and a red plane
+    pub two_color: bool,
+    /// Pixels whose hue is within this many degrees of pure red print in red
+    pub hue_tolerance: f32,
+    /// Minimum saturation (0-1) for a pixel to count as red rather than grey
+    pub min_saturation: f32,
+}
+
+impl Default for ColorOptions {
+    fn default() -> Self {
+        Self {
+            two_color: false,
+            hue_tolerance: 30.0,
+            min_saturation: 0.4,
+        }
+    }
+}
+
+/// Red pixels darker than this brightness (0-1) print in black
+const RED_MIN_VALUE: f32 = 0.35;
+
+/// Largest difference from the border colour that still counts as border when trimming
+const TRIM_TOLERANCE: u8 = 16;
+
+/// Largest band `GS v 0` accepts (yH <= 8)
+const RASTER_MAX_ROWS: u32 = 2303;
+
+/// Largest payload of a single `GS ( L` command (pL/pH minus the header)
+const GRAPHICS_MAX_DATA: u32 = 65535 - 10;
+
+/// Tallest band `GS ( L` function 112 accepts at normal vertical scale
+const GRAPHICS_MAX_ROWS: u32 = 1662;
+
 /// Thermal printer image converter
 pub struct ThermalImage;
 
@@ -54,13 +251,94 @@ impl ThermalImage {
     /// Maximum width for 80mm thermal printer (typically 576 or 512 dots)
     pub const MAX_WIDTH_80MM: u32 = 576;
 
-    /// Maximum width for 58mm thermal printer (typically 384 dots)
-    pub const MAX_WIDTH_58MM: u32 = 384;
-
     /// Convert image to ESC/POS bitmap data for thermal printer
-    pub fn to_escpos_bitmap(image: &DynamicImage, max_width: u32) -> Result<Vec<u8>> {
-        let mut data = Vec::new();
+    pub fn to_escpos_bitmap(image: &DynamicImage, profile: &PrinterProfile) -> Result<Vec<u8>> {
+        let bitmap = Self::to_mono(image, profile.dot_width, &profile.dithering);
+
+        let mut data = generate_setup_command(profile);
+        data.extend_from_slice(&encode_bitmap(&bitmap, profile));
+
+        Ok(data)
+    }
+
+    /// Convert a receipt image with the profile's trimming, scaling and alignment.
+    /// On two-colour printers red parts of the image print in red.
+    pub fn image_to_escpos(image: &DynamicImage, profile: &PrinterProfile) -> Result<Vec<u8>> {
+        let mut data = generate_setup_command(profile);
+
+        if profile.color.two_color {
+            let (black, red) = Self::fit_two_color(image, profile);
+            data.extend_from_slice(&profile.emulation.commands().two_color_image(&black, &red, profile));
+            return Ok(data);
+        }
+
+        let bitmap = Self::fit(image, profile);
+        data.extend_from_slice(&encode_bitmap(&bitmap, profile));
+
+        Ok(data)
+    }
+
+    /// Trim, scale, dither and place a receipt image per the profile's image options
+    pub fn fit(image: &DynamicImage, profile: &PrinterProfile) -> MonoBitmap {
+        let image = Self::prepare(image, profile);
+        let bitmap = Self::to_mono(&image, profile.dot_width, &profile.dithering);
+        Self::arrange(vec![bitmap], profile).remove(0)
+    }
+
+    /// [`Self::fit`] for two-colour paper: the black and the red plane, split by hue
+    pub fn fit_two_color(image: &DynamicImage, profile: &PrinterProfile) -> (MonoBitmap, MonoBitmap) {
+        let image = Self::prepare(image, profile);
+        let (black, red) = split_red(&image.to_rgb8(), &profile.color);
+        let planes = vec![
+            dither::dither(&black, &profile.dithering),
+            dither::dither(&red, &profile.dithering),
+        ];
+        let mut planes = Self::arrange(planes, profile).into_iter();
+        (planes.next().unwrap(), planes.next().unwrap())
+    }
+
+    /// Trim and scale an image to the printable width, before dithering
+    fn prepare(image: &DynamicImage, profile: &PrinterProfile) -> DynamicImage {
+        let options = &profile.image;
+        let mut image = image.clone();
+
+        if options.trim {
+            let gray = image.to_luma8();
+            if let Some([left, top, right, bottom]) = border_bounds(&gray) {
+                image = image.crop_imm(left, top, right - left, bottom - top);
+            }
+        }
+
+        if options.upscale && image.width() < profile.dot_width {
+            let factor = (profile.dot_width as f32 / image.width() as f32).min(options.max_upscale.max(1.0));
+            let width = ((image.width() as f32 * factor) as u32).min(profile.dot_width);
+            let height = (image.height() as f32 * factor).round() as u32;
+            image = image.resize_exact(width, height.max(1), image::imageops::FilterType::Lanczos3);
+        }
 
+        if image.width() > profile.dot_width {
+            let height = (image.height() as f32 * profile.dot_width as f32 / image.width() as f32) as u32;
+            image = image.resize(profile.dot_width, height, image::imageops::FilterType::Lanczos3);
+        }
+        image
+    }
+
+    /// Collapse blank runs and place narrow images, the same way on every plane
+    fn arrange(mut planes: Vec<MonoBitmap>, profile: &PrinterProfile) -> Vec<MonoBitmap> {
+        let options = &profile.image;
+        if options.max_blank_rows > 0 {
+            planes = collapse_blank_rows(&planes, options.max_blank_rows);
+        }
+        if options.align != Justify::Left {
+            for plane in planes.iter_mut().filter(|p| p.width() < profile.dot_width) {
+                *plane = place(plane, profile.dot_width, options.align);
+            }
+        }
+        planes
+    }
+
+    /// Scale an image down to the printable width and dither it to 1-bit
+    pub fn to_mono(image: &DynamicImage, max_width: u32, dithering: &DitherOptions) -> MonoBitmap {
         // Resize image if needed
         let img = if image.width() > max_width {
             let ratio = max_width as f32 / image.width() as f32;
@@ -70,63 +348,22 @@ impl ThermalImage {
             image.clone()
         };
 
-        // Convert to grayscale
+        // Convert to grayscale and reduce to black/white dots
         let gray = img.to_luma8();
-        let (width, height) = gray.dimensions();
-
-        // Width must be divisible by 8
-        let byte_width = (width + 7) / 8;
-        let padded_width = byte_width * 8;
-
-        // Initialize printer
-        data.extend_from_slice(EscPos::INIT);
-
-        // Set line spacing to 24 dots (for 24-dot high image slices)
-        data.extend_from_slice(&EscPos::line_spacing(24));
-
-        // Process image in 24-row strips (for 24-dot vertical mode)
-        for y_start in (0..height).step_by(24) {
-            // Select bit image mode: 24-dot double-density
-            data.push(0x1B);
-            data.push(0x2A);
-            data.push(33); // Mode 33 = 24-dot double-density
-            data.push((padded_width & 0xFF) as u8);
-            data.push(((padded_width >> 8) & 0xFF) as u8);
-
-            // Generate bitmap data for this strip
-            for x in 0..padded_width {
-                for k in 0..3 {
-                    // 3 bytes per column (24 dots)
-                    let mut byte: u8 = 0;
-                    for bit in 0..8 {
-                        let y = y_start + k * 8 + bit;
-                        if y < height && x < width {
-                            let pixel = gray.get_pixel(x, y);
-                            // Use threshold to determine black/white
-                            if pixel[0] < 128 {
-                                byte |= 0x80 >> bit;
-                            }
-                        }
-                    }
-                    data.push(byte);
-                }
-            }
-
-            // Line feed
-            data.extend_from_slice(EscPos::LF);
-        }
-
-        // Reset line spacing
-        data.extend_from_slice(EscPos::LINE_SPACING_DEFAULT);
-
-        Ok(data)
+        dither::dither(&gray, dithering)
     }
 
     /// Convert base64 image to ESC/POS bitmap
-    pub fn base64_to_escpos(base64_str: &str, max_width: u32) -> Result<Vec<u8>> {
+    pub fn base64_to_escpos(base64_str: &str, profile: &PrinterProfile) -> Result<Vec<u8>> {
+        let img = Self::decode_base64(base64_str)?;
+        Self::image_to_escpos(&img, profile)
+    }
+
+    /// Decode a base64 or data URL image
+    pub fn decode_base64(base64_str: &str) -> Result<DynamicImage> {
         // Remove data URL prefix if present
         let base64_data = if base64_str.contains(',') {
-            base64_str.split(',').last().unwrap_or(base64_str)
+            base64_str.rsplit(',').next().unwrap_or(base64_str)
         } else {
             base64_str
         };
@@ -141,18 +378,15 @@ impl ThermalImage {
         let img = image::load_from_memory(&image_data)
             .context("Failed to load image from memory")?;
 
-        Self::to_escpos_bitmap(&img, max_width)
+        Ok(img)
     }
 
     /// Load image from URL and convert to ESC/POS
-    pub async fn url_to_escpos(url: &str, max_width: u32) -> Result<Vec<u8>> {
+    pub async fn url_to_escpos(url: &str, profile: &PrinterProfile) -> Result<Vec<u8>> {
         // Download image
         println!("Downloading image from URL: {}", url);
-        let response = reqwest::get(url).await
+        let image_data = api::download(url).await
             .context("Failed to download image")?;
-
-        let image_data = response.bytes().await
-            .context("Failed to read image data")?;
         println!("Downloaded {} bytes from URL", image_data.len());
 
         // Load image
@@ -160,18 +394,16 @@ impl ThermalImage {
             .context("Failed to load image from memory")?;
         println!("Loaded image from URL: {}x{} pixels", img.width(), img.height());
 
-        let result = Self::to_escpos_bitmap(&img, max_width)?;
+        let result = Self::image_to_escpos(&img, profile)?;
         println!("Generated {} bytes of ESC/POS commands", result.len());
 
         Ok(result)
     }
 
     /// Create a simple test pattern for thermal printer
-    pub fn test_pattern(_width: u32) -> Vec<u8> {
-        let mut data = Vec::new();
-
+    pub fn test_pattern(profile: &PrinterProfile) -> Vec<u8> {
         // Initialize
-        data.extend_from_slice(EscPos::INIT);
+        let mut data = generate_setup_command(profile);
 
         // Print text header
         data.extend_from_slice(b"================================\n");
@@ -181,9 +413,13 @@ impl ThermalImage {
 
         // Print timestamp
         let now = chrono::Local::now();
-        let timestamp = format!("Date: {}\n\n", now.format("%Y-%m-%d %H:%M:%S"));
+        let timestamp = format!("Date: {}\n", now.format("%Y-%m-%d %H:%M:%S"));
         data.extend_from_slice(timestamp.as_bytes());
 
+        // Print profile summary
+        let profile_line = format!("Width: {} dots @ {} dpi\n\n", profile.dot_width, profile.dpi);
+        data.extend_from_slice(profile_line.as_bytes());
+
         // Print test lines
         data.extend_from_slice(b"Test Line 1: ABCDEFGHIJKLMNOP\n");
         data.extend_from_slice(b"Test Line 2: 1234567890\n");
@@ -206,17 +442,916 @@ impl ThermalImage {
     }
 }
 
-/// Generate cut command
-pub fn generate_cut_command() -> Vec<u8> {
+/// Bounds `[left, top, right, bottom)` of everything that differs from the
+/// top-left pixel, `None` when the image is a single colour
+fn border_bounds(gray: &image::GrayImage) -> Option<[u32; 4]> {
+    let border = gray.get_pixel(0, 0)[0];
+    let mut bounds: Option<[u32; 4]> = None;
+    for (x, y, pixel) in gray.enumerate_pixels() {
+        if pixel[0].abs_diff(border) <= TRIM_TOLERANCE {
+            continue;
+        }
+        let b = bounds.get_or_insert([x, y, x + 1, y + 1]);
+        *b = [b[0].min(x), b[1].min(y), b[2].max(x + 1), b[3].max(y + 1)];
+    }
+    bounds
+}
+
+/// Shorten every run of rows blank on all planes to at most `max` rows
+fn collapse_blank_rows(planes: &[MonoBitmap], max: u32) -> Vec<MonoBitmap> {
+    let (width, height) = (planes[0].width(), planes[0].height());
+    let blank = |y: u32| planes.iter().all(|p| (0..width).all(|x| !p.get(x, y)));
+    let mut rows = Vec::with_capacity(height as usize);
+    let mut run = 0;
+    for y in 0..height {
+        run = if blank(y) { run + 1 } else { 0 };
+        if run <= max {
+            rows.push(y);
+        }
+    }
+
+    planes
+        .iter()
+        .map(|bitmap| {
+            let mut collapsed = MonoBitmap::new(width, rows.len() as u32);
+            for (row, &y) in rows.iter().enumerate() {
+                for x in (0..width).filter(|&x| bitmap.get(x, y)) {
+                    collapsed.set(x, row as u32, true);
+                }
+            }
+            collapsed
+        })
+        .collect()
+}
+
+/// Split an image into a black and a red layer by hue. Red pixels are
+/// white on the black layer; on the red layer their saturation sets the ink.
+fn split_red(rgb: &image::RgbImage, options: &ColorOptions) -> (image::GrayImage, image::GrayImage) {
+    let mut black = image::GrayImage::new(rgb.width(), rgb.height());
+    let mut red = image::GrayImage::from_pixel(rgb.width(), rgb.height(), image::Luma([255]));
+
+    for (x, y, pixel) in rgb.enumerate_pixels() {
+        let [r, g, b] = pixel.0.map(|c| c as f32 / 255.0);
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+        let hue = match max - min {
+            d if d <= 0.0 => 0.0,
+            d if max == r => 60.0 * ((g - b) / d).rem_euclid(6.0),
+            d if max == g => 60.0 * ((b - r) / d + 2.0),
+            d => 60.0 * ((r - g) / d + 4.0),
+        };
+        let distance = hue.min(360.0 - hue);
+
+        if distance <= options.hue_tolerance && saturation >= options.min_saturation && max >= RED_MIN_VALUE {
+            black.put_pixel(x, y, image::Luma([255]));
+            red.put_pixel(x, y, image::Luma([(255.0 * (1.0 - saturation)) as u8]));
+        } else {
+            let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+            black.put_pixel(x, y, image::Luma([(luma * 255.0).round() as u8]));
+        }
+    }
+    (black, red)
+}
+
+/// Pad a bitmap to `width` dots, placing it per `justify`
+fn place(bitmap: &MonoBitmap, width: u32, justify: Justify) -> MonoBitmap {
+    let free = width - bitmap.width();
+    let offset = match justify {
+        Justify::Left => 0,
+        Justify::Center => free / 2,
+        Justify::Right => free,
+    };
+
+    let mut placed = MonoBitmap::new(width, bitmap.height());
+    for y in 0..bitmap.height() {
+        for x in (0..bitmap.width()).filter(|&x| bitmap.get(x, y)) {
+            placed.set(x + offset, y, true);
+        }
+    }
+    placed
+}
+
+/// Encode a bitmap with the profile's command set and image mode
+pub fn encode_bitmap(bitmap: &MonoBitmap, profile: &PrinterProfile) -> Vec<u8> {
+    profile.emulation.commands().image(bitmap, profile)
+}
+
+/// Encode a bitmap as `ESC *` 24-dot double-density column strips
+pub fn encode_column(bitmap: &MonoBitmap) -> Vec<u8> {
+    let mut data = Vec::new();
+    let height = bitmap.height();
+    let widths = ink_widths(bitmap);
+    let mut skipped = 0;
+
+    // Set line spacing to 24 dots (for 24-dot high image slices)
+    data.extend_from_slice(&EscPos::line_spacing(24));
+
+    // Process image in 24-row strips (for 24-dot vertical mode)
+    for y_start in (0..height).step_by(24) {
+        // Only send columns up to the last one with ink; blank strips become a feed
+        let rows = y_start..(y_start + 24).min(height);
+        let width = widths[rows.start as usize..rows.end as usize].iter().copied().max().unwrap_or(0);
+        if width == 0 {
+            skipped += rows.len() as u32;
+            continue;
+        }
+        feed_dots(&mut data, std::mem::take(&mut skipped));
+
+        // Select bit image mode: 24-dot double-density
+        data.extend_from_slice(EscPos::SELECT_BIT_IMAGE);
+        data.push(33); // Mode 33 = 24-dot double-density
+        data.extend_from_slice(&(width as u16).to_le_bytes());
+
+        // Generate bitmap data for this strip
+        for x in 0..width {
+            for k in 0..3 {
+                // 3 bytes per column (24 dots)
+                let mut byte: u8 = 0;
+                for bit in 0..8 {
+                    let y = y_start + k * 8 + bit;
+                    if bitmap.get(x, y) {
+                        byte |= 0x80 >> bit;
+                    }
+                }
+                data.push(byte);
+            }
+        }
+
+        // Line feed, or for a last strip shorter than 24 rows print it and
+        // feed only the rows it has
+        match rows.len() {
+            24 => data.extend_from_slice(EscPos::LF),
+            n => data.extend_from_slice(&[0x1B, 0x4A, n as u8]),
+        }
+    }
+    feed_dots(&mut data, skipped);
+
+    // Reset line spacing
+    data.extend_from_slice(EscPos::LINE_SPACING_DEFAULT);
+
+    data
+}
+
+/// Encode a bitmap as `GS v 0` raster blocks of at most `max_band_height` rows
+/// (0 = as tall as the command allows)
+pub fn encode_raster(bitmap: &MonoBitmap, max_band_height: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    let band = band_height(max_band_height, RASTER_MAX_ROWS);
+
+    for block in split_blocks(bitmap, band, |_| band) {
+        let Block::Image { top, rows, width } = block else {
+            feed_dots(&mut data, block.rows());
+            continue;
+        };
+        let byte_width = (width + 7) / 8;
+
+        // GS v 0 m xL xH yL yH, m = 0 normal density
+        data.extend_from_slice(&[0x1D, 0x76, 0x30, 0x00]);
+        data.extend_from_slice(&(byte_width as u16).to_le_bytes());
+        data.extend_from_slice(&(rows as u16).to_le_bytes());
+
+        for y in top..top + rows {
+            pack_row_prefix(bitmap, y, byte_width, &mut data);
+        }
+    }
+
+    data
+}
+
+/// Encode a bitmap through the `GS ( L` graphics buffer: store a band with
+/// function 112, then print it with function 50
+pub fn encode_graphics(bitmap: &MonoBitmap, max_band_height: u32) -> Vec<u8> {
+    encode_graphics_planes(&[bitmap], max_band_height)
+}
+
+/// Encode colour planes of the same size through the `GS ( L` graphics
+/// buffer: every band stores one plane per colour (first black, then red),
+/// then prints them together
+pub fn encode_graphics_planes(planes: &[&MonoBitmap], max_band_height: u32) -> Vec<u8> {
     let mut data = Vec::new();
-    data.extend_from_slice(EscPos::LF);
-    data.extend_from_slice(EscPos::LF);
-    data.extend_from_slice(EscPos::LF);
-    data.extend_from_slice(EscPos::CUT_FULL);
+    let band = |width: u32| {
+        let limit = GRAPHICS_MAX_DATA.checked_div((width + 7) / 8).unwrap_or(GRAPHICS_MAX_DATA);
+        band_height(max_band_height, limit.min(GRAPHICS_MAX_ROWS))
+    };
+
+    // Bands and widths follow the ink of all planes together
+    let mut ink = planes[0].clone();
+    for plane in &planes[1..] {
+        ink.overlay(plane);
+    }
+    let widest = band(ink.width());
+
+    for block in split_blocks(&ink, widest, band) {
+        let Block::Image { top, rows, width } = block else {
+            feed_dots(&mut data, block.rows());
+            continue;
+        };
+        let byte_width = (width + 7) / 8;
+        let size = 10 + byte_width * rows;
+
+        for (color, plane) in (49u8..).zip(planes) {
+            // GS ( L pL pH m fn a bx by c xL xH yL yH
+            data.extend_from_slice(&[0x1D, 0x28, 0x4C]);
+            data.extend_from_slice(&(size as u16).to_le_bytes());
+            data.extend_from_slice(&[48, 112, 48, 1, 1, color]);
+            data.extend_from_slice(&((byte_width * 8) as u16).to_le_bytes());
+            data.extend_from_slice(&(rows as u16).to_le_bytes());
+
+            for y in top..top + rows {
+                pack_row_prefix(plane, y, byte_width, &mut data);
+            }
+        }
+
+        // Print the buffered graphics
+        data.extend_from_slice(&[0x1D, 0x28, 0x4C, 0x02, 0x00, 48, 50]);
+    }
+
     data
 }
 
+/// Blank runs at least this tall are fed over with `ESC J` instead of sent as rows
+const MIN_SKIP_ROWS: u32 = 24;
+
+/// Part of a bitmap sent as one raster/graphics command, or skipped with a feed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Block {
+    Feed(u32),
+    /// Rows `top..top + rows`, the ink ending before column `width`
+    Image { top: u32, rows: u32, width: u32 },
+}
+
+impl Block {
+    fn rows(&self) -> u32 {
+        match *self {
+            Block::Feed(rows) => rows,
+            Block::Image { rows, .. } => rows,
+        }
+    }
+}
+
+/// Split a bitmap into image blocks and feeds over blank runs. `max_rows`
+/// gives the tallest block for the block's width so far, never above `limit`.
+fn split_blocks(bitmap: &MonoBitmap, limit: u32, max_rows: impl Fn(u32) -> u32) -> Vec<Block> {
+    let widths = ink_widths(bitmap);
+    let height = bitmap.height();
+    // A blank run worth skipping: tall enough, or running to the end of the image
+    let skippable = |y: u32| {
+        let run = widths[y as usize..].iter().take_while(|&&w| w == 0).count() as u32;
+        (run >= MIN_SKIP_ROWS || y + run == height).then_some(run)
+    };
+
+    let mut blocks = Vec::new();
+    let mut y = 0;
+    while y < height {
+        if let Some(run) = skippable(y).filter(|&run| run > 0) {
+            blocks.push(Block::Feed(run));
+            y += run;
+            continue;
+        }
+
+        let (top, mut width) = (y, 0);
+        while y < height && y - top < limit.min(max_rows(width.max(widths[y as usize]))) {
+            if widths[y as usize] == 0 && skippable(y).is_some() {
+                break;
+            }
+            width = width.max(widths[y as usize]);
+            y += 1;
+        }
+        blocks.push(match width {
+            0 => Block::Feed(y - top),
+            _ => Block::Image { top, rows: y - top, width },
+        });
+    }
+    blocks
+}
+
+/// Columns up to and including the last printed dot of every row
+pub(crate) fn ink_widths(bitmap: &MonoBitmap) -> Vec<u32> {
+    (0..bitmap.height())
+        .map(|y| {
+            (0..bitmap.width())
+                .rev()
+                .find(|&x| bitmap.get(x, y))
+                .map_or(0, |x| x + 1)
+        })
+        .collect()
+}
+
+/// Pack the first `byte_width` bytes of a row
+pub(crate) fn pack_row_prefix(bitmap: &MonoBitmap, y: u32, byte_width: u32, out: &mut Vec<u8>) {
+    let start = out.len();
+    bitmap.pack_row(y, out);
+    out.truncate(start + byte_width as usize);
+}
+
+/// Feed the paper `dots` without printing (ESC J n)
+fn feed_dots(data: &mut Vec<u8>, mut dots: u32) {
+    while dots > 0 {
+        let n = dots.min(255);
+        data.extend_from_slice(&[0x1B, 0x4A, n as u8]);
+        dots -= n;
+    }
+}
+
+/// Rows per band: the configured height capped at what the command allows
+fn band_height(max_band_height: u32, limit: u32) -> u32 {
+    match max_band_height {
+        0 => limit,
+        n => n.min(limit),
+    }
+    .max(1)
+}
+
+/// Commands sent at the start of every job: for ESC/POS a reset, then the
+/// profile's code page and density
+pub fn generate_setup_command(profile: &PrinterProfile) -> Vec<u8> {
+    profile.emulation.commands().setup(profile)
+}
+
+/// Generate cut command: feed past the cutter, then cut as the profile says
+pub fn generate_cut_command(profile: &PrinterProfile) -> Vec<u8> {
+    profile.emulation.commands().cut(&profile.cut)
+}
+
 /// Generate drawer open command
-pub fn generate_drawer_command(pin: u8) -> Vec<u8> {
-    EscPos::open_drawer(pin).to_vec()
+pub fn generate_drawer_command(pin: u8, profile: &PrinterProfile) -> Result<Vec<u8>> {
+    profile.emulation.commands().drawer(pin, &profile.drawer)
+}
+
+/// Builds an ESC/POS byte stream from typed commands.
+///
+/// ```ignore
+/// let data = EscPosBuilder::with_profile(&profile)
+///     .justify(Justify::Center)
+///     .size(2, 2)
+///     .line("ORDER 42")
+///     .reset_style()
+///     .cut(&profile.cut)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct EscPosBuilder {
+    data: Vec<u8>,
+    code_page: CodePage,
+    replacement: char,
+    color: InkColor,
+    emulation: Emulation,
+}
+
+impl Default for EscPosBuilder {
+    fn default() -> Self {
+        Self {
+            data: Vec::new(),
+            code_page: CodePage::default(),
+            replacement: '?',
+            color: InkColor::Black,
+            emulation: Emulation::EscPos,
+        }
+    }
+}
+
+impl EscPosBuilder {
+    /// Start an empty document
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a document with the profile's setup commands (reset, code page,
+    /// density); cuts, drawer kicks and images follow its command set
+    pub fn with_profile(profile: &PrinterProfile) -> Self {
+        let mut builder = Self {
+            code_page: profile.code_page,
+            replacement: profile.replacement,
+            emulation: profile.emulation,
+            ..Self::new()
+        };
+        builder.raw(&generate_setup_command(profile));
+        builder
+    }
+
+    /// Reset the printer (ESC @)
+    pub fn init(&mut self) -> &mut Self {
+        self.raw(EscPos::INIT)
+    }
+
+    /// Append bytes as-is
+    pub fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    /// Select the character table used by following text; `table` overrides
+    /// the Epson `ESC t` number
+    pub fn code_page(&mut self, code_page: CodePage, table: Option<u8>) -> &mut Self {
+        self.code_page = code_page;
+        self.raw(&EscPos::code_page(table.unwrap_or(code_page.table())))
+    }
+
+    /// Append text without a line break, encoded with the current code page
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        let encoded = self.code_page.encode_lossy(text, self.replacement);
+        self.raw(&encoded)
+    }
+
+    /// Append text followed by a line feed
+    pub fn line(&mut self, text: &str) -> &mut Self {
+        self.text(text).raw(EscPos::LF)
+    }
+
+    pub fn bold(&mut self, on: bool) -> &mut Self {
+        self.raw(&self.emulation.commands().bold(on))
+    }
+
+    pub fn underline(&mut self, weight: u8) -> &mut Self {
+        self.raw(&self.emulation.commands().underline(weight))
+    }
+
+    pub fn inverse(&mut self, on: bool) -> &mut Self {
+        self.raw(&self.emulation.commands().inverse(on))
+    }
+
+    pub fn size(&mut self, width: u8, height: u8) -> &mut Self {
+        self.raw(&self.emulation.commands().size(width, height))
+    }
+
+    pub fn justify(&mut self, justify: Justify) -> &mut Self {
+        self.raw(&self.emulation.commands().justify(justify))
+    }
+
+    pub fn font(&mut self, font: Font) -> &mut Self {
+        self.raw(&self.emulation.commands().font(font))
+    }
+
+    /// Black or red ink, for two-colour printers
+    pub fn color(&mut self, color: InkColor) -> &mut Self {
+        self.color = color;
+        self.raw(&self.emulation.commands().color(color))
+    }
+
+    /// Apply every attribute of a text style
+    pub fn style(&mut self, style: &TextStyle) -> &mut Self {
+        self.font(style.font)
+            .justify(style.justify)
+            .size(style.width, style.height)
+            .bold(style.bold)
+            .underline(style.underline)
+            .inverse(style.inverse);
+        // Only sent when red is involved, printers without it may not know ESC r
+        if style.color != self.color {
+            self.color(style.color);
+        }
+        self
+    }
+
+    /// Back to plain, left-aligned font A text
+    pub fn reset_style(&mut self) -> &mut Self {
+        self.style(&TextStyle::default())
+    }
+
+    /// Line spacing in dots, or the printer default when `None`
+    pub fn line_spacing(&mut self, dots: Option<u8>) -> &mut Self {
+        self.raw(&self.emulation.commands().line_spacing(dots))
+    }
+
+    pub fn feed(&mut self, lines: u8) -> &mut Self {
+        self.raw(&self.emulation.commands().feed(lines))
+    }
+
+    pub fn cut(&mut self, cut: &CutOptions) -> &mut Self {
+        self.raw(&self.emulation.commands().cut(cut))
+    }
+
+    pub fn drawer(&mut self, pin: u8, drawer: &DrawerOptions) -> Result<&mut Self> {
+        let command = self.emulation.commands().drawer(pin, drawer)?;
+        Ok(self.raw(&command))
+    }
+
+    pub fn beep(&mut self, times: u8, duration: u8) -> Result<&mut Self> {
+        let command = self.emulation.commands().beep(times, duration)?;
+        Ok(self.raw(&command))
+    }
+
+    /// Print a logo stored in the printer's NV memory, see [`crate::printer::store_logo`]
+    pub fn logo(&mut self, key: &str, profile: &PrinterProfile) -> Result<&mut Self> {
+        if self.emulation != Emulation::EscPos {
+            bail!("Stored logos need an ESC/POS printer");
+        }
+        let Some(stored) = profile.logos.get(key) else {
+            bail!("Logo {:?} is not stored on this printer", key);
+        };
+        let command = match (profile.logo_memory, stored.number) {
+            (LogoMemory::Graphics, _) => logo::print_command(logo::key_code(key)?),
+            (LogoMemory::Legacy, Some(number)) => logo::legacy_print_command(number),
+            (LogoMemory::Legacy, None) => bail!("Logo {:?} is not stored in legacy NV memory", key),
+        };
+        Ok(self.raw(&command))
+    }
+
+    /// Print a bitmap with the profile's image mode
+    pub fn image(&mut self, bitmap: &MonoBitmap, profile: &PrinterProfile) -> &mut Self {
+        self.raw(&encode_bitmap(bitmap, profile))
+    }
+
+    /// Print a barcode with the printer's own encoder (GS k), which takes
+    /// at most 255 bytes of data
+    pub fn barcode_with(&mut self, barcode: &Barcode, options: &BarcodeOptions) -> Result<&mut Self> {
+        let (symbology, payload) = barcode.native();
+        let Ok(len) = u8::try_from(payload.len()) else {
+            bail!("Barcode data is {} bytes, the printer encodes at most 255", payload.len());
+        };
+        let hri = if options.hri { 0x02 } else { 0x00 };
+
+        Ok(self.aligned(options.justify, |builder| {
+            builder
+                .raw(&[0x1D, 0x48, hri]) // GS H: HRI below or off
+                .raw(&[0x1D, 0x68, options.height.max(1)]) // GS h: height in dots
+                .raw(&[0x1D, 0x77, options.module_width.clamp(1, 6)]) // GS w: module width
+                .raw(&[0x1D, 0x6B, symbology, len])
+                .raw(&payload)
+                .raw(EscPos::LF);
+        }))
+    }
+
+    /// Print a QR code with the printer's own encoder (GS ( k), which takes
+    /// at most 65532 bytes of data
+    pub fn qr_with(&mut self, data: &str, options: &QrOptions) -> Result<&mut Self> {
+        let Ok(store_len) = u16::try_from(data.len() + 3) else {
+            bail!("QR code data is {} bytes, the printer encodes at most 65532", data.len());
+        };
+        let model = if options.model == 1 { 0x31 } else { 0x32 };
+        let level = match options.error_correction {
+            ErrorCorrection::Low => 0x30,
+            ErrorCorrection::Medium => 0x31,
+            ErrorCorrection::Quartile => 0x32,
+            ErrorCorrection::High => 0x33,
+        };
+
+        Ok(self.aligned(options.justify, |builder| {
+            builder
+                .raw(&[0x1D, 0x28, 0x6B, 0x04, 0x00, 0x31, 0x41, model, 0x00])
+                .raw(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x43, options.module_size.clamp(1, 16)])
+                .raw(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x45, level])
+                .raw(&[0x1D, 0x28, 0x6B])
+                .raw(&store_len.to_le_bytes())
+                .raw(&[0x31, 0x50, 0x30])
+                .raw(data.as_bytes())
+                .raw(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x51, 0x30]) // print
+                .raw(EscPos::LF);
+        }))
+    }
+
+    /// Print a barcode natively or as an image, as the profile's `barcode_rendering`
+    /// selects; only ESC/POS printers get native barcodes
+    pub fn barcode_for(&mut self, barcode: &Barcode, options: &BarcodeOptions, profile: &PrinterProfile) -> Result<&mut Self> {
+        Ok(match profile.barcode_rendering {
+            BarcodeRendering::Native if self.emulation == Emulation::EscPos => self.barcode_with(barcode, options)?,
+            _ => {
+                let bitmap = barcode::render_barcode(barcode, options, profile)?;
+                self.image(&bitmap, profile)
+            }
+        })
+    }
+
+    /// Print a QR code natively or as an image, as the profile's `barcode_rendering`
+    /// selects; only ESC/POS printers get native QR codes
+    pub fn qr_for(&mut self, data: &str, options: &QrOptions, profile: &PrinterProfile) -> Result<&mut Self> {
+        Ok(match profile.barcode_rendering {
+            BarcodeRendering::Native if self.emulation == Emulation::EscPos => self.qr_with(data, options)?,
+            _ => {
+                let bitmap = barcode::render_qr(data, options, profile)?;
+                self.image(&bitmap, profile)
+            }
+        })
+    }
+
+    /// Run `commands` with the given justification, back to left afterwards
+    fn aligned(&mut self, justify: Justify, commands: impl FnOnce(&mut Self)) -> &mut Self {
+        if justify != Justify::Left {
+            self.justify(justify);
+        }
+        commands(self);
+        if justify != Justify::Left {
+            self.justify(Justify::Left);
+        }
+        self
+    }
+
+    /// The finished byte stream
+    pub fn build(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::barcode::Symbology;
+    use crate::config::CutStyle;
+
+    #[test]
+    fn builder_emits_style_commands() {
+        let data = EscPosBuilder::new()
+            .init()
+            .bold(true)
+            .underline(1)
+            .inverse(true)
+            .size(2, 3)
+            .justify(Justify::Center)
+            .font(Font::B)
+            .line("Hi")
+            .build();
+
+        assert_eq!(
+            data,
+            vec![
+                0x1B, 0x40,
+                0x1B, 0x45, 1,
+                0x1B, 0x2D, 1,
+                0x1D, 0x42, 1,
+                0x1D, 0x21, 0x12,
+                0x1B, 0x61, 1,
+                0x1B, 0x4D, 1,
+                b'H', b'i', 0x0A,
+            ]
+        );
+    }
+
+    #[test]
+    fn fit_trims_collapses_and_places_images() {
+        // 40x100 white image with two 10x10 black squares 50 rows apart
+        let mut gray = image::GrayImage::from_pixel(40, 100, image::Luma([255]));
+        for (x, y) in (0..10).flat_map(|x| (0..10).map(move |y| (x, y))) {
+            gray.put_pixel(15 + x, 10 + y, image::Luma([0]));
+            gray.put_pixel(15 + x, 70 + y, image::Luma([0]));
+        }
+        let image = DynamicImage::ImageLuma8(gray);
+
+        let mut profile = PrinterProfile {
+            dot_width: 64,
+            ..PrinterProfile::default()
+        };
+        assert_eq!(ThermalImage::fit(&image, &profile).width(), 40);
+
+        profile.image = ImageOptions {
+            trim: true,
+            max_blank_rows: 8,
+            align: Justify::Center,
+            upscale: true,
+            max_upscale: 2.0,
+        };
+        let bitmap = ThermalImage::fit(&image, &profile);
+
+        // Trimmed to 10x70, doubled to 20x140, the 100-row gap cut to 8
+        assert_eq!((bitmap.width(), bitmap.height()), (64, 20 + 8 + 20));
+        assert!(bitmap.get(22, 0) && bitmap.get(41, 47));
+        assert!(!bitmap.get(21, 0) && !bitmap.get(42, 0) && !bitmap.get(30, 24));
+    }
+
+    /// Ink in rows 0..30 and 35..60 (a gap too short to skip), then blank
+    /// until 100..130; nothing right of column 70
+    fn banded_bitmap() -> MonoBitmap {
+        let mut bitmap = MonoBitmap::new(100, 130);
+        for y in (0..30).chain(35..60).chain(100..130) {
+            for x in (y % 7..70).step_by(3) {
+                bitmap.set(x, y, true);
+            }
+        }
+        bitmap
+    }
+
+    #[test]
+    fn blank_bands_become_feeds() {
+        let bitmap = banded_bitmap();
+        for (data, height) in [
+            (encode_column(&bitmap), 144),
+            (encode_raster(&bitmap, 16), 130),
+            (encode_graphics(&bitmap, 0), 130),
+        ] {
+            assert!(data.windows(2).any(|w| w == [0x1B, 0x4A]));
+
+            // The printer still puts every dot in the same place
+            let image = crate::emulator::render(&data, 104).image;
+            assert_eq!(image.height(), height);
+            for (x, y, pixel) in image.enumerate_pixels() {
+                assert_eq!(pixel[0] == 0, x < 100 && y < 130 && bitmap.get(x, y), "dot {},{}", x, y);
+            }
+        }
+
+        // The last strip has 10 rows and feeds only those, so a second
+        // copy starts right below the first
+        let twice = [encode_column(&bitmap), encode_column(&bitmap)].concat();
+        let image = crate::emulator::render(&twice, 104).image;
+        for y in 130..260 {
+            for x in 0..100 {
+                assert_eq!(image.get_pixel(x, y)[0] == 0, bitmap.get(x, y - 130), "dot {},{}", x, y);
+            }
+        }
+
+        // 72 of 104 padded columns, the blank strip fed over and the last
+        // 10-row strip fed by its own height
+        assert_eq!(encode_column(&bitmap).len(), 3 + 5 * (5 + 70 * 3 + 1) + 2 + 3 + 2);
+        // Blocks of 16 rows, 9 bytes wide, and one feed of 40 rows
+        let raster = encode_raster(&bitmap, 16);
+        assert_eq!(raster.len(), 4 * 8 + 60 * 9 + 3 + 2 * 8 + 30 * 9);
+        assert_eq!(raster[4..6], [9, 0]);
+    }
+
+    #[test]
+    fn graphics_bands_stay_within_command_limits() {
+        // Narrow enough that the data size alone would allow 8000-row bands
+        let mut bitmap = MonoBitmap::new(64, 4000);
+        for y in 0..4000 {
+            bitmap.set(y % 64, y, true);
+        }
+
+        let data = encode_graphics(&bitmap, 0);
+        let heights: Vec<u32> = data
+            .windows(15)
+            .filter(|w| w[..3] == [0x1D, 0x28, 0x4C] && w[6] == 112)
+            .map(|w| u16::from_le_bytes([w[13], w[14]]) as u32)
+            .collect();
+        assert_eq!(heights, [GRAPHICS_MAX_ROWS, GRAPHICS_MAX_ROWS, 4000 - 2 * GRAPHICS_MAX_ROWS]);
+    }
+
+    /// Compare the bytes sent for typical receipts against full-width bands
+    /// without feeds. Run with `cargo test --release encode_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn encode_benchmark() {
+        let profile = PrinterProfile::default();
+        let lines: String = (1..=25)
+            .map(|i| format!("<tr><td>Item {}</td><td align=right>{}.00</td></tr>", i, i * 3))
+            .collect();
+        let html = format!(
+            "<h2 align=center>VOPECS</h2><p>Order 1042<br>Table 7</p><table width=100%>{}</table><p><br><br><br></p><p align=center>Thank you</p>",
+            lines
+        );
+        let receipt = crate::html::render(&html, &profile);
+
+        // A backend image with a short ticket on a long blank tail
+        let mut tail = image::GrayImage::from_pixel(576, 1800, image::Luma([255]));
+        image::imageops::replace(&mut tail, &crate::text::rasterize("Kitchen ticket\n2x Tea\n1x Cake", &profile), 0, 0);
+
+        for (name, gray) in [("html receipt", receipt), ("blank tail", tail)] {
+            let bitmap = ThermalImage::to_mono(&DynamicImage::ImageLuma8(gray), profile.dot_width, &profile.dithering);
+            let (width, height) = ((bitmap.width() + 7) / 8, bitmap.height());
+            let strips = (height + 23) / 24;
+            let full = [
+                ("column", 5 + strips * (6 + width * 8 * 3)),
+                ("raster", 8 * ((height + 2302) / 2303) + width * height),
+            ];
+
+            for (mode, before) in full {
+                let start = std::time::Instant::now();
+                let data = match mode {
+                    "column" => encode_column(&bitmap),
+                    _ => encode_raster(&bitmap, 0),
+                };
+                let elapsed = start.elapsed();
+                // 115200 baud serial moves about 11.5 KB/s
+                let seconds = |bytes: u32| bytes as f32 / 11_520.0;
+                println!(
+                    "{} ({}x{}) {}: {} -> {} bytes ({:.0}% saved), {:.2}s -> {:.2}s at 115200 baud, encoded in {:?}",
+                    name,
+                    bitmap.width(),
+                    height,
+                    mode,
+                    before,
+                    data.len(),
+                    100.0 - data.len() as f32 * 100.0 / before as f32,
+                    seconds(before),
+                    seconds(data.len() as u32),
+                    elapsed
+                );
+                assert!((data.len() as u32) < before);
+            }
+        }
+    }
+
+    #[test]
+    fn red_text_and_two_color_images() {
+        let red = TextStyle {
+            color: InkColor::Red,
+            ..TextStyle::default()
+        };
+        let data = EscPosBuilder::new().style(&red).line("Nuts").reset_style().build();
+        assert_eq!(data.windows(3).filter(|w| w[..2] == [0x1B, 0x72]).collect::<Vec<_>>(), vec![&[0x1B, 0x72, 1], &[0x1B, 0x72, 0]]);
+
+        // Black square on the left, red square on the right, pink (too pale) below
+        let image = image::RgbImage::from_fn(48, 48, |x, y| match (x / 16, y / 16) {
+            (0, 0) => image::Rgb([0, 0, 0]),
+            (2, 0) => image::Rgb([220, 20, 30]),
+            (1, 2) => image::Rgb([255, 200, 200]),
+            _ => image::Rgb([255, 255, 255]),
+        });
+        let mut profile = PrinterProfile {
+            dot_width: 48,
+            ..PrinterProfile::default()
+        };
+        profile.color.two_color = true;
+
+        let image = DynamicImage::ImageRgb8(image);
+        let (black, red) = ThermalImage::fit_two_color(&image, &profile);
+        assert!(black.get(0, 0) && !black.get(40, 0) && !black.get(20, 40));
+        assert!(red.get(40, 0) && !red.get(0, 0) && !red.get(20, 40));
+
+        let data = ThermalImage::image_to_escpos(&image, &profile).unwrap();
+        let printout = crate::emulator::render(&data, 48);
+        assert_eq!(printout.image.get_pixel(0, 0)[0], 0);
+        assert_eq!(printout.image.get_pixel(40, 0)[0], 96);
+        assert_eq!(printout.image.get_pixel(20, 0)[0], 255);
+    }
+
+    #[test]
+    fn reset_style_restores_defaults() {
+        let data = EscPosBuilder::new().reset_style().build();
+        assert_eq!(
+            data,
+            vec![
+                0x1B, 0x4D, 0,
+                0x1B, 0x61, 0,
+                0x1D, 0x21, 0,
+                0x1B, 0x45, 0,
+                0x1B, 0x2D, 0,
+                0x1D, 0x42, 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn size_is_clamped() {
+        assert_eq!(EscPos::char_size(0, 9), vec![0x1D, 0x21, 0x07]);
+        assert_eq!(EscPos::char_size(8, 1), vec![0x1D, 0x21, 0x70]);
+    }
+
+    #[test]
+    fn cut_and_drawer_follow_profile() {
+        let cut = CutOptions {
+            style: CutStyle::Partial,
+            feed_lines: 2,
+        };
+        let drawer = DrawerOptions {
+            pin: Some(1),
+            pulse_on_ms: 100,
+            pulse_off_ms: 200,
+        };
+
+        let data = EscPosBuilder::new().cut(&cut).drawer(0, &drawer).unwrap().build();
+        assert_eq!(
+            data,
+            vec![0x0A, 0x0A, 0x1D, 0x56, 0x01, 0x1B, 0x70, 1, 50, 100]
+        );
+    }
+
+    #[test]
+    fn qr_stores_then_prints() {
+        let options = QrOptions {
+            module_size: 6,
+            ..QrOptions::default()
+        };
+        let data = EscPosBuilder::new().qr_with("AB", &options).unwrap().build();
+        assert_eq!(
+            data,
+            vec![
+                0x1D, 0x28, 0x6B, 0x04, 0x00, 0x31, 0x41, 0x32, 0x00,
+                0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x43, 6,
+                0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x45, 0x31,
+                0x1D, 0x28, 0x6B, 0x05, 0x00, 0x31, 0x50, 0x30, b'A', b'B',
+                0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x51, 0x30,
+                0x0A,
+            ]
+        );
+    }
+
+    #[test]
+    fn qr_data_must_fit_the_store_command() {
+        let options = QrOptions::default();
+        let data = EscPosBuilder::new().qr_with(&"A".repeat(65532), &options).unwrap().build();
+        assert_eq!(&data[25..28], [0x1D, 0x28, 0x6B]);
+        assert_eq!(&data[28..30], [0xFF, 0xFF]);
+        assert!(EscPosBuilder::new().qr_with(&"A".repeat(65533), &options).is_err());
+    }
+
+    #[test]
+    fn barcode_with_options() {
+        let barcode = Barcode::new(Symbology::UpcA, "03600029145").unwrap();
+        let options = BarcodeOptions {
+            symbology: Symbology::UpcA,
+            height: 50,
+            module_width: 3,
+            hri: false,
+            justify: Justify::Center,
+        };
+        let data = EscPosBuilder::new().barcode_with(&barcode, &options).unwrap().build();
+        assert_eq!(
+            data,
+            [
+                &[0x1B, 0x61, 1][..],
+                &[0x1D, 0x48, 0, 0x1D, 0x68, 50, 0x1D, 0x77, 3, 0x1D, 0x6B, 65, 12],
+                b"036000291452\n",
+                &[0x1B, 0x61, 0],
+            ]
+            .concat()
+        );
+    }
 }
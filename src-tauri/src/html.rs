@@ -0,0 +1,1808 @@
+use image::{imageops::FilterType, GrayImage, Luma};
+use std::collections::HashMap;
+use unicode_bidi::{BidiInfo, Level};
+use crate::config::PrinterProfile;
+use crate::escpos::{Justify, ThermalImage};
+use crate::text::{TextOptions, TextRasterizer};
+
+/// Browser defaults for the supported elements
+const USER_AGENT_CSS: &str = r#"
+head, script, style, title, meta, link, template { display: none }
+html, body, div, p, h1, h2, h3, h4, h5, h6, ul, ol, dl, dt, dd, pre, hr, center,
+header, footer, section, article, main, nav, aside, address, blockquote, figure,
+figcaption, form, fieldset, caption { display: block }
+li { display: list-item }
+table { display: table }
+thead, tbody, tfoot { display: table-row-group }
+tr { display: table-row }
+td, th { display: table-cell; padding: 1px }
+p, ul, ol, dl, pre, blockquote { margin: 1em 0 }
+h1 { font-size: 2em; margin: .67em 0 }
+h2 { font-size: 1.5em; margin: .83em 0 }
+h3 { font-size: 1.17em; margin: 1em 0 }
+h4 { margin: 1.33em 0 }
+h5 { font-size: .83em; margin: 1.67em 0 }
+h6 { font-size: .67em; margin: 2.33em 0 }
+h1, h2, h3, h4, h5, h6, b, strong, th { font-weight: bold }
+u, ins { text-decoration: underline }
+small { font-size: .83em }
+big { font-size: 1.2em }
+center, th { text-align: center }
+pre { white-space: pre }
+pre, code, kbd, samp, tt { font-family: monospace }
+ul, ol { padding-left: 40px }
+dd { margin-left: 40px }
+blockquote { margin-left: 40px; margin-right: 40px }
+hr { margin: .5em 0; border-top: 1px solid }
+"#;
+
+/// Elements without content or end tag
+const VOID_TAGS: &[&str] = &["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "wbr"];
+
+/// Starting one of these closes an open `<p>`
+const CLOSES_P: &[&str] = &[
+    "address", "article", "aside", "blockquote", "center", "div", "dl", "fieldset", "figure", "footer", "form",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "main", "nav", "ol", "p", "pre", "section", "table", "ul",
+];
+
+/// Render an HTML receipt to a black-on-white image `profile.dot_width` wide.
+///
+/// Supports a subset of HTML and CSS: block and inline text, tables with
+/// column widths, font size and weight, monospace text, `text-align`,
+/// borders, `<hr>`, lists and `<img>` with data URLs. CSS comes from `<style>`
+/// blocks (tag, class, id and descendant selectors) and `style` attributes.
+/// CSS pixels are converted to dots at 96 px per inch.
+pub fn render(html: &str, profile: &PrinterProfile) -> GrayImage {
+    let (root, css) = parse(html);
+    let mut layout = Layout::new(profile, &css);
+
+    let width = profile.dot_width as f32;
+    let style = Style::root(16.0 * layout.px);
+    let bottom = layout.children(&root, &style, &mut Vec::new(), 0.0, width, 0.0);
+    layout.paint(profile.dot_width, bottom)
+}
+
+// ---------------------------------------------------------------------------
+// Document tree
+
+#[derive(Debug)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug)]
+struct Element {
+    tag: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn new(tag: &str) -> Self {
+        Self {
+            tag: tag.to_string(),
+            attrs: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Parse HTML into a tree under a synthetic root, plus the text of its `<style>` blocks.
+/// Like a browser, unclosed and mis-nested tags are tolerated.
+fn parse(html: &str) -> (Element, String) {
+    let mut stack = vec![Element::new("#root")];
+    let mut css = String::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if let Some(tag) = rest.strip_prefix("</") {
+            let end = tag.find('>').unwrap_or(tag.len());
+            let name = tag[..end].trim().to_ascii_lowercase();
+            close(&mut stack, &[name.as_str()], &[]);
+            rest = tag.get(end + 1..).unwrap_or("");
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (element, self_closing, after) = start_tag(&rest[1..]);
+            rest = after;
+            open(&mut stack, &element.tag);
+
+            if element.tag == "style" || element.tag == "script" || element.tag == "title" {
+                // Raw text up to the end tag
+                let end_tag = format!("</{}", element.tag);
+                let end = rest.to_ascii_lowercase().find(&end_tag).unwrap_or(rest.len());
+                if element.tag == "style" {
+                    css.push_str(&rest[..end]);
+                    css.push('\n');
+                }
+                rest = &rest[end..];
+                rest = rest.find('>').map_or("", |close| &rest[close + 1..]);
+                push(&mut stack, Node::Element(element));
+            } else if self_closing || VOID_TAGS.contains(&element.tag.as_str()) {
+                push(&mut stack, Node::Element(element));
+            } else {
+                stack.push(element);
+            }
+        } else {
+            let skip = rest.chars().next().map_or(1, char::len_utf8);
+            let end = rest[skip..].find('<').map_or(rest.len(), |i| i + skip);
+            let text = decode_entities(&rest[..end]);
+            match stack.last_mut().and_then(|e| e.children.last_mut()) {
+                Some(Node::Text(previous)) => previous.push_str(&text),
+                _ => push(&mut stack, Node::Text(text)),
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    while stack.len() > 1 {
+        let element = stack.pop().unwrap();
+        push(&mut stack, Node::Element(element));
+    }
+    (stack.pop().unwrap(), css)
+}
+
+/// Parse a start tag after its `<`: the element, whether it ended with `/>`, and the rest
+fn start_tag(input: &str) -> (Element, bool, &str) {
+    let name_end = input
+        .find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+        .unwrap_or(input.len());
+    let mut element = Element::new(&input[..name_end].to_ascii_lowercase());
+    let mut rest = &input[name_end..];
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return (element, true, after);
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return (element, false, after);
+        }
+        if let Some(after) = rest.strip_prefix('/') {
+            rest = after;
+            continue;
+        }
+        if rest.is_empty() {
+            return (element, false, rest);
+        }
+
+        let name_end = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '>' || c == '/')
+            .unwrap_or(rest.len())
+            .max(1);
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    let end = inner.find(quote).unwrap_or(inner.len());
+                    (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after
+                        .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                        .unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_entities(raw);
+            rest = remaining;
+        }
+        element.attrs.push((name, value));
+    }
+}
+
+/// Close elements implied by starting `tag`, e.g. an open `<td>` when the next one starts
+fn open(stack: &mut Vec<Element>, tag: &str) {
+    if CLOSES_P.contains(&tag) {
+        close(stack, &["p"], &["td", "th", "table", "li"]);
+    }
+    match tag {
+        "li" => close(stack, &["li"], &["ul", "ol", "table"]),
+        "dt" | "dd" => close(stack, &["dt", "dd"], &["dl", "table"]),
+        "td" | "th" => close(stack, &["td", "th"], &["tr", "table"]),
+        "tr" => close(stack, &["tr"], &["table"]),
+        "thead" | "tbody" | "tfoot" => close(stack, &["thead", "tbody", "tfoot"], &["table"]),
+        _ => {}
+    }
+}
+
+/// Close the innermost open element named in `tags`, unless a `boundary` element comes first
+fn close(stack: &mut Vec<Element>, tags: &[&str], boundary: &[&str]) {
+    let found = stack.iter().rposition(|e| {
+        tags.contains(&e.tag.as_str()) || boundary.contains(&e.tag.as_str()) || e.tag == "#root"
+    });
+    let Some(index) = found.filter(|&i| i > 0 && tags.contains(&stack[i].tag.as_str())) else {
+        return;
+    };
+
+    while stack.len() > index {
+        let element = stack.pop().unwrap();
+        push(stack, Node::Element(element));
+    }
+}
+
+fn push(stack: &mut [Element], node: Node) {
+    if let Some(parent) = stack.last_mut() {
+        parent.children.push(node);
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest.find(';').filter(|&end| end <= 10);
+        let decoded = end.and_then(|end| {
+            let name = &rest[1..end];
+            let c = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = name.strip_prefix('#') {
+                dec.parse().ok().and_then(char::from_u32)
+            } else {
+                named_entity(name)
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                if c != '\u{AD}' {
+                    out.push(c);
+                }
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    Some(match name {
+        "nbsp" => '\u{A0}',
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "times" => '×',
+        "divide" => '÷',
+        "euro" => '€',
+        "pound" => '£',
+        "yen" => '¥',
+        "cent" => '¢',
+        "deg" => '°',
+        "plusmn" => '±',
+        "middot" => '·',
+        "bull" => '•',
+        "hellip" => '…',
+        "ndash" => '–',
+        "mdash" => '—',
+        "laquo" => '«',
+        "raquo" => '»',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "frac12" => '½',
+        "frac14" => '¼',
+        "frac34" => '¾',
+        "sect" => '§',
+        "para" => '¶',
+        "shy" => '\u{AD}',
+        "zwnj" => '\u{200C}',
+        "zwj" => '\u{200D}',
+        "lrm" => '\u{200E}',
+        "rlm" => '\u{200F}',
+        _ => return None,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Style
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Length {
+    Dots(f32),
+    Em(f32),
+    Percent(f32),
+}
+
+impl Length {
+    /// Parse a CSS length; unitless numbers are pixels, as in HTML attributes
+    fn parse(value: &str, px: f32) -> Option<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        let split = value
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(split);
+        let n: f32 = number.parse().ok()?;
+
+        Some(match unit.trim() {
+            "" | "px" => Length::Dots(n * px),
+            "pt" => Length::Dots(n * px * 96.0 / 72.0),
+            "mm" => Length::Dots(n * px * 96.0 / 25.4),
+            "cm" => Length::Dots(n * px * 96.0 / 2.54),
+            "in" => Length::Dots(n * px * 96.0),
+            "em" | "rem" => Length::Em(n),
+            "%" => Length::Percent(n),
+            _ => return None,
+        })
+    }
+
+    fn resolve(self, font_size: f32, reference: f32) -> f32 {
+        match self {
+            Length::Dots(d) => d,
+            Length::Em(e) => e * font_size,
+            Length::Percent(p) => p / 100.0 * reference,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Display {
+    Block,
+    Inline,
+    None,
+    ListItem,
+    Table,
+    RowGroup,
+    Row,
+    Cell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineStyle {
+    None,
+    Solid,
+    Dashed,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Border {
+    width: f32,
+    style: LineStyle,
+}
+
+impl Border {
+    fn width(&self) -> f32 {
+        if self.style == LineStyle::None { 0.0 } else { self.width }
+    }
+}
+
+/// Computed style of an element. Sides are top, right, bottom, left.
+#[derive(Debug, Clone)]
+struct Style {
+    // Inherited
+    font_size: f32,
+    bold: bool,
+    monospace: bool,
+    underline: bool,
+    align: Option<Justify>,
+    rtl: Option<bool>,
+    uppercase: bool,
+    pre: bool,
+    // Not inherited
+    display: Display,
+    margin: [f32; 4],
+    /// `margin-left` and `margin-right` are both `auto`
+    centered: bool,
+    padding: [f32; 4],
+    border: [Border; 4],
+    width: Option<Length>,
+    max_width: Option<Length>,
+    height: Option<Length>,
+}
+
+impl Style {
+    fn root(font_size: f32) -> Self {
+        Self {
+            font_size,
+            bold: false,
+            monospace: false,
+            underline: false,
+            align: None,
+            rtl: None,
+            uppercase: false,
+            pre: false,
+            display: Display::Block,
+            margin: [0.0; 4],
+            centered: false,
+            padding: [0.0; 4],
+            border: [Border { width: 0.0, style: LineStyle::None }; 4],
+            width: None,
+            max_width: None,
+            height: None,
+        }
+    }
+
+    /// Style of a child before its own rules apply
+    fn inherit(&self) -> Self {
+        Self {
+            display: Display::Inline,
+            ..Style::root(self.font_size)
+        }
+        .with_inherited(self)
+    }
+
+    fn with_inherited(mut self, parent: &Style) -> Self {
+        self.bold = parent.bold;
+        self.monospace = parent.monospace;
+        self.underline = parent.underline;
+        self.align = parent.align;
+        self.rtl = parent.rtl;
+        self.uppercase = parent.uppercase;
+        self.pre = parent.pre;
+        self
+    }
+
+    /// Apply declarations in cascade order
+    fn apply(&mut self, declarations: &[(String, String)], parent_size: f32, px: f32, page_width: f32) {
+        // Font size first, `em` lengths below depend on it
+        for (property, value) in declarations {
+            match property.as_str() {
+                "font-size" => {
+                    if let Some(size) = font_size(value, parent_size, px) {
+                        self.font_size = size;
+                    }
+                }
+                "font" => {
+                    for token in value.split_ascii_whitespace() {
+                        let token = token.split('/').next().unwrap_or(token);
+                        if let Some(size) = font_size(token, parent_size, px) {
+                            self.font_size = size;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let length = |v: &str| Length::parse(v, px);
+        let resolve = |v: &str, size: f32| length(v).map(|l| l.resolve(size, page_width));
+        for (property, value) in declarations {
+            let value = value.as_str();
+            let lower = value.to_ascii_lowercase();
+            let size = self.font_size;
+
+            match property.as_str() {
+                "display" => {
+                    self.display = match lower.as_str() {
+                        "none" => Display::None,
+                        "inline" | "inline-block" | "inline-flex" => Display::Inline,
+                        "list-item" => Display::ListItem,
+                        "table" | "inline-table" => Display::Table,
+                        "table-row-group" | "table-header-group" | "table-footer-group" => Display::RowGroup,
+                        "table-row" => Display::Row,
+                        "table-cell" => Display::Cell,
+                        _ => Display::Block,
+                    }
+                }
+                "font-weight" => {
+                    self.bold = match lower.as_str() {
+                        "bold" | "bolder" => true,
+                        "normal" | "lighter" => false,
+                        n => n.parse::<u32>().map_or(self.bold, |w| w >= 600),
+                    }
+                }
+                "font-family" => self.monospace = is_monospace(&lower),
+                "font" => {
+                    self.bold = lower.split_ascii_whitespace().any(|t| t == "bold" || t.parse::<u32>().is_ok_and(|w| w >= 600));
+                    self.monospace = is_monospace(&lower);
+                }
+                "text-align" => {
+                    self.align = match lower.as_str() {
+                        "center" => Some(Justify::Center),
+                        "right" => Some(Justify::Right),
+                        "end" => Some(if self.rtl == Some(true) { Justify::Left } else { Justify::Right }),
+                        "left" | "justify" => Some(Justify::Left),
+                        _ => None,
+                    }
+                }
+                "direction" => self.rtl = Some(lower == "rtl"),
+                "text-decoration" | "text-decoration-line" => self.underline = lower.contains("underline"),
+                "text-transform" => self.uppercase = lower == "uppercase",
+                "white-space" => self.pre = lower.starts_with("pre"),
+                "width" => self.width = length(value),
+                "max-width" => self.max_width = length(value),
+                "height" => self.height = length(value),
+                "margin" | "padding" => {
+                    let values: Vec<&str> = value.split_ascii_whitespace().collect();
+                    let sides = expand_sides(&values);
+                    if property == "margin" {
+                        self.centered = sides[1] == "auto" && sides[3] == "auto";
+                        self.margin = sides.map(|v| resolve(v, size).unwrap_or(0.0));
+                    } else {
+                        self.padding = sides.map(|v| resolve(v, size).unwrap_or(0.0));
+                    }
+                }
+                "border" => {
+                    let border = parse_border(&lower, px);
+                    self.border = [border; 4];
+                }
+                "border-width" | "border-style" => {
+                    let values: Vec<&str> = lower.split_ascii_whitespace().collect();
+                    for (border, value) in self.border.iter_mut().zip(expand_sides(&values)) {
+                        set_border_part(border, value, px);
+                    }
+                }
+                property => {
+                    let Some((side, part)) = border_side(property) else {
+                        if let Some(side) = box_side(property, "margin-") {
+                            self.margin[side] = resolve(value, size).unwrap_or(0.0);
+                        } else if let Some(side) = box_side(property, "padding-") {
+                            self.padding[side] = resolve(value, size).unwrap_or(0.0);
+                        }
+                        continue;
+                    };
+                    match part {
+                        "" => self.border[side] = parse_border(&lower, px),
+                        _ => set_border_part(&mut self.border[side], &lower, px),
+                    }
+                }
+            }
+        }
+    }
+
+    fn horizontal_extra(&self) -> f32 {
+        self.padding[1] + self.padding[3] + self.border[1].width() + self.border[3].width()
+    }
+}
+
+fn font_size(value: &str, parent: f32, px: f32) -> Option<f32> {
+    let medium = 16.0 * px;
+    let keyword = match value.trim().to_ascii_lowercase().as_str() {
+        "xx-small" => Some(medium * 0.6),
+        "x-small" => Some(medium * 0.75),
+        "small" => Some(medium * 0.89),
+        "medium" => Some(medium),
+        "large" => Some(medium * 1.2),
+        "x-large" => Some(medium * 1.5),
+        "xx-large" => Some(medium * 2.0),
+        "smaller" => Some(parent / 1.2),
+        "larger" => Some(parent * 1.2),
+        _ => None,
+    };
+    keyword.or_else(|| Length::parse(value, px).map(|l| l.resolve(parent, parent)))
+}
+
+fn is_monospace(family: &str) -> bool {
+    ["mono", "courier", "consolas", "menlo", "fixed"].iter().any(|m| family.contains(m))
+}
+
+/// Expand 1-4 CSS values to top, right, bottom, left
+fn expand_sides<'v>(values: &[&'v str]) -> [&'v str; 4] {
+    match values {
+        [all] => [all, all, all, all],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left, ..] => [top, right, bottom, left],
+        [] => ["0"; 4],
+    }
+}
+
+fn box_side(property: &str, prefix: &str) -> Option<usize> {
+    match property.strip_prefix(prefix)? {
+        "top" => Some(0),
+        "right" => Some(1),
+        "bottom" => Some(2),
+        "left" => Some(3),
+        _ => None,
+    }
+}
+
+/// `border-top` -> (0, ""), `border-left-style` -> (3, "style")
+fn border_side(property: &str) -> Option<(usize, &str)> {
+    let rest = property.strip_prefix("border-")?;
+    let (side, part) = rest.split_once('-').unwrap_or((rest, ""));
+    let index = box_side(&format!("x-{}", side), "x-")?;
+    matches!(part, "" | "width" | "style").then_some((index, part))
+}
+
+/// Parse a border shorthand like `1px dashed #000`; colours are ignored
+fn parse_border(value: &str, px: f32) -> Border {
+    let mut border = Border {
+        width: 3.0 * px,
+        style: LineStyle::None,
+    };
+    for token in value.split_ascii_whitespace() {
+        set_border_part(&mut border, token, px);
+    }
+    border
+}
+
+fn set_border_part(border: &mut Border, token: &str, px: f32) {
+    match token {
+        "none" | "hidden" => border.style = LineStyle::None,
+        "solid" | "double" | "groove" | "ridge" | "inset" | "outset" => border.style = LineStyle::Solid,
+        "dashed" | "dotted" => border.style = LineStyle::Dashed,
+        "thin" => border.width = px,
+        "medium" => border.width = 3.0 * px,
+        "thick" => border.width = 5.0 * px,
+        token => {
+            if let Some(Length::Dots(width)) = Length::parse(token, px) {
+                // Keep hairlines visible on the printer
+                border.width = if width > 0.0 { width.max(1.0) } else { 0.0 };
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Selectors
+
+/// Tag, id and classes of an element, what selectors match against
+#[derive(Debug, Clone)]
+struct Key {
+    tag: String,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Key {
+    fn of(element: &Element) -> Self {
+        Self {
+            tag: element.tag.clone(),
+            id: element.attr("id").map(str::to_string),
+            classes: element
+                .attr("class")
+                .map(|c| c.split_ascii_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// `tag.class#id`, any part optional
+#[derive(Debug)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Compound {
+    fn parse(text: &str) -> Option<Self> {
+        if text.contains([':', '[', '+', '~']) {
+            return None;
+        }
+
+        let mut compound = Compound {
+            tag: None,
+            id: None,
+            classes: Vec::new(),
+        };
+        let mut parts = text.split_inclusive(['.', '#']).peekable();
+        let mut prefix = ' ';
+        while let Some(part) = parts.next() {
+            let (name, next) = match part.chars().last() {
+                Some(c @ ('.' | '#')) if parts.peek().is_some() || part.len() > 1 => (&part[..part.len() - 1], c),
+                _ => (part, ' '),
+            };
+            match prefix {
+                '.' => compound.classes.push(name.to_string()),
+                '#' => compound.id = Some(name.to_string()),
+                _ if !name.is_empty() && name != "*" => compound.tag = Some(name.to_ascii_lowercase()),
+                _ => {}
+            }
+            prefix = next;
+        }
+        Some(compound)
+    }
+
+    fn matches(&self, key: &Key) -> bool {
+        self.tag.as_ref().map_or(true, |t| *t == key.tag)
+            && self.id.as_ref().map_or(true, |id| key.id.as_ref() == Some(id))
+            && self.classes.iter().all(|c| key.classes.contains(c))
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    /// Descendant chain, the last compound matches the element itself
+    selector: Vec<Compound>,
+    specificity: (usize, usize, usize),
+    declarations: Vec<(String, String)>,
+}
+
+impl Rule {
+    fn matches(&self, key: &Key, ancestors: &[Key]) -> bool {
+        let Some((last, rest)) = self.selector.split_last() else {
+            return false;
+        };
+        if !last.matches(key) {
+            return false;
+        }
+
+        // Each remaining compound must match some ancestor, innermost first
+        let mut ancestors = ancestors.iter().rev();
+        rest.iter()
+            .rev()
+            .all(|compound| ancestors.any(|a| compound.matches(a)))
+    }
+}
+
+fn declarations(text: &str) -> Vec<(String, String)> {
+    text.split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let value = value.trim().trim_end_matches("!important").trim();
+            Some((property.trim().to_ascii_lowercase(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Parse a style sheet; `@media print` and `@media all` blocks are included,
+/// other at-rules skipped
+fn parse_css(css: &str, rules: &mut Vec<Rule>) {
+    let mut css = css.to_string();
+    while let Some(start) = css.find("/*") {
+        let end = css[start..].find("*/").map_or(css.len(), |e| start + e + 2);
+        css.replace_range(start..end, "");
+    }
+
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find('{') {
+        let prelude = rest[..open].trim();
+        let body_start = open + 1;
+
+        if let Some(at_rule) = prelude.strip_prefix('@') {
+            // Find the matching brace of the block
+            let mut depth = 1;
+            let mut end = rest.len();
+            for (i, c) in rest[body_start..].char_indices() {
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = body_start + i;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            let media = at_rule.to_ascii_lowercase();
+            if media.starts_with("media") && (media.contains("print") || media.contains("all")) {
+                parse_css(&rest[body_start..end], rules);
+            }
+            rest = rest.get(end + 1..).unwrap_or("");
+            continue;
+        }
+
+        let close = rest[body_start..].find('}').map_or(rest.len(), |c| body_start + c);
+        let body = declarations(&rest[body_start..close]);
+        for selector in prelude.split(',') {
+            let compounds: Option<Vec<Compound>> = selector
+                .split(|c: char| c.is_ascii_whitespace() || c == '>')
+                .filter(|s| !s.is_empty())
+                .map(Compound::parse)
+                .collect();
+            let Some(selector) = compounds.filter(|c| !c.is_empty()) else {
+                continue;
+            };
+            let specificity = selector.iter().fold((0, 0, 0), |(a, b, c), s| {
+                (a + s.id.is_some() as usize, b + s.classes.len(), c + s.tag.is_some() as usize)
+            });
+            rules.push(Rule {
+                selector,
+                specificity,
+                declarations: body.clone(),
+            });
+        }
+        rest = rest.get(close + 1..).unwrap_or("");
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Layout
+
+/// Rasterizer cache key: font size in half dots, bold, monospace
+type FontKey = (u32, bool, bool);
+
+enum Op {
+    Text { font: FontKey, text: String, x: f32, baseline: f32 },
+    Rect { x: f32, y: f32, width: f32, height: f32, dashed: bool },
+    Image { x: f32, y: f32, image: GrayImage },
+}
+
+enum Atom {
+    Word { text: String, font: FontKey, underline: bool, space: bool },
+    Image { image: GrayImage, space: bool },
+    Break,
+}
+
+struct Layout {
+    /// Dots per CSS pixel
+    px: f32,
+    page_width: f32,
+    user_agent: Vec<Rule>,
+    author: Vec<Rule>,
+    /// Presentational hints a `<table>` passes to its cells (`border`, `cellpadding`)
+    cell_hints: Vec<Vec<(String, String)>>,
+    fonts: HashMap<FontKey, TextRasterizer>,
+    images: HashMap<String, Option<GrayImage>>,
+    ops: Vec<Op>,
+}
+
+impl Layout {
+    fn new(profile: &PrinterProfile, css: &str) -> Self {
+        let mut user_agent = Vec::new();
+        parse_css(USER_AGENT_CSS, &mut user_agent);
+        let mut author = Vec::new();
+        parse_css(css, &mut author);
+        // Later rules win among equal specificity; the sort is stable
+        author.sort_by_key(|r| r.specificity);
+
+        Self {
+            px: profile.dpi.max(1) as f32 / 96.0,
+            page_width: profile.dot_width as f32,
+            user_agent,
+            author,
+            cell_hints: Vec::new(),
+            fonts: HashMap::new(),
+            images: HashMap::new(),
+            ops: Vec::new(),
+        }
+    }
+
+    fn compute(&self, element: &Element, parent: &Style, ancestors: &[Key]) -> Style {
+        let key = Key::of(element);
+        let mut cascade: Vec<(String, String)> = Vec::new();
+
+        for rule in self.user_agent.iter().filter(|r| r.matches(&key, ancestors)) {
+            cascade.extend(rule.declarations.iter().cloned());
+        }
+        cascade.extend(self.hints(element));
+        for rule in self.author.iter().filter(|r| r.matches(&key, ancestors)) {
+            cascade.extend(rule.declarations.iter().cloned());
+        }
+        if let Some(inline) = element.attr("style") {
+            cascade.extend(declarations(inline));
+        }
+
+        let mut style = parent.inherit();
+        style.apply(&cascade, parent.font_size, self.px, self.page_width);
+        style
+    }
+
+    /// Presentational attributes as CSS declarations
+    fn hints(&self, element: &Element) -> Vec<(String, String)> {
+        let mut hints = Vec::new();
+        let mut hint = |property: &str, value: String| hints.push((property.to_string(), value));
+
+        if let Some(align) = element.attr("align").filter(|_| element.tag != "table" && element.tag != "img") {
+            hint("text-align", align.to_string());
+        }
+        if let Some(width) = element.attr("width") {
+            hint("width", width.to_string());
+        }
+        if let Some(height) = element.attr("height") {
+            hint("height", height.to_string());
+        }
+        if let Some(dir) = element.attr("dir") {
+            hint("direction", dir.to_string());
+        }
+        match element.tag.as_str() {
+            "table" => {
+                if let Some(border) = element.attr("border").and_then(|b| b.trim().parse::<f32>().ok()) {
+                    if border > 0.0 {
+                        hint("border", format!("{}px solid", border));
+                    }
+                }
+            }
+            "td" | "th" => {
+                if let Some(table) = self.cell_hints.last() {
+                    hints.extend(table.iter().cloned());
+                }
+            }
+            "font" => {
+                if let Some(size) = element.attr("size").and_then(|s| s.trim().parse::<i32>().ok()) {
+                    let px = [10, 13, 16, 18, 24, 32, 48][(size.clamp(1, 7) - 1) as usize];
+                    hints.push(("font-size".to_string(), format!("{}px", px)));
+                }
+            }
+            _ => {}
+        }
+        hints
+    }
+
+    fn font(&mut self, key: FontKey) -> &TextRasterizer {
+        self.fonts.entry(key).or_insert_with(|| {
+            TextRasterizer::new(TextOptions {
+                size: key.0 as f32 / 2.0,
+                stretch: 1.0,
+                bold: key.1,
+                monospace: key.2,
+                justify: None,
+            })
+        })
+    }
+
+    fn font_key(style: &Style) -> FontKey {
+        ((style.font_size * 2.0).round().max(2.0) as u32, style.bold, style.monospace)
+    }
+
+    /// Lay out an element's children in a content box, returning its bottom
+    fn children(&mut self, element: &Element, style: &Style, path: &mut Vec<Key>, x: f32, width: f32, y: f32) -> f32 {
+        let mut y = y;
+        let mut margin = 0.0f32;
+        let mut inline: Vec<&Node> = Vec::new();
+        let mut item = 0;
+
+        for child in &element.children {
+            let Node::Element(child_element) = child else {
+                inline.push(child);
+                continue;
+            };
+            let child_style = self.compute(child_element, style, path);
+            match child_style.display {
+                Display::None => continue,
+                Display::Inline => {
+                    inline.push(child);
+                    continue;
+                }
+                _ => {}
+            }
+
+            y = self.inline(&inline, style, path, x, width, y, &mut margin);
+            inline.clear();
+
+            y += margin.max(child_style.margin[0]);
+            let marker = (child_style.display == Display::ListItem).then(|| {
+                item += 1;
+                if element.tag == "ol" { format!("{}.", item) } else { "•".to_string() }
+            });
+
+            path.push(Key::of(element));
+            let bottom = self.block(child_element, &child_style, path, x, width, y);
+            path.pop();
+
+            if let Some(marker) = marker {
+                let font = Self::font_key(&child_style);
+                let rasterizer = self.font(font);
+                let marker_x = x + child_style.margin[3] - rasterizer.measure(&marker) - rasterizer.measure(" ");
+                let baseline = y + child_style.border[0].width() + child_style.padding[0] + rasterizer.ascent();
+                self.ops.push(Op::Text { font, text: marker, x: marker_x.max(0.0), baseline });
+            }
+
+            y = bottom;
+            margin = child_style.margin[2];
+        }
+
+        y = self.inline(&inline, style, path, x, width, y, &mut margin);
+        y + margin
+    }
+
+    /// Lay out a block-level element with its top border edge at `y`, returning its bottom
+    fn block(&mut self, element: &Element, style: &Style, path: &mut Vec<Key>, x: f32, width: f32, y: f32) -> f32 {
+        let available = (width - style.margin[1] - style.margin[3]).max(1.0);
+        let mut box_width = available;
+        if let Some(w) = style.width {
+            box_width = w.resolve(style.font_size, width) + style.horizontal_extra();
+        }
+        if let Some(max) = style.max_width {
+            box_width = box_width.min(max.resolve(style.font_size, width) + style.horizontal_extra());
+        }
+        box_width = box_width.clamp(1.0, available);
+
+        let box_x = if style.centered {
+            x + (width - box_width) / 2.0
+        } else {
+            x + style.margin[3]
+        };
+        let content_x = box_x + style.border[3].width() + style.padding[3];
+        let content_width = (box_width - style.horizontal_extra()).max(1.0);
+        let content_y = y + style.border[0].width() + style.padding[0];
+
+        let content_bottom = if style.display == Display::Table {
+            self.table(element, style, path, content_x, content_width, content_y)
+        } else {
+            self.children(element, style, path, content_x, content_width, content_y)
+        };
+
+        let bottom = content_bottom + style.padding[2] + style.border[2].width();
+        self.borders(box_x, y, box_width, bottom - y, &style.border);
+        bottom
+    }
+
+    fn table(&mut self, table: &Element, style: &Style, path: &mut Vec<Key>, x: f32, width: f32, y: f32) -> f32 {
+        struct Cell<'e> {
+            element: &'e Element,
+            style: Style,
+            path: Vec<Key>,
+            column: usize,
+            span: usize,
+        }
+
+        // Cells inherit borders and padding from the table's attributes
+        let mut cell_hints = Vec::new();
+        if table.attr("border").and_then(|b| b.trim().parse::<f32>().ok()).is_some_and(|b| b > 0.0) {
+            cell_hints.push(("border".to_string(), "1px solid".to_string()));
+        }
+        if let Some(padding) = table.attr("cellpadding") {
+            cell_hints.push(("padding".to_string(), padding.to_string()));
+        }
+        self.cell_hints.push(cell_hints);
+
+        path.push(Key::of(table));
+        let mut rows: Vec<(Style, Vec<Cell>)> = Vec::new();
+        let mut row_elements: Vec<(&Element, Style, Vec<Key>)> = Vec::new();
+        for child in &table.children {
+            let Node::Element(child) = child else {
+                continue;
+            };
+            let child_style = self.compute(child, style, path);
+            match child_style.display {
+                Display::Row => row_elements.push((child, child_style, path.clone())),
+                Display::RowGroup => {
+                    path.push(Key::of(child));
+                    for row in &child.children {
+                        if let Node::Element(row) = row {
+                            let row_style = self.compute(row, &child_style, path);
+                            if row_style.display == Display::Row {
+                                row_elements.push((row, row_style, path.clone()));
+                            }
+                        }
+                    }
+                    path.pop();
+                }
+                _ => {}
+            }
+        }
+
+        let mut columns = 0;
+        for (row, row_style, row_path) in row_elements {
+            let mut cell_path = row_path;
+            cell_path.push(Key::of(row));
+            let mut cells = Vec::new();
+            let mut column = 0;
+            for cell in &row.children {
+                let Node::Element(cell) = cell else {
+                    continue;
+                };
+                let cell_style = self.compute(cell, &row_style, &cell_path);
+                if cell_style.display == Display::None {
+                    continue;
+                }
+                let span = cell
+                    .attr("colspan")
+                    .and_then(|s| s.trim().parse::<usize>().ok())
+                    .unwrap_or(1)
+                    .clamp(1, 64);
+                cells.push(Cell {
+                    element: cell,
+                    style: cell_style,
+                    path: cell_path.clone(),
+                    column,
+                    span,
+                });
+                column += span;
+            }
+            columns = columns.max(column);
+            rows.push((row_style, cells));
+        }
+        path.pop();
+
+        // Column widths: explicit widths first, the rest shared by content width
+        let mut fixed: Vec<Option<f32>> = vec![None; columns];
+        let mut natural = vec![0.0f32; columns];
+        let mut minimum = vec![0.0f32; columns];
+        for (_, cells) in &rows {
+            for cell in cells.iter().filter(|c| c.span == 1) {
+                let extra = cell.style.horizontal_extra();
+                if let Some(w) = cell.style.width {
+                    let w = w.resolve(cell.style.font_size, width) + extra;
+                    fixed[cell.column] = Some(fixed[cell.column].map_or(w, |f: f32| f.max(w)));
+                }
+                let (min, max) = self.content_widths(cell.element, &cell.style, &mut cell.path.clone());
+                natural[cell.column] = natural[cell.column].max(max + extra);
+                minimum[cell.column] = minimum[cell.column].max(min + extra);
+            }
+        }
+        let widths = distribute(width, &fixed, &natural, &minimum);
+
+        let mut y = y;
+        for (row_style, cells) in &rows {
+            let top = y;
+            let mut bottom = top;
+            for cell in cells {
+                let cell_x = x + widths[..cell.column].iter().sum::<f32>();
+                let cell_width: f32 = widths[cell.column..(cell.column + cell.span).min(columns)].iter().sum();
+                let s = &cell.style;
+                let content_x = cell_x + s.border[3].width() + s.padding[3];
+                let content_width = (cell_width - s.horizontal_extra()).max(1.0);
+                let content_y = top + s.border[0].width() + s.padding[0];
+                let content_bottom = self.children(cell.element, s, &mut cell.path.clone(), content_x, content_width, content_y);
+                bottom = bottom.max(content_bottom + s.padding[2] + s.border[2].width());
+            }
+            for cell in cells {
+                let cell_x = x + widths[..cell.column].iter().sum::<f32>();
+                let cell_width: f32 = widths[cell.column..(cell.column + cell.span).min(columns)].iter().sum();
+                self.borders(cell_x, top, cell_width, bottom - top, &cell.style.border);
+            }
+            self.borders(x, top, width, bottom - top, &row_style.border);
+            y = bottom;
+        }
+
+        self.cell_hints.pop();
+        y
+    }
+
+    /// Narrowest and widest an element's content can be laid out: its longest
+    /// word and its longest unbroken line
+    fn content_widths(&mut self, element: &Element, style: &Style, path: &mut Vec<Key>) -> (f32, f32) {
+        let mut atoms = Vec::new();
+        path.push(Key::of(element));
+        for child in &element.children {
+            self.collect(child, style, path, &mut atoms);
+        }
+        path.pop();
+
+        let (mut min, mut max, mut line) = (0.0f32, 0.0f32, 0.0f32);
+        for atom in &atoms {
+            let (width, space) = match atom {
+                Atom::Break => {
+                    max = max.max(line);
+                    line = 0.0;
+                    continue;
+                }
+                Atom::Word { text, font, space, .. } => {
+                    let rasterizer = self.font(*font);
+                    (rasterizer.measure(text), if *space { rasterizer.measure(" ") } else { 0.0 })
+                }
+                Atom::Image { image, .. } => (image.width() as f32, 0.0),
+            };
+            min = min.max(width);
+            line += width + space;
+        }
+        (min, max.max(line))
+    }
+
+    /// Gather inline content into words, images and line breaks
+    fn collect(&mut self, node: &Node, style: &Style, path: &mut Vec<Key>, atoms: &mut Vec<Atom>) {
+        let element = match node {
+            Node::Text(text) => return self.words(text, style, atoms),
+            Node::Element(element) => element,
+        };
+        let element_style = self.compute(element, style, path);
+        if element_style.display == Display::None {
+            return;
+        }
+
+        match element.tag.as_str() {
+            "br" => atoms.push(Atom::Break),
+            "img" => {
+                if let Some(image) = self.image(element, &element_style) {
+                    atoms.push(Atom::Image { image, space: false });
+                }
+            }
+            _ => {
+                // Block content inside inline content sits on its own lines
+                let block = element_style.display != Display::Inline;
+                if block && !matches!(atoms.last(), None | Some(Atom::Break)) {
+                    atoms.push(Atom::Break);
+                }
+                path.push(Key::of(element));
+                for child in &element.children {
+                    self.collect(child, &element_style, path, atoms);
+                }
+                path.pop();
+                if block {
+                    atoms.push(Atom::Break);
+                }
+            }
+        }
+    }
+
+    fn words(&mut self, text: &str, style: &Style, atoms: &mut Vec<Atom>) {
+        let font = Self::font_key(style);
+        let word = |text: &str, space: bool| Atom::Word {
+            text: if style.uppercase { text.to_uppercase() } else { text.to_string() },
+            font,
+            underline: style.underline,
+            space,
+        };
+
+        if style.pre {
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    atoms.push(Atom::Break);
+                }
+                let line = line.trim_end_matches('\r').replace('\t', "        ");
+                if !line.is_empty() {
+                    atoms.push(word(&line, false));
+                }
+            }
+            return;
+        }
+
+        if text.starts_with(|c: char| c.is_ascii_whitespace()) {
+            if let Some(Atom::Word { space, .. } | Atom::Image { space, .. }) = atoms.last_mut() {
+                *space = true;
+            }
+        }
+        let mut words = text.split_ascii_whitespace().peekable();
+        while let Some(w) = words.next() {
+            let space = words.peek().is_some() || text.ends_with(|c: char| c.is_ascii_whitespace());
+            atoms.push(word(w, space));
+        }
+    }
+
+    /// Decode an `<img>` with a data URL, sized from its width and height
+    fn image(&mut self, element: &Element, style: &Style) -> Option<GrayImage> {
+        let src = element.attr("src")?.trim();
+        if !src.starts_with("data:") {
+            println!("⚠️ Skipping HTML image that is not a data URL");
+            return None;
+        }
+
+        let decoded = self
+            .images
+            .entry(src.to_string())
+            .or_insert_with(|| match ThermalImage::decode_base64(src) {
+                Ok(image) => {
+                    // Flatten transparency onto white paper
+                    let rgba = image.to_rgba8();
+                    Some(GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                        let p = rgba.get_pixel(x, y);
+                        let luma = (p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114) / 1000;
+                        let a = p[3] as u32;
+                        Luma([((luma * a + 255 * (255 - a)) / 255) as u8])
+                    }))
+                }
+                Err(e) => {
+                    eprintln!("Failed to decode HTML image: {}", e);
+                    None
+                }
+            })
+            .clone()?;
+
+        let (natural_width, natural_height) = (decoded.width() as f32 * self.px, decoded.height() as f32 * self.px);
+        let width = style.width.map(|w| w.resolve(style.font_size, self.page_width));
+        let height = style.height.map(|h| h.resolve(style.font_size, self.page_width));
+        let (mut width, mut height) = match (width, height) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, w * natural_height / natural_width),
+            (None, Some(h)) => (h * natural_width / natural_height, h),
+            (None, None) => (natural_width, natural_height),
+        };
+        if let Some(max) = style.max_width.map(|m| m.resolve(style.font_size, self.page_width)) {
+            if width > max {
+                height *= max / width;
+                width = max;
+            }
+        }
+
+        let (width, height) = (width.round().max(1.0) as u32, height.round().max(1.0) as u32);
+        Some(image::imageops::resize(&decoded, width, height, FilterType::Lanczos3))
+    }
+
+    /// Lay out a run of inline nodes into lines, returning the bottom of the last one
+    #[allow(clippy::too_many_arguments)]
+    fn inline(
+        &mut self,
+        nodes: &[&Node],
+        style: &Style,
+        path: &mut Vec<Key>,
+        x: f32,
+        width: f32,
+        y: f32,
+        margin: &mut f32,
+    ) -> f32 {
+        let mut atoms = Vec::new();
+        for node in nodes {
+            self.collect(node, style, path, &mut atoms);
+        }
+        while matches!(atoms.last(), Some(Atom::Break)) && !style.pre {
+            atoms.pop();
+        }
+        if atoms.is_empty() {
+            return y;
+        }
+
+        let mut y = y + *margin;
+        *margin = 0.0;
+
+        // Break into lines, splitting words longer than a whole line
+        let mut lines: Vec<Vec<Atom>> = vec![Vec::new()];
+        let mut cursor = 0.0;
+        for atom in atoms {
+            let (atom_width, space) = match &atom {
+                Atom::Break => {
+                    lines.push(Vec::new());
+                    cursor = 0.0;
+                    continue;
+                }
+                Atom::Word { text, font, space, .. } => {
+                    let rasterizer = self.font(*font);
+                    (rasterizer.measure(text), if *space { rasterizer.measure(" ") } else { 0.0 })
+                }
+                Atom::Image { image, .. } => (image.width() as f32, 0.0),
+            };
+
+            let line = lines.last_mut().unwrap();
+            if !line.is_empty() && cursor + atom_width > width + 0.5 {
+                lines.push(Vec::new());
+                cursor = 0.0;
+            }
+
+            if atom_width > width + 0.5 {
+                for piece in self.split_to_width(atom, width) {
+                    if !lines.last().unwrap().is_empty() {
+                        lines.push(Vec::new());
+                    }
+                    lines.last_mut().unwrap().push(piece);
+                }
+                cursor = width;
+                continue;
+            }
+
+            lines.last_mut().unwrap().push(atom);
+            cursor += atom_width + space;
+        }
+
+        let rtl = style.rtl.unwrap_or_else(|| {
+            let text: String = lines
+                .iter()
+                .flatten()
+                .filter_map(|a| match a {
+                    Atom::Word { text, .. } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            BidiInfo::new(&text, None).paragraphs.first().is_some_and(|p| p.level.is_rtl())
+        });
+        let justify = style.align.unwrap_or(if rtl { Justify::Right } else { Justify::Left });
+        let default_font = Self::font_key(style);
+
+        for line in lines {
+            y = self.line(line, rtl, justify, default_font, x, width, y);
+        }
+        y
+    }
+
+    /// Split an atom wider than the line: words at character boundaries, images scaled down
+    fn split_to_width(&mut self, atom: Atom, width: f32) -> Vec<Atom> {
+        match atom {
+            Atom::Word { text, font, underline, space } => {
+                let rasterizer = self.font(font);
+                let mut pieces = Vec::new();
+                let mut start = 0;
+                for (i, c) in text.char_indices() {
+                    let end = i + c.len_utf8();
+                    if i > start && rasterizer.measure(&text[start..end]) > width {
+                        pieces.push(text[start..i].to_string());
+                        start = i;
+                    }
+                }
+                pieces.push(text[start..].to_string());
+
+                let count = pieces.len();
+                pieces
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, text)| Atom::Word {
+                        text,
+                        font,
+                        underline,
+                        space: space && i + 1 == count,
+                    })
+                    .collect()
+            }
+            Atom::Image { image, space } => {
+                let w = width.max(1.0) as u32;
+                let h = ((image.height() as f32 * w as f32 / image.width() as f32).round() as u32).max(1);
+                vec![Atom::Image {
+                    image: image::imageops::resize(&image, w, h, FilterType::Lanczos3),
+                    space,
+                }]
+            }
+            Atom::Break => vec![Atom::Break],
+        }
+    }
+
+    /// Place one line, returning its bottom
+    #[allow(clippy::too_many_arguments)]
+    fn line(&mut self, line: Vec<Atom>, rtl: bool, justify: Justify, default_font: FontKey, x: f32, width: f32, y: f32) -> f32 {
+        // Measure
+        let mut ascent = 0.0f32;
+        let mut height = 0.0f32;
+        let mut widths = Vec::with_capacity(line.len());
+        let mut spaces = Vec::with_capacity(line.len());
+        for atom in &line {
+            let (w, s) = match atom {
+                Atom::Word { text, font, space, .. } => {
+                    let rasterizer = self.font(*font);
+                    ascent = ascent.max(rasterizer.ascent());
+                    height = height.max(rasterizer.line_height() as f32);
+                    (rasterizer.measure(text), if *space { rasterizer.measure(" ") } else { 0.0 })
+                }
+                Atom::Image { image, .. } => {
+                    ascent = ascent.max(image.height() as f32);
+                    height = height.max(image.height() as f32);
+                    (image.width() as f32, 0.0)
+                }
+                Atom::Break => (0.0, 0.0),
+            };
+            widths.push(w);
+            spaces.push(s);
+        }
+        if line.is_empty() {
+            return y + self.font(default_font).line_height() as f32;
+        }
+        // Text below an image's bottom edge, e.g. descenders, still needs room
+        let descent = line
+            .iter()
+            .filter_map(|a| match a {
+                Atom::Word { font, .. } => {
+                    let rasterizer = self.font(*font);
+                    Some(rasterizer.line_height() as f32 - rasterizer.ascent())
+                }
+                _ => None,
+            })
+            .fold(0.0f32, f32::max);
+        height = height.max(ascent + descent);
+
+        let order = visual_order(&line, rtl);
+        let gap = |a: usize, b: usize| spaces[a.min(b)];
+        let line_width: f32 = widths.iter().sum::<f32>()
+            + order.windows(2).map(|pair| gap(pair[0], pair[1])).sum::<f32>();
+        let free = (width - line_width).max(0.0);
+        let mut cursor = x + match justify {
+            Justify::Left => 0.0,
+            Justify::Center => (free / 2.0).floor(),
+            Justify::Right => free,
+        };
+
+        let baseline = y + ascent;
+        let mut line = line.into_iter().map(Some).collect::<Vec<_>>();
+        for (position, &index) in order.iter().enumerate() {
+            match line[index].take() {
+                Some(Atom::Word { text, font, underline, .. }) => {
+                    if underline {
+                        let thickness = (font.0 as f32 / 2.0 / 16.0).max(1.0);
+                        self.ops.push(Op::Rect {
+                            x: cursor,
+                            y: baseline + thickness,
+                            width: widths[index],
+                            height: thickness,
+                            dashed: false,
+                        });
+                    }
+                    self.ops.push(Op::Text { font, text, x: cursor, baseline });
+                }
+                Some(Atom::Image { image, .. }) => {
+                    self.ops.push(Op::Image {
+                        x: cursor,
+                        y: baseline - image.height() as f32,
+                        image,
+                    });
+                }
+                _ => {}
+            }
+            cursor += widths[index];
+            if let Some(&next) = order.get(position + 1) {
+                cursor += gap(index, next);
+            }
+        }
+
+        y + height
+    }
+
+    fn borders(&mut self, x: f32, y: f32, width: f32, height: f32, border: &[Border; 4]) {
+        let [top, right, bottom, left] = border;
+        let rect = |x: f32, y: f32, width: f32, height: f32, border: &Border| Op::Rect {
+            x,
+            y,
+            width,
+            height,
+            dashed: border.style == LineStyle::Dashed,
+        };
+        if top.width() > 0.0 {
+            self.ops.push(rect(x, y, width, top.width(), top));
+        }
+        if bottom.width() > 0.0 {
+            self.ops.push(rect(x, y + height - bottom.width(), width, bottom.width(), bottom));
+        }
+        if left.width() > 0.0 {
+            self.ops.push(rect(x, y, left.width(), height, left));
+        }
+        if right.width() > 0.0 {
+            self.ops.push(rect(x + width - right.width(), y, right.width(), height, right));
+        }
+    }
+
+    fn paint(&mut self, width: u32, bottom: f32) -> GrayImage {
+        let height = bottom.ceil().max(1.0) as u32;
+        let mut image = GrayImage::from_pixel(width, height, Luma([255]));
+        let dash = (4.0 * self.px).round().max(2.0) as u32;
+
+        for op in std::mem::take(&mut self.ops) {
+            match op {
+                Op::Text { font, text, x, baseline } => {
+                    self.font(font).draw(&mut image, &text, x, baseline);
+                }
+                Op::Rect { x, y, width: w, height: h, dashed } => {
+                    let (x0, y0) = (x.round().max(0.0) as u32, y.round().max(0.0) as u32);
+                    let x1 = ((x + w).round().max(0.0) as u32).min(width).max(x0 + 1);
+                    let y1 = ((y + h).round().max(0.0) as u32).min(height).max(y0 + 1);
+                    // Dashes run along the line
+                    let horizontal = w >= h;
+                    for py in y0..y1.min(height) {
+                        for px in x0..x1.min(width) {
+                            let along = if horizontal { px - x0 } else { py - y0 };
+                            if !dashed || (along / dash) % 2 == 0 {
+                                image.put_pixel(px, py, Luma([0]));
+                            }
+                        }
+                    }
+                }
+                Op::Image { x, y, image: picture } => {
+                    let (ox, oy) = (x.round() as i64, y.round() as i64);
+                    for (px, py, pixel) in picture.enumerate_pixels() {
+                        let (tx, ty) = (ox + px as i64, oy + py as i64);
+                        if tx >= 0 && ty >= 0 && (tx as u32) < width && (ty as u32) < height {
+                            let target = image.get_pixel_mut(tx as u32, ty as u32);
+                            target[0] = target[0].min(pixel[0]);
+                        }
+                    }
+                }
+            }
+        }
+
+        image
+    }
+}
+
+/// Share the table width between columns. Columns with an explicit width get
+/// it; the others get their content width, stretched or squeezed (down to
+/// their longest word) to fill the rest.
+fn distribute(width: f32, fixed: &[Option<f32>], natural: &[f32], minimum: &[f32]) -> Vec<f32> {
+    let columns = fixed.len();
+    if columns == 0 {
+        return Vec::new();
+    }
+
+    let fixed_total: f32 = fixed.iter().flatten().sum();
+    let flexible: Vec<usize> = (0..columns).filter(|&c| fixed[c].is_none()).collect();
+    let mut widths: Vec<f32> = fixed.iter().map(|f| f.unwrap_or(0.0)).collect();
+
+    if flexible.is_empty() || fixed_total >= width {
+        // Only fixed columns, or they take everything: scale them to fit
+        let total = if flexible.is_empty() { fixed_total } else { fixed_total + flexible.len() as f32 };
+        let scale = if total > 0.0 { width / total } else { 0.0 };
+        for (c, w) in widths.iter_mut().enumerate() {
+            *w = if fixed[c].is_some() { *w * scale } else { scale };
+        }
+        if total <= 0.0 {
+            widths = vec![width / columns as f32; columns];
+        }
+        return widths;
+    }
+
+    let remaining = width - fixed_total;
+    let natural_total: f32 = flexible.iter().map(|&c| natural[c]).sum();
+    let minimum_total: f32 = flexible.iter().map(|&c| minimum[c]).sum();
+
+    for &c in &flexible {
+        widths[c] = if natural_total <= 0.0 {
+            remaining / flexible.len() as f32
+        } else if natural_total <= remaining {
+            natural[c] * remaining / natural_total
+        } else if minimum_total < remaining {
+            let stretch = natural_total - minimum_total;
+            let share = if stretch > 0.0 { (natural[c] - minimum[c]) / stretch } else { 0.0 };
+            minimum[c] + (remaining - minimum_total) * share
+        } else if minimum_total > 0.0 {
+            minimum[c] * remaining / minimum_total
+        } else {
+            remaining / flexible.len() as f32
+        };
+    }
+    widths
+}
+
+/// Display order of a line's atoms. Words are kept whole and reordered with
+/// the Unicode bidi algorithm, so Arabic runs read right to left.
+fn visual_order(line: &[Atom], rtl: bool) -> Vec<usize> {
+    let mut text = String::new();
+    let mut starts = Vec::with_capacity(line.len());
+    for atom in line {
+        starts.push(text.len());
+        match atom {
+            Atom::Word { text: word, .. } => text.push_str(word),
+            // Object replacement character, neutral like an image
+            _ => text.push('\u{FFFC}'),
+        }
+        text.push(' ');
+    }
+
+    let level = if rtl { Level::rtl() } else { Level::ltr() };
+    let bidi = BidiInfo::new(&text, Some(level));
+    let levels: Vec<u8> = starts.iter().map(|&s| bidi.levels[s].number()).collect();
+
+    let mut order: Vec<usize> = (0..line.len()).collect();
+    let max = levels.iter().copied().max().unwrap_or(0);
+    let min_odd = levels.iter().copied().filter(|l| l % 2 == 1).min().unwrap_or(max + 1);
+
+    // Rule L2: from the highest level down to the lowest odd one, reverse
+    // every run of atoms at that level or higher
+    for level in (min_odd..=max).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> PrinterProfile {
+        PrinterProfile::default()
+    }
+
+    /// Leftmost and rightmost black columns within rows `top..bottom`
+    fn ink_columns(image: &GrayImage, top: u32, bottom: u32) -> Option<(u32, u32)> {
+        let columns: Vec<u32> = (0..image.width())
+            .filter(|&x| (top..bottom.min(image.height())).any(|y| image.get_pixel(x, y)[0] < 128))
+            .collect();
+        Some((*columns.first()?, *columns.last()?))
+    }
+
+    fn black(image: &GrayImage) -> usize {
+        image.pixels().filter(|p| p[0] < 128).count()
+    }
+
+    #[test]
+    fn parses_tolerant_markup() {
+        let (root, css) = parse(
+            "<style>.x { color: red }</style><p>One<p>Two &amp; <b>three</b><br>four\
+             <table><tr><td>a<td>b<tr><td>c</table><img src=x>",
+        );
+        assert!(css.contains(".x"));
+
+        let tags: Vec<&str> = root
+            .children
+            .iter()
+            .filter_map(|n| match n {
+                Node::Element(e) => Some(e.tag.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tags, vec!["style", "p", "p", "table", "img"]);
+
+        let Node::Element(table) = &root.children[3] else { unreachable!() };
+        let rows: Vec<usize> = table
+            .children
+            .iter()
+            .map(|r| match r {
+                Node::Element(row) => row.children.len(),
+                _ => 0,
+            })
+            .collect();
+        assert_eq!(rows, vec![2, 1]);
+        assert_eq!(decode_entities("5 &lt; 6 &#169; &#x41; &bogus;"), "5 < 6 © A &bogus;");
+    }
+
+    #[test]
+    fn text_align_and_tables_keep_columns() {
+        let html = r#"
+            <div style="text-align:center">CENTER</div>
+            <table style="width:100%"><tr><td>Item</td><td style="text-align:right">12.00</td></tr></table>
+        "#;
+        let image = render(html, &profile());
+        let line = TextRasterizer::new(TextOptions {
+            size: 16.0 * 203.0 / 96.0,
+            ..TextOptions::from_profile(&profile())
+        })
+        .line_height();
+
+        let (left, right) = ink_columns(&image, 0, line).unwrap();
+        assert!(left > 150 && right < 426, "centered text at {}..{}", left, right);
+
+        let (left, right) = ink_columns(&image, line, line * 2 + 4).unwrap();
+        assert!(left < 20, "first cell starts at {}", left);
+        assert!(right > 550, "right-aligned cell ends at {}", right);
+    }
+
+    #[test]
+    fn bold_and_font_size_change_the_ink() {
+        let normal = render("<p style='margin:0'>TOTAL</p>", &profile());
+        let bold = render("<p style='margin:0'><b>TOTAL</b></p>", &profile());
+        let big = render("<p style='margin:0;font-size:24px'>TOTAL</p>", &profile());
+
+        assert!(black(&bold) > black(&normal));
+        assert!(big.height() > normal.height());
+    }
+
+    #[test]
+    fn hr_borders_and_hidden_elements() {
+        let image = render("<hr style='margin:0; border:0; border-top:2px solid'>", &profile());
+        let (left, right) = ink_columns(&image, 0, image.height()).unwrap();
+        assert_eq!((left, right), (0, 575));
+
+        let dashed = render("<div style='border-bottom:2px dashed'>&nbsp;</div>", &profile());
+        let ink = (0..dashed.height())
+            .map(|y| (0..576).filter(|&x| dashed.get_pixel(x, y)[0] == 0).count())
+            .max()
+            .unwrap();
+        assert!(ink > 200 && ink < 400, "dashed line has {} dots", ink);
+
+        let hidden = render("<style>.no-print{display:none}</style><p class='no-print'>X</p>", &profile());
+        assert_eq!(black(&hidden), 0);
+    }
+
+    #[test]
+    fn column_widths_follow_content_and_attributes() {
+        let widths = distribute(500.0, &[None, None], &[100.0, 25.0], &[50.0, 25.0]);
+        assert_eq!(widths, vec![400.0, 100.0]);
+
+        let widths = distribute(100.0, &[None, None], &[300.0, 20.0], &[40.0, 20.0]);
+        assert_eq!(widths, vec![80.0, 20.0]);
+
+        let widths = distribute(500.0, &[Some(100.0), None], &[0.0, 10.0], &[0.0, 10.0]);
+        assert_eq!(widths, vec![100.0, 400.0]);
+    }
+
+    #[test]
+    fn data_url_images_are_drawn() {
+        use base64::Engine;
+        let mut png = Vec::new();
+        image::DynamicImage::ImageLuma8(GrayImage::from_pixel(10, 10, Luma([0])))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let src = format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png));
+
+        let image = render(&format!("<center><img src='{}' width='100'></center>", src), &profile());
+        let (left, right) = ink_columns(&image, 0, image.height()).unwrap();
+        let width = right - left + 1;
+        assert!((209..=213).contains(&width), "image is {} dots wide", width);
+        assert!(left > 170, "image is centred, starts at {}", left);
+    }
+
+    #[test]
+    fn arabic_lines_run_right_to_left() {
+        let image = render("<p style='margin:0'>المجموع 12.00</p>", &profile());
+        let (_, right) = ink_columns(&image, 0, image.height()).unwrap();
+        assert!(right > 560, "Arabic paragraph is right-aligned, ends at {}", right);
+    }
+}
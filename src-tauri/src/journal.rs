@@ -0,0 +1,417 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use crate::{api::PrintJob, config};
+use crate::retry::FailureClass;
+
+/// How long acknowledged jobs are kept around before being pruned
+const ACKNOWLEDGED_RETENTION_HOURS: i64 = 24;
+
+/// Lifecycle of a pulled job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Returned by `poll_print_jobs`, nothing printed yet
+    Pulled,
+    /// Being rendered / sent; may or may not have reached the printer
+    Rendering,
+    /// Failed, waiting for `next_attempt_at` before trying again
+    Retrying,
+    /// The printer reported it can't print (paper out, cover open...); checked
+    /// again at `next_attempt_at`, without counting as a failed attempt
+    Held,
+    /// Sent to the printer, `done` not yet acknowledged by the backend
+    Sent,
+    /// Backend has accepted the final status
+    Acknowledged,
+    /// Printing failed, `failed` not yet acknowledged by the backend
+    Failed,
+}
+
+/// A journaled job and its progress
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub job: PrintJob,
+    pub state: JobState,
+    #[serde(default)]
+    pub printer: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub attempts: u32,
+    /// Copies already sent, so an interrupted job carries on with the next one
+    #[serde(default)]
+    pub copies_sent: u32,
+    #[serde(default)]
+    pub failure_class: Option<FailureClass>,
+    #[serde(default)]
+    pub next_attempt_at: Option<String>,
+    /// Retries exhausted; kept until staff reprint or discard it
+    #[serde(default)]
+    pub dead_letter: bool,
+    #[serde(default)]
+    pub ack_attempts: u32,
+    pub pulled_at: String,
+    pub updated_at: String,
+}
+
+impl JournalEntry {
+    pub fn new(job: PrintJob) -> Self {
+        let now = chrono::Local::now().to_rfc3339();
+        Self {
+            job,
+            state: JobState::Pulled,
+            printer: None,
+            reason: None,
+            attempts: 0,
+            copies_sent: 0,
+            failure_class: None,
+            next_attempt_at: None,
+            dead_letter: false,
+            ack_attempts: 0,
+            pulled_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    /// Move to a new state, stamping the update time
+    pub fn set_state(&mut self, state: JobState) {
+        self.state = state;
+        self.updated_at = chrono::Local::now().to_rfc3339();
+    }
+
+    /// Mark the job failed with a reason to report to the backend
+    pub fn fail(&mut self, reason: &str) {
+        self.reason = Some(reason.to_string());
+        self.next_attempt_at = None;
+        self.set_state(JobState::Failed);
+    }
+
+    /// Schedule another attempt after a failure
+    pub fn schedule_retry(&mut self, class: FailureClass, reason: &str, delay: std::time::Duration) {
+        let next = chrono::Local::now()
+            + chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::zero());
+        self.failure_class = Some(class);
+        self.reason = Some(reason.to_string());
+        self.next_attempt_at = Some(next.to_rfc3339());
+        self.set_state(JobState::Retrying);
+    }
+
+    /// Keep the job until the printer is ready again, without reporting anything
+    pub fn hold(&mut self, reason: &str, recheck: std::time::Duration) {
+        let next = chrono::Local::now()
+            + chrono::Duration::from_std(recheck).unwrap_or_else(|_| chrono::Duration::zero());
+        self.reason = Some(reason.to_string());
+        self.next_attempt_at = Some(next.to_rfc3339());
+        self.set_state(JobState::Held);
+    }
+
+    /// Give up on the job: report it failed and keep it in the dead-letter list
+    pub fn dead_letter(&mut self, class: FailureClass, reason: &str) {
+        self.failure_class = Some(class);
+        self.dead_letter = true;
+        self.fail(reason);
+    }
+
+    /// Whether the job still has to be (re)printed
+    pub fn needs_print(&self) -> bool {
+        matches!(self.state, JobState::Pulled | JobState::Rendering)
+    }
+
+    /// Whether a scheduled retry or a held job's recheck is due
+    pub fn retry_due(&self) -> bool {
+        matches!(self.state, JobState::Retrying | JobState::Held)
+            && self.next_attempt_at
+                .as_deref()
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| t <= chrono::Local::now())
+                .unwrap_or(true)
+    }
+
+    /// Status that still has to be reported to the backend, if any
+    pub fn pending_ack(&self) -> Option<&'static str> {
+        match self.state {
+            JobState::Sent => Some("done"),
+            JobState::Failed => Some("failed"),
+            _ => None,
+        }
+    }
+}
+
+/// On-disk journal of pulled jobs under `~/.vopecsprinter/journal`.
+///
+/// Each job is stored as its own JSON file and rewritten atomically on every
+/// state change, so a crash at any point leaves the last known state behind.
+/// Jobs interrupted before reaching `Sent` are printed again on replay, which
+/// favours a duplicate ticket over a lost one.
+pub struct Journal {
+    dir: PathBuf,
+}
+
+impl Journal {
+    /// Open the journal in the config directory, creating it if needed
+    pub fn open() -> Result<Self> {
+        Self::open_in(config::get_config_dir().join("journal"))
+    }
+
+    /// Open the journal in `dir`, creating it if needed
+    pub fn open_in(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .context("Failed to create journal directory")?;
+        Ok(Self { dir })
+    }
+
+    fn entry_path(&self, job_id: i64) -> PathBuf {
+        self.dir.join(format!("{}.json", job_id))
+    }
+
+    /// Persist an entry, replacing any previous state for the same job
+    pub fn record(&self, entry: &JournalEntry) -> Result<()> {
+        let path = self.entry_path(entry.job.id);
+        let temp_path = path.with_extension("json.tmp");
+
+        let content = serde_json::to_string_pretty(entry)
+            .context("Failed to serialize journal entry")?;
+
+        fs::write(&temp_path, content)
+            .context("Failed to write journal entry")?;
+        fs::rename(&temp_path, &path)
+            .context("Failed to commit journal entry")?;
+
+        Ok(())
+    }
+
+    /// Load every journaled job, oldest first
+    pub fn load_all(&self) -> Result<Vec<JournalEntry>> {
+        let mut entries = Vec::new();
+
+        for dir_entry in fs::read_dir(&self.dir).context("Failed to read journal directory")? {
+            let path = dir_entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    eprintln!("Failed to read journal entry {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            match serde_json::from_str::<JournalEntry>(&content) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => eprintln!("Skipping corrupt journal entry {}: {}", path.display(), e),
+            }
+        }
+
+        entries.sort_by(|a, b| a.pulled_at.cmp(&b.pulled_at).then(a.job.id.cmp(&b.job.id)));
+        Ok(entries)
+    }
+
+    /// Load a single journaled job
+    pub fn get(&self, job_id: i64) -> Result<Option<JournalEntry>> {
+        let path = self.entry_path(job_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)
+            .context("Failed to read journal entry")?;
+        let entry = serde_json::from_str(&content)
+            .context("Failed to parse journal entry")?;
+
+        Ok(Some(entry))
+    }
+
+    /// Jobs whose retries were exhausted, oldest first
+    pub fn dead_letters(&self) -> Result<Vec<JournalEntry>> {
+        Ok(self.load_all()?.into_iter().filter(|e| e.dead_letter).collect())
+    }
+
+    /// Drop a job from the journal
+    pub fn remove(&self, job_id: i64) -> Result<()> {
+        let path = self.entry_path(job_id);
+        if path.exists() {
+            fs::remove_file(&path)
+                .context("Failed to remove journal entry")?;
+        }
+        Ok(())
+    }
+
+    /// Drop a dead letter staff no longer want. Live jobs, and dead letters
+    /// whose failure hasn't reached the backend yet, are refused.
+    pub fn discard(&self, job_id: i64) -> Result<()> {
+        match self.get(job_id)? {
+            Some(entry) if entry.dead_letter && entry.pending_ack().is_none() => self.remove(job_id),
+            Some(entry) if entry.dead_letter => {
+                anyhow::bail!("Job #{} can't be discarded until its failure is reported", job_id)
+            }
+            _ => anyhow::bail!("Job #{} is not in the dead-letter list", job_id),
+        }
+    }
+
+    /// Delete acknowledged jobs older than the retention period.
+    /// Dead letters are kept until handled by staff.
+    pub fn prune(&self) -> Result<()> {
+        let cutoff = chrono::Local::now() - chrono::Duration::hours(ACKNOWLEDGED_RETENTION_HOURS);
+
+        for entry in self.load_all()? {
+            if entry.state != JobState::Acknowledged || entry.dead_letter {
+                continue;
+            }
+
+            let expired = chrono::DateTime::parse_from_rfc3339(&entry.updated_at)
+                .map(|t| t < cutoff)
+                .unwrap_or(true);
+
+            if expired {
+                let _ = fs::remove_file(self.entry_path(entry.job.id));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Journal in a fresh directory, removed again when dropped
+    struct TempJournal(Journal);
+
+    impl TempJournal {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("vopecs_journal_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            Self(Journal::open_in(dir).unwrap())
+        }
+    }
+
+    impl Drop for TempJournal {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0.dir);
+        }
+    }
+
+    fn entry(id: i64) -> JournalEntry {
+        JournalEntry::new(PrintJob {
+            id,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn records_and_loads_entries() {
+        let journal = TempJournal::new("records");
+        let journal = &journal.0;
+
+        let mut first = entry(2);
+        first.pulled_at = "2026-01-01T10:00:00+00:00".to_string();
+        let mut second = entry(1);
+        second.pulled_at = "2026-01-01T11:00:00+00:00".to_string();
+        journal.record(&second).unwrap();
+        journal.record(&first).unwrap();
+
+        // Rewriting replaces the previous state
+        first.set_state(JobState::Sent);
+        journal.record(&first).unwrap();
+
+        let entries = journal.load_all().unwrap();
+        let ids: Vec<i64> = entries.iter().map(|e| e.job.id).collect();
+        assert_eq!(ids, [2, 1]);
+        assert_eq!(entries[0].state, JobState::Sent);
+        assert_eq!(journal.get(1).unwrap().unwrap().state, JobState::Pulled);
+        assert!(journal.get(3).unwrap().is_none());
+
+        // Corrupt files are skipped rather than failing the load
+        fs::write(journal.dir.join("3.json"), "{").unwrap();
+        assert_eq!(journal.load_all().unwrap().len(), 2);
+
+        journal.remove(1).unwrap();
+        assert!(journal.get(1).unwrap().is_none());
+    }
+
+    #[test]
+    fn only_reported_dead_letters_are_discarded() {
+        let journal = TempJournal::new("discard");
+        let journal = &journal.0;
+
+        let mut live = entry(1);
+        live.schedule_retry(FailureClass::PrinterOffline, "offline", Duration::from_secs(60));
+        let mut dead = entry(2);
+        dead.dead_letter(FailureClass::PaperOut, "paper out");
+        journal.record(&live).unwrap();
+        journal.record(&dead).unwrap();
+
+        assert!(journal.discard(1).is_err());
+        assert!(journal.discard(2).is_err());
+        assert!(journal.discard(3).is_err());
+
+        dead.set_state(JobState::Acknowledged);
+        journal.record(&dead).unwrap();
+        journal.discard(2).unwrap();
+        assert!(journal.get(2).unwrap().is_none());
+        assert!(journal.get(1).unwrap().is_some());
+    }
+
+    #[test]
+    fn state_changes_decide_what_happens_next() {
+        let mut job = entry(1);
+        assert!(job.needs_print());
+        assert_eq!(job.pending_ack(), None);
+
+        job.schedule_retry(FailureClass::PrinterOffline, "offline", Duration::from_secs(60));
+        assert_eq!(job.state, JobState::Retrying);
+        assert!(!job.needs_print());
+        assert!(!job.retry_due());
+
+        job.hold("paper out", Duration::ZERO);
+        assert_eq!(job.state, JobState::Held);
+        assert!(job.retry_due());
+        assert_eq!(job.pending_ack(), None);
+
+        job.set_state(JobState::Sent);
+        assert!(!job.retry_due());
+        assert_eq!(job.pending_ack(), Some("done"));
+
+        job.dead_letter(FailureClass::PaperOut, "paper out");
+        assert!(job.dead_letter);
+        assert_eq!(job.state, JobState::Failed);
+        assert_eq!(job.next_attempt_at, None);
+        assert_eq!(job.pending_ack(), Some("failed"));
+    }
+
+    #[test]
+    fn prunes_old_acknowledged_jobs_only() {
+        let journal = TempJournal::new("prune");
+        let journal = &journal.0;
+        let old = (chrono::Local::now() - chrono::Duration::hours(ACKNOWLEDGED_RETENTION_HOURS + 1)).to_rfc3339();
+
+        let mut expired = entry(1);
+        expired.state = JobState::Acknowledged;
+        expired.updated_at = old.clone();
+        let mut recent = entry(2);
+        recent.set_state(JobState::Acknowledged);
+        let mut dead = entry(3);
+        dead.dead_letter(FailureClass::PrinterOffline, "offline");
+        dead.state = JobState::Acknowledged;
+        dead.updated_at = old.clone();
+        let mut pending = entry(4);
+        pending.updated_at = old;
+
+        for entry in [&expired, &recent, &dead, &pending] {
+            journal.record(entry).unwrap();
+        }
+        journal.prune().unwrap();
+
+        let ids: Vec<i64> = journal.load_all().unwrap().iter().map(|e| e.job.id).collect();
+        assert_eq!(ids.len(), 3);
+        assert!(!ids.contains(&1));
+        assert_eq!(journal.dead_letters().unwrap().len(), 1);
+    }
+}
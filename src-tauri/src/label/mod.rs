@@ -0,0 +1,323 @@
+//! Label printers: ZPL (Zebra) and TSPL (TSC) output.
+//!
+//! Documents and text jobs are laid out top to bottom with the label
+//! language's own text, barcode and QR fields, starting a new label when the
+//! current one is full or at a `cut` element. Images, HTML and PDF jobs are
+//! printed as graphics, one label per label-height slice.
+
+mod tspl;
+mod zpl;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use crate::barcode::{self, Barcode, BarcodeOptions, BarcodeRendering, QrOptions};
+use crate::command_set::CommandSet;
+use crate::config::{CutOptions, DrawerOptions, PrinterProfile};
+use crate::document::{self, Element};
+use crate::dither::MonoBitmap;
+use crate::escpos::{Font, InkColor, Justify, TextStyle, ThermalImage};
+
+pub use tspl::Tspl;
+pub use zpl::Zpl;
+
+/// Label stock and print settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LabelOptions {
+    /// Label width in mm; content is laid out across `dot_width`
+    pub width_mm: f32,
+    /// Label height in mm
+    pub height_mm: f32,
+    /// Gap between labels in mm, 0 for continuous media
+    pub gap_mm: f32,
+    /// Print darkness, ZPL 0-30 or TSPL 0-15; printer default when unset
+    pub darkness: Option<u8>,
+}
+
+impl Default for LabelOptions {
+    fn default() -> Self {
+        Self {
+            width_mm: 50.0,
+            height_mm: 30.0,
+            gap_mm: 3.0,
+            darkness: None,
+        }
+    }
+}
+
+/// Height of label text at size 1, in dots
+const TEXT_HEIGHT: u32 = 24;
+
+/// Distance between lines of label text at size 1, in dots
+const LINE_HEIGHT: u32 = 30;
+
+/// Space left below barcodes, QR codes and images, in dots
+const FIELD_GAP: u32 = 8;
+
+/// Room for the text line printed below barcodes, in dots
+const HRI_HEIGHT: u32 = 30;
+
+/// Fields of one label language
+pub trait LabelLanguage: Sync {
+    /// Label settings and start of a label
+    fn start(&self, profile: &PrinterProfile) -> Vec<u8>;
+
+    /// One line of text in the printer font, justified across `width` dots
+    fn text(&self, y: u32, width: u32, text: &str, style: &TextStyle) -> Vec<u8>;
+
+    fn image(&self, x: u32, y: u32, bitmap: &MonoBitmap) -> Vec<u8>;
+
+    fn barcode(&self, x: u32, y: u32, barcode: &Barcode, options: &BarcodeOptions) -> Vec<u8>;
+
+    fn qr(&self, x: u32, y: u32, data: &str, options: &QrOptions) -> Vec<u8>;
+
+    /// Print the label
+    fn end(&self) -> Vec<u8>;
+}
+
+/// Label height in dots
+fn label_height(profile: &PrinterProfile) -> u32 {
+    ((profile.label.height_mm * profile.dpi as f32 / 25.4) as u32).max(1)
+}
+
+/// Fills labels top to bottom, starting a new one when a field doesn't fit
+struct Layout<'a> {
+    language: &'a dyn LabelLanguage,
+    profile: &'a PrinterProfile,
+    height: u32,
+    data: Vec<u8>,
+    /// Fields of the current label and the next free row, `None` between labels
+    label: Option<(Vec<u8>, u32)>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(language: &'a dyn LabelLanguage, profile: &'a PrinterProfile) -> Self {
+        Self {
+            language,
+            profile,
+            height: label_height(profile),
+            data: Vec::new(),
+            label: None,
+        }
+    }
+
+    /// Row a field `height` dots tall goes on, on a new label if needed
+    fn reserve(&mut self, height: u32) -> u32 {
+        if matches!(self.label, Some((_, y)) if y > 0 && y + height > self.height) {
+            self.finish();
+        }
+        let (_, y) = self.label.get_or_insert_with(|| (Vec::new(), 0));
+        let top = *y;
+        *y += height;
+        top
+    }
+
+    fn push(&mut self, height: u32, field: impl FnOnce(&dyn LabelLanguage, u32) -> Vec<u8>) {
+        let top = self.reserve(height);
+        let bytes = field(self.language, top);
+        if let Some((fields, _)) = &mut self.label {
+            fields.extend_from_slice(&bytes);
+        }
+    }
+
+    /// Move down without printing
+    fn skip(&mut self, height: u32) {
+        self.reserve(height);
+    }
+
+    /// Add an image, split over as many labels as it needs
+    fn image(&mut self, bitmap: &MonoBitmap) {
+        let mut top = 0;
+        while top < bitmap.height() {
+            let free = match self.label {
+                Some((_, y)) if y < self.height => self.height - y,
+                Some(_) => {
+                    self.finish();
+                    self.height
+                }
+                None => self.height,
+            };
+            let rows = free.min(bitmap.height() - top);
+            let slice = crop_rows(bitmap, top, rows);
+            if !slice.is_blank() {
+                self.push(rows, |language, y| language.image(0, y, &slice));
+            } else {
+                self.skip(rows);
+            }
+            top += rows;
+        }
+    }
+
+    /// Print the current label, if anything was added to it
+    fn finish(&mut self) {
+        if let Some((fields, _)) = self.label.take() {
+            self.data.extend_from_slice(&self.language.start(self.profile));
+            self.data.extend_from_slice(&fields);
+            self.data.extend_from_slice(&self.language.end());
+        }
+    }
+
+    fn build(mut self) -> Vec<u8> {
+        self.finish();
+        self.data
+    }
+}
+
+/// Rows `top..top + rows` of a bitmap
+fn crop_rows(bitmap: &MonoBitmap, top: u32, rows: u32) -> MonoBitmap {
+    let mut slice = MonoBitmap::new(bitmap.width(), rows);
+    for y in 0..rows {
+        for x in (0..bitmap.width()).filter(|&x| bitmap.get(x, top + y)) {
+            slice.set(x, y, true);
+        }
+    }
+    slice
+}
+
+/// Whether label text has to be drawn as an image: label fonts only cover ASCII
+fn needs_raster(text: &str, profile: &PrinterProfile) -> bool {
+    match profile.text_rendering {
+        crate::text::TextRendering::Auto => !text.is_ascii(),
+        crate::text::TextRendering::Native => false,
+        crate::text::TextRendering::Raster => true,
+    }
+}
+
+/// Lay document elements out on labels in the profile's label language
+pub fn render(elements: &[Element], profile: &PrinterProfile) -> Result<Vec<u8>> {
+    let Some(language) = profile.emulation.label_language() else {
+        bail!("{:?} is not a label printer language", profile.emulation);
+    };
+    let width = profile.dot_width;
+    let mut layout = Layout::new(language, profile);
+
+    for element in elements {
+        match element {
+            Element::Text { text, style } if needs_raster(text, profile) => {
+                layout.image(&document::rasterize_text(text, style, profile));
+            }
+            Element::Text { text, style } => {
+                let line_height = LINE_HEIGHT * style.height.clamp(1, 8) as u32;
+                for line in text.lines() {
+                    if line.trim().is_empty() {
+                        layout.skip(line_height);
+                    } else {
+                        layout.push(line_height, |language, y| language.text(y, width, line, style));
+                    }
+                }
+            }
+            Element::Feed { lines } => layout.skip(LINE_HEIGHT * *lines as u32),
+            Element::Cut { .. } => layout.finish(),
+            Element::Barcode { data, options } => {
+                let barcode = Barcode::new(options.symbology, data)?;
+                if profile.barcode_rendering == BarcodeRendering::Raster {
+                    layout.image(&barcode::render_barcode(&barcode, options, profile)?);
+                } else {
+                    let bars = barcode.modules().len() as u32 * options.module_width.clamp(1, 6) as u32;
+                    let x = barcode::offset(options.justify, width, bars.min(width));
+                    let height = options.height.max(1) as u32 + if options.hri { HRI_HEIGHT } else { 0 };
+                    layout.push(height + FIELD_GAP, |language, y| language.barcode(x, y, &barcode, options));
+                }
+            }
+            Element::Qr { data, options } => qr(&mut layout, data, options)?,
+            Element::Zatca { invoice, options } => qr(&mut layout, &invoice.qr_payload()?, options)?,
+            Element::Image { image } => {
+                let image = ThermalImage::decode_base64(image)?;
+                layout.image(&ThermalImage::to_mono(&image, width, &profile.dithering));
+            }
+            Element::Logo { key, .. } => bail!("Logo {:?} can't be printed: label printers have no stored logos", key),
+            // Nothing to do on a label printer
+            Element::LineSpacing { .. } | Element::Drawer { .. } | Element::Beep { .. } => {}
+        }
+    }
+
+    Ok(layout.build())
+}
+
+fn qr(layout: &mut Layout, data: &str, options: &QrOptions) -> Result<()> {
+    let profile = layout.profile;
+    let left = QrOptions {
+        justify: Justify::Left,
+        ..options.clone()
+    };
+    // Same module count and size as the printer will use
+    let side = barcode::render_qr(data, &left, profile)?.height();
+
+    if profile.barcode_rendering == BarcodeRendering::Raster {
+        layout.image(&barcode::render_qr(data, options, profile)?);
+    } else {
+        let x = barcode::offset(options.justify, profile.dot_width, side);
+        layout.push(side + FIELD_GAP, |language, y| language.qr(x, y, data, options));
+    }
+    Ok(())
+}
+
+/// Label languages as a [`CommandSet`]: images become labels, and there is
+/// nothing to set up, cut, kick or style (text goes through [`render`])
+pub struct LabelCommands(pub &'static dyn LabelLanguage);
+
+impl CommandSet for LabelCommands {
+    fn setup(&self, _profile: &PrinterProfile) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn image(&self, bitmap: &MonoBitmap, profile: &PrinterProfile) -> Vec<u8> {
+        let mut layout = Layout::new(self.0, profile);
+        layout.image(bitmap);
+        layout.build()
+    }
+
+    fn two_color_image(&self, black: &MonoBitmap, red: &MonoBitmap, profile: &PrinterProfile) -> Vec<u8> {
+        let mut merged = black.clone();
+        merged.overlay(red);
+        self.image(&merged, profile)
+    }
+
+    fn cut(&self, _cut: &CutOptions) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn drawer(&self, _pin: u8, _drawer: &DrawerOptions) -> Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    fn feed(&self, _lines: u8) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn line_spacing(&self, _dots: Option<u8>) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn justify(&self, _justify: Justify) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn font(&self, _font: Font) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn size(&self, _width: u8, _height: u8) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn bold(&self, _on: bool) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn underline(&self, _weight: u8) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn inverse(&self, _on: bool) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn color(&self, _color: InkColor) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn beep(&self, _times: u8, _duration: u8) -> Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,139 @@
+use crate::barcode::{Barcode, BarcodeOptions, ErrorCorrection, QrOptions, Symbology};
+use crate::config::PrinterProfile;
+use crate::dither::MonoBitmap;
+use crate::escpos::{Justify, TextStyle};
+use super::LabelLanguage;
+
+/// TSC Printer Language
+pub struct Tspl;
+
+impl LabelLanguage for Tspl {
+    /// SIZE, GAP and DENSITY, then CLS to start an empty label
+    fn start(&self, profile: &PrinterProfile) -> Vec<u8> {
+        let label = &profile.label;
+        let mut tspl = format!(
+            "SIZE {} mm,{} mm\r\nGAP {} mm,0 mm\r\n",
+            label.width_mm, label.height_mm, label.gap_mm
+        );
+        if let Some(darkness) = label.darkness {
+            tspl += &format!("DENSITY {}\r\n", darkness.min(15));
+        }
+        tspl += "CLS\r\n";
+        tspl.into_bytes()
+    }
+
+    /// Built-in font 3 (16x24 dots), anchored at the left, middle or right of the line
+    fn text(&self, y: u32, width: u32, text: &str, style: &TextStyle) -> Vec<u8> {
+        let (x, alignment) = match style.justify {
+            Justify::Left => (0, 1),
+            Justify::Center => (width / 2, 2),
+            Justify::Right => (width, 3),
+        };
+        format!(
+            "TEXT {},{},\"3\",0,{},{},{},\"{}\"\r\n",
+            x, y, style.width.clamp(1, 8), style.height.clamp(1, 8), alignment, escape(text)
+        )
+        .into_bytes()
+    }
+
+    /// BITMAP in overwrite mode; TSPL prints 0 bits, so the rows are inverted
+    fn image(&self, x: u32, y: u32, bitmap: &MonoBitmap) -> Vec<u8> {
+        let row_bytes = (bitmap.width() + 7) / 8;
+        let mut data = format!("BITMAP {},{},{},{},0,", x, y, row_bytes, bitmap.height()).into_bytes();
+        let start = data.len();
+        for row in 0..bitmap.height() {
+            bitmap.pack_row(row, &mut data);
+        }
+        for byte in &mut data[start..] {
+            *byte = !*byte;
+        }
+        data.extend_from_slice(b"\r\n");
+        data
+    }
+
+    fn barcode(&self, x: u32, y: u32, barcode: &Barcode, options: &BarcodeOptions) -> Vec<u8> {
+        let kind = match options.symbology {
+            Symbology::Code128 => "128",
+            Symbology::Ean13 => "EAN13",
+            Symbology::UpcA => "UPCA",
+            Symbology::Code39 => "39",
+            Symbology::Itf => "25",
+        };
+        let narrow = options.module_width.clamp(1, 6);
+        format!(
+            "BARCODE {},{},\"{}\",{},{},0,{},{},\"{}\"\r\n",
+            x, y, kind, options.height.max(1), options.hri as u8, narrow, narrow * 3, barcode.data()
+        )
+        .into_bytes()
+    }
+
+    fn qr(&self, x: u32, y: u32, data: &str, options: &QrOptions) -> Vec<u8> {
+        let level = match options.error_correction {
+            ErrorCorrection::Low => 'L',
+            ErrorCorrection::Medium => 'M',
+            ErrorCorrection::Quartile => 'Q',
+            ErrorCorrection::High => 'H',
+        };
+        let model = if options.model == 1 { "M1" } else { "M2" };
+        format!(
+            "QRCODE {},{},{},{},A,0,{},\"{}\"\r\n",
+            x, y, level, options.module_size.clamp(1, 10), model, escape(data)
+        )
+        .into_bytes()
+    }
+
+    fn end(&self) -> Vec<u8> {
+        b"PRINT 1,1\r\n".to_vec()
+    }
+}
+
+/// String contents with double quotes written as TSPL's `\["]`
+fn escape(text: &str) -> String {
+    text.replace('"', "\\[\"]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_set::Emulation;
+    use crate::document::Document;
+
+    #[test]
+    fn documents_become_tspl_commands() {
+        let json = serde_json::json!({ "elements": [
+            { "type": "text", "text": "Bag \"7\"", "justify": "right" },
+            { "type": "cut" },
+            { "type": "barcode", "data": "400638133393", "symbology": "ean13", "hri": false, "justify": "center" },
+            { "type": "qr", "data": "42", "errorCorrection": "h", "size": 3 }
+        ] });
+        let mut profile = PrinterProfile {
+            emulation: Emulation::Tspl,
+            dot_width: 400,
+            ..PrinterProfile::default()
+        };
+        profile.label.gap_mm = 2.0;
+
+        let data = Document::from_json(&json).unwrap().render(&profile).unwrap();
+        let header = "SIZE 50 mm,30 mm\r\nGAP 2 mm,0 mm\r\nCLS\r\n";
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            [
+                header,
+                "TEXT 400,0,\"3\",0,1,1,3,\"Bag \\[\"]7\\[\"]\"\r\n",
+                "PRINT 1,1\r\n",
+                header,
+                "BARCODE 105,0,\"EAN13\",80,0,0,2,6,\"4006381333931\"\r\n",
+                "QRCODE 0,88,H,3,A,0,M2,\"42\"\r\n",
+                "PRINT 1,1\r\n",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn bitmaps_print_zero_bits() {
+        let mut bitmap = MonoBitmap::new(10, 1);
+        bitmap.set(0, 0, true);
+        assert_eq!(Tspl.image(8, 16, &bitmap), [&b"BITMAP 8,16,2,1,0,"[..], &[0x7F, 0xFF], b"\r\n"].concat());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::barcode::{Barcode, BarcodeOptions, ErrorCorrection, QrOptions, Symbology};
+use crate::config::PrinterProfile;
+use crate::dither::MonoBitmap;
+use crate::escpos::{Justify, TextStyle};
+use super::{LabelLanguage, TEXT_HEIGHT};
+
+/// Zebra Programming Language
+pub struct Zpl;
+
+impl LabelLanguage for Zpl {
+    /// Darkness (~SD), then ^XA with the label size, media type and UTF-8 text
+    fn start(&self, profile: &PrinterProfile) -> Vec<u8> {
+        let label = &profile.label;
+        let mut zpl = String::new();
+        if let Some(darkness) = label.darkness {
+            zpl += &format!("~SD{:02}\n", darkness.min(30));
+        }
+        let dots = |mm: f32| (mm * profile.dpi as f32 / 25.4).round() as u32;
+        // ^MNY senses the gap between labels, ^MNN is continuous media
+        let media = if label.gap_mm > 0.0 { 'Y' } else { 'N' };
+        zpl += &format!("^XA\n^CI28\n^PW{}\n^LL{}\n^MN{}\n", dots(label.width_mm), dots(label.height_mm), media);
+        zpl.into_bytes()
+    }
+
+    fn text(&self, y: u32, width: u32, text: &str, style: &TextStyle) -> Vec<u8> {
+        let justify = match style.justify {
+            Justify::Left => 'L',
+            Justify::Center => 'C',
+            Justify::Right => 'R',
+        };
+        let height = TEXT_HEIGHT * style.height.clamp(1, 8) as u32;
+        let char_width = TEXT_HEIGHT * style.width.clamp(1, 8) as u32;
+        format!(
+            "^FO0,{}^A0N,{},{}^FB{},1,0,{},0^FH^FD{}^FS\n",
+            y, height, char_width, width, justify, escape(text)
+        )
+        .into_bytes()
+    }
+
+    /// ^GF graphic field as ASCII hex
+    fn image(&self, x: u32, y: u32, bitmap: &MonoBitmap) -> Vec<u8> {
+        let row_bytes = (bitmap.width() + 7) / 8;
+        let total = row_bytes * bitmap.height();
+        let mut rows = Vec::with_capacity(total as usize);
+        for row in 0..bitmap.height() {
+            bitmap.pack_row(row, &mut rows);
+        }
+        let hex: String = rows.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("^FO{},{}^GFA,{},{},{},{}^FS\n", x, y, total, total, row_bytes, hex).into_bytes()
+    }
+
+    fn barcode(&self, x: u32, y: u32, barcode: &Barcode, options: &BarcodeOptions) -> Vec<u8> {
+        let height = options.height.max(1);
+        let hri = if options.hri { 'Y' } else { 'N' };
+        // ZPL adds the check digit of EAN-13 and UPC-A itself
+        let (command, data) = match options.symbology {
+            Symbology::Code128 => (format!("^BCN,{},{},N,N,A", height, hri), barcode.data()),
+            Symbology::Ean13 => (format!("^BEN,{},{},N", height, hri), &barcode.data()[..12]),
+            Symbology::UpcA => (format!("^BUN,{},{},N,Y", height, hri), &barcode.data()[..11]),
+            Symbology::Code39 => (format!("^B3N,N,{},{},N", height, hri), barcode.data()),
+            Symbology::Itf => (format!("^B2N,{},{},N,N", height, hri), barcode.data()),
+        };
+        format!(
+            "^FO{},{}^BY{},3,{}{}^FH^FD{}^FS\n",
+            x, y, options.module_width.clamp(1, 6), height, command, escape(data)
+        )
+        .into_bytes()
+    }
+
+    fn qr(&self, x: u32, y: u32, data: &str, options: &QrOptions) -> Vec<u8> {
+        let level = match options.error_correction {
+            ErrorCorrection::Low => 'L',
+            ErrorCorrection::Medium => 'M',
+            ErrorCorrection::Quartile => 'Q',
+            ErrorCorrection::High => 'H',
+        };
+        let model = if options.model == 1 { 1 } else { 2 };
+        format!(
+            "^FO{},{}^BQN,{},{}^FH^FD{}A,{}^FS\n",
+            x, y, model, options.module_size.clamp(1, 10), level, escape(data)
+        )
+        .into_bytes()
+    }
+
+    fn end(&self) -> Vec<u8> {
+        b"^XZ\n".to_vec()
+    }
+}
+
+/// Field data with the characters ZPL reads as commands written as ^FH hex escapes
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '^' | '~' | '_' => format!("_{:02X}", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_set::Emulation;
+    use crate::document::Document;
+
+    #[test]
+    fn documents_become_zpl_fields() {
+        let json = serde_json::json!({ "elements": [
+            { "type": "text", "text": "CUP 2 ^ LATTE", "justify": "center", "height": 2 },
+            { "type": "barcode", "data": "A_42", "height": 60 },
+            { "type": "qr", "data": "42", "size": 3 }
+        ] });
+        let mut profile = PrinterProfile {
+            emulation: Emulation::Zpl,
+            dot_width: 400,
+            ..PrinterProfile::default()
+        };
+        profile.label.darkness = Some(20);
+
+        let data = Document::from_json(&json).unwrap().render(&profile).unwrap();
+        let zpl = String::from_utf8(data).unwrap();
+        assert_eq!(
+            zpl,
+            "~SD20\n^XA\n^CI28\n^PW400\n^LL240\n^MNY\n\
+             ^FO0,0^A0N,48,24^FB400,1,0,C,0^FH^FDCUP 2 _5E LATTE^FS\n\
+             ^FO0,60^BY2,3,60^BCN,60,Y,N,N,A^FH^FDA_5F42^FS\n\
+             ^FO0,158^BQN,2,3^FH^FDMA,42^FS\n\
+             ^XZ\n"
+        );
+    }
+
+    #[test]
+    fn tall_images_span_labels() {
+        let profile = PrinterProfile {
+            emulation: Emulation::Zpl,
+            dot_width: 16,
+            ..PrinterProfile::default()
+        };
+        let mut bitmap = MonoBitmap::new(16, 300);
+        bitmap.set(0, 0, true);
+        bitmap.set(15, 299, true);
+
+        let zpl = String::from_utf8(crate::escpos::encode_bitmap(&bitmap, &profile)).unwrap();
+        assert_eq!(zpl.matches("^XA").count(), 2);
+        assert!(zpl.contains("^FO0,0^GFA,478,478,2,8000"));
+        assert!(zpl.contains("^FO0,0^GFA,122,122,2,"));
+        assert!(zpl.ends_with("0001^FS\n^XZ\n"));
+    }
+}
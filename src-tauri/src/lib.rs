@@ -1,8 +1,24 @@
+mod barcode;
 mod commands;
+mod command_set;
+mod codepage;
 mod config;
 mod printer;
+mod retry;
 mod escpos;
+mod dither;
+mod emulator;
+mod document;
+mod html;
 mod api;
+mod journal;
+mod label;
+mod logo;
+mod pdf;
+mod service;
+mod text;
+mod transport;
+mod zatca;
 
 use tauri::{
     menu::{Menu, MenuItem},
@@ -24,6 +40,7 @@ pub fn run() {
             MacosLauncher::LaunchAgent,
             Some(vec!["--minimized"]),
         ))
+        .manage(service::PrintService::default())
         .setup(|app| {
             // Create tray menu
             let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
@@ -74,6 +91,16 @@ pub fn run() {
                 eprintln!("Failed to create config directory: {}", e);
             }
 
+            // Auto-start the print service if the API is configured
+            if let Ok(cfg) = config::load_config() {
+                if !cfg.domain_url.is_empty() && !cfg.key.is_empty() {
+                    let service = app.state::<service::PrintService>();
+                    if let Err(e) = service.start(app.handle().clone()) {
+                        eprintln!("Failed to start print service: {}", e);
+                    }
+                }
+            }
+
             Ok(())
         })
         .on_window_event(|window, event| {
@@ -89,9 +116,20 @@ pub fn run() {
             commands::save_config,
             commands::test_connection,
 
+            // Service commands
+            commands::start_service,
+            commands::stop_service,
+            commands::get_service_status,
+            commands::get_printer_health,
+            commands::get_dead_letters,
+            commands::reprint_dead_letter,
+            commands::discard_dead_letter,
+
             // Printer commands
             commands::fetch_printers,
             commands::get_system_printers,
+            commands::get_printer_status,
+            commands::query_printer_status,
             commands::poll_print_jobs,
             commands::update_job_status,
             commands::test_print,
@@ -104,6 +142,11 @@ pub fn run() {
             commands::print_pdf_to_thermal,
             commands::print_text_content,
             commands::print_html_content,
+            commands::print_document,
+            commands::preview_print_job,
+            commands::store_printer_logo,
+            commands::delete_printer_logo,
+            commands::get_printer_logos,
 
             // Printer control commands
             commands::cut_paper,
@@ -0,0 +1,214 @@
+use anyhow::{bail, Context, Result};
+use image::{GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use crate::config;
+use crate::dither::MonoBitmap;
+
+/// Printer memory logos are stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogoMemory {
+    /// NV graphics addressed by a two-character key (`GS ( L` functions 67/69)
+    #[default]
+    Graphics,
+    /// Numbered NV bit images (`FS q` / `FS p`) on older printers; storing
+    /// one logo rewrites all of them
+    Legacy,
+}
+
+/// A logo uploaded to a printer, tracked in its profile under the logo's key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredLogo {
+    pub width: u32,
+    pub height: u32,
+    /// `FS p` image number when stored in legacy memory
+    #[serde(default)]
+    pub number: Option<u8>,
+    /// When the logo was uploaded, RFC 3339
+    pub stored_at: String,
+}
+
+/// Check a logo key: two printable ASCII characters, e.g. `"LG"`
+pub fn key_code(key: &str) -> Result<[u8; 2]> {
+    match key.as_bytes() {
+        &[k1, k2] if (32..=126).contains(&k1) && (32..=126).contains(&k2) => Ok([k1, k2]),
+        _ => bail!("Logo key must be two printable ASCII characters, got {:?}", key),
+    }
+}
+
+/// Store a bitmap as NV graphics under `key` (GS ( L / GS 8 L function 67)
+pub fn store_command(key: [u8; 2], bitmap: &MonoBitmap) -> Vec<u8> {
+    // m fn a kc1 kc2 b xL xH yL yH c, then the rows
+    let mut body = vec![48, 67, 48, key[0], key[1], 1];
+    body.extend_from_slice(&(bitmap.width() as u16).to_le_bytes());
+    body.extend_from_slice(&(bitmap.height() as u16).to_le_bytes());
+    body.push(49);
+    for y in 0..bitmap.height() {
+        bitmap.pack_row(y, &mut body);
+    }
+
+    let mut data = Vec::with_capacity(body.len() + 7);
+    if body.len() <= 0xFFFF {
+        data.extend_from_slice(&[0x1D, 0x28, 0x4C]);
+        data.extend_from_slice(&(body.len() as u16).to_le_bytes());
+    } else {
+        // Logos over 64 KB need the extended form with a 32-bit length
+        data.extend_from_slice(&[0x1D, 0x38, 0x4C]);
+        data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    }
+    data.extend_from_slice(&body);
+    data
+}
+
+/// Print the NV graphics stored under `key` at normal size (GS ( L function 69)
+pub fn print_command(key: [u8; 2]) -> Vec<u8> {
+    vec![0x1D, 0x28, 0x4C, 0x06, 0x00, 48, 69, key[0], key[1], 1, 1]
+}
+
+/// Delete the NV graphics stored under `key` (GS ( L function 66)
+pub fn delete_command(key: [u8; 2]) -> Vec<u8> {
+    vec![0x1D, 0x28, 0x4C, 0x04, 0x00, 48, 66, key[0], key[1]]
+}
+
+/// Replace every legacy NV bit image with `bitmaps`, numbered from 1 (FS q)
+pub fn legacy_store_command(bitmaps: &[&MonoBitmap]) -> Vec<u8> {
+    let mut data = vec![0x1C, 0x71, bitmaps.len() as u8];
+    for bitmap in bitmaps {
+        // Sizes are in units of 8 dots, data goes column by column, top byte first
+        let (x, y) = ((bitmap.width() + 7) / 8, (bitmap.height() + 7) / 8);
+        data.extend_from_slice(&(x as u16).to_le_bytes());
+        data.extend_from_slice(&(y as u16).to_le_bytes());
+        for column in 0..x * 8 {
+            for row in 0..y {
+                let mut byte = 0u8;
+                for bit in 0..8 {
+                    if bitmap.get(column, row * 8 + bit) {
+                        byte |= 0x80 >> bit;
+                    }
+                }
+                data.push(byte);
+            }
+        }
+    }
+    data
+}
+
+/// Print legacy NV bit image `number` at normal size (FS p)
+pub fn legacy_print_command(number: u8) -> Vec<u8> {
+    vec![0x1C, 0x70, number, 0]
+}
+
+/// NV bit image memory of common printers, shared by all legacy logos
+const LEGACY_MAX_BYTES: usize = 256 * 1024;
+
+/// Check that legacy images fit one `FS q`: at most 255 images of at most 2304 rows
+pub fn check_legacy(bitmaps: &[&MonoBitmap]) -> Result<()> {
+    if bitmaps.len() > 255 {
+        bail!("Legacy NV memory holds at most 255 logos");
+    }
+    if let Some(bitmap) = bitmaps.iter().find(|b| b.height() > 2304) {
+        bail!("Legacy NV logos are limited to 2304 rows, got {}", bitmap.height());
+    }
+    let total: usize = bitmaps.iter().map(|b| (((b.width() + 7) / 8) * ((b.height() + 7) / 8) * 8) as usize).sum();
+    if total > LEGACY_MAX_BYTES {
+        bail!("Logos need {} bytes, more than legacy NV memory allows", total);
+    }
+    Ok(())
+}
+
+/// Where the bitmap of a stored logo is kept, so legacy memory can be rewritten
+fn bitmap_path(printer_name: &str, key: &str) -> PathBuf {
+    let printer: String = printer_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let key: String = key.bytes().map(|b| format!("{:02x}", b)).collect();
+    config::get_config_dir().join("logos").join(format!("{}_{}.png", printer, key))
+}
+
+/// Keep a copy of a logo's bitmap next to the config
+pub fn save_bitmap(printer_name: &str, key: &str, bitmap: &MonoBitmap) -> Result<()> {
+    let path = bitmap_path(printer_name, key);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create logo directory")?;
+    }
+    let image = GrayImage::from_fn(bitmap.width(), bitmap.height(), |x, y| {
+        Luma([if bitmap.get(x, y) { 0 } else { 255 }])
+    });
+    image
+        .save(&path)
+        .with_context(|| format!("Failed to save logo {}", path.display()))
+}
+
+/// Load the copy of a stored logo's bitmap
+pub fn load_bitmap(printer_name: &str, key: &str) -> Result<MonoBitmap> {
+    let path = bitmap_path(printer_name, key);
+    let image = image::open(&path)
+        .with_context(|| format!("Logo {:?} has no saved bitmap at {}", key, path.display()))?
+        .to_luma8();
+    let mut bitmap = MonoBitmap::new(image.width(), image.height());
+    for (x, y, pixel) in image.enumerate_pixels() {
+        bitmap.set(x, y, pixel[0] < 128);
+    }
+    Ok(bitmap)
+}
+
+/// Remove the copy of a logo's bitmap
+pub fn remove_bitmap(printer_name: &str, key: &str) {
+    let _ = fs::remove_file(bitmap_path(printer_name, key));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_two_printable_characters() {
+        assert_eq!(key_code("LG").unwrap(), [b'L', b'G']);
+        assert!(key_code("L").is_err());
+        assert!(key_code("LGO").is_err());
+        assert!(key_code("L\n").is_err());
+    }
+
+    #[test]
+    fn graphics_commands() {
+        let mut bitmap = MonoBitmap::new(10, 2);
+        bitmap.set(0, 0, true);
+        bitmap.set(9, 1, true);
+
+        assert_eq!(
+            store_command(*b"LG", &bitmap),
+            vec![
+                0x1D, 0x28, 0x4C, 15, 0, 48, 67, 48, b'L', b'G', 1, 10, 0, 2, 0, 49,
+                0x80, 0x00,
+                0x00, 0x40,
+            ]
+        );
+        assert_eq!(print_command(*b"LG"), vec![0x1D, 0x28, 0x4C, 6, 0, 48, 69, b'L', b'G', 1, 1]);
+    }
+
+    #[test]
+    fn legacy_images_are_column_major() {
+        let mut bitmap = MonoBitmap::new(8, 16);
+        bitmap.set(0, 0, true);
+        bitmap.set(1, 15, true);
+
+        let data = legacy_store_command(&[&bitmap]);
+        assert_eq!(&data[..7], &[0x1C, 0x71, 1, 1, 0, 2, 0]);
+        assert_eq!(&data[7..11], &[0x80, 0x00, 0x00, 0x01]);
+        assert_eq!(data.len(), 7 + 16);
+    }
+
+    #[test]
+    fn legacy_memory_counts_whole_blocks() {
+        // 256 x 128 blocks of 8x8 dots fill the memory exactly
+        let fits = MonoBitmap::new(2041, 1024);
+        assert!(check_legacy(&[&fits]).is_ok());
+
+        let too_tall = MonoBitmap::new(2041, 1025);
+        assert!(check_legacy(&[&too_tall]).is_err());
+    }
+}
@@ -0,0 +1,1638 @@
+use anyhow::{bail, Context, Result};
+use image::{imageops::FilterType, DynamicImage, GrayImage, Luma};
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use tiny_skia::{
+    Color, FillRule, FilterQuality, LineCap, LineJoin, Mask, Paint, Path, PathBuilder, Pixmap,
+    PixmapPaint, Stroke, StrokeDash, Transform,
+};
+use ttf_parser as ttf;
+use crate::text::FONT;
+
+/// Page selection and sizing for PDF jobs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PdfOptions {
+    /// Pages to print, e.g. "1-3,5" or "2-"; every page when unset
+    pub pages: Option<String>,
+    /// Width of the printed page as a fraction of the printable width
+    pub scale: f32,
+    /// Cut the white margins around the page content before scaling
+    pub trim: bool,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            pages: None,
+            scale: 1.0,
+            trim: true,
+        }
+    }
+}
+
+impl PdfOptions {
+    /// These options with a job's page range and scale, where given
+    pub fn with_overrides(&self, pages: Option<String>, scale: Option<f32>) -> Self {
+        Self {
+            pages: pages.or_else(|| self.pages.clone()),
+            scale: scale.unwrap_or(self.scale),
+            trim: self.trim,
+        }
+    }
+}
+
+/// Pages are drawn at this many pixels per printer dot and averaged down,
+/// so hairlines and small text survive the reduction to the printer width
+const OVERSAMPLE: f32 = 3.0;
+
+/// Upper bound on the pixels drawn for one page, very long pages are drawn coarser
+const MAX_PAGE_PIXELS: f32 = 40_000_000.0;
+
+/// Pages longer than this many times their width (or the other way round)
+/// are refused, they would print as an image of millions of rows
+const MAX_PAGE_ASPECT: f32 = 100.0;
+
+/// Nesting limit for form XObjects drawing each other
+const MAX_FORM_DEPTH: usize = 8;
+
+/// Pixels lighter than this count as paper when trimming
+const WHITE_LEVEL: u8 = 250;
+
+/// Decode a base64 or data URL PDF
+pub fn decode_base64(source: &str) -> Result<Vec<u8>> {
+    // Remove data URL prefix if present
+    let base64_data = source.rsplit(',').next().unwrap_or(source);
+    let base64_data: String = base64_data.chars().filter(|c| !c.is_whitespace()).collect();
+
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD
+        .decode(base64_data)
+        .context("Failed to decode base64 PDF")
+}
+
+/// Parse a 1-based page selection like "1-3,5,8-" against the page count.
+/// Ranges running past the last page are clamped to it.
+pub fn parse_page_range(spec: &str, page_count: u32) -> Result<Vec<u32>> {
+    fn page(s: &str, default: u32) -> Result<u32> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(default);
+        }
+        s.parse().with_context(|| format!("Invalid page number: {}", s))
+    }
+
+    let mut pages = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (page(start, 1)?, page(end, page_count)?),
+            None => {
+                let p = page(part, 0)?;
+                (p, p)
+            }
+        };
+
+        if start == 0 || start > end || start > page_count {
+            bail!("Page range {} is outside 1-{}", part, page_count);
+        }
+        pages.extend(start..=end.min(page_count));
+    }
+
+    if pages.is_empty() {
+        bail!("No pages selected");
+    }
+    Ok(pages)
+}
+
+/// Render the selected pages of a PDF, each scaled to `width` dots times the
+/// option's scale. Pages without any ink are skipped when trimming.
+pub fn render_pages(data: &[u8], width: u32, options: &PdfOptions) -> Result<Vec<GrayImage>> {
+    let mut doc = Document::load_mem(data).context("Failed to parse PDF")?;
+    if doc.is_encrypted() {
+        doc.decrypt("").context("PDF is password protected")?;
+    }
+
+    let pages = doc.get_pages();
+    let count = pages.len() as u32;
+    if count == 0 {
+        bail!("PDF has no pages");
+    }
+
+    let selected = match &options.pages {
+        Some(spec) => parse_page_range(spec, count)?,
+        None => (1..=count).collect(),
+    };
+    let target = ((width as f32 * options.scale.clamp(0.05, 1.0)).round() as u32).max(1);
+
+    let mut renderer = Renderer::new(&doc);
+    let mut images = Vec::new();
+    for number in selected {
+        let page = Page::load(&doc, pages[&number])?;
+        match renderer.render(&page, target, options.trim)? {
+            Some(image) => images.push(image),
+            None => println!("📄 Skipping blank PDF page {}", number),
+        }
+    }
+
+    Ok(images)
+}
+
+/// Attributes of one page, with inherited values resolved
+struct Page<'a> {
+    id: ObjectId,
+    /// Visible area in default user space: x0, y0, x1, y1
+    bounds: [f32; 4],
+    resources: Option<&'a Dictionary>,
+    rotate: i64,
+}
+
+impl<'a> Page<'a> {
+    fn load(doc: &'a Document, id: ObjectId) -> Result<Self> {
+        let dict = doc.get_dictionary(id).context("Invalid PDF page")?;
+
+        let bounds = inherited(doc, dict, b"CropBox")
+            .or_else(|| inherited(doc, dict, b"MediaBox"))
+            .and_then(|o| o.as_array().ok())
+            .and_then(|a| numbers(doc, a).try_into().ok())
+            .map(|[x0, y0, x1, y1]: [f32; 4]| [x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)])
+            .filter(|b| b[2] > b[0] && b[3] > b[1])
+            // US Letter, the spec's default
+            .unwrap_or([0.0, 0.0, 612.0, 792.0]);
+
+        let (width, height) = (bounds[2] - bounds[0], bounds[3] - bounds[1]);
+        if width / height > MAX_PAGE_ASPECT || height / width > MAX_PAGE_ASPECT {
+            bail!("PDF page of {} x {} points is too narrow to print", width, height);
+        }
+
+        Ok(Self {
+            id,
+            bounds,
+            resources: inherited(doc, dict, b"Resources").and_then(|o| o.as_dict().ok()),
+            rotate: inherited(doc, dict, b"Rotate").and_then(|o| o.as_i64().ok()).unwrap_or(0),
+        })
+    }
+
+    fn content(&self, doc: &Document) -> Vec<u8> {
+        // Separate the streams, a page may split its content anywhere between tokens
+        let mut data = Vec::new();
+        for id in doc.get_page_contents(self.id) {
+            if let Ok(stream) = doc.get_object(id).and_then(Object::as_stream) {
+                data.extend(stream_data(stream));
+                data.push(b'\n');
+            }
+        }
+        data
+    }
+}
+
+/// Look a page attribute up, walking up the page tree
+fn inherited<'a>(doc: &'a Document, mut dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    for _ in 0..32 {
+        if let Ok(value) = dict.get(key) {
+            return deref(doc, value);
+        }
+        dict = dict.get(b"Parent").ok().and_then(|p| deref(doc, p))?.as_dict().ok()?;
+    }
+    None
+}
+
+fn deref<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    doc.dereference(object).ok().map(|(_, o)| o)
+}
+
+/// Dictionary entry with references resolved
+fn get<'a>(doc: &'a Document, dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    deref(doc, dict.get(key).ok()?)
+}
+
+fn numbers(doc: &Document, array: &[Object]) -> Vec<f32> {
+    array
+        .iter()
+        .filter_map(|o| deref(doc, o)?.as_float().ok())
+        .collect()
+}
+
+/// Decoded stream content, or the raw bytes when a filter isn't supported
+fn stream_data(stream: &Stream) -> Vec<u8> {
+    if stream.filters().map(|f| f.is_empty()).unwrap_or(true) {
+        return stream.content.clone();
+    }
+    stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone())
+}
+
+fn matrix(values: &[f32]) -> Option<Transform> {
+    match values {
+        [a, b, c, d, e, f] => Some(Transform::from_row(*a, *b, *c, *d, *e, *f)),
+        _ => None,
+    }
+}
+
+/// Draws pages, keeping loaded fonts across pages
+struct Renderer<'a> {
+    doc: &'a Document,
+    fonts: HashMap<ObjectId, Rc<PdfFont>>,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(doc: &'a Document) -> Self {
+        Self {
+            doc,
+            fonts: HashMap::new(),
+        }
+    }
+
+    /// Render a page `target` dots wide, optionally trimmed to its ink
+    fn render(&mut self, page: &Page<'a>, target: u32, trim: bool) -> Result<Option<GrayImage>> {
+        let [x0, y0, x1, y1] = page.bounds;
+        let rotated = page.rotate.rem_euclid(360) / 90 % 2 == 1;
+        // After rotation the page's height is what spans the paper width
+        let span = if rotated { y1 - y0 } else { x1 - x0 };
+
+        let mut view = page.bounds;
+        if trim {
+            // Rough pass at one pixel per dot to find the ink
+            let zoom = (target as f32 / span).min((MAX_PAGE_PIXELS / ((x1 - x0) * (y1 - y0))).sqrt());
+            let (pixmap, _) = self.draw(page, view, zoom, 1.0)?;
+            let Some([left, top, right, bottom]) = ink_bounds(&pixmap) else {
+                return Ok(None);
+            };
+            view = [
+                x0 + (left.saturating_sub(1)) as f32 / zoom,
+                y1 - (bottom + 2) as f32 / zoom,
+                x0 + (right + 2) as f32 / zoom,
+                y1 - (top.saturating_sub(1)) as f32 / zoom,
+            ];
+            view = [view[0].max(x0), view[1].max(y0), view[2].min(x1), view[3].min(y1)];
+        }
+
+        let (w, h) = (view[2] - view[0], view[3] - view[1]);
+        let span = if rotated { h } else { w };
+        let mut zoom = target as f32 * OVERSAMPLE / span;
+        zoom = zoom.min((MAX_PAGE_PIXELS / (w * h)).sqrt());
+
+        let (pixmap, skipped) = self.draw(page, view, zoom, OVERSAMPLE)?;
+        if skipped > 0 {
+            println!("⚠️ {} PDF operation(s) could not be drawn", skipped);
+        }
+        let gray = to_gray(&pixmap);
+        let gray = match page.rotate.rem_euclid(360) {
+            90 => image::imageops::rotate90(&gray),
+            180 => image::imageops::rotate180(&gray),
+            270 => image::imageops::rotate270(&gray),
+            _ => gray,
+        };
+
+        let height = ((gray.height() as f32 * target as f32 / gray.width() as f32).round() as u32).max(1);
+        let resized = DynamicImage::ImageLuma8(gray).resize_exact(target, height, FilterType::Triangle);
+        Ok(Some(resized.to_luma8()))
+    }
+
+    /// Draw the part of a page inside `view` at `zoom` pixels per point
+    fn draw(&mut self, page: &Page<'a>, view: [f32; 4], zoom: f32, min_line: f32) -> Result<(Pixmap, usize)> {
+        let width = ((view[2] - view[0]) * zoom).ceil().max(1.0) as u32;
+        let height = ((view[3] - view[1]) * zoom).ceil().max(1.0) as u32;
+        let Some(mut pixmap) = Pixmap::new(width, height) else {
+            bail!("PDF page is too large to draw at {} x {} pixels", width, height);
+        };
+        pixmap.fill(Color::WHITE);
+
+        let base = Transform::from_row(zoom, 0.0, 0.0, -zoom, -view[0] * zoom, view[3] * zoom);
+        let content = page.content(self.doc);
+        let mut canvas = Canvas {
+            renderer: self,
+            pixmap: &mut pixmap,
+            base,
+            min_line,
+            depth: 0,
+            skipped: 0,
+        };
+        canvas.run(&content, page.resources, GraphicsState::default());
+        let skipped = canvas.skipped;
+
+        Ok((pixmap, skipped))
+    }
+
+    fn font(&mut self, object: &'a Object) -> Option<Rc<PdfFont>> {
+        let id = object.as_reference().ok();
+        if let Some(font) = id.and_then(|id| self.fonts.get(&id)) {
+            return Some(font.clone());
+        }
+
+        let dict = deref(self.doc, object)?.as_dict().ok()?;
+        let font = Rc::new(PdfFont::load(self.doc, dict));
+        if let Some(id) = id {
+            self.fonts.insert(id, font.clone());
+        }
+        Some(font)
+    }
+}
+
+/// Bounding box of the non-white pixels: left, top, right, bottom
+fn ink_bounds(pixmap: &Pixmap) -> Option<[u32; 4]> {
+    let width = pixmap.width();
+    let mut bounds: Option<[u32; 4]> = None;
+
+    for (i, pixel) in pixmap.pixels().iter().enumerate() {
+        if luma(pixel.red(), pixel.green(), pixel.blue()) >= WHITE_LEVEL {
+            continue;
+        }
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        bounds = Some(match bounds {
+            None => [x, y, x, y],
+            Some([l, t, r, b]) => [l.min(x), t.min(y), r.max(x), b.max(y)],
+        });
+    }
+
+    bounds
+}
+
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+fn to_gray(pixmap: &Pixmap) -> GrayImage {
+    let pixels = pixmap.pixels();
+    GrayImage::from_fn(pixmap.width(), pixmap.height(), |x, y| {
+        let p = pixels[(y * pixmap.width() + x) as usize];
+        Luma([luma(p.red(), p.green(), p.blue())])
+    })
+}
+
+/// Colour space set with `cs`/`CS`, used to read `sc`/`scn` operands
+#[derive(Clone)]
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// Single ink tint, 1.0 is full ink
+    Separation,
+    Indexed { base: Box<ColorSpace>, lookup: Vec<u8> },
+    Pattern,
+}
+
+impl ColorSpace {
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Separation | ColorSpace::Indexed { .. } => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+            ColorSpace::Pattern => 0,
+        }
+    }
+
+    /// Colour of samples in 0..1
+    fn color(&self, values: &[f32]) -> Option<Color> {
+        let v = |i: usize| values.get(i).copied().unwrap_or(0.0).clamp(0.0, 1.0);
+        match self {
+            ColorSpace::Gray => Some(gray(v(0))),
+            ColorSpace::Separation => Some(gray(1.0 - v(0))),
+            ColorSpace::Rgb => Color::from_rgba(v(0), v(1), v(2), 1.0),
+            ColorSpace::Cmyk => Some(cmyk(v(0), v(1), v(2), v(3))),
+            ColorSpace::Indexed { base, lookup } => {
+                let n = base.components();
+                let index = values.first().copied().unwrap_or(0.0).max(0.0) as usize;
+                let entry: Vec<f32> = (0..n)
+                    .map(|c| lookup.get(index * n + c).copied().unwrap_or(0) as f32 / 255.0)
+                    .collect();
+                base.color(&entry)
+            }
+            ColorSpace::Pattern => None,
+        }
+    }
+
+    fn parse(doc: &Document, object: &Object, resources: Option<&Dictionary>) -> ColorSpace {
+        let Some(object) = deref(doc, object) else {
+            return ColorSpace::Gray;
+        };
+
+        match object {
+            Object::Name(name) => match name.as_slice() {
+                b"DeviceGray" | b"CalGray" | b"G" => ColorSpace::Gray,
+                b"DeviceRGB" | b"CalRGB" | b"RGB" | b"Lab" => ColorSpace::Rgb,
+                b"DeviceCMYK" | b"CMYK" => ColorSpace::Cmyk,
+                b"Pattern" => ColorSpace::Pattern,
+                // Named resource
+                _ => resources
+                    .and_then(|r| r.get(b"ColorSpace").ok())
+                    .and_then(|o| deref(doc, o)?.as_dict().ok())
+                    .and_then(|d| d.get(name).ok())
+                    .map(|o| ColorSpace::parse(doc, o, None))
+                    .unwrap_or(ColorSpace::Gray),
+            },
+            Object::Array(items) => {
+                let family = items.first().and_then(|o| o.as_name().ok()).unwrap_or(b"");
+                match family {
+                    b"ICCBased" => {
+                        let n = items
+                            .get(1)
+                            .and_then(|o| deref(doc, o)?.as_stream().ok())
+                            .and_then(|s| s.dict.get(b"N").ok()?.as_i64().ok())
+                            .unwrap_or(3);
+                        match n {
+                            1 => ColorSpace::Gray,
+                            4 => ColorSpace::Cmyk,
+                            _ => ColorSpace::Rgb,
+                        }
+                    }
+                    b"Indexed" | b"I" => {
+                        let base = items
+                            .get(1)
+                            .map(|o| ColorSpace::parse(doc, o, resources))
+                            .unwrap_or(ColorSpace::Rgb);
+                        let lookup = match items.get(3).and_then(|o| deref(doc, o)) {
+                            Some(Object::String(bytes, _)) => bytes.clone(),
+                            Some(Object::Stream(stream)) => stream_data(stream),
+                            _ => Vec::new(),
+                        };
+                        ColorSpace::Indexed {
+                            base: Box::new(base),
+                            lookup,
+                        }
+                    }
+                    b"Separation" | b"DeviceN" => ColorSpace::Separation,
+                    b"Pattern" => ColorSpace::Pattern,
+                    b"CalRGB" | b"Lab" => ColorSpace::Rgb,
+                    b"CalGray" => ColorSpace::Gray,
+                    _ => ColorSpace::Gray,
+                }
+            }
+            _ => ColorSpace::Gray,
+        }
+    }
+}
+
+fn gray(v: f32) -> Color {
+    Color::from_rgba(v, v, v, 1.0).unwrap_or(Color::BLACK)
+}
+
+fn cmyk(c: f32, m: f32, y: f32, k: f32) -> Color {
+    Color::from_rgba((1.0 - c) * (1.0 - k), (1.0 - m) * (1.0 - k), (1.0 - y) * (1.0 - k), 1.0)
+        .unwrap_or(Color::BLACK)
+}
+
+/// The part of the PDF graphics state that affects a black and white print
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Transform,
+    /// `None` paints nothing, used for patterns
+    fill: Option<Color>,
+    stroke: Option<Color>,
+    fill_space: ColorSpace,
+    stroke_space: ColorSpace,
+    fill_alpha: f32,
+    stroke_alpha: f32,
+    line_width: f32,
+    line_cap: LineCap,
+    line_join: LineJoin,
+    dash: Option<(Vec<f32>, f32)>,
+    clip: Option<Rc<Mask>>,
+    text: TextState,
+}
+
+impl Default for GraphicsState {
+    fn default() -> Self {
+        Self {
+            ctm: Transform::identity(),
+            fill: Some(Color::BLACK),
+            stroke: Some(Color::BLACK),
+            fill_space: ColorSpace::Gray,
+            stroke_space: ColorSpace::Gray,
+            fill_alpha: 1.0,
+            stroke_alpha: 1.0,
+            line_width: 1.0,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            dash: None,
+            clip: None,
+            text: TextState::default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TextState {
+    font: Option<Rc<PdfFont>>,
+    size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    /// Horizontal scaling, 1.0 = 100%
+    scale: f32,
+    leading: f32,
+    rise: f32,
+    render_mode: i64,
+    matrix: Transform,
+    line_matrix: Transform,
+}
+
+impl Default for TextState {
+    fn default() -> Self {
+        Self {
+            font: None,
+            size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            scale: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+            render_mode: 0,
+            matrix: Transform::identity(),
+            line_matrix: Transform::identity(),
+        }
+    }
+}
+
+/// Executes content streams onto a pixmap
+struct Canvas<'r, 'a> {
+    renderer: &'r mut Renderer<'a>,
+    pixmap: &'r mut Pixmap,
+    /// Default user space to pixels
+    base: Transform,
+    /// Thinnest line drawn, in pixels
+    min_line: f32,
+    depth: usize,
+    /// Operations that couldn't be drawn
+    skipped: usize,
+}
+
+impl<'a> Canvas<'_, 'a> {
+    fn run(&mut self, data: &[u8], resources: Option<&'a Dictionary>, state: GraphicsState) {
+        let content = match Content::decode(data) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("Failed to parse PDF content: {}", e);
+                self.skipped += 1;
+                return;
+            }
+        };
+
+        let doc = self.renderer.doc;
+        let mut stack: Vec<GraphicsState> = Vec::new();
+        let mut gs = state;
+        let mut path = PathBuilder::new();
+        let mut current = (0.0f32, 0.0f32);
+        let mut pending_clip: Option<FillRule> = None;
+
+        for op in &content.operations {
+            let operands = &op.operands;
+            let n = |i: usize| -> f32 { operands.get(i).and_then(|o| o.as_float().ok()).unwrap_or(0.0) };
+            let floats = || -> Vec<f32> { operands.iter().filter_map(|o| o.as_float().ok()).collect() };
+
+            match op.operator.as_str() {
+                // Graphics state
+                "q" => stack.push(gs.clone()),
+                "Q" => {
+                    if let Some(saved) = stack.pop() {
+                        gs = saved;
+                    }
+                }
+                "cm" => {
+                    if let Some(m) = matrix(&floats()) {
+                        gs.ctm = gs.ctm.pre_concat(m);
+                    }
+                }
+                "w" => gs.line_width = n(0),
+                "J" => {
+                    gs.line_cap = match n(0) as i64 {
+                        1 => LineCap::Round,
+                        2 => LineCap::Square,
+                        _ => LineCap::Butt,
+                    }
+                }
+                "j" => {
+                    gs.line_join = match n(0) as i64 {
+                        1 => LineJoin::Round,
+                        2 => LineJoin::Bevel,
+                        _ => LineJoin::Miter,
+                    }
+                }
+                "d" => {
+                    let array = operands
+                        .first()
+                        .and_then(|o| o.as_array().ok())
+                        .map(|a| numbers(doc, a))
+                        .unwrap_or_default();
+                    gs.dash = if array.is_empty() { None } else { Some((array, n(1))) };
+                }
+                "gs" => {
+                    if let Some(ext) = operands
+                        .first()
+                        .and_then(|o| self.resource(resources, b"ExtGState", o))
+                        .and_then(|o| o.as_dict().ok())
+                    {
+                        self.apply_ext_state(ext, &mut gs);
+                    }
+                }
+
+                // Colours
+                "g" => {
+                    gs.fill_space = ColorSpace::Gray;
+                    gs.fill = Some(gray(n(0)));
+                }
+                "G" => {
+                    gs.stroke_space = ColorSpace::Gray;
+                    gs.stroke = Some(gray(n(0)));
+                }
+                "rg" => {
+                    gs.fill_space = ColorSpace::Rgb;
+                    gs.fill = ColorSpace::Rgb.color(&floats());
+                }
+                "RG" => {
+                    gs.stroke_space = ColorSpace::Rgb;
+                    gs.stroke = ColorSpace::Rgb.color(&floats());
+                }
+                "k" => {
+                    gs.fill_space = ColorSpace::Cmyk;
+                    gs.fill = ColorSpace::Cmyk.color(&floats());
+                }
+                "K" => {
+                    gs.stroke_space = ColorSpace::Cmyk;
+                    gs.stroke = ColorSpace::Cmyk.color(&floats());
+                }
+                "cs" | "CS" => {
+                    let space = operands
+                        .first()
+                        .map(|o| ColorSpace::parse(doc, o, resources))
+                        .unwrap_or(ColorSpace::Gray);
+                    // Selecting a space resets the colour to black
+                    let color = match space {
+                        ColorSpace::Pattern => None,
+                        _ => Some(Color::BLACK),
+                    };
+                    if op.operator == "cs" {
+                        gs.fill_space = space;
+                        gs.fill = color;
+                    } else {
+                        gs.stroke_space = space;
+                        gs.stroke = color;
+                    }
+                }
+                "sc" | "scn" => gs.fill = gs.fill_space.color(&floats()),
+                "SC" | "SCN" => gs.stroke = gs.stroke_space.color(&floats()),
+
+                // Path construction
+                "m" => {
+                    current = (n(0), n(1));
+                    path.move_to(n(0), n(1));
+                }
+                "l" => {
+                    current = (n(0), n(1));
+                    path.line_to(n(0), n(1));
+                }
+                "c" => {
+                    current = (n(4), n(5));
+                    path.cubic_to(n(0), n(1), n(2), n(3), n(4), n(5));
+                }
+                "v" => {
+                    path.cubic_to(current.0, current.1, n(0), n(1), n(2), n(3));
+                    current = (n(2), n(3));
+                }
+                "y" => {
+                    current = (n(2), n(3));
+                    path.cubic_to(n(0), n(1), n(2), n(3), n(2), n(3));
+                }
+                "h" => path.close(),
+                "re" => {
+                    let (x, y, w, h) = (n(0), n(1), n(2), n(3));
+                    path.move_to(x, y);
+                    path.line_to(x + w, y);
+                    path.line_to(x + w, y + h);
+                    path.line_to(x, y + h);
+                    path.close();
+                    current = (x, y);
+                }
+
+                // Path painting
+                "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "n" => {
+                    let operator = op.operator.as_str();
+                    if matches!(operator, "s" | "b" | "b*") {
+                        path.close();
+                    }
+                    let finished = std::mem::replace(&mut path, PathBuilder::new()).finish();
+
+                    if let Some(finished) = finished {
+                        let even_odd = operator.ends_with('*');
+                        let rule = if even_odd { FillRule::EvenOdd } else { FillRule::Winding };
+                        if matches!(operator, "f" | "F" | "f*" | "B" | "B*" | "b" | "b*") {
+                            self.fill(&finished, rule, &gs);
+                        }
+                        if matches!(operator, "S" | "s" | "B" | "B*" | "b" | "b*") {
+                            self.stroke(&finished, &gs);
+                        }
+                        if let Some(rule) = pending_clip {
+                            self.clip(&finished, rule, &mut gs);
+                        }
+                    }
+                    pending_clip = None;
+                }
+                "W" => pending_clip = Some(FillRule::Winding),
+                "W*" => pending_clip = Some(FillRule::EvenOdd),
+
+                // Text
+                "BT" => {
+                    gs.text.matrix = Transform::identity();
+                    gs.text.line_matrix = Transform::identity();
+                }
+                "ET" => {}
+                "Tf" => {
+                    gs.text.font = operands
+                        .first()
+                        .and_then(|o| self.font_resource(resources, o));
+                    gs.text.size = n(1);
+                }
+                "Tc" => gs.text.char_spacing = n(0),
+                "Tw" => gs.text.word_spacing = n(0),
+                "Tz" => gs.text.scale = n(0) / 100.0,
+                "TL" => gs.text.leading = n(0),
+                "Ts" => gs.text.rise = n(0),
+                "Tr" => gs.text.render_mode = n(0) as i64,
+                "Td" => next_line(&mut gs.text, n(0), n(1)),
+                "TD" => {
+                    gs.text.leading = -n(1);
+                    next_line(&mut gs.text, n(0), n(1));
+                }
+                "Tm" => {
+                    if let Some(m) = matrix(&floats()) {
+                        gs.text.matrix = m;
+                        gs.text.line_matrix = m;
+                    }
+                }
+                "T*" => {
+                    let leading = gs.text.leading;
+                    next_line(&mut gs.text, 0.0, -leading);
+                }
+                "Tj" => {
+                    if let Some(Object::String(bytes, _)) = operands.first() {
+                        self.show_text(bytes, &mut gs);
+                    }
+                }
+                "'" => {
+                    let leading = gs.text.leading;
+                    next_line(&mut gs.text, 0.0, -leading);
+                    if let Some(Object::String(bytes, _)) = operands.first() {
+                        self.show_text(bytes, &mut gs);
+                    }
+                }
+                "\"" => {
+                    gs.text.word_spacing = n(0);
+                    gs.text.char_spacing = n(1);
+                    let leading = gs.text.leading;
+                    next_line(&mut gs.text, 0.0, -leading);
+                    if let Some(Object::String(bytes, _)) = operands.get(2) {
+                        self.show_text(bytes, &mut gs);
+                    }
+                }
+                "TJ" => {
+                    let items = operands.first().and_then(|o| o.as_array().ok());
+                    for item in items.into_iter().flatten() {
+                        match item {
+                            Object::String(bytes, _) => self.show_text(bytes, &mut gs),
+                            other => {
+                                let adjust = other.as_float().unwrap_or(0.0);
+                                let tx = -adjust / 1000.0 * gs.text.size * gs.text.scale;
+                                gs.text.matrix = gs.text.matrix.pre_translate(tx, 0.0);
+                            }
+                        }
+                    }
+                }
+
+                // XObjects
+                "Do" => {
+                    if let Some(stream) = operands
+                        .first()
+                        .and_then(|o| self.resource(resources, b"XObject", o))
+                        .and_then(|o| o.as_stream().ok())
+                    {
+                        self.draw_xobject(stream, resources, &gs);
+                    }
+                }
+
+                // Shadings and inline images are not drawn
+                "sh" | "BI" | "ID" | "EI" => self.skipped += 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// Look a named resource up in one of the resource categories
+    fn resource(&self, resources: Option<&'a Dictionary>, category: &[u8], name: &Object) -> Option<&'a Object> {
+        let doc = self.renderer.doc;
+        let category = deref(doc, resources?.get(category).ok()?)?.as_dict().ok()?;
+        deref(doc, category.get(name.as_name().ok()?).ok()?)
+    }
+
+    fn font_resource(&mut self, resources: Option<&'a Dictionary>, name: &Object) -> Option<Rc<PdfFont>> {
+        let doc = self.renderer.doc;
+        let fonts = deref(doc, resources?.get(b"Font").ok()?)?.as_dict().ok()?;
+        let font = fonts.get(name.as_name().ok()?).ok()?;
+        self.renderer.font(font)
+    }
+
+    fn apply_ext_state(&mut self, ext: &'a Dictionary, gs: &mut GraphicsState) {
+        if let Ok(width) = ext.get(b"LW").and_then(Object::as_float) {
+            gs.line_width = width;
+        }
+        if let Ok(alpha) = ext.get(b"ca").and_then(Object::as_float) {
+            gs.fill_alpha = alpha.clamp(0.0, 1.0);
+        }
+        if let Ok(alpha) = ext.get(b"CA").and_then(Object::as_float) {
+            gs.stroke_alpha = alpha.clamp(0.0, 1.0);
+        }
+        if let Ok(Object::Array(font)) = ext.get(b"Font") {
+            if let (Some(object), Some(size)) = (font.first(), font.get(1)) {
+                gs.text.font = self.renderer.font(object);
+                gs.text.size = size.as_float().unwrap_or(gs.text.size);
+            }
+        }
+    }
+
+    fn paint(color: Color, alpha: f32) -> Paint<'static> {
+        let mut paint = Paint::default();
+        let mut color = color;
+        color.apply_opacity(alpha);
+        paint.set_color(color);
+        paint.anti_alias = true;
+        paint
+    }
+
+    fn fill(&mut self, path: &Path, rule: FillRule, gs: &GraphicsState) {
+        let Some(color) = gs.fill else {
+            return;
+        };
+        let paint = Self::paint(color, gs.fill_alpha);
+        let transform = self.base.pre_concat(gs.ctm);
+        self.pixmap.fill_path(path, &paint, rule, transform, gs.clip.as_deref());
+    }
+
+    fn stroke(&mut self, path: &Path, gs: &GraphicsState) {
+        let Some(color) = gs.stroke else {
+            return;
+        };
+        let paint = Self::paint(color, gs.stroke_alpha);
+        let transform = self.base.pre_concat(gs.ctm);
+
+        // Keep lines at least one printer dot wide, in user space units
+        let scale = (transform.sx * transform.sy - transform.kx * transform.ky).abs().sqrt();
+        let min_width = if scale > 0.0 { self.min_line / scale } else { 0.0 };
+
+        let stroke = Stroke {
+            width: gs.line_width.max(min_width),
+            line_cap: gs.line_cap,
+            line_join: gs.line_join,
+            dash: gs.dash.as_ref().and_then(|(array, phase)| {
+                let mut array = array.clone();
+                if array.len() % 2 == 1 {
+                    array.extend_from_within(..);
+                }
+                StrokeDash::new(array, *phase)
+            }),
+            ..Stroke::default()
+        };
+        self.pixmap.stroke_path(path, &paint, &stroke, transform, gs.clip.as_deref());
+    }
+
+    fn clip(&mut self, path: &Path, rule: FillRule, gs: &mut GraphicsState) {
+        let transform = self.base.pre_concat(gs.ctm);
+        let mask = match &gs.clip {
+            Some(clip) => {
+                let mut mask = (**clip).clone();
+                mask.intersect_path(path, rule, true, transform);
+                mask
+            }
+            None => {
+                let Some(mut mask) = Mask::new(self.pixmap.width(), self.pixmap.height()) else {
+                    return;
+                };
+                mask.fill_path(path, rule, true, transform);
+                mask
+            }
+        };
+        gs.clip = Some(Rc::new(mask));
+    }
+
+    fn show_text(&mut self, bytes: &[u8], gs: &mut GraphicsState) {
+        let Some(font) = gs.text.font.clone() else {
+            return;
+        };
+        let text = &gs.text;
+        let visible = !matches!(text.render_mode, 3 | 7);
+        let codes: Vec<u32> = if font.two_byte {
+            bytes
+                .chunks(2)
+                .map(|c| c.iter().fold(0u32, |code, b| code << 8 | *b as u32))
+                .collect()
+        } else {
+            bytes.iter().map(|b| *b as u32).collect()
+        };
+
+        let (size, scale, rise) = (text.size, text.scale, text.rise);
+        for code in codes {
+            let text = &gs.text;
+            if visible {
+                if let Some(glyph) = font.glyph(code) {
+                    let glyph_matrix = Transform::from_row(size * scale, 0.0, 0.0, size, 0.0, rise);
+                    let transform = self
+                        .base
+                        .pre_concat(gs.ctm)
+                        .pre_concat(text.matrix)
+                        .pre_concat(glyph_matrix);
+                    if let Some(color) = gs.fill {
+                        let paint = Self::paint(color, gs.fill_alpha);
+                        self.pixmap.fill_path(&glyph.path, &paint, FillRule::Winding, transform, gs.clip.as_deref());
+                        if glyph.embolden {
+                            let stroke = Stroke {
+                                width: 0.04,
+                                ..Stroke::default()
+                            };
+                            self.pixmap.stroke_path(&glyph.path, &paint, &stroke, transform, gs.clip.as_deref());
+                        }
+                    }
+                }
+            }
+
+            let spacing = text.char_spacing
+                + if !font.two_byte && code == 32 { text.word_spacing } else { 0.0 };
+            let advance = (font.width(code) / 1000.0 * size + spacing) * scale;
+            gs.text.matrix = gs.text.matrix.pre_translate(advance, 0.0);
+        }
+    }
+
+    fn draw_xobject(&mut self, stream: &'a Stream, resources: Option<&'a Dictionary>, gs: &GraphicsState) {
+        let doc = self.renderer.doc;
+        match stream.dict.get(b"Subtype").and_then(Object::as_name).unwrap_or(b"") {
+            b"Image" => {
+                let Some(image) = decode_image(doc, stream, resources, gs.fill.unwrap_or(Color::BLACK)) else {
+                    self.skipped += 1;
+                    return;
+                };
+                let (w, h) = (image.width() as f32, image.height() as f32);
+                // Images fill the unit square, with their first row at the top
+                let transform = self
+                    .base
+                    .pre_concat(gs.ctm)
+                    .pre_concat(Transform::from_row(1.0 / w, 0.0, 0.0, -1.0 / h, 0.0, 1.0));
+                let paint = PixmapPaint {
+                    opacity: gs.fill_alpha,
+                    quality: FilterQuality::Bilinear,
+                    ..PixmapPaint::default()
+                };
+                self.pixmap.draw_pixmap(0, 0, image.as_ref(), &paint, transform, gs.clip.as_deref());
+            }
+            b"Form" => {
+                if self.depth >= MAX_FORM_DEPTH {
+                    return;
+                }
+                let form_resources = stream
+                    .dict
+                    .get(b"Resources")
+                    .ok()
+                    .and_then(|o| deref(doc, o)?.as_dict().ok())
+                    .or(resources);
+
+                let mut state = gs.clone();
+                if let Some(m) = stream
+                    .dict
+                    .get(b"Matrix")
+                    .ok()
+                    .and_then(|o| o.as_array().ok())
+                    .and_then(|a| matrix(&numbers(doc, a)))
+                {
+                    state.ctm = state.ctm.pre_concat(m);
+                }
+
+                self.depth += 1;
+                self.run(&stream_data(stream), form_resources, state);
+                self.depth -= 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn next_line(text: &mut TextState, tx: f32, ty: f32) {
+    text.line_matrix = text.line_matrix.pre_translate(tx, ty);
+    text.matrix = text.line_matrix;
+}
+
+/// Decode an image XObject to premultiplied RGBA. Stencil masks are painted
+/// with the current fill colour.
+fn decode_image(doc: &Document, stream: &Stream, resources: Option<&Dictionary>, fill: Color) -> Option<Pixmap> {
+    let dict = &stream.dict;
+    let int = |key: &[u8]| dict.get(key).ok().and_then(|o| deref(doc, o)?.as_i64().ok());
+    let width = int(b"Width").or_else(|| int(b"W"))? as u32;
+    let height = int(b"Height").or_else(|| int(b"H"))? as u32;
+    if width == 0 || height == 0 || width as u64 * height as u64 > 50_000_000 {
+        return None;
+    }
+
+    let stencil = dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false);
+    let filters = stream.filters().unwrap_or_default();
+    let mut rgba = vec![0u8; (width * height * 4) as usize];
+
+    if filters.iter().any(|f| f == "DCTDecode" || f == "DCT" || f == "JPXDecode") {
+        let decoded = image::load_from_memory(&stream.content).ok()?.to_rgba8();
+        let decoded = image::imageops::resize(&decoded, width, height, FilterType::Triangle);
+        rgba.copy_from_slice(decoded.as_raw());
+    } else {
+        let data = image_data(stream)?;
+        let bits = int(b"BitsPerComponent").or_else(|| int(b"BPC")).unwrap_or(if stencil { 1 } else { 8 }) as usize;
+        if !matches!(bits, 1 | 2 | 4 | 8 | 16) {
+            return None;
+        }
+
+        let space = if stencil {
+            ColorSpace::Gray
+        } else {
+            dict.get(b"ColorSpace")
+                .or_else(|_| dict.get(b"CS"))
+                .map(|o| ColorSpace::parse(doc, o, resources))
+                .unwrap_or(ColorSpace::Gray)
+        };
+        let components = space.components().max(1);
+        let row_bytes = (width as usize * components * bits + 7) / 8;
+        let max = ((1u32 << bits.min(8)) - 1) as f32;
+        let inverted = dict
+            .get(b"Decode")
+            .ok()
+            .and_then(|o| o.as_array().ok())
+            .and_then(|a| a.first()?.as_float().ok())
+            .is_some_and(|v| v >= 1.0);
+        let indexed = matches!(space, ColorSpace::Indexed { .. });
+
+        let mut samples = vec![0f32; components];
+        for y in 0..height as usize {
+            let row = data.get(y * row_bytes..(y + 1) * row_bytes)?;
+            for x in 0..width as usize {
+                for (c, sample) in samples.iter_mut().enumerate() {
+                    let raw = read_sample(row, (x * components + c) * bits, bits);
+                    *sample = if indexed { raw as f32 } else { raw as f32 / max };
+                    if inverted && !indexed {
+                        *sample = 1.0 - *sample;
+                    }
+                }
+
+                let out = &mut rgba[(y * width as usize + x) * 4..][..4];
+                if stencil {
+                    // Sample 0 marks painted pixels unless the decode array flips it
+                    if samples[0] < 0.5 {
+                        let c = fill.to_color_u8();
+                        out.copy_from_slice(&[c.red(), c.green(), c.blue(), 255]);
+                    }
+                } else {
+                    let c = space.color(&samples).unwrap_or(Color::BLACK).to_color_u8();
+                    out.copy_from_slice(&[c.red(), c.green(), c.blue(), 255]);
+                }
+            }
+        }
+    }
+
+    // Soft mask: a gray image used as alpha
+    if let Some(mask) = dict.get(b"SMask").ok().and_then(|o| deref(doc, o)?.as_stream().ok()) {
+        if let Some(alpha) = decode_image(doc, mask, None, Color::BLACK) {
+            let alpha = image::RgbaImage::from_raw(alpha.width(), alpha.height(), alpha.data().to_vec())?;
+            let alpha = image::imageops::resize(&alpha, width, height, FilterType::Triangle);
+            for (pixel, a) in rgba.chunks_mut(4).zip(alpha.pixels()) {
+                pixel[3] = ((pixel[3] as u32 * a[0] as u32) / 255) as u8;
+            }
+        }
+    }
+
+    // tiny-skia wants premultiplied alpha
+    for pixel in rgba.chunks_mut(4) {
+        let a = pixel[3] as u32;
+        for c in &mut pixel[..3] {
+            *c = (*c as u32 * a / 255) as u8;
+        }
+    }
+
+    Pixmap::from_vec(rgba, tiny_skia::IntSize::from_wh(width, height)?)
+}
+
+/// Decompress image data. lopdf refuses to decode image streams itself.
+fn image_data(stream: &Stream) -> Option<Vec<u8>> {
+    if stream.filters().map(|f| f.is_empty()).unwrap_or(true) {
+        return Some(stream.content.clone());
+    }
+    let mut plain = stream.clone();
+    plain.dict.remove(b"Subtype");
+    plain.decompressed_content().ok()
+}
+
+fn read_sample(row: &[u8], bit: usize, bits: usize) -> u32 {
+    match bits {
+        8 => row.get(bit / 8).copied().unwrap_or(0) as u32,
+        // Keep the high byte
+        16 => row.get(bit / 8).copied().unwrap_or(0) as u32,
+        _ => {
+            let byte = row.get(bit / 8).copied().unwrap_or(0) as u32;
+            let shift = 8 - bits - bit % 8;
+            (byte >> shift) & ((1 << bits) - 1)
+        }
+    }
+}
+
+/// Embedded font program
+enum FontProgram {
+    /// TrueType or OpenType
+    Sfnt(Cow<'static, [u8]>),
+    /// Bare CFF, as embedded for Type1C and CIDFontType0C fonts
+    Cff(Vec<u8>),
+}
+
+/// A glyph outline in text space, 1.0 = font size
+struct Glyph {
+    path: Path,
+    /// Drawn with the substitute font for a bold font, thicken it
+    embolden: bool,
+}
+
+/// A PDF font: how character codes map to outlines and advance widths
+struct PdfFont {
+    program: Option<FontProgram>,
+    /// Type0 fonts with two-byte codes
+    two_byte: bool,
+    /// Glyph widths in thousandths of the font size
+    widths: HashMap<u32, f32>,
+    default_width: Option<f32>,
+    /// Unicode of each code of a simple font, used with the substitute font
+    unicode: Vec<Option<char>>,
+    /// Glyph names from the font's `Differences`
+    names: Vec<Option<String>>,
+    cid_to_gid: Option<Vec<u16>>,
+    bold: bool,
+    glyphs: RefCell<HashMap<u32, Option<Rc<Glyph>>>>,
+}
+
+impl PdfFont {
+    fn load(doc: &Document, dict: &Dictionary) -> Self {
+        let name = |d: &Dictionary, key: &[u8]| get(doc, d, key).and_then(|o| o.as_name().ok()).map(<[u8]>::to_vec);
+        let base_font = String::from_utf8_lossy(&name(dict, b"BaseFont").unwrap_or_default()).into_owned();
+
+        let mut font = Self {
+            program: None,
+            two_byte: false,
+            widths: HashMap::new(),
+            default_width: None,
+            unicode: (0..256u32).map(win_ansi).collect(),
+            names: vec![None; 256],
+            cid_to_gid: None,
+            bold: base_font.contains("Bold") || base_font.contains("Black") || base_font.contains("Heavy"),
+            glyphs: RefCell::new(HashMap::new()),
+        };
+
+                let descriptor;
+
+        if name(dict, b"Subtype").as_deref() == Some(&b"Type0"[..]) {
+            font.two_byte = true;
+            let Some(descendant) = get(doc, dict, b"DescendantFonts")
+                .and_then(|o| o.as_array().ok())
+                .and_then(|a| deref(doc, a.first()?)?.as_dict().ok())
+            else {
+                return font;
+            };
+            descriptor = get(doc, descendant, b"FontDescriptor").and_then(|o| o.as_dict().ok());
+
+            font.default_width = Some(get(doc, descendant, b"DW").and_then(|o| o.as_float().ok()).unwrap_or(1000.0));
+            if let Some(widths) = get(doc, descendant, b"W").and_then(|o| o.as_array().ok()) {
+                let mut items = widths.iter().filter_map(|o| deref(doc, o)).peekable();
+                while let Some(first) = items.next() {
+                    let Ok(first) = first.as_i64() else {
+                        break;
+                    };
+                    match items.next() {
+                        Some(Object::Array(list)) => {
+                            for (i, w) in numbers(doc, list).into_iter().enumerate() {
+                                font.widths.insert(first as u32 + i as u32, w);
+                            }
+                        }
+                        Some(last) => {
+                            let (Ok(last), Some(w)) = (last.as_i64(), items.next().and_then(|o| o.as_float().ok())) else {
+                                break;
+                            };
+                            for cid in first..=last.min(first + 0xFFFF) {
+                                font.widths.insert(cid as u32, w);
+                            }
+                        }
+                        None => break,
+                    }
+                }
+            }
+
+            if let Some(map) = get(doc, descendant, b"CIDToGIDMap").and_then(|o| o.as_stream().ok()) {
+                let data = stream_data(map);
+                font.cid_to_gid = Some(data.chunks(2).map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)])).collect());
+            }
+        } else {
+            descriptor = get(doc, dict, b"FontDescriptor").and_then(|o| o.as_dict().ok());
+
+            let first = get(doc, dict, b"FirstChar").and_then(|o| o.as_i64().ok()).unwrap_or(0);
+            if let Some(widths) = get(doc, dict, b"Widths").and_then(|o| o.as_array().ok()) {
+                for (i, w) in numbers(doc, widths).into_iter().enumerate() {
+                    font.widths.insert((first + i as i64) as u32, w);
+                }
+            }
+            font.default_width = descriptor
+                .and_then(|d| get(doc, d, b"MissingWidth"))
+                .and_then(|o| o.as_float().ok());
+
+            if let Some(encoding) = get(doc, dict, b"Encoding").and_then(|o| o.as_dict().ok()) {
+                if let Some(differences) = get(doc, encoding, b"Differences").and_then(|o| o.as_array().ok()) {
+                    let mut code = 0usize;
+                    for item in differences.iter().filter_map(|o| deref(doc, o)) {
+                        match item {
+                            Object::Integer(i) => code = *i as usize,
+                            Object::Name(glyph) if code < 256 => {
+                                let glyph = String::from_utf8_lossy(glyph).into_owned();
+                                font.unicode[code] = glyph_name_char(&glyph);
+                                font.names[code] = Some(glyph);
+                                code += 1;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(descriptor) = descriptor {
+            font.bold |= get(doc, descriptor, b"FontWeight")
+                .and_then(|o| o.as_float().ok())
+                .is_some_and(|w| w >= 600.0);
+
+            if let Some(stream) = get(doc, descriptor, b"FontFile2").and_then(|o| o.as_stream().ok()) {
+                font.program = Some(FontProgram::Sfnt(Cow::Owned(stream_data(stream))));
+            } else if let Some(stream) = get(doc, descriptor, b"FontFile3").and_then(|o| o.as_stream().ok()) {
+                let data = stream_data(stream);
+                font.program = Some(match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+                    Ok(b"OpenType") => FontProgram::Sfnt(Cow::Owned(data)),
+                    _ => FontProgram::Cff(data),
+                });
+            } else if let Some(stream) = get(doc, descriptor, b"FontFile").and_then(|o| o.as_stream().ok()) {
+                // Type 1 programs are drawn with the substitute font, but their
+                // built-in encoding tells which glyph each code is
+                let data = stream_data(stream);
+                let clear = get(doc, &stream.dict, b"Length1")
+                    .and_then(|o| o.as_i64().ok())
+                    .map_or(data.len(), |n| (n as usize).min(data.len()));
+                let clear = String::from_utf8_lossy(&data[..clear]);
+                let mut tokens = clear.split_whitespace();
+                while let Some(token) = tokens.next() {
+                    if token != "dup" {
+                        continue;
+                    }
+                    let (Some(code), Some(glyph)) = (tokens.next(), tokens.next()) else {
+                        break;
+                    };
+                    let (Ok(code), Some(glyph)) = (code.parse::<usize>(), glyph.strip_prefix('/')) else {
+                        continue;
+                    };
+                    if code < 256 && font.names[code].is_none() {
+                        font.unicode[code] = glyph_name_char(glyph);
+                    }
+                }
+            }
+        }
+
+        font
+    }
+
+    /// Advance width of a code in thousandths of the font size
+    fn width(&self, code: u32) -> f32 {
+        if let Some(w) = self.widths.get(&code) {
+            return *w;
+        }
+        if let Some(w) = self.default_width.filter(|_| !self.widths.is_empty() || self.two_byte) {
+            return w;
+        }
+
+        // No widths at all: a standard font, measure the substitute
+        let face = ttf::Face::parse(FONT, 0).expect("bundled font is valid");
+        self.unicode
+            .get(code as usize)
+            .copied()
+            .flatten()
+            .and_then(|c| face.glyph_index(c))
+            .and_then(|g| face.glyph_hor_advance(g))
+            .map(|a| a as f32 * 1000.0 / face.units_per_em() as f32)
+            .unwrap_or(500.0)
+    }
+
+    fn glyph(&self, code: u32) -> Option<Rc<Glyph>> {
+        if let Some(glyph) = self.glyphs.borrow().get(&code) {
+            return glyph.clone();
+        }
+
+        let glyph = self.outline(code).map(Rc::new);
+        self.glyphs.borrow_mut().insert(code, glyph.clone());
+        glyph
+    }
+
+    fn outline(&self, code: u32) -> Option<Glyph> {
+        let embedded = match &self.program {
+            Some(FontProgram::Sfnt(data)) => ttf::Face::parse(data.as_ref(), 0).ok().and_then(|face| {
+                let gid = self.sfnt_glyph(&face, code)?;
+                let scale = 1.0 / face.units_per_em() as f32;
+                outline_path(|b| face.outline_glyph(gid, b).is_some(), scale, scale)
+            }),
+            Some(FontProgram::Cff(data)) => ttf::cff::Table::parse(data).and_then(|table| {
+                let gid = self.cff_glyph(&table, code)?;
+                let m = table.matrix();
+                outline_path(|b| table.outline(gid, b).is_ok(), m.sx, m.sy)
+            }),
+            None => None,
+        };
+        if let Some(path) = embedded {
+            return Some(Glyph { path, embolden: false });
+        }
+
+        // Not embedded, or a program we can't read: use the bundled font
+        let c = if self.two_byte { None } else { self.unicode.get(code as usize).copied().flatten() }?;
+        let face = ttf::Face::parse(FONT, 0).ok()?;
+        let gid = face.glyph_index(c)?;
+        let scale = 1.0 / face.units_per_em() as f32;
+        let path = outline_path(|b| face.outline_glyph(gid, b).is_some(), scale, scale)?;
+        Some(Glyph { path, embolden: self.bold })
+    }
+
+    fn sfnt_glyph(&self, face: &ttf::Face, code: u32) -> Option<ttf::GlyphId> {
+        if self.two_byte {
+            let gid = match &self.cid_to_gid {
+                Some(map) => *map.get(code as usize)?,
+                None => code as u16,
+            };
+            return (gid != 0).then_some(ttf::GlyphId(gid));
+        }
+
+        if let Some(gid) = self.names[code as usize].as_deref().and_then(|n| face.glyph_index_by_name(n)) {
+            return Some(gid);
+        }
+
+        let subtables = || face.tables().cmap.into_iter().flat_map(|cmap| cmap.subtables);
+        // Symbolic fonts map codes into the private use area
+        for table in subtables().filter(|t| t.platform_id == ttf::PlatformId::Windows && t.encoding_id == 0) {
+            if let Some(gid) = table.glyph_index(0xF000 + code).or_else(|| table.glyph_index(code)) {
+                return Some(gid);
+            }
+        }
+        if let Some(gid) = self.unicode[code as usize].and_then(|c| face.glyph_index(c)) {
+            return Some(gid);
+        }
+        for table in subtables().filter(|t| t.platform_id == ttf::PlatformId::Macintosh) {
+            if let Some(gid) = table.glyph_index(code) {
+                return Some(gid);
+            }
+        }
+        None
+    }
+
+    fn cff_glyph(&self, table: &ttf::cff::Table, code: u32) -> Option<ttf::GlyphId> {
+        if self.two_byte {
+            if table.glyph_cid(ttf::GlyphId(0)).is_some() {
+                // CID-keyed font: find the glyph carrying this CID
+                return (1..table.number_of_glyphs())
+                    .map(ttf::GlyphId)
+                    .find(|g| table.glyph_cid(*g) == Some(code as u16));
+            }
+            return Some(ttf::GlyphId(code as u16));
+        }
+
+        let name = self.names[code as usize]
+            .clone()
+            .or_else(|| self.unicode[code as usize].map(char_glyph_name));
+        name.and_then(|n| table.glyph_index_by_name(&n))
+            .or_else(|| table.glyph_index(code as u8))
+            .filter(|g| g.0 != 0)
+    }
+}
+
+/// Collect an outline into a path, scaling font units to text space
+fn outline_path(draw: impl FnOnce(&mut dyn ttf::OutlineBuilder) -> bool, sx: f32, sy: f32) -> Option<Path> {
+    struct Builder {
+        path: PathBuilder,
+        sx: f32,
+        sy: f32,
+    }
+
+    impl ttf::OutlineBuilder for Builder {
+        fn move_to(&mut self, x: f32, y: f32) {
+            self.path.move_to(x * self.sx, y * self.sy);
+        }
+        fn line_to(&mut self, x: f32, y: f32) {
+            self.path.line_to(x * self.sx, y * self.sy);
+        }
+        fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+            self.path.quad_to(x1 * self.sx, y1 * self.sy, x * self.sx, y * self.sy);
+        }
+        fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+            self.path.cubic_to(x1 * self.sx, y1 * self.sy, x2 * self.sx, y2 * self.sy, x * self.sx, y * self.sy);
+        }
+        fn close(&mut self) {
+            self.path.close();
+        }
+    }
+
+    let mut builder = Builder {
+        path: PathBuilder::new(),
+        sx,
+        sy,
+    };
+    if !draw(&mut builder) {
+        return None;
+    }
+    builder.path.finish()
+}
+
+/// WinAnsiEncoding, the default for simple fonts
+fn win_ansi(code: u32) -> Option<char> {
+    const HIGH: [char; 32] = [
+        '€', '\0', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\0', 'Ž', '\0',
+        '\0', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\0', 'ž', 'Ÿ',
+    ];
+    match code {
+        0x20..=0x7E | 0xA0..=0xFF => char::from_u32(code),
+        0x80..=0x9F => Some(HIGH[(code - 0x80) as usize]).filter(|c| *c != '\0'),
+        _ => None,
+    }
+}
+
+/// Glyph names used by PDF encodings beyond single letters and digits
+const GLYPH_NAMES: &[(&str, char)] = &[
+    ("space", ' '), ("exclam", '!'), ("quotedbl", '"'), ("numbersign", '#'), ("dollar", '$'),
+    ("percent", '%'), ("ampersand", '&'), ("quotesingle", '\''), ("quoteright", '’'),
+    ("parenleft", '('), ("parenright", ')'), ("asterisk", '*'), ("plus", '+'), ("comma", ','),
+    ("hyphen", '-'), ("period", '.'), ("slash", '/'), ("zero", '0'), ("one", '1'), ("two", '2'),
+    ("three", '3'), ("four", '4'), ("five", '5'), ("six", '6'), ("seven", '7'), ("eight", '8'),
+    ("nine", '9'), ("colon", ':'), ("semicolon", ';'), ("less", '<'), ("equal", '='),
+    ("greater", '>'), ("question", '?'), ("at", '@'), ("bracketleft", '['), ("backslash", '\\'),
+    ("bracketright", ']'), ("asciicircum", '^'), ("underscore", '_'), ("grave", '`'),
+    ("quoteleft", '‘'), ("braceleft", '{'), ("bar", '|'), ("braceright", '}'), ("asciitilde", '~'),
+    ("Euro", '€'), ("quotesinglbase", '‚'), ("florin", 'ƒ'), ("quotedblbase", '„'),
+    ("ellipsis", '…'), ("dagger", '†'), ("daggerdbl", '‡'), ("circumflex", 'ˆ'),
+    ("perthousand", '‰'), ("Scaron", 'Š'), ("guilsinglleft", '‹'), ("OE", 'Œ'), ("Zcaron", 'Ž'),
+    ("quotedblleft", '“'), ("quotedblright", '”'), ("bullet", '•'), ("endash", '–'),
+    ("emdash", '—'), ("tilde", '˜'), ("trademark", '™'), ("scaron", 'š'), ("guilsinglright", '›'),
+    ("oe", 'œ'), ("zcaron", 'ž'), ("Ydieresis", 'Ÿ'), ("exclamdown", '¡'), ("cent", '¢'),
+    ("sterling", '£'), ("currency", '¤'), ("yen", '¥'), ("brokenbar", '¦'), ("section", '§'),
+    ("dieresis", '¨'), ("copyright", '©'), ("ordfeminine", 'ª'), ("guillemotleft", '«'),
+    ("logicalnot", '¬'), ("registered", '®'), ("macron", '¯'), ("degree", '°'),
+    ("plusminus", '±'), ("twosuperior", '²'), ("threesuperior", '³'), ("acute", '´'), ("mu", 'µ'),
+    ("paragraph", '¶'), ("periodcentered", '·'), ("cedilla", '¸'), ("onesuperior", '¹'),
+    ("ordmasculine", 'º'), ("guillemotright", '»'), ("onequarter", '¼'), ("onehalf", '½'),
+    ("threequarters", '¾'), ("questiondown", '¿'), ("Agrave", 'À'), ("Aacute", 'Á'),
+    ("Acircumflex", 'Â'), ("Atilde", 'Ã'), ("Adieresis", 'Ä'), ("Aring", 'Å'), ("AE", 'Æ'),
+    ("Ccedilla", 'Ç'), ("Egrave", 'È'), ("Eacute", 'É'), ("Ecircumflex", 'Ê'), ("Edieresis", 'Ë'),
+    ("Igrave", 'Ì'), ("Iacute", 'Í'), ("Icircumflex", 'Î'), ("Idieresis", 'Ï'), ("Eth", 'Ð'),
+    ("Ntilde", 'Ñ'), ("Ograve", 'Ò'), ("Oacute", 'Ó'), ("Ocircumflex", 'Ô'), ("Otilde", 'Õ'),
+    ("Odieresis", 'Ö'), ("multiply", '×'), ("Oslash", 'Ø'), ("Ugrave", 'Ù'), ("Uacute", 'Ú'),
+    ("Ucircumflex", 'Û'), ("Udieresis", 'Ü'), ("Yacute", 'Ý'), ("Thorn", 'Þ'),
+    ("germandbls", 'ß'), ("agrave", 'à'), ("aacute", 'á'), ("acircumflex", 'â'), ("atilde", 'ã'),
+    ("adieresis", 'ä'), ("aring", 'å'), ("ae", 'æ'), ("ccedilla", 'ç'), ("egrave", 'è'),
+    ("eacute", 'é'), ("ecircumflex", 'ê'), ("edieresis", 'ë'), ("igrave", 'ì'), ("iacute", 'í'),
+    ("icircumflex", 'î'), ("idieresis", 'ï'), ("eth", 'ð'), ("ntilde", 'ñ'), ("ograve", 'ò'),
+    ("oacute", 'ó'), ("ocircumflex", 'ô'), ("otilde", 'õ'), ("odieresis", 'ö'), ("divide", '÷'),
+    ("oslash", 'ø'), ("ugrave", 'ù'), ("uacute", 'ú'), ("ucircumflex", 'û'), ("udieresis", 'ü'),
+    ("yacute", 'ý'), ("thorn", 'þ'), ("ydieresis", 'ÿ'), ("minus", '−'), ("fi", 'ﬁ'), ("fl", 'ﬂ'),
+    ("ff", 'ﬀ'), ("ffi", 'ﬃ'), ("ffl", 'ﬄ'), ("dotlessi", 'ı'), ("nbspace", '\u{A0}'),
+];
+
+/// Unicode character of a glyph name, e.g. "eacute", "uni0627" or "A.sc"
+fn glyph_name_char(name: &str) -> Option<char> {
+    let name = name.split('.').next().unwrap_or(name);
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphabetic() {
+            return Some(c);
+        }
+    }
+
+    if let Some(&(_, c)) = GLYPH_NAMES.iter().find(|(n, _)| *n == name) {
+        return Some(c);
+    }
+    let hex = match name.strip_prefix("uni") {
+        Some(hex) => hex.get(..4)?,
+        None => name.strip_prefix('u').filter(|hex| (4..=6).contains(&hex.len()))?,
+    };
+    u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+}
+
+/// Glyph name of a Unicode character, the reverse of [`glyph_name_char`]
+fn char_glyph_name(c: char) -> String {
+    if c.is_ascii_alphabetic() {
+        return c.to_string();
+    }
+    match GLYPH_NAMES.iter().find(|(_, g)| *g == c) {
+        Some((name, _)) => name.to_string(),
+        None => format!("uni{:04X}", c as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    /// One-page PDF with a Helvetica font and the given content stream
+    fn pdf(content: &str, width: i64, height: i64) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.as_bytes().to_vec()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+
+        let mut data = Vec::new();
+        doc.save_to(&mut data).unwrap();
+        data
+    }
+
+    fn black_pixels(image: &GrayImage) -> usize {
+        image.pixels().filter(|p| p[0] < 128).count()
+    }
+
+    #[test]
+    fn page_ranges() {
+        assert_eq!(parse_page_range("1-3,5", 6).unwrap(), vec![1, 2, 3, 5]);
+        assert_eq!(parse_page_range("4-", 6).unwrap(), vec![4, 5, 6]);
+        assert_eq!(parse_page_range("2-99", 3).unwrap(), vec![2, 3]);
+        assert!(parse_page_range("7", 6).is_err());
+        assert!(parse_page_range("3-1", 6).is_err());
+        assert!(parse_page_range("x", 6).is_err());
+    }
+
+    #[test]
+    fn trims_margins_and_fits_width() {
+        // A 100 x 50 black box in the middle of an A4 page
+        let data = pdf("0 g 200 400 100 50 re f", 595, 842);
+        let pages = render_pages(&data, 576, &PdfOptions::default()).unwrap();
+
+        assert_eq!(pages.len(), 1);
+        let page = &pages[0];
+        assert_eq!(page.width(), 576);
+        // Aspect ratio of the box is kept, give or take the trim padding
+        assert!((page.height() as i32 - 288).abs() < 16, "height {}", page.height());
+        assert!(black_pixels(page) > 576 * 200);
+    }
+
+    #[test]
+    fn refuses_absurdly_narrow_pages() {
+        let data = pdf("0 g 0 0 1 10 re f", 1, 100000);
+        let error = render_pages(&data, 576, &PdfOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("too narrow"), "{:#}", error);
+    }
+
+    #[test]
+    fn scale_and_untrimmed_pages() {
+        let data = pdf("0 g 0 0 10 10 re f", 200, 100);
+        let options = PdfOptions {
+            scale: 0.5,
+            trim: false,
+            ..PdfOptions::default()
+        };
+        let page = &render_pages(&data, 576, &options).unwrap()[0];
+        assert_eq!((page.width(), page.height()), (288, 144));
+        // The box stays in the bottom left corner
+        assert!(page.get_pixel(2, 141)[0] < 128);
+        assert!(page.get_pixel(100, 20)[0] > 128);
+    }
+
+    #[test]
+    fn draws_text_with_substitute_font() {
+        let data = pdf("BT /F1 24 Tf 10 10 Td (Total 12.00) Tj ET", 300, 50);
+        let page = &render_pages(&data, 576, &PdfOptions::default()).unwrap()[0];
+        assert!(black_pixels(page) > 1000);
+
+        let blank = pdf("BT /F1 24 Tf 3 Tr 10 10 Td (hidden) Tj ET", 300, 50);
+        assert!(render_pages(&blank, 576, &PdfOptions::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn glyph_names() {
+        assert_eq!(glyph_name_char("eacute"), Some('é'));
+        assert_eq!(glyph_name_char("uni0627"), Some('ا'));
+        assert_eq!(glyph_name_char("A.sc"), Some('A'));
+        assert_eq!(char_glyph_name('€'), "Euro");
+        assert_eq!(win_ansi(0x80), Some('€'));
+    }
+}
@@ -1,315 +1,107 @@
 use anyhow::{Context, Result};
-use std::process::Command;
-use std::fs;
-use crate::escpos::{ThermalImage, generate_cut_command, generate_drawer_command};
-
-/// Print raw data to printer using system command
-#[cfg(target_os = "macos")]
-pub fn print_raw(printer_name: &str, data: &[u8]) -> Result<()> {
-    // Create temp file
-    let temp_dir = std::env::temp_dir();
-    let temp_file = temp_dir.join(format!("vopecs_print_{}.bin", std::process::id()));
-
-    // Write data to temp file
-    fs::write(&temp_file, data)
-        .context("Failed to write temp file")?;
-
-    // Print using lp command
-    let output = Command::new("lp")
-        .args([
-            "-d", printer_name,
-            "-o", "raw",
-            temp_file.to_str().unwrap()
-        ])
-        .output()
-        .context("Failed to execute lp command")?;
-
-    // Clean up temp file
-    let _ = fs::remove_file(&temp_file);
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("lp command failed: {}", stderr);
-    }
-
-    Ok(())
-}
-
-/// Print raw data to printer using system command (Windows)
-#[cfg(target_os = "windows")]
+use crate::api::{self, PrintJob};
+use crate::config::{self, PrinterProfile};
+use crate::document::{Document, Element};
+use crate::emulator::{self, Effect};
+use crate::html;
+use crate::label;
+use crate::pdf::{self, PdfOptions};
+use crate::barcode::QrOptions;
+use crate::escpos::{EscPosBuilder, Justify, TextStyle, ThermalImage, generate_cut_command, generate_drawer_command, generate_setup_command};
+use crate::dither::MonoBitmap;
+use crate::logo::{self, LogoMemory, StoredLogo};
+use crate::text;
+use crate::command_set::Emulation;
+use crate::transport::{self, PrinterStatus, TransportStatus};
+use base64::Engine;
+use image::DynamicImage;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Print raw data to a printer target.
+///
+/// The target is resolved to a transport by its URI scheme, see
+/// [`transport::Target::parse`].
 pub fn print_raw(printer_name: &str, data: &[u8]) -> Result<()> {
-    use std::os::windows::process::CommandExt;
-    use std::io::Write;
-
-    // Create temp file with unique timestamp
-    let temp_dir = std::env::temp_dir();
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_millis();
-    let temp_file = temp_dir.join(format!("vopecs_print_{}.prn", timestamp));
-
-    // Write data to temp file
-    fs::write(&temp_file, data)
-        .context("Failed to write temp file")?;
-
-    let temp_path = temp_file.to_str().unwrap();
-    println!("Printing {} bytes to Windows printer: {}", data.len(), printer_name);
-
-    // Use PowerShell to send raw data directly to printer via .NET
-    // This is the most reliable method for thermal/ESC-POS printers on Windows
-    let ps_script = format!(
-        r#"
-        $printerName = '{}'
-        $filePath = '{}'
-
-        # Method 1: Try using RawPrinterHelper via .NET
-        Add-Type -TypeDefinition @'
-        using System;
-        using System.Runtime.InteropServices;
-
-        public class RawPrinterHelper {{
-            [DllImport("winspool.drv", CharSet = CharSet.Unicode, SetLastError = true)]
-            public static extern bool OpenPrinter(string pPrinterName, out IntPtr phPrinter, IntPtr pDefault);
-
-            [DllImport("winspool.drv", SetLastError = true)]
-            public static extern bool ClosePrinter(IntPtr hPrinter);
-
-            [DllImport("winspool.drv", CharSet = CharSet.Unicode, SetLastError = true)]
-            public static extern bool StartDocPrinter(IntPtr hPrinter, int level, ref DOCINFO pDocInfo);
-
-            [DllImport("winspool.drv", SetLastError = true)]
-            public static extern bool EndDocPrinter(IntPtr hPrinter);
-
-            [DllImport("winspool.drv", SetLastError = true)]
-            public static extern bool StartPagePrinter(IntPtr hPrinter);
-
-            [DllImport("winspool.drv", SetLastError = true)]
-            public static extern bool EndPagePrinter(IntPtr hPrinter);
-
-            [DllImport("winspool.drv", SetLastError = true)]
-            public static extern bool WritePrinter(IntPtr hPrinter, IntPtr pBytes, int dwCount, out int dwWritten);
-
-            [StructLayout(LayoutKind.Sequential, CharSet = CharSet.Unicode)]
-            public struct DOCINFO {{
-                public string pDocName;
-                public string pOutputFile;
-                public string pDataType;
-            }}
-
-            public static bool SendBytesToPrinter(string printerName, byte[] bytes) {{
-                IntPtr hPrinter = IntPtr.Zero;
-                DOCINFO di = new DOCINFO();
-                di.pDocName = "VopecsPrinter RAW Document";
-                di.pDataType = "RAW";
-
-                if (!OpenPrinter(printerName, out hPrinter, IntPtr.Zero)) return false;
-
-                try {{
-                    if (!StartDocPrinter(hPrinter, 1, ref di)) return false;
-                    if (!StartPagePrinter(hPrinter)) return false;
-
-                    IntPtr pBytes = Marshal.AllocCoTaskMem(bytes.Length);
-                    Marshal.Copy(bytes, 0, pBytes, bytes.Length);
-
-                    int written = 0;
-                    bool success = WritePrinter(hPrinter, pBytes, bytes.Length, out written);
-
-                    Marshal.FreeCoTaskMem(pBytes);
-
-                    EndPagePrinter(hPrinter);
-                    EndDocPrinter(hPrinter);
-
-                    return success && written == bytes.Length;
-                }} finally {{
-                    ClosePrinter(hPrinter);
-                }}
-            }}
-        }}
-'@
-
-        $bytes = [System.IO.File]::ReadAllBytes($filePath)
-        $result = [RawPrinterHelper]::SendBytesToPrinter($printerName, $bytes)
-        if ($result) {{
-            Write-Output "SUCCESS"
-            exit 0
-        }} else {{
-            Write-Error "Failed to send to printer"
-            exit 1
-        }}
-        "#,
-        printer_name.replace("'", "''"),
-        temp_path.replace("\\", "\\\\").replace("'", "''")
-    );
-
-    let output = Command::new("powershell")
-        .args(["-NoProfile", "-NonInteractive", "-ExecutionPolicy", "Bypass", "-Command", &ps_script])
-        .creation_flags(0x08000000) // CREATE_NO_WINDOW
-        .output()
-        .context("Failed to execute PowerShell")?;
-
-    // Clean up temp file
-    let _ = fs::remove_file(&temp_file);
-
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        if stdout.contains("SUCCESS") {
-            println!("✅ RAW print to Windows printer succeeded");
-            return Ok(());
-        }
-    }
-
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    anyhow::bail!("Print failed: {} {}", stdout, stderr)
+    transport::send(printer_name, data)
 }
 
-/// Print raw data to printer (Linux)
-#[cfg(target_os = "linux")]
-pub fn print_raw(printer_name: &str, data: &[u8]) -> Result<()> {
-    // Create temp file
-    let temp_dir = std::env::temp_dir();
-    let temp_file = temp_dir.join(format!("vopecs_print_{}.bin", std::process::id()));
-
-    // Write data to temp file
-    fs::write(&temp_file, data)
-        .context("Failed to write temp file")?;
-
-    // Print using lp command (same as macOS, uses CUPS)
-    let output = Command::new("lp")
-        .args([
-            "-d", printer_name,
-            "-o", "raw",
-            temp_file.to_str().unwrap()
-        ])
-        .output()
-        .context("Failed to execute lp command")?;
-
-    // Clean up temp file
-    let _ = fs::remove_file(&temp_file);
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("lp command failed: {}", stderr);
+/// Print raw data, checking the printer's status before and after when the
+/// profile asks for it, see [`transport::send_checked`]
+pub fn print_checked(printer_name: &str, data: &[u8], profile: &PrinterProfile) -> Result<Option<PrinterStatus>> {
+    // DLE EOT and GS r are ESC/POS commands
+    if !profile.status_check || profile.emulation != Emulation::EscPos {
+        print_raw(printer_name, data)?;
+        return Ok(None);
     }
-
-    Ok(())
+    transport::send_checked(printer_name, data)
 }
 
-/// Get list of available printers
-#[cfg(target_os = "macos")]
+/// Get list of available printer targets (spooler queues and device files)
 pub fn get_system_printers() -> Result<Vec<String>> {
-    let output = Command::new("lpstat")
-        .args(["-p"])
-        .output()
-        .context("Failed to execute lpstat command")?;
-
-    if !output.status.success() {
-        return Ok(Vec::new());
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let printers: Vec<String> = stdout
-        .lines()
-        .filter_map(|line| {
-            // lpstat -p output format: "printer PRINTER_NAME is idle..."
-            if line.starts_with("printer ") {
-                line.split_whitespace()
-                    .nth(1)
-                    .map(|s| s.to_string())
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    Ok(printers)
+    transport::list_targets()
 }
 
-/// Get list of available printers (Windows)
-#[cfg(target_os = "windows")]
-pub fn get_system_printers() -> Result<Vec<String>> {
-    use std::os::windows::process::CommandExt;
-
-    let output = Command::new("wmic")
-        .args(["printer", "get", "name"])
-        .creation_flags(0x08000000) // CREATE_NO_WINDOW
-        .output()
-        .context("Failed to execute wmic command")?;
-
-    if !output.status.success() {
-        return Ok(Vec::new());
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let printers: Vec<String> = stdout
-        .lines()
-        .skip(1) // Skip header
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    Ok(printers)
+/// Check whether a printer target is reachable
+pub fn printer_status(printer_name: &str) -> Result<TransportStatus> {
+    transport::status(printer_name)
 }
 
-/// Get list of available printers (Linux)
-#[cfg(target_os = "linux")]
-pub fn get_system_printers() -> Result<Vec<String>> {
-    let output = Command::new("lpstat")
-        .args(["-p"])
-        .output()
-        .context("Failed to execute lpstat command")?;
-
-    if !output.status.success() {
-        return Ok(Vec::new());
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let printers: Vec<String> = stdout
-        .lines()
-        .filter_map(|line| {
-            if line.starts_with("printer ") {
-                line.split_whitespace()
-                    .nth(1)
-                    .map(|s| s.to_string())
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    Ok(printers)
+/// Ask a printer for its paper, cover and error status; `None` when its
+/// transport can't read it or it doesn't answer
+pub fn query_printer_status(printer_name: &str) -> Result<Option<PrinterStatus>> {
+    transport::query_status(printer_name)
 }
 
 /// Print a test page to the specified printer
 pub fn print_test_page(printer_name: &str) -> Result<()> {
-    let mut test_data = ThermalImage::test_pattern(576);
+    let profile = config::load_printer_profile(printer_name)?;
+    if profile.emulation.is_label() {
+        let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+        return print_raw(printer_name, &render_text(&format!("VOPECS PRINTER TEST\n{}", now), &profile)?);
+    }
+    let mut test_data = ThermalImage::test_pattern(&profile);
 
     // Add cut command at the end
-    test_data.extend_from_slice(&generate_cut_command());
+    test_data.extend_from_slice(&generate_cut_command(&profile));
 
     print_raw(printer_name, &test_data)?;
     Ok(())
 }
 
-/// Print base64 image to thermal printer
-pub fn print_base64_image(printer_name: &str, base64_image: &str, max_width: u32) -> Result<()> {
-    let mut escpos_data = ThermalImage::base64_to_escpos(base64_image, max_width)?;
+/// Render a base64 image to ESC/POS data, including the final cut
+pub fn render_base64_image(base64_image: &str, profile: &PrinterProfile) -> Result<Vec<u8>> {
+    let mut escpos_data = ThermalImage::base64_to_escpos(base64_image, profile)?;
 
     // Add cut command at the end
-    escpos_data.extend_from_slice(&generate_cut_command());
+    escpos_data.extend_from_slice(&generate_cut_command(profile));
+
+    Ok(escpos_data)
+}
 
+/// Print base64 image to thermal printer
+pub fn print_base64_image(printer_name: &str, base64_image: &str) -> Result<()> {
+    let profile = config::load_printer_profile(printer_name)?;
+    let escpos_data = render_base64_image(base64_image, &profile)?;
     print_raw(printer_name, &escpos_data)?;
     Ok(())
 }
 
-/// Print image from URL to thermal printer
-pub async fn print_url_image(printer_name: &str, url: &str, max_width: u32) -> Result<()> {
-    println!("🖨️ Printing image from URL: {}", url);
-    let mut escpos_data = ThermalImage::url_to_escpos(url, max_width).await?;
+/// Download an image and render it to ESC/POS data, including the final cut
+pub async fn render_url_image(url: &str, profile: &PrinterProfile) -> Result<Vec<u8>> {
+    let mut escpos_data = ThermalImage::url_to_escpos(url, profile).await?;
 
     // Add cut command at the end
-    escpos_data.extend_from_slice(&generate_cut_command());
+    escpos_data.extend_from_slice(&generate_cut_command(profile));
+
+    Ok(escpos_data)
+}
+
+/// Print image from URL to thermal printer
+pub async fn print_url_image(printer_name: &str, url: &str) -> Result<()> {
+    println!("🖨️ Printing image from URL: {}", url);
+    let profile = config::load_printer_profile(printer_name)?;
+    let escpos_data = render_url_image(url, &profile).await?;
 
     println!("Printing raw data to printer: {} ({} bytes including cut)", printer_name, escpos_data.len());
     print_raw(printer_name, &escpos_data)?;
@@ -318,108 +110,360 @@ pub async fn print_url_image(printer_name: &str, url: &str, max_width: u32) -> R
     Ok(())
 }
 
+/// Render text content to ESC/POS data, including the final cut
+pub fn render_text(content: &str, profile: &PrinterProfile) -> Result<Vec<u8>> {
+    if profile.emulation.is_label() {
+        let text = Element::Text {
+            text: content.to_string(),
+            style: TextStyle::default(),
+        };
+        return label::render(&[text], profile);
+    }
+
+    if text::needs_raster(content, profile) {
+        // Printer fonts can't shape Arabic or reorder right-to-left text
+        let image = DynamicImage::ImageLuma8(text::rasterize(content, profile));
+        let mut data = ThermalImage::to_escpos_bitmap(&image, profile)?;
+        data.extend_from_slice(&generate_cut_command(profile));
+        return Ok(data);
+    }
+
+    Ok(EscPosBuilder::with_profile(profile)
+        .line(content)
+        .cut(&profile.cut)
+        .build())
+}
+
 /// Print text content to thermal printer
 pub fn print_text(printer_name: &str, content: &str) -> Result<()> {
-    let mut data = Vec::new();
-
-    // Initialize printer
-    data.extend_from_slice(&[0x1B, 0x40]); // ESC @
+    let profile = config::load_printer_profile(printer_name)?;
+    print_raw(printer_name, &render_text(content, &profile)?)?;
+    Ok(())
+}
 
-    // Print content
-    data.extend_from_slice(content.as_bytes());
+/// Print a structured document to thermal printer
+pub fn print_document(printer_name: &str, document: &Document) -> Result<()> {
+    let profile = config::load_printer_profile(printer_name)?;
+    print_raw(printer_name, &document.render(&profile)?)?;
+    Ok(())
+}
 
-    // Add line feeds and cut
-    data.extend_from_slice(b"\n\n\n");
-    data.extend_from_slice(&[0x1D, 0x56, 0x00]); // GS V 0 - full cut
+/// Render HTML to a bitmap at the printer width, including the final cut
+pub fn render_html(html: &str, profile: &PrinterProfile) -> Result<Vec<u8>> {
+    let image = DynamicImage::ImageLuma8(html::render(html, profile));
+    let mut data = ThermalImage::to_escpos_bitmap(&image, profile)?;
+    data.extend_from_slice(&generate_cut_command(profile));
+    Ok(data)
+}
 
-    print_raw(printer_name, &data)?;
+/// Print HTML content to thermal printer
+pub fn print_html(printer_name: &str, html: &str) -> Result<()> {
+    let profile = config::load_printer_profile(printer_name)?;
+    print_raw(printer_name, &render_html(html, &profile)?)?;
     Ok(())
 }
 
-/// Print PDF from URL to thermal printer
-pub async fn print_pdf_url(printer_name: &str, url: &str, max_width: u32) -> Result<()> {
-    // Download PDF
-    let response = reqwest::get(url).await
-        .context("Failed to download PDF")?;
+/// Load a PDF from a URL, base64 string or data URL
+pub async fn load_pdf(source: &str) -> Result<Vec<u8>> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        api::download(source).await
+            .context("Failed to download PDF")
+    } else {
+        pdf::decode_base64(source)
+    }
+}
+
+/// Rasterize a PDF's pages to ESC/POS data, including the final cut
+pub fn render_pdf_data(data: &[u8], profile: &PrinterProfile, options: &PdfOptions) -> Result<Vec<u8>> {
+    let pages = pdf::render_pages(data, profile.dot_width, options)?;
+    println!("📄 Rendered {} PDF page(s)", pages.len());
 
-    let pdf_data = response.bytes().await
-        .context("Failed to read PDF data")?;
+    let mut builder = EscPosBuilder::with_profile(profile);
+    for page in pages {
+        let bitmap = ThermalImage::to_mono(&DynamicImage::ImageLuma8(page), profile.dot_width, &profile.dithering);
+        builder.image(&bitmap, profile);
+    }
 
-    // For now, we'll try to render PDF as image using pdf crate if available
-    // As a fallback, just print the URL info
-    // In production, you'd use a PDF rendering library like pdfium-render or pdf-extract
+    Ok(builder.cut(&profile.cut).build())
+}
 
-    // Fallback: Print a message that PDF was received
-    let mut data = Vec::new();
-    data.extend_from_slice(&[0x1B, 0x40]); // ESC @
-    data.extend_from_slice(b"================================\n");
-    data.extend_from_slice(b"       PDF DOCUMENT             \n");
-    data.extend_from_slice(b"================================\n");
-    data.extend_from_slice(format!("Size: {} bytes\n", pdf_data.len()).as_bytes());
-    data.extend_from_slice(b"\n\n\n");
-    data.extend_from_slice(&[0x1D, 0x56, 0x00]); // Cut
+/// Load a PDF and render it to ESC/POS data, including the final cut
+pub async fn render_pdf(source: &str, profile: &PrinterProfile, options: &PdfOptions) -> Result<Vec<u8>> {
+    let data = load_pdf(source).await?;
+    render_pdf_data(&data, profile, options)
+}
 
+/// Print a PDF from a URL, base64 string or data URL to thermal printer.
+/// `pages` and `scale` override the printer profile's PDF options.
+pub async fn print_pdf(printer_name: &str, source: &str, pages: Option<String>, scale: Option<f32>) -> Result<()> {
+    let profile = config::load_printer_profile(printer_name)?;
+    let options = profile.pdf.with_overrides(pages, scale);
+    let data = render_pdf(source, &profile, &options).await?;
     print_raw(printer_name, &data)?;
     Ok(())
 }
 
+/// Render a print job from the API to ESC/POS data for a single copy
+pub async fn render_job(job: &PrintJob, profile: &PrinterProfile) -> Result<Vec<u8>> {
+    let mut data = render_receipt(job, profile).await?;
+
+    if let Some(key) = &job.logo {
+        if profile.emulation.is_label() {
+            anyhow::bail!("Logo {:?} can't be printed: label printers have no stored logos", key);
+        }
+        // The stored logo goes at the top, right after the setup commands
+        let mut logo = EscPosBuilder::new();
+        logo.justify(Justify::Center).logo(key, profile)?.justify(Justify::Left);
+        let setup = generate_setup_command(profile);
+        let start = if data.starts_with(&setup) { setup.len() } else { 0 };
+        data.splice(start..start, logo.build());
+    }
+
+    Ok(data)
+}
+
+/// Render a job's content followed by its ZATCA QR code, if any
+async fn render_receipt(job: &PrintJob, profile: &PrinterProfile) -> Result<Vec<u8>> {
+    let Some(invoice) = &job.zatca else {
+        return render_content(job, profile).await;
+    };
+
+    let options = QrOptions {
+        justify: Justify::Center,
+        ..QrOptions::default()
+    };
+    let qr = if profile.emulation.is_label() {
+        // A label of its own
+        let qr = Element::Qr {
+            data: invoice.qr_payload()?,
+            options,
+        };
+        label::render(&[qr], profile)?
+    } else {
+        let mut qr = EscPosBuilder::new();
+        qr.qr_for(&invoice.qr_payload()?, &options, profile)?;
+        qr.build()
+    };
+
+    if job.get_job_type() == "zatca" {
+        // Nothing else to print, just the QR code
+        return Ok(EscPosBuilder::with_profile(profile)
+            .raw(&qr)
+            .cut(&profile.cut)
+            .build());
+    }
+
+    // The QR code goes at the end of the receipt, before the cut
+    let mut data = render_content(job, profile).await?;
+    let cut = generate_cut_command(profile);
+    let end = if data.ends_with(&cut) { data.len() - cut.len() } else { data.len() };
+    data.splice(end..end, qr);
+    Ok(data)
+}
+
+/// A job as it would come out of the printer
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrintPreview {
+    /// The printed receipt as a base64 PNG
+    pub png_base64: String,
+    pub width: u32,
+    pub height: u32,
+    /// Size of the printer data for one copy
+    pub byte_size: usize,
+    /// Paper used by one copy, including the feed before the cut
+    pub paper_length_mm: f32,
+    /// Cuts, drawer pulses and commands the preview skipped
+    pub effects: Vec<Effect>,
+}
+
+/// Run rendered printer data through the emulator, which only reads ESC/POS
+pub fn preview(data: &[u8], profile: &PrinterProfile) -> Result<PrintPreview> {
+    if profile.emulation != Emulation::EscPos {
+        anyhow::bail!("Preview is not supported for {:?} printers", profile.emulation);
+    }
+    let printout = emulator::render(data, profile.dot_width);
+    Ok(PrintPreview {
+        png_base64: base64::engine::general_purpose::STANDARD.encode(printout.png()?),
+        width: printout.image.width(),
+        height: printout.image.height(),
+        byte_size: data.len(),
+        paper_length_mm: printout.image.height() as f32 * 25.4 / profile.dpi.max(1) as f32,
+        effects: printout.effects,
+    })
+}
+
+/// Render a job with the same pipeline as printing and preview the result
+pub async fn preview_job(job: &PrintJob, profile: &PrinterProfile) -> Result<PrintPreview> {
+    let data = render_job(job, profile).await?;
+    println!("🔍 Previewing {} job ({} bytes)", job.get_job_type(), data.len());
+    preview(&data, profile)
+}
+
+/// Render a job's receipt content
+async fn render_content(job: &PrintJob, profile: &PrinterProfile) -> Result<Vec<u8>> {
+    if let Some(document) = &job.document {
+        // Structured receipt built from typed elements
+        Document::from_json(document)?.render(profile)
+    } else if let Some(image) = &job.image {
+        // Base64 image printing
+        render_base64_image(image, profile)
+    } else if let Some(url) = &job.image_path {
+        // Image from URL (common format from VopecsPOS)
+        render_url_image(url, profile).await
+    } else if let Some(pdf) = &job.pdf {
+        let options = profile.pdf.with_overrides(job.pdf_pages.clone(), job.pdf_scale);
+        render_pdf(pdf, profile, &options).await
+    } else if let Some(html) = &job.html {
+        render_html(html, profile)
+    } else if let Some(url) = &job.url {
+        render_url_image(url, profile).await
+    } else if let Some(content) = &job.content {
+        render_text(content, profile)
+    } else {
+        anyhow::bail!("Unknown job type: {}", job.get_job_type())
+    }
+}
+
 /// Cut paper on printer
 pub fn cut_paper(printer_name: &str) -> Result<()> {
-    let cut_data = generate_cut_command();
+    let profile = config::load_printer_profile(printer_name)?;
+    let cut_data = generate_cut_command(&profile);
     print_raw(printer_name, &cut_data)?;
     Ok(())
 }
 
-/// Open cash drawer
+/// Open cash drawer. The printer profile's pin, if set, overrides `pin`.
 pub fn open_drawer(printer_name: &str, pin: u8) -> Result<()> {
+    let profile = config::load_printer_profile(printer_name)?;
+    let pin = profile.drawer.pin.unwrap_or(pin);
     println!("💰 Opening cash drawer on printer: {} (pin: {})", printer_name, pin);
-    let drawer_data = generate_drawer_command(pin);
+    let drawer_data = generate_drawer_command(pin, &profile)?;
     print_raw(printer_name, &drawer_data)?;
     println!("✅ Cash drawer opened successfully");
     Ok(())
 }
 
-/// Print with drawer open after print
-pub fn print_with_drawer(printer_name: &str, data: &[u8], pin: u8) -> Result<()> {
-    let mut full_data = data.to_vec();
-    full_data.extend_from_slice(&generate_cut_command());
-    full_data.extend_from_slice(&generate_drawer_command(pin));
-    print_raw(printer_name, &full_data)?;
-    Ok(())
+/// Upload a logo to a printer's NV memory and record it in the printer's profile,
+/// so jobs can print it by key without sending the bitmap again
+pub fn store_logo(printer_name: &str, key: &str, image: &str) -> Result<StoredLogo> {
+    let code = logo::key_code(key)?;
+    let mut config = config::load_config()?;
+    let mut profile = config::get_printer_profile(&config, printer_name);
+    if profile.emulation.is_label() {
+        anyhow::bail!("Label printers can't store logos");
+    }
+
+    let image = ThermalImage::decode_base64(image)?;
+    let bitmap = ThermalImage::to_mono(&image, profile.dot_width, &profile.dithering);
+    profile.logos.insert(
+        key.to_string(),
+        StoredLogo {
+            width: bitmap.width(),
+            height: bitmap.height(),
+            number: None,
+            stored_at: chrono::Local::now().to_rfc3339(),
+        },
+    );
+
+    println!("🖼️ Storing logo {:?} ({}x{}) on printer: {}", key, bitmap.width(), bitmap.height(), printer_name);
+    match profile.logo_memory {
+        LogoMemory::Graphics => print_raw(printer_name, &logo::store_command(code, &bitmap))?,
+        LogoMemory::Legacy => write_legacy_logos(printer_name, &mut profile.logos, Some((key, &bitmap)))?,
+    }
+    logo::save_bitmap(printer_name, key, &bitmap)?;
+
+    let stored = profile.logos[key].clone();
+    config.printer_profiles.insert(printer_name.to_string(), profile);
+    config::save_config(&config)?;
+    println!("✅ Logo {:?} stored", key);
+    Ok(stored)
 }
 
-/// Clear print jobs for a printer (macOS/Linux)
-#[cfg(any(target_os = "macos", target_os = "linux"))]
-pub fn clear_print_jobs(printer_name: &str) -> Result<()> {
-    let output = Command::new("cancel")
-        .args(["-a", printer_name])
-        .output()
-        .context("Failed to execute cancel command")?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        // Don't fail if there are no jobs to cancel
-        if !stderr.contains("no job") {
-            anyhow::bail!("cancel command failed: {}", stderr);
-        }
+/// Remove a logo from a printer's NV memory and from its profile
+pub fn delete_logo(printer_name: &str, key: &str) -> Result<()> {
+    let code = logo::key_code(key)?;
+    let mut config = config::load_config()?;
+    let mut profile = config::get_printer_profile(&config, printer_name);
+    if profile.logos.remove(key).is_none() {
+        anyhow::bail!("Logo {:?} is not stored on {}", key, printer_name);
     }
 
+    match profile.logo_memory {
+        LogoMemory::Graphics => print_raw(printer_name, &logo::delete_command(code))?,
+        LogoMemory::Legacy => write_legacy_logos(printer_name, &mut profile.logos, None)?,
+    }
+    logo::remove_bitmap(printer_name, key);
+
+    config.printer_profiles.insert(printer_name.to_string(), profile);
+    config::save_config(&config)?;
     Ok(())
 }
 
-/// Clear print jobs for a printer (Windows)
-#[cfg(target_os = "windows")]
-pub fn clear_print_jobs(printer_name: &str) -> Result<()> {
-    use std::os::windows::process::CommandExt;
+/// Logos stored on a printer, by key
+pub fn list_logos(printer_name: &str) -> Result<BTreeMap<String, StoredLogo>> {
+    Ok(config::load_printer_profile(printer_name)?.logos)
+}
 
-    let output = Command::new("cmd")
-        .args([
-            "/C",
-            &format!("net stop spooler && net start spooler")
-        ])
-        .creation_flags(0x08000000)
-        .output()
-        .context("Failed to clear print queue")?;
+/// Rewrite legacy NV memory with every logo in `logos`, numbering them in key order.
+/// `FS q` replaces all images at once, so the saved bitmaps of the others are sent again.
+fn write_legacy_logos(
+    printer_name: &str,
+    logos: &mut BTreeMap<String, StoredLogo>,
+    new: Option<(&str, &MonoBitmap)>,
+) -> Result<()> {
+    let bitmaps = logos
+        .keys()
+        .map(|key| match new {
+            Some((new_key, bitmap)) if new_key == key => Ok(bitmap.clone()),
+            _ => logo::load_bitmap(printer_name, key),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let bitmaps: Vec<&MonoBitmap> = bitmaps.iter().collect();
+    logo::check_legacy(&bitmaps)?;
 
+    if !bitmaps.is_empty() {
+        print_raw(printer_name, &logo::legacy_store_command(&bitmaps))?;
+    }
+    for (number, stored) in (1u8..).zip(logos.values_mut()) {
+        stored.number = Some(number);
+    }
     Ok(())
 }
+
+/// Clear queued print jobs for a printer target
+pub fn clear_print_jobs(printer_name: &str) -> Result<()> {
+    transport::open(printer_name)?.clear_jobs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_measures_rendered_job() {
+        let profile = PrinterProfile::default();
+        let data = render_text("Hello", &profile).unwrap();
+        let preview = preview(&data, &profile).unwrap();
+
+        assert_eq!(preview.byte_size, data.len());
+        assert_eq!(preview.width, profile.dot_width);
+        // One text line and the feed before the cut, 30 dots each at 203 dpi
+        assert!((preview.paper_length_mm - 120.0 * 25.4 / 203.0).abs() < 0.5);
+        assert!(matches!(preview.effects.last(), Some(Effect::Cut { .. })));
+
+        let png = base64::engine::general_purpose::STANDARD.decode(&preview.png_base64).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().height(), preview.height);
+    }
+
+    #[test]
+    fn preview_rejects_other_emulations() {
+        let profile = PrinterProfile {
+            emulation: Emulation::StarLine,
+            ..PrinterProfile::default()
+        };
+        let data = render_text("Hello", &profile).unwrap();
+        let error = preview(&data, &profile).unwrap_err();
+        assert_eq!(error.to_string(), "Preview is not supported for StarLine printers");
+    }
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use crate::transport::{DeliveryError, NotReadyError};
+
+/// Broad cause of a failed print attempt, used to pick a retry policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    /// Printer could not be reached or rejected the data
+    PrinterOffline,
+    /// Printer reported it is out of paper
+    PaperOut,
+    /// Job content could not be downloaded: timeout, connection or HTTP error status
+    DownloadTimeout,
+    /// Job content could not be decoded (bad base64, corrupt image, invalid document)
+    DecodeError,
+    /// Anything else
+    Other,
+}
+
+impl FailureClass {
+    /// Classify an error by walking its cause chain
+    pub fn classify(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(e) = cause.downcast_ref::<NotReadyError>() {
+                return if e.status.paper_out {
+                    FailureClass::PaperOut
+                } else {
+                    FailureClass::PrinterOffline
+                };
+            }
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() || e.is_status() {
+                    return FailureClass::DownloadTimeout;
+                }
+            }
+            if cause.is::<image::ImageError>()
+                || cause.is::<base64::DecodeError>()
+                || cause.is::<serde_json::Error>()
+            {
+                return FailureClass::DecodeError;
+            }
+        }
+
+        if error.downcast_ref::<DeliveryError>().is_some() {
+            return FailureClass::PrinterOffline;
+        }
+
+        FailureClass::Other
+    }
+}
+
+/// Exponential backoff settings for one failure class; fields left out of
+/// the config take the values of [`RetryPolicy::default`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one; 1 disables retries
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+    /// Random spread applied to each delay, as a fraction (0.2 = ±20%)
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3, 5_000, 60_000, 2.0)
+    }
+}
+
+impl RetryPolicy {
+    const fn new(max_attempts: u32, initial_delay_ms: u64, max_delay_ms: u64, multiplier: f64) -> Self {
+        Self {
+            max_attempts,
+            initial_delay_ms,
+            max_delay_ms,
+            multiplier,
+            jitter: 0.2,
+        }
+    }
+
+    /// Whether another attempt is allowed after `attempts` failed ones
+    pub fn should_retry(&self, attempts: u32) -> bool {
+        attempts < self.max_attempts
+    }
+
+    /// Delay before the next attempt after `attempts` failed ones
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(32) as i32;
+        let base = self.initial_delay_ms as f64 * self.multiplier.max(1.0).powi(exponent);
+        let base = base.min(self.max_delay_ms as f64);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (fastrand::f64() * 2.0 - 1.0);
+
+        Duration::from_millis((base * factor).max(0.0) as u64)
+    }
+}
+
+/// Retry policies for every failure class
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryPolicies {
+    pub printer_offline: RetryPolicy,
+    pub paper_out: RetryPolicy,
+    pub download_timeout: RetryPolicy,
+    pub decode_error: RetryPolicy,
+    pub other: RetryPolicy,
+}
+
+impl Default for RetryPolicies {
+    fn default() -> Self {
+        Self {
+            // Printer rebooting or network blip: retry for roughly 15 minutes
+            printer_offline: RetryPolicy::new(8, 5_000, 300_000, 2.0),
+            // Staff need time to reload paper
+            paper_out: RetryPolicy::new(10, 30_000, 600_000, 1.5),
+            download_timeout: RetryPolicy::new(5, 2_000, 60_000, 2.0),
+            // Bad data won't fix itself
+            decode_error: RetryPolicy::new(1, 0, 0, 1.0),
+            other: RetryPolicy::default(),
+        }
+    }
+}
+
+impl RetryPolicies {
+    pub fn for_class(&self, class: FailureClass) -> &RetryPolicy {
+        match class {
+            FailureClass::PrinterOffline => &self.printer_offline,
+            FailureClass::PaperOut => &self.paper_out,
+            FailureClass::DownloadTimeout => &self.download_timeout,
+            FailureClass::DecodeError => &self.decode_error,
+            FailureClass::Other => &self.other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::PrinterStatus;
+    use anyhow::Context;
+
+    fn steady(max_delay_ms: u64) -> RetryPolicy {
+        RetryPolicy {
+            jitter: 0.0,
+            ..RetryPolicy::new(5, 1_000, max_delay_ms, 2.0)
+        }
+    }
+
+    #[test]
+    fn delays_grow_until_capped() {
+        let policy = steady(5_000);
+        let delays: Vec<_> = (1..=5).map(|attempt| policy.delay(attempt).as_millis()).collect();
+        assert_eq!(delays, vec![1_000, 2_000, 4_000, 5_000, 5_000]);
+        assert!(policy.should_retry(4) && !policy.should_retry(5));
+
+        let jittery = RetryPolicy {
+            jitter: 0.2,
+            ..steady(60_000)
+        };
+        for _ in 0..200 {
+            let delay = jittery.delay(2).as_millis();
+            assert!((1_600..=2_400).contains(&delay), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn partial_policies_fill_in_defaults() {
+        let policies: RetryPolicies = serde_json::from_value(serde_json::json!({
+            "paperOut": { "maxAttempts": 20 }
+        }))
+        .unwrap();
+        assert_eq!(policies.paper_out.max_attempts, 20);
+        assert_eq!(policies.paper_out.initial_delay_ms, RetryPolicy::default().initial_delay_ms);
+        assert_eq!(policies.printer_offline, RetryPolicies::default().printer_offline);
+    }
+
+    #[test]
+    fn classifies_by_error_type() {
+        let offline = Err::<(), _>(anyhow::anyhow!("connection refused"))
+            .context(DeliveryError {
+                target: "tcp://10.0.0.20:9100".to_string(),
+            })
+            .unwrap_err();
+        assert_eq!(FailureClass::classify(&offline), FailureClass::PrinterOffline);
+
+        let status = PrinterStatus {
+            online: true,
+            paper_out: true,
+            ..PrinterStatus::default()
+        };
+        let paper_out = status.ensure_ready("tcp://10.0.0.20:9100").unwrap_err();
+        assert_eq!(FailureClass::classify(&paper_out), FailureClass::PaperOut);
+
+        let decode = anyhow::Error::from(serde_json::from_str::<u8>("x").unwrap_err()).context("Invalid document");
+        assert_eq!(FailureClass::classify(&decode), FailureClass::DecodeError);
+
+        // Only typed errors count, not what the message says
+        let message = anyhow::anyhow!("Printer says: paper out");
+        assert_eq!(FailureClass::classify(&message), FailureClass::Other);
+    }
+}
@@ -0,0 +1,888 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::sync::watch;
+use crate::{api, config, printer};
+use crate::journal::{JobState, Journal, JournalEntry};
+use crate::retry::FailureClass;
+use crate::command_set::Emulation;
+use crate::transport::{AsbEvent, AsbListener, NotReadyError, PrinterStatus};
+
+/// Event carrying a log line for the UI
+pub const LOG_EVENT: &str = "service-log";
+
+/// Event emitted whenever a job changes state
+pub const JOB_EVENT: &str = "job-status";
+
+/// Event emitted whenever the service status changes
+pub const STATUS_EVENT: &str = "service-status";
+
+/// Event emitted whenever a printer watched with ASB changes state
+pub const HEALTH_EVENT: &str = "printer-health";
+
+/// Polling interval used when the config doesn't specify one
+const DEFAULT_POLLING_INTERVAL_MS: u64 = 3000;
+
+/// Lower bound so a bad config can't hammer the backend
+const MIN_POLLING_INTERVAL_MS: u64 = 1000;
+
+/// How often a printer holding jobs is asked again whether it's ready
+const HELD_RECHECK: Duration = Duration::from_secs(15);
+
+/// Snapshot of the background print service
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceStatus {
+    pub running: bool,
+    pub polling_interval: u64,
+    pub started_at: Option<String>,
+    pub last_poll_at: Option<String>,
+    pub jobs_printed: u64,
+    pub jobs_failed: u64,
+    pub pending_acks: usize,
+    pub last_error: Option<String>,
+}
+
+/// Live state of a printer watched with Automatic Status Back
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrinterHealth {
+    pub printer: String,
+    pub connected: bool,
+    /// Last status the printer reported, kept while reconnecting
+    pub status: Option<PrinterStatus>,
+    /// Why the status connection is down
+    pub reason: Option<String>,
+    pub updated_at: String,
+}
+
+/// Health of every watched printer, keyed by target
+type HealthMap = Arc<Mutex<HashMap<String, PrinterHealth>>>;
+
+#[derive(Debug, Clone, Serialize)]
+struct LogEntry {
+    level: &'static str,
+    message: String,
+}
+
+/// Job state change pushed to the UI
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobEvent {
+    pub job_id: i64,
+    pub printer: Option<String>,
+    pub status: String,
+    pub reason: Option<String>,
+}
+
+/// Background print service owned by the Tauri app.
+///
+/// Polls the backend for jobs, prints them on the mapped local printer
+/// and reports the result, independently of the webview.
+#[derive(Default)]
+pub struct PrintService {
+    status: Arc<Mutex<ServiceStatus>>,
+    health: HealthMap,
+    /// Stops the worker; closed once the worker has finished
+    stop: Mutex<Option<Arc<watch::Sender<bool>>>>,
+}
+
+impl PrintService {
+    /// Start polling; does nothing if the service is already running.
+    /// Fails while a stopped worker is still finishing its cycle, so two
+    /// workers never print the same journal entry.
+    pub fn start<R: Runtime>(&self, app: AppHandle<R>) -> Result<()> {
+        let mut stop = self.stop.lock().unwrap();
+        if let Some(running) = stop.as_ref().filter(|s| !s.is_closed()) {
+            if *running.borrow() {
+                anyhow::bail!("Print service is still stopping, try again in a moment");
+            }
+            return Ok(());
+        }
+
+        let cfg = config::load_config()?;
+        if cfg.domain_url.is_empty() || cfg.key.is_empty() {
+            anyhow::bail!("Please configure API settings first");
+        }
+
+        let journal = Journal::open()?;
+
+        let (stop_tx, stop_rx) = watch::channel(false);
+        *stop = Some(Arc::new(stop_tx));
+
+        {
+            let mut status = self.status.lock().unwrap();
+            status.running = true;
+            status.polling_interval = polling_interval(&cfg);
+            status.started_at = Some(chrono::Local::now().to_rfc3339());
+            status.last_error = None;
+        }
+
+        let worker = Worker {
+            app,
+            status: self.status.clone(),
+            health: self.health.clone(),
+            listeners: Mutex::new(HashMap::new()),
+            journal,
+        };
+        worker.emit_status();
+        tauri::async_runtime::spawn(worker.run(stop_rx));
+
+        Ok(())
+    }
+
+    /// Stop polling and wait for the job currently being printed (if any)
+    pub async fn stop<R: Runtime>(&self, app: &AppHandle<R>) {
+        let stop = self.stop.lock().unwrap().clone();
+        if let Some(stop) = stop {
+            let _ = stop.send(true);
+            stop.closed().await;
+        }
+
+        let status = {
+            let mut status = self.status.lock().unwrap();
+            status.running = false;
+            status.clone()
+        };
+        let _ = app.emit(STATUS_EVENT, status);
+    }
+
+    pub fn status(&self) -> ServiceStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Live state of the printers watched with ASB
+    pub fn health(&self) -> Vec<PrinterHealth> {
+        let mut health: Vec<_> = self.health.lock().unwrap().values().cloned().collect();
+        health.sort_by(|a, b| a.printer.cmp(&b.printer));
+        health
+    }
+}
+
+/// Effective polling interval in milliseconds
+fn polling_interval(cfg: &config::Config) -> u64 {
+    match cfg.polling_interval {
+        0 => DEFAULT_POLLING_INTERVAL_MS,
+        ms => ms.max(MIN_POLLING_INTERVAL_MS),
+    }
+}
+
+/// A running ASB listener. Events it reports after being dropped from
+/// [`Worker::listeners`] are ignored, so the printer leaves the health map.
+struct Watch {
+    _listener: AsbListener,
+    active: Arc<AtomicBool>,
+}
+
+/// The polling task itself
+struct Worker<R: Runtime> {
+    app: AppHandle<R>,
+    status: Arc<Mutex<ServiceStatus>>,
+    health: HealthMap,
+    /// ASB listeners by target, stopped when dropped
+    listeners: Mutex<HashMap<String, Watch>>,
+    journal: Journal,
+}
+
+impl<R: Runtime> Worker<R> {
+    async fn run(self, mut stop: watch::Receiver<bool>) {
+        let interval = self.status.lock().unwrap().polling_interval;
+        self.log("info", format!(
+            "Print service started - Polling every {} seconds",
+            interval as f64 / 1000.0
+        ));
+
+        if let Err(e) = self.journal.prune() {
+            self.fail(format!("Failed to prune job journal: {:#}", e));
+        }
+
+        let mut replayed = false;
+        loop {
+            // Reload every cycle so mapping and drawer changes apply immediately
+            let interval = match config::load_config() {
+                Ok(cfg) => {
+                    let client = api::ApiClient::new(&cfg.domain_url, &cfg.key);
+                    self.watch_printers(&cfg);
+                    if !replayed {
+                        self.replay(&client, &cfg).await;
+                        replayed = true;
+                    }
+                    self.flush_acks(&client).await;
+                    self.retry_due(&client, &cfg).await;
+                    self.poll(&client, &cfg).await;
+                    polling_interval(&cfg)
+                }
+                Err(e) => {
+                    self.fail(format!("Failed to load config: {}", e));
+                    interval
+                }
+            };
+
+            tokio::select! {
+                _ = stop.changed() => break,
+                _ = tokio::time::sleep(Duration::from_millis(interval)) => {}
+            }
+        }
+
+        let mut listeners = self.listeners.lock().unwrap();
+        {
+            let mut health = self.health.lock().unwrap();
+            for watch in listeners.values() {
+                watch.active.store(false, Ordering::Relaxed);
+            }
+            health.clear();
+        }
+        listeners.clear();
+        drop(listeners);
+        self.log("info", "Print service stopped".to_string());
+    }
+
+    /// Keep one ASB listener per `tcp://` printer whose profile enables it
+    fn watch_printers(&self, cfg: &config::Config) {
+        let watched: Vec<&String> = cfg.printer_profiles
+            .iter()
+            .filter(|(target, profile)| {
+                profile.asb && profile.emulation == Emulation::EscPos && target.starts_with("tcp://")
+            })
+            .map(|(target, _)| target)
+            .collect();
+
+        let mut listeners = self.listeners.lock().unwrap();
+        listeners.retain(|target, watch| {
+            let keep = watched.contains(&target);
+            if !keep {
+                let mut health = self.health.lock().unwrap();
+                watch.active.store(false, Ordering::Relaxed);
+                health.remove(target);
+            }
+            keep
+        });
+
+        for target in watched {
+            if listeners.contains_key(target) {
+                continue;
+            }
+            let app = self.app.clone();
+            let health = self.health.clone();
+            let active = Arc::new(AtomicBool::new(true));
+            let watching = active.clone();
+            let printer = target.clone();
+            let on_event = move |event| update_health(&app, &health, &watching, &printer, event);
+            match AsbListener::start(target, on_event) {
+                Ok(listener) => {
+                    self.log("info", format!("📡 Watching {} with Automatic Status Back", target));
+                    listeners.insert(target.clone(), Watch { _listener: listener, active });
+                }
+                Err(e) => self.fail(format!("Failed to watch {}: {:#}", target, e)),
+            }
+        }
+    }
+
+    /// Status last pushed by a watched printer that is still connected
+    fn live_status(&self, printer: &str) -> Option<PrinterStatus> {
+        self.health.lock().unwrap()
+            .get(printer)
+            .filter(|health| health.connected)
+            .and_then(|health| health.status)
+    }
+
+    /// Print jobs left unfinished by a previous run
+    async fn replay(&self, client: &api::ApiClient, cfg: &config::Config) {
+        let entries = match self.journal.load_all() {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.fail(format!("Failed to read job journal: {:#}", e));
+                return;
+            }
+        };
+
+        let unfinished: Vec<_> = entries.into_iter().filter(|e| e.needs_print()).collect();
+        if unfinished.is_empty() {
+            return;
+        }
+
+        self.log("warning", format!("Replaying {} unfinished jobs from journal", unfinished.len()));
+        for entry in unfinished {
+            self.process_entry(client, cfg, entry).await;
+        }
+    }
+
+    /// Print jobs whose retry delay has elapsed
+    async fn retry_due(&self, client: &api::ApiClient, cfg: &config::Config) {
+        let entries = match self.journal.load_all() {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.fail(format!("Failed to read job journal: {:#}", e));
+                return;
+            }
+        };
+
+        for entry in entries.into_iter().filter(|e| e.retry_due()) {
+            if entry.state == JobState::Retrying {
+                self.log("info", format!("Retrying job #{} (attempt {})", entry.job.id, entry.attempts + 1));
+            }
+            self.process_entry(client, cfg, entry).await;
+        }
+    }
+
+    /// Retry status reports that did not reach the backend
+    async fn flush_acks(&self, client: &api::ApiClient) {
+        let entries = match self.journal.load_all() {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.fail(format!("Failed to read job journal: {:#}", e));
+                return;
+            }
+        };
+
+        let mut pending = 0;
+        for mut entry in entries.into_iter().filter(|e| e.pending_ack().is_some()) {
+            if !self.acknowledge(client, &mut entry).await {
+                pending += 1;
+            }
+        }
+
+        self.status.lock().unwrap().pending_acks = pending;
+    }
+
+    /// Pull pending jobs and process them one by one
+    async fn poll(&self, client: &api::ApiClient, cfg: &config::Config) {
+        self.status.lock().unwrap().last_poll_at = Some(chrono::Local::now().to_rfc3339());
+        self.emit_status();
+
+        let jobs = match client.poll_print_jobs().await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                self.fail(format!("Poll failed: {:#}", e));
+                return;
+            }
+        };
+
+        if jobs.is_empty() {
+            return;
+        }
+
+        self.log("info", format!("Processing {} print jobs", jobs.len()));
+
+        // The server treats jobs as pulled once returned, so journal them all before printing any
+        let entries: Vec<_> = jobs.into_iter().map(JournalEntry::new).collect();
+        for entry in &entries {
+            self.record(entry);
+        }
+
+        for entry in entries {
+            self.process_entry(client, cfg, entry).await;
+        }
+    }
+
+    async fn process_entry(&self, client: &api::ApiClient, cfg: &config::Config, mut entry: JournalEntry) {
+        let job = entry.job.clone();
+        let api_printer = job.get_printer_name().unwrap_or_default();
+        let Some(local_printer) = config::get_mapped_printer(cfg, &api_printer).filter(|p| !p.is_empty()) else {
+            self.log("warning", format!("No printer mapping for: {}", api_printer));
+            entry.fail("No printer mapping configured");
+            self.record(&entry);
+            self.acknowledge(client, &mut entry).await;
+            return;
+        };
+
+        let held = entry.state == JobState::Held;
+        entry.printer = Some(local_printer.clone());
+
+        // A watched printer's queue is paused while it reports it can't print
+        if let Some(Err(e)) = self.live_status(&local_printer).map(|s| s.ensure_ready(&local_printer)) {
+            self.hold(&mut entry, held, &e);
+            return;
+        }
+
+        self.log("info", format!(
+            "Processing job #{} (type: {}) for printer: {}",
+            job.id,
+            job.get_job_type(),
+            local_printer
+        ));
+
+        entry.set_state(JobState::Rendering);
+        self.record(&entry);
+
+        match self.print_job(cfg, &mut entry, &local_printer).await {
+            Ok(()) => {
+                self.status.lock().unwrap().jobs_printed += 1;
+                self.log("info", format!("✅ Job #{} printed successfully", job.id));
+                entry.set_state(JobState::Sent);
+            }
+            Err(e) if e.chain().any(|cause| cause.is::<NotReadyError>()) => {
+                self.hold(&mut entry, held, &e);
+                return;
+            }
+            Err(e) => {
+                let reason = format!("{:#}", e);
+                let class = FailureClass::classify(&e);
+                let policy = cfg.retry_policies.for_class(class);
+                entry.attempts += 1;
+
+                if policy.should_retry(entry.attempts) {
+                    let delay = policy.delay(entry.attempts);
+                    self.log("warning", format!(
+                        "Job #{} failed ({:?}, attempt {}/{}), retrying in {}s: {}",
+                        job.id,
+                        class,
+                        entry.attempts,
+                        policy.max_attempts,
+                        delay.as_secs(),
+                        reason
+                    ));
+                    entry.schedule_retry(class, &reason, delay);
+                    self.record(&entry);
+                    return;
+                }
+
+                self.status.lock().unwrap().jobs_failed += 1;
+                self.fail(format!(
+                    "Failed to process job #{} after {} attempts, moved to dead letters: {}",
+                    job.id, entry.attempts, reason
+                ));
+                entry.dead_letter(class, &reason);
+            }
+        }
+
+        self.record(&entry);
+        self.acknowledge(client, &mut entry).await;
+    }
+
+    /// Keep a job until its printer is ready, telling the UI the first time
+    fn hold(&self, entry: &mut JournalEntry, held: bool, e: &anyhow::Error) {
+        let reason = format!("{:#}", e);
+        if !held {
+            self.log("warning", format!("⏸️ Holding job #{}: {}", entry.job.id, reason));
+            let _ = self.app.emit(JOB_EVENT, JobEvent {
+                job_id: entry.job.id,
+                printer: entry.printer.clone(),
+                status: "held".to_string(),
+                reason: Some(reason.clone()),
+            });
+        }
+        entry.hold(&reason, HELD_RECHECK);
+        self.record(entry);
+    }
+
+    /// Render the job once, send the copies not sent yet and open the drawer
+    /// if configured. Progress is journaled after every copy so a retry
+    /// doesn't print the earlier ones again.
+    async fn print_job(&self, cfg: &config::Config, entry: &mut JournalEntry, local_printer: &str) -> Result<()> {
+        let profile = config::get_printer_profile(cfg, local_printer);
+        let data = printer::render_job(&entry.job, &profile).await?;
+        let copies = entry.job.get_copies().max(1) as u32;
+
+        for copy in entry.copies_sent + 1..=copies {
+            if copies > 1 {
+                self.log("info", format!("Printing copy {} of {}", copy, copies));
+            }
+            let status = send_checked(local_printer, data.clone(), &profile).await?;
+            entry.copies_sent = copy;
+            self.record(entry);
+            if status.is_some_and(|s| s.paper_near_end) {
+                self.log("warning", format!("Paper is running low on {}", local_printer));
+            }
+        }
+
+        if cfg.open_drawer_after_print {
+            // The receipt is out, so a stuck drawer must not get it retried
+            if let Err(e) = self.open_drawer(cfg, local_printer, &profile).await {
+                self.log("warning", format!("Failed to open cash drawer on {}: {:#}", local_printer, e));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn open_drawer(&self, cfg: &config::Config, local_printer: &str, profile: &config::PrinterProfile) -> Result<()> {
+        self.log("info", format!(
+            "💰 Opening cash drawer on printer: {} (pin: {})",
+            local_printer, profile.drawer.pin.unwrap_or(cfg.drawer_pin)
+        ));
+        let printer_name = local_printer.to_string();
+        let pin = cfg.drawer_pin;
+        tokio::task::spawn_blocking(move || printer::open_drawer(&printer_name, pin)).await?
+    }
+
+    /// Report the entry's final status to the backend.
+    /// Returns false if the report has to be retried later.
+    async fn acknowledge(&self, client: &api::ApiClient, entry: &mut JournalEntry) -> bool {
+        let Some(status) = entry.pending_ack() else {
+            return true;
+        };
+
+        let reason = entry.reason.clone();
+        if let Err(e) = client.update_job_status(entry.job.id, status, reason.as_deref()).await {
+            entry.ack_attempts += 1;
+            self.record(entry);
+            self.fail(format!(
+                "Failed to update job #{} status to '{}' (attempt {}), will retry: {:#}",
+                entry.job.id, status, entry.ack_attempts, e
+            ));
+            return false;
+        }
+
+        entry.set_state(JobState::Acknowledged);
+        self.record(entry);
+
+        let _ = self.app.emit(JOB_EVENT, JobEvent {
+            job_id: entry.job.id,
+            printer: entry.printer.clone(),
+            status: status.to_string(),
+            reason,
+        });
+        self.emit_status();
+        true
+    }
+
+    /// Persist an entry; journal failures are logged but never stop printing
+    fn record(&self, entry: &JournalEntry) {
+        if let Err(e) = self.journal.record(entry) {
+            self.fail(format!("Failed to journal job #{}: {:#}", entry.job.id, e));
+        }
+    }
+
+    fn log(&self, level: &'static str, message: String) {
+        emit_log(&self.app, level, message);
+    }
+
+    /// Log an error and remember it as the last error
+    fn fail(&self, message: String) {
+        self.status.lock().unwrap().last_error = Some(message.clone());
+        self.log("error", message);
+    }
+
+    fn emit_status(&self) {
+        let status = self.status.lock().unwrap().clone();
+        let _ = self.app.emit(STATUS_EVENT, status);
+    }
+}
+
+fn emit_log<R: Runtime>(app: &AppHandle<R>, level: &'static str, message: String) {
+    println!("[{}] {}", level.to_uppercase(), message);
+    let _ = app.emit(LOG_EVENT, LogEntry { level, message });
+}
+
+/// Record what an ASB listener reported, telling the UI when it changes.
+/// `active` is checked under the map's lock, so a listener that was just
+/// dropped can't put its printer back.
+fn update_health<R: Runtime>(app: &AppHandle<R>, health: &HealthMap, active: &AtomicBool, printer: &str, event: AsbEvent) {
+    let mut map = health.lock().unwrap();
+    if !active.load(Ordering::Relaxed) {
+        return;
+    }
+    let previous = map.get(printer).cloned();
+    let (connected, status, reason) = match event {
+        AsbEvent::Status(status) => (true, Some(status), None),
+        AsbEvent::Disconnected(reason) => (false, previous.as_ref().and_then(|h| h.status), Some(reason)),
+    };
+    if previous.as_ref().is_some_and(|h| h.connected == connected && h.status == status && h.reason == reason) {
+        return;
+    }
+
+    let current = PrinterHealth {
+        printer: printer.to_string(),
+        connected,
+        status,
+        reason,
+        updated_at: chrono::Local::now().to_rfc3339(),
+    };
+    map.insert(printer.to_string(), current.clone());
+    drop(map);
+
+    match (&current.reason, current.status) {
+        (Some(reason), _) => emit_log(app, "warning", format!("📡 Lost status connection to {}: {}", printer, reason)),
+        (None, Some(status)) if !status.is_ready() => emit_log(app, "warning", format!(
+            "⏸️ {} can't print ({}), pausing its jobs",
+            printer,
+            status.problems().join(", ")
+        )),
+        (None, Some(status)) if status.paper_near_end => {
+            emit_log(app, "warning", format!("Paper is running low on {}", printer));
+        }
+        _ => emit_log(app, "info", format!("🟢 {} is ready", printer)),
+    }
+    let _ = app.emit(HEALTH_EVENT, current);
+}
+
+/// Send data to a printer with the profile's status checks, without
+/// blocking the async runtime
+async fn send_checked(printer_name: &str, data: Vec<u8>, profile: &config::PrinterProfile) -> Result<Option<PrinterStatus>> {
+    let printer_name = printer_name.to_string();
+    let profile = profile.clone();
+    tokio::task::spawn_blocking(move || printer::print_checked(&printer_name, &data, &profile)).await?
+}
+
+/// Print a dead-lettered job again on demand.
+///
+/// Uses `printer_name` if given, otherwise the job's current mapping or the
+/// printer it last failed on. Copies that already printed are skipped. On
+/// success the job leaves the dead-letter list.
+pub async fn reprint_dead_letter(job_id: i64, printer_name: Option<&str>) -> Result<()> {
+    let journal = Journal::open()?;
+    let mut entry = journal.get(job_id)?
+        .filter(|e| e.dead_letter)
+        .ok_or_else(|| anyhow::anyhow!("Job #{} is not in the dead-letter list", job_id))?;
+
+    let cfg = config::load_config()?;
+    let local_printer = printer_name
+        .map(|p| p.to_string())
+        .or_else(|| {
+            entry.job.get_printer_name()
+                .and_then(|name| config::get_mapped_printer(&cfg, &name))
+                .filter(|p| !p.is_empty())
+        })
+        .or_else(|| entry.printer.clone())
+        .ok_or_else(|| anyhow::anyhow!("No printer mapping configured for job #{}", job_id))?;
+
+    let profile = config::get_printer_profile(&cfg, &local_printer);
+    let data = printer::render_job(&entry.job, &profile).await?;
+    // Copies that came out before the job failed aren't printed again
+    for copy in entry.copies_sent + 1..=entry.job.get_copies().max(1) as u32 {
+        send_checked(&local_printer, data.clone(), &profile).await?;
+        entry.copies_sent = copy;
+        journal.record(&entry)?;
+    }
+
+    entry.dead_letter = false;
+    entry.printer = Some(local_printer);
+    entry.set_state(JobState::Acknowledged);
+    journal.record(&entry)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::PrintJob;
+    use crate::config::PrinterProfile;
+    use crate::retry::RetryPolicy;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use tauri::test::{mock_app, MockRuntime};
+
+    /// Scratch directory, removed again when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("vopecs_service_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        /// `file://` target writing every copy to `name`
+        fn target(&self, name: &str) -> String {
+            format!("file://{}", self.0.join(name).display())
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Backend accepting every status update, recorded as "<job id> <status>"
+    fn backend() -> (api::ApiClient, Arc<Mutex<Vec<String>>>) {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = api::ApiClient::new(&format!("http://{}", server.local_addr().unwrap()), "key");
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorded = updates.clone();
+
+        std::thread::spawn(move || {
+            for socket in server.incoming().flatten() {
+                let mut reader = BufReader::new(socket);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    match header.split_once(':') {
+                        Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                            length = value.trim().parse().unwrap();
+                        }
+                        None => break,
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let path = request.split_whitespace().nth(1).unwrap();
+                let job_id = path.rsplit('/').next().unwrap();
+                recorded.lock().unwrap().push(format!("{} {}", job_id, body["status"].as_str().unwrap()));
+                let _ = reader
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}");
+            }
+        });
+
+        (client, updates)
+    }
+
+    fn worker(app: &tauri::App<MockRuntime>, dir: &TempDir) -> Worker<MockRuntime> {
+        Worker {
+            app: app.handle().clone(),
+            status: Arc::default(),
+            health: Arc::default(),
+            listeners: Mutex::default(),
+            journal: Journal::open_in(dir.0.join("journal")).unwrap(),
+        }
+    }
+
+    /// Config mapping the "Kitchen" printer to `target`
+    fn config(target: &str) -> config::Config {
+        let mut cfg = config::Config::new();
+        cfg.printer_mappings.insert("Kitchen".to_string(), target.to_string());
+        cfg
+    }
+
+    fn job(copies: i32) -> JournalEntry {
+        JournalEntry::new(PrintJob {
+            id: 7,
+            printer_name: Some("Kitchen".to_string()),
+            content: Some("Hello".to_string()),
+            copies: Some(copies),
+            ..Default::default()
+        })
+    }
+
+    /// Size of one printed copy of `job`
+    fn copy_len() -> usize {
+        printer::render_text("Hello", &PrinterProfile::default()).unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn prints_every_copy_and_acknowledges() {
+        let (app, dir) = (mock_app(), TempDir::new("print"));
+        let (client, updates) = backend();
+        let worker = worker(&app, &dir);
+
+        worker.process_entry(&client, &config(&dir.target("out.bin")), job(2)).await;
+
+        let entry = worker.journal.get(7).unwrap().unwrap();
+        assert_eq!(entry.state, JobState::Acknowledged);
+        assert_eq!(entry.copies_sent, 2);
+        assert_eq!(fs::read(dir.0.join("out.bin")).unwrap().len(), 2 * copy_len());
+        assert_eq!(*updates.lock().unwrap(), ["7 done"]);
+    }
+
+    #[tokio::test]
+    async fn resumes_after_the_last_sent_copy() {
+        let (app, dir) = (mock_app(), TempDir::new("resume"));
+        let (client, _) = backend();
+        let worker = worker(&app, &dir);
+
+        let mut entry = job(3);
+        entry.copies_sent = 2;
+        worker.process_entry(&client, &config(&dir.target("out.bin")), entry).await;
+
+        assert_eq!(fs::read(dir.0.join("out.bin")).unwrap().len(), copy_len());
+        assert_eq!(worker.journal.get(7).unwrap().unwrap().copies_sent, 3);
+    }
+
+    #[tokio::test]
+    async fn retries_then_dead_letters() {
+        let (app, dir) = (mock_app(), TempDir::new("retry"));
+        let (client, updates) = backend();
+        let worker = worker(&app, &dir);
+
+        // A file where the output directory should be makes every write fail
+        fs::write(dir.0.join("blocked"), b"").unwrap();
+        let mut cfg = config(&dir.target("blocked/out.bin"));
+        cfg.retry_policies.printer_offline = RetryPolicy {
+            max_attempts: 2,
+            ..RetryPolicy::default()
+        };
+
+        worker.process_entry(&client, &cfg, job(1)).await;
+        let entry = worker.journal.get(7).unwrap().unwrap();
+        assert_eq!(entry.state, JobState::Retrying);
+        assert_eq!(entry.attempts, 1);
+        assert_eq!(entry.failure_class, Some(FailureClass::PrinterOffline));
+        assert!(!entry.retry_due());
+        assert!(updates.lock().unwrap().is_empty());
+
+        worker.process_entry(&client, &cfg, entry).await;
+        let entry = worker.journal.get(7).unwrap().unwrap();
+        assert!(entry.dead_letter);
+        assert_eq!(entry.attempts, 2);
+        assert_eq!(entry.state, JobState::Acknowledged);
+        assert_eq!(*updates.lock().unwrap(), ["7 failed"]);
+    }
+
+    #[tokio::test]
+    async fn holds_jobs_while_the_printer_reports_a_problem() {
+        let (app, dir) = (mock_app(), TempDir::new("hold"));
+        let (client, updates) = backend();
+        let worker = worker(&app, &dir);
+        let target = dir.target("out.bin");
+        let cfg = config(&target);
+
+        let report = |status: PrinterStatus| PrinterHealth {
+            printer: target.clone(),
+            connected: true,
+            status: Some(status),
+            reason: None,
+            updated_at: String::new(),
+        };
+        let ready = PrinterStatus {
+            online: true,
+            ..PrinterStatus::default()
+        };
+        let paper_out = PrinterStatus {
+            paper_out: true,
+            ..ready
+        };
+        worker.health.lock().unwrap().insert(target.clone(), report(paper_out));
+
+        worker.process_entry(&client, &cfg, job(1)).await;
+        let entry = worker.journal.get(7).unwrap().unwrap();
+        assert_eq!(entry.state, JobState::Held);
+        assert_eq!(entry.attempts, 0);
+        assert!(!dir.0.join("out.bin").exists());
+        assert!(updates.lock().unwrap().is_empty());
+
+        // Paper reloaded, the next check prints it
+        worker.health.lock().unwrap().insert(target.clone(), report(ready));
+        worker.process_entry(&client, &cfg, entry).await;
+        assert_eq!(worker.journal.get(7).unwrap().unwrap().state, JobState::Acknowledged);
+        assert_eq!(*updates.lock().unwrap(), ["7 done"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_unacknowledged_jobs_for_the_next_poll() {
+        let (app, dir) = (mock_app(), TempDir::new("ack"));
+        let worker = worker(&app, &dir);
+        let cfg = config(&dir.target("out.bin"));
+
+        // Nothing listens on the backend's port
+        let offline = {
+            let server = TcpListener::bind("127.0.0.1:0").unwrap();
+            api::ApiClient::new(&format!("http://{}", server.local_addr().unwrap()), "key")
+        };
+        worker.process_entry(&offline, &cfg, job(1)).await;
+        let entry = worker.journal.get(7).unwrap().unwrap();
+        assert_eq!(entry.state, JobState::Sent);
+        assert_eq!(entry.ack_attempts, 1);
+
+        let (client, updates) = backend();
+        worker.flush_acks(&client).await;
+        assert_eq!(worker.journal.get(7).unwrap().unwrap().state, JobState::Acknowledged);
+        assert_eq!(*updates.lock().unwrap(), ["7 done"]);
+        assert_eq!(fs::read(dir.0.join("out.bin")).unwrap().len(), copy_len());
+    }
+}
@@ -0,0 +1,335 @@
+use ab_glyph::{point, Font, FontRef, GlyphId, PxScale, ScaleFont};
+use image::{GrayImage, Luma};
+use rustybuzz::{Direction, UnicodeBuffer};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use unicode_bidi::BidiInfo;
+use crate::config::PrinterProfile;
+use crate::escpos::Justify;
+
+/// Font used for rasterized text: DejaVu Sans, which covers Latin and Arabic
+pub static FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// Fixed-pitch companion, for receipts that line columns up with spaces
+static MONO_FONT: &[u8] = include_bytes!("../fonts/DejaVuSansMono.ttf");
+
+/// When text is drawn into a bitmap instead of being sent as printer text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TextRendering {
+    /// Rasterize only text the profile's code page can't represent
+    #[default]
+    Auto,
+    /// Always send printer text, replacing characters missing from the code page
+    Native,
+    /// Always rasterize
+    Raster,
+}
+
+/// Whether `text` has to be rasterized on this printer
+pub fn needs_raster(text: &str, profile: &PrinterProfile) -> bool {
+    match profile.text_rendering {
+        TextRendering::Auto => !profile.code_page.can_encode(text),
+        TextRendering::Native => false,
+        TextRendering::Raster => true,
+    }
+}
+
+/// Layout settings for rasterized text
+#[derive(Debug, Clone)]
+pub struct TextOptions {
+    /// Line height in dots
+    pub size: f32,
+    /// Horizontal stretch, 1.0 = normal width
+    pub stretch: f32,
+    pub bold: bool,
+    /// Use the fixed-pitch font
+    pub monospace: bool,
+    /// Alignment; `None` follows each paragraph's direction (right for Arabic)
+    pub justify: Option<Justify>,
+}
+
+impl TextOptions {
+    pub fn from_profile(profile: &PrinterProfile) -> Self {
+        Self {
+            size: profile.font_size,
+            stretch: 1.0,
+            bold: false,
+            monospace: false,
+            justify: None,
+        }
+    }
+}
+
+/// A positioned glyph, in dots
+struct Glyph {
+    id: u16,
+    advance: f32,
+    x_offset: f32,
+    y_offset: f32,
+}
+
+/// Shapes and draws text with the bundled font.
+///
+/// Each paragraph goes through the Unicode bidi algorithm, is wrapped to the
+/// line width, and every directional run is shaped separately, so Arabic
+/// letters join correctly and mixed Arabic/English lines read in the right order.
+pub struct TextRasterizer {
+    face: rustybuzz::Face<'static>,
+    font: FontRef<'static>,
+    scale: PxScale,
+    options: TextOptions,
+}
+
+impl TextRasterizer {
+    pub fn new(options: TextOptions) -> Self {
+        let data = if options.monospace { MONO_FONT } else { FONT };
+        let face = rustybuzz::Face::from_slice(data, 0).expect("bundled font is valid");
+        let font = FontRef::try_from_slice(data).expect("bundled font is valid");
+        let size = options.size.max(1.0);
+        let scale = PxScale {
+            x: size * options.stretch.max(0.1),
+            y: size,
+        };
+
+        Self {
+            face,
+            font,
+            scale,
+            options,
+        }
+    }
+
+    /// Shape a run of text; `rtl` forces the direction, otherwise it is guessed
+    fn shape(&self, text: &str, rtl: Option<bool>) -> Vec<Glyph> {
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.guess_segment_properties();
+        match rtl {
+            Some(true) => buffer.set_direction(Direction::RightToLeft),
+            Some(false) => buffer.set_direction(Direction::LeftToRight),
+            None => {}
+        }
+
+        let output = rustybuzz::shape(&self.face, &[], buffer);
+        let scaled = self.font.as_scaled(self.scale);
+        let (h, v) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+
+        output
+            .glyph_infos()
+            .iter()
+            .zip(output.glyph_positions())
+            .map(|(info, pos)| Glyph {
+                id: info.glyph_id as u16,
+                advance: pos.x_advance as f32 * h,
+                x_offset: pos.x_offset as f32 * h,
+                y_offset: pos.y_offset as f32 * v,
+            })
+            .collect()
+    }
+
+    /// Whether the font has a glyph for `c`
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.font.glyph_id(c).0 != 0
+    }
+
+    /// Width of a piece of text in dots
+    pub fn measure(&self, text: &str) -> f32 {
+        self.shape(text, None).iter().map(|g| g.advance).sum()
+    }
+
+    /// Split a paragraph into line ranges no wider than `width`, breaking at
+    /// whitespace where possible
+    fn wrap(&self, paragraph: &str, width: f32) -> Vec<Range<usize>> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut last_break = None;
+
+        for (i, c) in paragraph.char_indices() {
+            let end = i + c.len_utf8();
+            if c.is_whitespace() {
+                last_break = Some(end);
+                continue;
+            }
+
+            if i > start && self.measure(paragraph[start..end].trim_end()) > width {
+                let line_end = match last_break {
+                    Some(b) if b > start => b,
+                    _ => i,
+                };
+                lines.push(start..line_end);
+                start = line_end;
+                last_break = None;
+            }
+        }
+
+        lines.push(start..paragraph.len());
+        lines
+    }
+
+    /// Distance between baselines in dots
+    pub fn line_height(&self) -> u32 {
+        let scaled = self.font.as_scaled(self.scale);
+        (scaled.height() + scaled.line_gap()).ceil().max(1.0) as u32
+    }
+
+    /// Height above the baseline in dots
+    pub fn ascent(&self) -> f32 {
+        self.font.as_scaled(self.scale).ascent()
+    }
+
+    /// Draw a run of text in one direction starting at `x`, on `baseline`
+    pub fn draw(&self, image: &mut GrayImage, text: &str, x: f32, baseline: f32) {
+        let mut x = x;
+        for glyph in self.shape(text, None) {
+            self.draw_glyph(image, &glyph, x + glyph.x_offset, baseline - glyph.y_offset);
+            x += glyph.advance;
+        }
+    }
+
+    /// Render text into a black-on-white image `width` dots wide
+    pub fn render(&self, text: &str, width: u32) -> GrayImage {
+        let text = text.replace('\t', "    ");
+        let paragraphs: Vec<&str> = text.lines().collect();
+
+        // Lay out every line first to know the image height
+        let mut layout = Vec::new();
+        for paragraph in &paragraphs {
+            let bidi = BidiInfo::new(paragraph, None);
+            let lines = if paragraph.trim().is_empty() {
+                Vec::new()
+            } else {
+                self.wrap(paragraph, width as f32)
+            };
+            layout.push((bidi, lines));
+        }
+
+        let line_height = self.line_height();
+        let line_count: usize = layout.iter().map(|(_, lines)| lines.len().max(1)).sum();
+        let mut image = GrayImage::from_pixel(width, line_height * line_count.max(1) as u32, Luma([255]));
+        let ascent = self.ascent();
+
+        let mut top = 0.0;
+        for ((bidi, lines), paragraph) in layout.iter().zip(&paragraphs) {
+            if lines.is_empty() {
+                top += line_height as f32;
+                continue;
+            }
+
+            let info = &bidi.paragraphs[0];
+            for range in lines {
+                let trimmed = paragraph[range.clone()].trim_end().len();
+                let range = range.start..range.start + trimmed;
+
+                let mut glyphs = Vec::new();
+                if !range.is_empty() {
+                    let (levels, runs) = bidi.visual_runs(info, range);
+                    for run in runs {
+                        let rtl = levels[run.start].is_rtl();
+                        glyphs.extend(self.shape(&paragraph[run], Some(rtl)));
+                    }
+                }
+
+                let line_width: f32 = glyphs.iter().map(|g| g.advance).sum();
+                let free = (width as f32 - line_width).max(0.0);
+                let justify = self.options.justify.unwrap_or(if info.level.is_rtl() {
+                    Justify::Right
+                } else {
+                    Justify::Left
+                });
+                let mut x = match justify {
+                    Justify::Left => 0.0,
+                    Justify::Center => (free / 2.0).floor(),
+                    Justify::Right => free,
+                };
+
+                let baseline = top + ascent;
+                for glyph in &glyphs {
+                    self.draw_glyph(&mut image, glyph, x + glyph.x_offset, baseline - glyph.y_offset);
+                    x += glyph.advance;
+                }
+
+                top += line_height as f32;
+            }
+        }
+
+        image
+    }
+
+    fn draw_glyph(&self, image: &mut GrayImage, glyph: &Glyph, x: f32, y: f32) {
+        let positioned = GlyphId(glyph.id).with_scale_and_position(self.scale, point(x, y));
+        let Some(outlined) = self.font.outline_glyph(positioned) else {
+            return;
+        };
+
+        let bounds = outlined.px_bounds();
+        let passes = if self.options.bold { 2 } else { 1 };
+        outlined.draw(|gx, gy, coverage| {
+            if coverage < 0.5 {
+                return;
+            }
+            for pass in 0..passes {
+                let px = bounds.min.x as i32 + gx as i32 + pass;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px >= 0 && py >= 0 && (px as u32) < image.width() && (py as u32) < image.height() {
+                    image.put_pixel(px as u32, py as u32, Luma([0]));
+                }
+            }
+        });
+    }
+}
+
+/// Rasterize text with the profile's font size across the printable width
+pub fn rasterize(text: &str, profile: &PrinterProfile) -> GrayImage {
+    TextRasterizer::new(TextOptions::from_profile(profile)).render(text, profile.dot_width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> TextOptions {
+        TextOptions::from_profile(&PrinterProfile::default())
+    }
+
+    /// Leftmost and rightmost black columns
+    fn ink_columns(image: &GrayImage) -> (u32, u32) {
+        let columns: Vec<u32> = (0..image.width())
+            .filter(|&x| (0..image.height()).any(|y| image.get_pixel(x, y)[0] == 0))
+            .collect();
+        (columns[0], *columns.last().unwrap())
+    }
+
+    #[test]
+    fn arabic_is_right_aligned_and_english_left_aligned() {
+        let rasterizer = TextRasterizer::new(options());
+
+        let (left, _) = ink_columns(&rasterizer.render("مرحبا", 576));
+        assert!(left > 288, "Arabic text should start in the right half, got {}", left);
+
+        let (_, right) = ink_columns(&rasterizer.render("Hello", 576));
+        assert!(right < 288, "English text should end in the left half, got {}", right);
+    }
+
+    #[test]
+    fn arabic_letters_are_joined() {
+        let rasterizer = TextRasterizer::new(options());
+        let isolated: f32 = "م ر ح ب ا".split(' ').map(|c| rasterizer.measure(c)).sum();
+        assert!(rasterizer.measure("مرحبا") < isolated);
+    }
+
+    #[test]
+    fn long_lines_wrap_at_spaces() {
+        let rasterizer = TextRasterizer::new(options());
+        let text = "word ".repeat(40);
+        let lines = rasterizer.wrap(text.trim_end(), 576.0);
+
+        assert!(lines.len() > 1);
+        for range in &lines[..lines.len() - 1] {
+            assert!(text[range.clone()].ends_with(' '));
+        }
+
+        let image = rasterizer.render(&text, 576);
+        assert_eq!(image.height(), rasterizer.line_height() * lines.len() as u32);
+    }
+}
@@ -0,0 +1,319 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use super::status::{AsbParser, AsbReply, ASB_DISABLE, ASB_ENABLE, COMPLETION_TIMEOUT, PAPER_SENSOR_REQUEST};
+use super::{DeliveryError, PrinterStatus, SocketTransport, Target};
+
+/// How often a blocked read wakes up to check whether the listener was stopped
+const ASB_READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Silence after which the printer is asked for its status to prove the
+/// connection is still alive
+const ASB_KEEPALIVE: Duration = Duration::from_secs(30);
+
+/// How long the printer has to answer that request
+const ASB_KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pause before reconnecting to a printer that dropped or refused the connection
+const ASB_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// DLE EOT 1, only sent for its reply
+const KEEPALIVE_REQUEST: [u8; 3] = [0x10, 0x04, 0x01];
+
+/// Connections of the running listeners by target. Watched printers may
+/// refuse a second connection, so everything sent to them goes over these.
+static CONNECTIONS: Mutex<BTreeMap<String, AsbConnection>> = Mutex::new(BTreeMap::new());
+
+/// Connection of the listener watching `target`, if any
+pub fn connection(target: &str) -> Option<AsbConnection> {
+    CONNECTIONS.lock().unwrap().get(target).cloned()
+}
+
+/// What an ASB listener reports
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsbEvent {
+    /// The printer sent a status report
+    Status(PrinterStatus),
+    /// The connection failed or was lost; the listener keeps reconnecting
+    Disconnected(String),
+}
+
+/// What the listener last heard, for jobs waiting on the printer
+#[derive(Debug, Default)]
+struct LinkState {
+    connected: bool,
+    status: Option<PrinterStatus>,
+    /// GS r replies no job has picked up yet
+    replies: VecDeque<u8>,
+}
+
+/// The listener's connection, shared so jobs can be sent over it
+#[derive(Clone)]
+pub struct AsbConnection {
+    stream: Arc<Mutex<Option<TcpStream>>>,
+    state: Arc<(Mutex<LinkState>, Condvar)>,
+    target: String,
+}
+
+impl AsbConnection {
+    /// Send data to the printer, false when the listener isn't connected.
+    /// Failures are tagged with [`DeliveryError`] like [`super::send`].
+    pub fn send(&self, data: &[u8]) -> Result<bool> {
+        let mut stream = self.stream.lock().unwrap();
+        let Some(stream) = stream.as_mut() else {
+            return Ok(false);
+        };
+        stream
+            .write_all(data)
+            .context("Failed to send data over the status connection")
+            .with_context(|| DeliveryError {
+                target: self.target.clone(),
+            })?;
+        Ok(true)
+    }
+
+    /// Send a job followed by GS r 1 and wait until the printer has worked
+    /// through it, as [`super::send_checked`] does on its own connection.
+    /// Fails with [`NotReadyError`](super::NotReadyError) when the printer
+    /// reports a problem meanwhile; false when the listener isn't connected.
+    pub fn send_confirmed(&self, data: &[u8]) -> Result<bool> {
+        self.state.0.lock().unwrap().replies.clear();
+        if !self.send(data)? {
+            return Ok(false);
+        }
+        if !self.send(&PAPER_SENSOR_REQUEST)? {
+            return Err(self.lost());
+        }
+
+        let (lock, changed) = &*self.state;
+        let (mut state, _) = changed
+            .wait_timeout_while(lock.lock().unwrap(), COMPLETION_TIMEOUT, |state| {
+                state.connected && state.replies.is_empty()
+            })
+            .unwrap();
+        let reply = state.replies.pop_front();
+        if reply.is_none() {
+            if !state.connected {
+                return Err(self.lost());
+            }
+            println!("⚠️ Printer did not confirm the job within {}s", COMPLETION_TIMEOUT.as_secs());
+        }
+
+        if let Some(mut status) = state.status {
+            if let Some(reply) = reply {
+                status.apply_paper_sensor(reply);
+            }
+            status.ensure_ready(&self.target)?;
+        }
+        Ok(true)
+    }
+
+    /// Last status the printer reported, `None` while disconnected
+    pub fn status(&self) -> Option<PrinterStatus> {
+        let state = self.state.0.lock().unwrap();
+        state.status.filter(|_| state.connected)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state.0.lock().unwrap().connected
+    }
+
+    fn lost(&self) -> anyhow::Error {
+        anyhow::Error::new(DeliveryError {
+            target: self.target.clone(),
+        })
+        .context("Lost the status connection before the printer confirmed the job")
+    }
+
+    fn set(&self, stream: Option<TcpStream>) {
+        let connected = stream.is_some();
+        *self.stream.lock().unwrap() = stream;
+        self.update(|state| state.connected = connected);
+    }
+
+    /// Change what the listener heard and wake the jobs waiting on it
+    fn update(&self, change: impl FnOnce(&mut LinkState)) {
+        let (lock, changed) = &*self.state;
+        change(&mut lock.lock().unwrap());
+        changed.notify_all();
+    }
+}
+
+/// Long-lived connection to a network printer with Automatic Status Back
+/// enabled, reporting every status change until dropped
+pub struct AsbListener {
+    stop: Arc<AtomicBool>,
+    connection: AsbConnection,
+}
+
+impl AsbListener {
+    /// Start listening to a `tcp://` target on a background thread
+    pub fn start(target: &str, on_event: impl Fn(AsbEvent) + Send + 'static) -> Result<Self> {
+        let Target::Socket(address) = Target::parse(target)? else {
+            anyhow::bail!("Automatic Status Back needs a tcp:// printer, got {}", target);
+        };
+        let transport = SocketTransport::new(&address)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let connection = AsbConnection {
+            stream: Arc::default(),
+            state: Arc::default(),
+            target: target.to_string(),
+        };
+        let listener = Self {
+            stop: stop.clone(),
+            connection: connection.clone(),
+        };
+        CONNECTIONS.lock().unwrap().insert(target.to_string(), connection.clone());
+
+        std::thread::Builder::new()
+            .name(format!("asb {}", address))
+            .spawn(move || run(&transport, &stop, &connection, &on_event))
+            .context("Failed to start status listener")?;
+
+        Ok(listener)
+    }
+}
+
+impl Drop for AsbListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        {
+            let mut connections = CONNECTIONS.lock().unwrap();
+            let target = &self.connection.target;
+            if connections.get(target).is_some_and(|c| Arc::ptr_eq(&c.state, &self.connection.state)) {
+                connections.remove(target);
+            }
+        }
+        let stream = self.connection.stream.lock().unwrap().take();
+        if let Some(mut stream) = stream {
+            let _ = stream.write_all(&ASB_DISABLE);
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.connection.update(|state| state.connected = false);
+    }
+}
+
+/// Connect, listen and reconnect until stopped
+fn run(transport: &SocketTransport, stop: &AtomicBool, connection: &AsbConnection, on_event: &dyn Fn(AsbEvent)) {
+    while !stop.load(Ordering::Relaxed) {
+        let result = transport.connect().and_then(|stream| listen(stream, stop, connection, on_event));
+        connection.set(None);
+
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        let reason = match result {
+            Ok(()) => "Status listener stopped".to_string(),
+            Err(e) => format!("{:#}", e),
+        };
+        on_event(AsbEvent::Disconnected(reason));
+
+        let retry_at = Instant::now() + ASB_RECONNECT_DELAY;
+        while !stop.load(Ordering::Relaxed) && Instant::now() < retry_at {
+            std::thread::sleep(ASB_READ_TIMEOUT.min(retry_at.saturating_duration_since(Instant::now())));
+        }
+    }
+}
+
+/// Enable ASB and parse what comes back until the connection fails or the
+/// listener is stopped
+fn listen(mut stream: TcpStream, stop: &AtomicBool, connection: &AsbConnection, on_event: &dyn Fn(AsbEvent)) -> Result<()> {
+    stream.set_read_timeout(Some(ASB_READ_TIMEOUT))?;
+    stream.write_all(&ASB_ENABLE).context("Failed to enable Automatic Status Back")?;
+    connection.set(Some(stream.try_clone()?));
+
+    let mut parser = AsbParser::default();
+    let mut buffer = [0; 64];
+    let mut last_heard = Instant::now();
+    let mut probed = false;
+
+    while !stop.load(Ordering::Relaxed) {
+        match stream.read(&mut buffer) {
+            Ok(0) => anyhow::bail!("Printer closed the status connection"),
+            Ok(n) => {
+                last_heard = Instant::now();
+                probed = false;
+                for reply in buffer[..n].iter().filter_map(|&byte| parser.push(byte)) {
+                    match reply {
+                        AsbReply::Status(status) => {
+                            connection.update(|state| state.status = Some(status));
+                            on_event(AsbEvent::Status(status));
+                        }
+                        AsbReply::PaperSensor(reply) => connection.update(|state| state.replies.push_back(reply)),
+                    }
+                }
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                let silence = last_heard.elapsed();
+                if silence >= ASB_KEEPALIVE + ASB_KEEPALIVE_TIMEOUT {
+                    anyhow::bail!("Printer stopped answering status requests");
+                }
+                if silence >= ASB_KEEPALIVE && !probed {
+                    // Jobs may be writing on the clone, so go through the connection
+                    connection.send(&KEEPALIVE_REQUEST)?;
+                    probed = true;
+                }
+            }
+            Err(e) => return Err(e).context("Failed to read from the status connection"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    #[test]
+    fn reports_status_pushed_by_the_printer() {
+        let printer = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = format!("tcp://{}", printer.local_addr().unwrap());
+
+        let (events, received) = mpsc::channel();
+        let listener = AsbListener::start(&target, move |event| {
+            let _ = events.send(event);
+        })
+        .unwrap();
+
+        let (mut socket, _) = printer.accept().unwrap();
+        let mut request = [0; 3];
+        socket.read_exact(&mut request).unwrap();
+        assert_eq!(request, ASB_ENABLE);
+
+        socket.write_all(&[0x10, 0x00, 0x0C, 0x00]).unwrap();
+        let AsbEvent::Status(status) = received.recv_timeout(Duration::from_secs(5)).unwrap() else {
+            panic!("expected a status report");
+        };
+        assert!(status.paper_out);
+
+        // Jobs go over the same connection and wait for GS r to be answered
+        let job_target = target.clone();
+        let job = std::thread::spawn(move || crate::transport::send(&job_target, b"Hi\n"));
+        let mut sent = [0; 6];
+        socket.read_exact(&mut sent).unwrap();
+        assert_eq!(sent, [b'H', b'i', b'\n', 0x1D, 0x72, 0x01]);
+        assert!(!job.is_finished());
+
+        // Paper was replaced, then the job is confirmed
+        socket.write_all(&[0x10, 0x00, 0x00, 0x00, 0x00]).unwrap();
+        job.join().unwrap().unwrap();
+
+        // The printer runs out again before confirming the next one
+        let watched = connection(&target).unwrap();
+        let job = std::thread::spawn(move || watched.send_confirmed(b"Hi\n"));
+        socket.read_exact(&mut sent).unwrap();
+        socket.write_all(&[0x10, 0x00, 0x0C, 0x00, 0x0C]).unwrap();
+        let error = job.join().unwrap().unwrap_err();
+        assert!(error.is::<crate::transport::NotReadyError>());
+
+        drop(listener);
+        assert!(connection(&target).is_none());
+    }
+}
@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use super::{PrinterTransport, StatusLink, TransportStatus};
+
+/// How long a write to a busy device may go without progress before giving up
+const DEVICE_WRITE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Pause between attempts on a non-blocking device that has nothing to give
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Transport that writes directly to a printer device node
+/// such as `/dev/usb/lp0` (Linux) or `\\.\COM3` (Windows)
+pub struct DeviceTransport {
+    path: PathBuf,
+}
+
+impl DeviceTransport {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl PrinterTransport for DeviceTransport {
+    fn send(&self, data: &[u8]) -> Result<()> {
+        println!("Printing {} bytes to device: {}", data.len(), self.path.display());
+
+        let mut device = OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open printer device {}", self.path.display()))?;
+
+        device
+            .write_all(data)
+            .with_context(|| format!("Failed to write to printer device {}", self.path.display()))?;
+        device
+            .flush()
+            .with_context(|| format!("Failed to flush printer device {}", self.path.display()))?;
+
+        Ok(())
+    }
+
+    fn status(&self) -> Result<TransportStatus> {
+        if !self.path.exists() {
+            return Ok(TransportStatus::Unavailable(format!(
+                "{} does not exist",
+                self.path.display()
+            )));
+        }
+
+        match OpenOptions::new().write(true).open(&self.path) {
+            Ok(_) => Ok(TransportStatus::Ready),
+            Err(e) => Ok(TransportStatus::Unavailable(e.to_string())),
+        }
+    }
+
+    fn list_targets(&self) -> Result<Vec<String>> {
+        list_devices()
+    }
+
+    /// Printer class devices on Linux can be read from when the printer is
+    /// bidirectional; a device that can't be opened for reading just gets data
+    #[cfg(target_os = "linux")]
+    fn open_link(&self) -> Result<Option<Box<dyn StatusLink>>> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&self.path);
+        Ok(device.ok().map(|device| {
+            Box::new(DeviceLink {
+                device,
+                path: self.path.clone(),
+            }) as Box<dyn StatusLink>
+        }))
+    }
+}
+
+/// Status requests and replies over a non-blocking device node
+struct DeviceLink {
+    device: File,
+    path: PathBuf,
+}
+
+impl StatusLink for DeviceLink {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        write_until_idle(&mut self.device, data, DEVICE_WRITE_TIMEOUT)
+            .with_context(|| format!("Failed to write to printer device {}", self.path.display()))
+    }
+
+    fn read_byte(&mut self, timeout: Duration) -> Result<Option<u8>> {
+        let deadline = Instant::now() + timeout;
+        let mut byte = [0];
+        loop {
+            match self.device.read(&mut byte) {
+                Ok(1) => return Ok(Some(byte[0])),
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read from printer device {}", self.path.display()));
+                }
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep(DEVICE_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Write all of `data` to a non-blocking device. Large jobs take as long as
+/// the printer needs; only a device that accepts nothing for `idle_timeout` fails.
+fn write_until_idle(device: &mut impl Write, mut data: &[u8], idle_timeout: Duration) -> Result<()> {
+    let mut deadline = Instant::now() + idle_timeout;
+    while !data.is_empty() {
+        match device.write(data) {
+            Ok(n) if n > 0 => {
+                data = &data[n..];
+                deadline = Instant::now() + idle_timeout;
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!("Printer accepted no data for {}s", idle_timeout.as_secs());
+        }
+        std::thread::sleep(DEVICE_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Find printer class device nodes (`/dev/usb/lp*`, `/dev/lp*`)
+#[cfg(target_os = "linux")]
+pub fn list_devices() -> Result<Vec<String>> {
+    let mut devices = Vec::new();
+
+    for (dir, prefix) in [("/dev/usb", "lp"), ("/dev", "lp")] {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let is_printer = name
+                .strip_prefix(prefix)
+                .map(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+                .unwrap_or(false);
+            if is_printer {
+                devices.push(format!("device://{}", entry.path().display()));
+            }
+        }
+    }
+
+    devices.sort();
+    Ok(devices)
+}
+
+/// Device discovery is only supported on Linux; other platforms
+/// can still target a device by explicit path
+#[cfg(not(target_os = "linux"))]
+pub fn list_devices() -> Result<Vec<String>> {
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Device that takes one byte every few attempts, like a slow USB printer
+    struct Trickle {
+        accepted: Vec<u8>,
+        attempts: usize,
+        every: usize,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.attempts += 1;
+            if self.every == 0 || self.attempts % self.every != 0 {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            self.accepted.push(data[0]);
+            Ok(1)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn slow_devices_only_fail_when_idle() {
+        // About 20 ms per byte, so the whole write outlasts the idle timeout
+        // while no single byte comes close to it
+        let mut slow = Trickle {
+            accepted: Vec::new(),
+            attempts: 0,
+            every: 3,
+        };
+        let started = Instant::now();
+        write_until_idle(&mut slow, b"receipt receipt", Duration::from_millis(200)).unwrap();
+        assert_eq!(slow.accepted, b"receipt receipt");
+        assert!(started.elapsed() > Duration::from_millis(200));
+
+        let mut stuck = Trickle {
+            accepted: Vec::new(),
+            attempts: 0,
+            every: 0,
+        };
+        assert!(write_until_idle(&mut stuck, b"receipt", Duration::from_millis(20)).is_err());
+    }
+}
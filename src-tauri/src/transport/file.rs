@@ -0,0 +1,161 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use super::{PrinterTransport, TransportStatus};
+
+/// Transport that writes print data to disk instead of a printer.
+///
+/// If the path is a directory (or ends with a separator) every job is
+/// written to its own `job_<timestamp>.bin` file (with a `_<n>` suffix when
+/// several jobs land in the same millisecond), otherwise jobs are
+/// appended to the given file.
+pub struct FileTransport {
+    path: PathBuf,
+}
+
+impl FileTransport {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    fn is_directory(&self) -> bool {
+        self.path.is_dir()
+            || self.path.to_string_lossy().ends_with(['/', '\\'])
+    }
+
+    /// Open the file the next job is written to
+    fn open_output(&self) -> Result<(File, PathBuf)> {
+        if !self.is_directory() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create output directory {}", parent.display()))?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .with_context(|| format!("Failed to open output file {}", self.path.display()))?;
+            return Ok((file, self.path.clone()));
+        }
+
+        fs::create_dir_all(&self.path)
+            .with_context(|| format!("Failed to create output directory {}", self.path.display()))?;
+
+        let stamp = chrono::Local::now().format("%Y%m%d_%H%M%S_%3f").to_string();
+        for n in 0u32.. {
+            let name = match n {
+                0 => format!("job_{}.bin", stamp),
+                n => format!("job_{}_{}.bin", stamp, n),
+            };
+            let output = self.path.join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&output) {
+                Ok(file) => return Ok((file, output)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to open output file {}", output.display()))
+                }
+            }
+        }
+        unreachable!("ran out of job file names")
+    }
+}
+
+impl PrinterTransport for FileTransport {
+    fn send(&self, data: &[u8]) -> Result<()> {
+        let (mut file, output) = self.open_output()?;
+
+        file.write_all(data)
+            .with_context(|| format!("Failed to write output file {}", output.display()))?;
+
+        println!("Wrote {} bytes to {}", data.len(), output.display());
+        Ok(())
+    }
+
+    fn status(&self) -> Result<TransportStatus> {
+        let dir = if self.is_directory() {
+            self.path.as_path()
+        } else {
+            match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => return Ok(TransportStatus::Ready),
+            }
+        };
+
+        match fs::metadata(dir) {
+            Ok(meta) if meta.permissions().readonly() => Ok(TransportStatus::Unavailable(
+                format!("{} is read-only", dir.display()),
+            )),
+            // Missing directories are created on the first write
+            _ => Ok(TransportStatus::Ready),
+        }
+    }
+
+    fn list_targets(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scratch directory, removed again when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("vopecs_file_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn file_mode_appends_every_job() {
+        let dir = TempDir::new("append");
+        let output = dir.0.join("nested").join("out.bin");
+        let transport = FileTransport::new(&output);
+
+        transport.send(b"first").unwrap();
+        transport.send(b"second").unwrap();
+
+        assert_eq!(fs::read(&output).unwrap(), b"firstsecond");
+    }
+
+    #[test]
+    fn directory_mode_writes_each_job_to_its_own_file() {
+        let dir = TempDir::new("jobs");
+        let transport = FileTransport::new(&PathBuf::from(format!("{}/", dir.0.display())));
+
+        // Fast enough that several jobs share a millisecond
+        for i in 0..5 {
+            transport.send(format!("job {}", i).as_bytes()).unwrap();
+        }
+
+        let names: Vec<_> = fs::read_dir(&dir.0)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        let mut jobs: Vec<_> = names
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect();
+        jobs.sort();
+
+        assert_eq!(jobs, ["job 0", "job 1", "job 2", "job 3", "job 4"]);
+        assert!(names.iter().all(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("job_") && name.ends_with(".bin")
+        }));
+    }
+}
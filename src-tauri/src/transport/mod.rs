@@ -0,0 +1,242 @@
+//! Output paths for raw printer data.
+//!
+//! Every printer target in `Config::printer_mappings` is a string that is
+//! resolved to a [`PrinterTransport`] by its URI scheme:
+//!
+//! | Target                      | Transport           |
+//! |-----------------------------|---------------------|
+//! | `Epson_TM-T20` / `spool://` | OS print spooler    |
+//! | `tcp://10.0.0.20:9100`      | raw TCP socket      |
+//! | `device:///dev/usb/lp0`     | device file         |
+//! | `file:///tmp/receipts/`     | file / directory    |
+//! | `virtual:///tmp/preview/`   | emulator, PNG files |
+
+mod asb;
+mod device;
+mod file;
+mod socket;
+mod spooler;
+mod status;
+mod virtual_printer;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+use crate::config;
+
+pub use asb::{AsbConnection, AsbEvent, AsbListener};
+pub use device::DeviceTransport;
+pub use file::FileTransport;
+pub use socket::SocketTransport;
+pub use spooler::SpoolerTransport;
+pub use status::{NotReadyError, PrinterStatus, StatusLink};
+pub use virtual_printer::VirtualTransport;
+
+/// Reachability of a printer target as seen by its transport
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", content = "detail", rename_all = "camelCase")]
+pub enum TransportStatus {
+    Ready,
+    Unavailable(String),
+    Unknown,
+}
+
+/// Error context marking that data could not be delivered to a printer,
+/// as opposed to a failure while preparing the data
+#[derive(Debug, thiserror::Error)]
+#[error("Printer {target} is unavailable")]
+pub struct DeliveryError {
+    pub target: String,
+}
+
+/// A way of getting bytes to a printer
+pub trait PrinterTransport: Send + Sync {
+    /// Send raw printer data
+    fn send(&self, data: &[u8]) -> Result<()>;
+
+    /// Check whether the target can currently accept data
+    fn status(&self) -> Result<TransportStatus>;
+
+    /// List targets this kind of transport can discover
+    fn list_targets(&self) -> Result<Vec<String>>;
+
+    /// Drop any data queued for the target
+    fn clear_jobs(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Open a two-way connection for status requests, `None` when the
+    /// transport can't read anything back from the printer
+    fn open_link(&self) -> Result<Option<Box<dyn StatusLink>>> {
+        Ok(None)
+    }
+}
+
+/// Parsed printer target
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Spooler(String),
+    Socket(String),
+    Device(PathBuf),
+    File(PathBuf),
+    Virtual(PathBuf),
+}
+
+impl Target {
+    /// Parse a printer target string by its URI scheme.
+    /// Strings without a scheme are spooler queue names, except absolute
+    /// `/dev/...` paths which are treated as device files. Unknown schemes
+    /// are rejected rather than mistaken for a queue name.
+    pub fn parse(target: &str) -> Result<Self> {
+        let target = target.trim();
+
+        if target.is_empty() {
+            anyhow::bail!("Printer target is empty");
+        }
+
+        if let Some(rest) = target.strip_prefix("tcp://") {
+            return Ok(Target::Socket(rest.to_string()));
+        }
+        if let Some(rest) = target.strip_prefix("device://") {
+            return Ok(Target::Device(PathBuf::from(rest)));
+        }
+        if let Some(rest) = target.strip_prefix("file://") {
+            return Ok(Target::File(PathBuf::from(rest)));
+        }
+        if let Some(rest) = target.strip_prefix("virtual://") {
+            return Ok(Target::Virtual(PathBuf::from(rest)));
+        }
+        if let Some(rest) = target.strip_prefix("spool://") {
+            return Ok(Target::Spooler(rest.to_string()));
+        }
+        if target.starts_with("/dev/") {
+            return Ok(Target::Device(PathBuf::from(target)));
+        }
+        if let Some((scheme, _)) = target.split_once("://") {
+            anyhow::bail!("Unsupported printer target scheme {}:// in {}", scheme, target);
+        }
+
+        Ok(Target::Spooler(target.to_string()))
+    }
+
+    /// Build the transport for this target
+    pub fn open(&self) -> Result<Box<dyn PrinterTransport>> {
+        Ok(match self {
+            Target::Spooler(queue) => Box::new(SpoolerTransport::new(queue)),
+            Target::Socket(address) => Box::new(SocketTransport::new(address)?),
+            Target::Device(path) => Box::new(DeviceTransport::new(path)),
+            Target::File(path) => Box::new(FileTransport::new(path)),
+            Target::Virtual(path) => {
+                // Profiles are keyed by the full target string
+                let profile = config::load_printer_profile(&format!("virtual://{}", path.display()))?;
+                Box::new(VirtualTransport::new(path, profile.dot_width))
+            }
+        })
+    }
+}
+
+/// Resolve a printer target string to its transport
+pub fn open(target: &str) -> Result<Box<dyn PrinterTransport>> {
+    Target::parse(target)?.open()
+}
+
+/// Send data to a printer target, tagging failures with [`DeliveryError`].
+/// Printers watched by an [`AsbListener`] get it over the listener's
+/// connection and are waited on, see [`AsbConnection::send_confirmed`].
+pub fn send(target: &str, data: &[u8]) -> Result<()> {
+    if let Some(connection) = asb::connection(target) {
+        if connection.send_confirmed(data)? {
+            return Ok(());
+        }
+    }
+    open(target)?
+        .send(data)
+        .with_context(|| DeliveryError {
+            target: target.to_string(),
+        })
+}
+
+/// Send data to an ESC/POS printer, checking its status before and after.
+///
+/// Fails with [`NotReadyError`] if the printer reports cover open, paper out
+/// or an error, either before the job (nothing is sent) or once it has worked
+/// through it. Transports that can't read the status, and printers that don't
+/// answer, just get the data as with [`send`].
+pub fn send_checked(target: &str, data: &[u8]) -> Result<Option<PrinterStatus>> {
+    if let Some(connection) = asb::connection(target) {
+        if connection.send_confirmed(data)? {
+            return Ok(connection.status());
+        }
+    }
+
+    let delivery = || DeliveryError {
+        target: target.to_string(),
+    };
+    let transport = open(target)?;
+    let Some(mut link) = transport.open_link().with_context(delivery)? else {
+        transport.send(data).with_context(delivery)?;
+        return Ok(None);
+    };
+
+    let Some(before) = status::query(link.as_mut()).with_context(delivery)? else {
+        // No status support, the data goes over the connection already open
+        link.write(data).with_context(delivery)?;
+        return Ok(None);
+    };
+    before.ensure_ready(target)?;
+
+    println!("Printing {} bytes to {} with status checks", data.len(), target);
+    let after = status::send_confirmed(link.as_mut(), data).with_context(delivery)?;
+    if let Some(after) = &after {
+        after.ensure_ready(target)?;
+    }
+    Ok(after)
+}
+
+/// Ask a printer for its real-time status, `None` when the transport or the
+/// printer can't tell. Watched printers answer with what they last reported.
+pub fn query_status(target: &str) -> Result<Option<PrinterStatus>> {
+    if let Some(connection) = asb::connection(target).filter(AsbConnection::is_connected) {
+        return Ok(connection.status());
+    }
+    let Some(mut link) = open(target)?.open_link()? else {
+        return Ok(None);
+    };
+    status::query(link.as_mut())
+}
+
+/// Check whether a printer target can currently accept data; watched
+/// printers are ready while their status connection is up
+pub fn status(target: &str) -> Result<TransportStatus> {
+    if asb::connection(target).is_some_and(|c| c.is_connected()) {
+        return Ok(TransportStatus::Ready);
+    }
+    open(target)?.status()
+}
+
+/// List every discoverable target: spooler queues followed by device files
+pub fn list_targets() -> Result<Vec<String>> {
+    let mut targets = SpoolerTransport::new("").list_targets()?;
+    targets.extend(device::list_devices()?);
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_targets_by_scheme() {
+        assert_eq!(Target::parse("tcp://[::1]:9100").unwrap(), Target::Socket("[::1]:9100".into()));
+        assert_eq!(Target::parse(" tcp://host ").unwrap(), Target::Socket("host".into()));
+        assert_eq!(Target::parse("/dev/usb/lp0").unwrap(), Target::Device("/dev/usb/lp0".into()));
+        assert_eq!(Target::parse("spool://Kitchen").unwrap(), Target::Spooler("Kitchen".into()));
+        assert_eq!(Target::parse("EPSON TM-T20").unwrap(), Target::Spooler("EPSON TM-T20".into()));
+
+        assert!(Target::parse("").is_err());
+        assert!(Target::parse("usb://printer").is_err());
+
+        // Addresses are checked when the transport is opened
+        assert!(open("tcp://host:99999").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use super::{PrinterTransport, StatusLink, TransportStatus};
+
+/// Default JetDirect / AppSocket port
+const TCP_DEFAULT_PORT: u16 = 9100;
+
+/// How long to wait for a network printer to accept the connection
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a single socket write may block before the printer is considered stuck
+const TCP_WRITE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Transport that streams data straight to a network printer's raw port
+pub struct SocketTransport {
+    address: String,
+}
+
+impl SocketTransport {
+    /// Create a transport for `host[:port]`, defaulting to port 9100
+    pub fn new(address: &str) -> Result<Self> {
+        let address = address.trim().trim_end_matches('/');
+
+        if address.is_empty() {
+            anyhow::bail!("Network printer target is missing a host");
+        }
+
+        let (host, port) = if let Some(rest) = address.strip_prefix('[') {
+            // Bracketed IPv6, "[::1]" or "[::1]:9100"
+            let (host, port) = rest
+                .split_once(']')
+                .with_context(|| format!("Network printer target {} is missing a closing ]", address))?;
+            match port {
+                "" => (host, None),
+                _ => match port.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => anyhow::bail!("Unexpected {} after the address in {}", port, address),
+                },
+            }
+        } else if address.matches(':').count() > 1 {
+            // Bare IPv6 address, which can't carry a port
+            (address, None)
+        } else {
+            match address.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (address, None),
+            }
+        };
+
+        if host.is_empty() {
+            anyhow::bail!("Network printer target {} is missing a host", address);
+        }
+
+        let port = match port {
+            None => TCP_DEFAULT_PORT,
+            Some(port) => match port.parse::<u16>() {
+                Ok(port) if port != 0 => port,
+                _ => anyhow::bail!("Invalid port {:?} in network printer target {}", port, address),
+            },
+        };
+
+        let address = if host.contains(':') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+
+        Ok(Self { address })
+    }
+
+    /// Open a connection to the printer, trying every resolved address
+    pub fn connect(&self) -> Result<TcpStream> {
+        let addrs: Vec<SocketAddr> = self.address
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve printer address {}", self.address))?
+            .collect();
+
+        if addrs.is_empty() {
+            anyhow::bail!("Printer address {} did not resolve to any host", self.address);
+        }
+
+        let mut last_error = None;
+        for addr in &addrs {
+            match TcpStream::connect_timeout(addr, TCP_CONNECT_TIMEOUT) {
+                Ok(stream) => {
+                    stream
+                        .set_write_timeout(Some(TCP_WRITE_TIMEOUT))
+                        .context("Failed to set printer socket write timeout")?;
+                    let _ = stream.set_nodelay(true);
+                    return Ok(stream);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        let e = last_error.expect("at least one address was tried");
+        Err(e).with_context(|| {
+            format!(
+                "Failed to connect to printer at {} (timeout {}s)",
+                self.address,
+                TCP_CONNECT_TIMEOUT.as_secs()
+            )
+        })
+    }
+}
+
+impl PrinterTransport for SocketTransport {
+    fn send(&self, data: &[u8]) -> Result<()> {
+        println!("Printing {} bytes to network printer: {}", data.len(), self.address);
+
+        let mut stream = self.connect()?;
+
+        stream
+            .write_all(data)
+            .with_context(|| format!("Failed to send data to printer at {}", self.address))?;
+        stream
+            .flush()
+            .with_context(|| format!("Failed to flush data to printer at {}", self.address))?;
+
+        // Half-close so the printer sees end-of-job before we drop the socket
+        let _ = stream.shutdown(Shutdown::Write);
+
+        println!("✅ RAW print to network printer succeeded");
+        Ok(())
+    }
+
+    fn status(&self) -> Result<TransportStatus> {
+        match self.connect() {
+            Ok(_) => Ok(TransportStatus::Ready),
+            Err(e) => Ok(TransportStatus::Unavailable(format!("{:#}", e))),
+        }
+    }
+
+    fn list_targets(&self) -> Result<Vec<String>> {
+        // Network printers are configured by address, there is no discovery
+        Ok(Vec::new())
+    }
+
+    fn open_link(&self) -> Result<Option<Box<dyn StatusLink>>> {
+        Ok(Some(Box::new(SocketLink {
+            stream: self.connect()?,
+            address: self.address.clone(),
+        })))
+    }
+}
+
+/// Status requests and replies over the printer's raw port
+struct SocketLink {
+    stream: TcpStream,
+    address: String,
+}
+
+impl StatusLink for SocketLink {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.stream
+            .write_all(data)
+            .with_context(|| format!("Failed to send data to printer at {}", self.address))
+    }
+
+    fn read_byte(&mut self, timeout: Duration) -> Result<Option<u8>> {
+        // A zero timeout would mean blocking forever
+        self.stream.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut byte = [0];
+        match self.stream.read(&mut byte) {
+            Ok(0) => anyhow::bail!("Printer at {} closed the connection", self.address),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read from printer at {}", self.address)),
+        }
+    }
+}
+
+impl Drop for SocketLink {
+    fn drop(&mut self) {
+        // Half-close so the printer sees end-of-job before we drop the socket
+        let _ = self.stream.shutdown(Shutdown::Write);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(target: &str) -> Result<String> {
+        SocketTransport::new(target).map(|transport| transport.address)
+    }
+
+    #[test]
+    fn parses_hosts_and_ports() {
+        assert_eq!(address("192.168.1.50").unwrap(), "192.168.1.50:9100");
+        assert_eq!(address("printer.local:9101/").unwrap(), "printer.local:9101");
+        assert_eq!(address("[::1]:9100").unwrap(), "[::1]:9100");
+        assert_eq!(address("[fe80::1]").unwrap(), "[fe80::1]:9100");
+        assert_eq!(address("::1").unwrap(), "[::1]:9100");
+
+        for bad in ["", ":9100", "host:", "host:abc", "host:0", "host:65536", "[::1", "[::1]9100", "[]:9100"] {
+            assert!(address(bad).is_err(), "{:?} should be rejected", bad);
+        }
+    }
+}
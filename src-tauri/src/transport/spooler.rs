@@ -0,0 +1,340 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use super::{PrinterTransport, TransportStatus};
+
+/// Transport that hands data to the operating system print spooler
+/// (`lp`/CUPS on macOS and Linux, winspool on Windows)
+pub struct SpoolerTransport {
+    queue: String,
+}
+
+impl SpoolerTransport {
+    pub fn new(queue: &str) -> Self {
+        Self {
+            queue: queue.to_string(),
+        }
+    }
+}
+
+impl PrinterTransport for SpoolerTransport {
+    fn send(&self, data: &[u8]) -> Result<()> {
+        send_raw(&self.queue, data)
+    }
+
+    fn status(&self) -> Result<TransportStatus> {
+        queue_status(&self.queue)
+    }
+
+    fn list_targets(&self) -> Result<Vec<String>> {
+        list_queues()
+    }
+
+    fn clear_jobs(&self) -> Result<()> {
+        clear_queue(&self.queue)
+    }
+}
+
+/// Temp file for one job, unique across concurrent sends and processes
+fn temp_file(extension: &str) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("vopecs_print_{}_{}.{}", std::process::id(), n, extension))
+}
+
+/// Send raw data to a CUPS queue using `lp`
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn send_raw(printer_name: &str, data: &[u8]) -> Result<()> {
+    let temp_file = temp_file("bin");
+
+    // Write data to temp file
+    fs::write(&temp_file, data)
+        .context("Failed to write temp file")?;
+
+    // Print using lp command
+    let output = Command::new("lp")
+        .args([
+            "-d", printer_name,
+            "-o", "raw",
+            temp_file.to_str().unwrap()
+        ])
+        .output()
+        .context("Failed to execute lp command")?;
+
+    // Clean up temp file
+    let _ = fs::remove_file(&temp_file);
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("lp command failed: {}", stderr);
+    }
+
+    Ok(())
+}
+
+/// Send raw data through the Windows spooler (winspool via PowerShell)
+#[cfg(target_os = "windows")]
+fn send_raw(printer_name: &str, data: &[u8]) -> Result<()> {
+    use std::os::windows::process::CommandExt;
+
+    let temp_file = temp_file("prn");
+
+    // Write data to temp file
+    fs::write(&temp_file, data)
+        .context("Failed to write temp file")?;
+
+    let temp_path = temp_file.to_str().unwrap();
+    println!("Printing {} bytes to Windows printer: {}", data.len(), printer_name);
+
+    // Use PowerShell to send raw data directly to printer via .NET
+    // This is the most reliable method for thermal/ESC-POS printers on Windows
+    let ps_script = format!(
+        r#"
+        $printerName = '{}'
+        $filePath = '{}'
+
+        # Method 1: Try using RawPrinterHelper via .NET
+        Add-Type -TypeDefinition @'
+        using System;
+        using System.Runtime.InteropServices;
+
+        public class RawPrinterHelper {{
+            [DllImport("winspool.drv", CharSet = CharSet.Unicode, SetLastError = true)]
+            public static extern bool OpenPrinter(string pPrinterName, out IntPtr phPrinter, IntPtr pDefault);
+
+            [DllImport("winspool.drv", SetLastError = true)]
+            public static extern bool ClosePrinter(IntPtr hPrinter);
+
+            [DllImport("winspool.drv", CharSet = CharSet.Unicode, SetLastError = true)]
+            public static extern bool StartDocPrinter(IntPtr hPrinter, int level, ref DOCINFO pDocInfo);
+
+            [DllImport("winspool.drv", SetLastError = true)]
+            public static extern bool EndDocPrinter(IntPtr hPrinter);
+
+            [DllImport("winspool.drv", SetLastError = true)]
+            public static extern bool StartPagePrinter(IntPtr hPrinter);
+
+            [DllImport("winspool.drv", SetLastError = true)]
+            public static extern bool EndPagePrinter(IntPtr hPrinter);
+
+            [DllImport("winspool.drv", SetLastError = true)]
+            public static extern bool WritePrinter(IntPtr hPrinter, IntPtr pBytes, int dwCount, out int dwWritten);
+
+            [StructLayout(LayoutKind.Sequential, CharSet = CharSet.Unicode)]
+            public struct DOCINFO {{
+                public string pDocName;
+                public string pOutputFile;
+                public string pDataType;
+            }}
+
+            public static bool SendBytesToPrinter(string printerName, byte[] bytes) {{
+                IntPtr hPrinter = IntPtr.Zero;
+                DOCINFO di = new DOCINFO();
+                di.pDocName = "VopecsPrinter RAW Document";
+                di.pDataType = "RAW";
+
+                if (!OpenPrinter(printerName, out hPrinter, IntPtr.Zero)) return false;
+
+                try {{
+                    if (!StartDocPrinter(hPrinter, 1, ref di)) return false;
+                    if (!StartPagePrinter(hPrinter)) return false;
+
+                    IntPtr pBytes = Marshal.AllocCoTaskMem(bytes.Length);
+                    Marshal.Copy(bytes, 0, pBytes, bytes.Length);
+
+                    int written = 0;
+                    bool success = WritePrinter(hPrinter, pBytes, bytes.Length, out written);
+
+                    Marshal.FreeCoTaskMem(pBytes);
+
+                    EndPagePrinter(hPrinter);
+                    EndDocPrinter(hPrinter);
+
+                    return success && written == bytes.Length;
+                }} finally {{
+                    ClosePrinter(hPrinter);
+                }}
+            }}
+        }}
+'@
+
+        $bytes = [System.IO.File]::ReadAllBytes($filePath)
+        $result = [RawPrinterHelper]::SendBytesToPrinter($printerName, $bytes)
+        if ($result) {{
+            Write-Output "SUCCESS"
+            exit 0
+        }} else {{
+            Write-Error "Failed to send to printer"
+            exit 1
+        }}
+        "#,
+        printer_name.replace("'", "''"),
+        temp_path.replace("\\", "\\\\").replace("'", "''")
+    );
+
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-ExecutionPolicy", "Bypass", "-Command", &ps_script])
+        .creation_flags(0x08000000) // CREATE_NO_WINDOW
+        .output()
+        .context("Failed to execute PowerShell")?;
+
+    // Clean up temp file
+    let _ = fs::remove_file(&temp_file);
+
+    if output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.contains("SUCCESS") {
+            println!("✅ RAW print to Windows printer succeeded");
+            return Ok(());
+        }
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    anyhow::bail!("Print failed: {} {}", stdout, stderr)
+}
+
+/// Get list of CUPS queues
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn list_queues() -> Result<Vec<String>> {
+    let output = Command::new("lpstat")
+        .args(["-p"])
+        .output()
+        .context("Failed to execute lpstat command")?;
+
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let printers: Vec<String> = stdout
+        .lines()
+        .filter_map(|line| {
+            // lpstat -p output format: "printer PRINTER_NAME is idle..."
+            if line.starts_with("printer ") {
+                line.split_whitespace()
+                    .nth(1)
+                    .map(|s| s.to_string())
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    Ok(printers)
+}
+
+/// Get list of installed printers (Windows)
+#[cfg(target_os = "windows")]
+fn list_queues() -> Result<Vec<String>> {
+    use std::os::windows::process::CommandExt;
+
+    let output = Command::new("wmic")
+        .args(["printer", "get", "name"])
+        .creation_flags(0x08000000) // CREATE_NO_WINDOW
+        .output()
+        .context("Failed to execute wmic command")?;
+
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let printers: Vec<String> = stdout
+        .lines()
+        .skip(1) // Skip header
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    Ok(printers)
+}
+
+/// Query a CUPS queue state with `lpstat -p`
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn queue_status(printer_name: &str) -> Result<TransportStatus> {
+    let output = Command::new("lpstat")
+        .args(["-p", printer_name])
+        .output()
+        .context("Failed to execute lpstat command")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Ok(TransportStatus::Unavailable(stderr.trim().to_string()));
+    }
+
+    // "printer NAME is idle.  enabled since ..." or "printer NAME disabled since ..."
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().next().unwrap_or_default().trim().to_string();
+    if line.is_empty() {
+        Ok(TransportStatus::Unknown)
+    } else if line.contains("disabled") {
+        Ok(TransportStatus::Unavailable(line))
+    } else {
+        Ok(TransportStatus::Ready)
+    }
+}
+
+/// Query a Windows printer state with `Get-Printer`
+#[cfg(target_os = "windows")]
+fn queue_status(printer_name: &str) -> Result<TransportStatus> {
+    use std::os::windows::process::CommandExt;
+
+    let script = format!(
+        "(Get-Printer -Name '{}').PrinterStatus",
+        printer_name.replace("'", "''")
+    );
+
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+        .creation_flags(0x08000000) // CREATE_NO_WINDOW
+        .output()
+        .context("Failed to execute PowerShell")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Ok(TransportStatus::Unavailable(stderr.trim().to_string()));
+    }
+
+    let state = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    match state.as_str() {
+        "Normal" | "Idle" | "Printing" | "Processing" | "WarmingUp" => Ok(TransportStatus::Ready),
+        "" => Ok(TransportStatus::Unknown),
+        _ => Ok(TransportStatus::Unavailable(state)),
+    }
+}
+
+/// Clear print jobs for a CUPS queue
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn clear_queue(printer_name: &str) -> Result<()> {
+    let output = Command::new("cancel")
+        .args(["-a", printer_name])
+        .output()
+        .context("Failed to execute cancel command")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // Don't fail if there are no jobs to cancel
+        if !stderr.contains("no job") {
+            anyhow::bail!("cancel command failed: {}", stderr);
+        }
+    }
+
+    Ok(())
+}
+
+/// Clear print jobs (Windows restarts the spooler service)
+#[cfg(target_os = "windows")]
+fn clear_queue(_printer_name: &str) -> Result<()> {
+    use std::os::windows::process::CommandExt;
+
+    Command::new("cmd")
+        .args(["/C", "net stop spooler && net start spooler"])
+        .creation_flags(0x08000000)
+        .output()
+        .context("Failed to clear print queue")?;
+
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the reply to a real-time status request
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait for a printer to work through a job before asking how it went
+pub(super) const COMPLETION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Real-time status request (DLE EOT n), answered even while the printer is offline
+fn real_time_request(n: u8) -> [u8; 3] {
+    [0x10, 0x04, n]
+}
+
+/// Paper sensor status (GS r 1), answered once everything before it is processed
+pub(super) const PAPER_SENSOR_REQUEST: [u8; 3] = [0x1D, 0x72, 0x01];
+
+/// Automatic Status Back (GS a n) for drawer, online, error and paper sensor changes
+pub const ASB_ENABLE: [u8; 3] = [0x1D, 0x61, 0x0F];
+
+/// Turn Automatic Status Back off again (GS a 0)
+pub const ASB_DISABLE: [u8; 3] = [0x1D, 0x61, 0x00];
+
+/// DLE EOT replies have bits 1 and 4 set, bits 0 and 7 clear
+fn is_real_time_reply(byte: u8) -> bool {
+    byte & 0x93 == 0x12
+}
+
+/// GS r replies have bits 4 and 7 clear
+fn is_paper_sensor_reply(byte: u8) -> bool {
+    byte & 0x90 == 0
+}
+
+/// What an ESC/POS printer reports about itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrinterStatus {
+    pub online: bool,
+    pub cover_open: bool,
+    pub paper_near_end: bool,
+    pub paper_out: bool,
+    pub cutter_error: bool,
+    /// Unrecoverable or automatically recoverable error, e.g. an overheated head
+    pub error: bool,
+    /// Drawer kick connector pin 3 is high, which most drawers report when open
+    pub drawer_open: bool,
+}
+
+impl PrinterStatus {
+    /// Decode the replies to DLE EOT 1 (printer), 2 (offline cause),
+    /// 3 (error cause) and 4 (paper sensor)
+    pub fn from_replies([printer, offline, error, paper]: [u8; 4]) -> Self {
+        let cutter_error = error & 0x08 != 0;
+        Self {
+            online: printer & 0x08 == 0,
+            drawer_open: printer & 0x04 != 0,
+            cover_open: offline & 0x04 != 0,
+            paper_near_end: paper & 0x0C != 0,
+            paper_out: offline & 0x20 != 0 || paper & 0x60 != 0,
+            cutter_error,
+            error: error & 0x60 != 0 || (offline & 0x40 != 0 && !cutter_error),
+        }
+    }
+
+    /// Decode a 4-byte ASB report: printer, error, paper sensor and a
+    /// reserved byte
+    pub fn from_asb([printer, error, paper, _]: [u8; 4]) -> Self {
+        Self {
+            online: printer & 0x08 == 0,
+            drawer_open: printer & 0x04 != 0,
+            cover_open: printer & 0x20 != 0,
+            paper_near_end: paper & 0x03 != 0,
+            paper_out: paper & 0x0C != 0,
+            cutter_error: error & 0x08 != 0,
+            error: error & 0x64 != 0,
+        }
+    }
+
+    /// Add the reply to GS r 1
+    pub fn apply_paper_sensor(&mut self, reply: u8) {
+        self.paper_near_end |= reply & 0x03 != 0;
+        self.paper_out |= reply & 0x0C != 0;
+    }
+
+    /// Whether the printer can print; a near-end roll still prints
+    pub fn is_ready(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    /// What keeps the printer from printing
+    pub fn problems(&self) -> Vec<&'static str> {
+        let mut problems = Vec::new();
+        if self.cover_open {
+            problems.push("cover open");
+        }
+        if self.paper_out {
+            problems.push("paper out");
+        }
+        if self.cutter_error {
+            problems.push("cutter error");
+        }
+        if self.error {
+            problems.push("printer error");
+        }
+        if !self.online && problems.is_empty() {
+            problems.push("offline");
+        }
+        problems
+    }
+
+    /// Fail with [`NotReadyError`] unless the printer is ready
+    pub fn ensure_ready(&self, target: &str) -> Result<()> {
+        if self.is_ready() {
+            return Ok(());
+        }
+        Err(NotReadyError {
+            target: target.to_string(),
+            status: *self,
+        }
+        .into())
+    }
+}
+
+/// The printer answered, but can't print right now. Jobs are held rather than
+/// failed until it is ready again.
+#[derive(Debug, thiserror::Error)]
+#[error("Printer {target} is not ready: {}", status.problems().join(", "))]
+pub struct NotReadyError {
+    pub target: String,
+    pub status: PrinterStatus,
+}
+
+/// Something a printer with ASB enabled sent on its own or in answer to GS r
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AsbReply {
+    Status(PrinterStatus),
+    PaperSensor(u8),
+}
+
+/// Picks 4-byte ASB reports and GS r replies out of everything else the
+/// printer sends
+#[derive(Debug, Default)]
+pub struct AsbParser {
+    report: Vec<u8>,
+}
+
+impl AsbParser {
+    /// Feed one received byte, returning a status once a report is complete
+    pub fn push(&mut self, byte: u8) -> Option<AsbReply> {
+        // The first byte has bit 4 set and bits 0, 1 and 7 clear, the others
+        // bits 4 and 7 clear, so a stray byte can't be mistaken for either
+        if byte & 0x93 == 0x10 {
+            self.report = vec![byte];
+            return None;
+        }
+        if byte & 0x90 != 0 {
+            self.report.clear();
+            return None;
+        }
+        // Outside a report, such a byte answers GS r
+        if self.report.is_empty() {
+            return Some(AsbReply::PaperSensor(byte));
+        }
+
+        self.report.push(byte);
+        let report: [u8; 4] = self.report.as_slice().try_into().ok()?;
+        self.report.clear();
+        Some(AsbReply::Status(PrinterStatus::from_asb(report)))
+    }
+}
+
+/// Two-way connection to a printer that answers status requests
+pub trait StatusLink {
+    fn write(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Read one byte, `None` when nothing arrives within `timeout`
+    fn read_byte(&mut self, timeout: Duration) -> Result<Option<u8>>;
+}
+
+/// Wait for a reply byte accepted by `valid`, skipping anything else
+/// (late replies to earlier requests)
+fn read_reply(link: &mut dyn StatusLink, valid: fn(u8) -> bool, timeout: Duration) -> Result<Option<u8>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match link.read_byte(left)? {
+            Some(byte) if valid(byte) => return Ok(Some(byte)),
+            Some(_) if !left.is_zero() => continue,
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Ask for the real-time status, `None` when the printer doesn't answer
+pub fn query(link: &mut dyn StatusLink) -> Result<Option<PrinterStatus>> {
+    let mut replies = [0; 4];
+    for (n, reply) in (1..=4).zip(&mut replies) {
+        link.write(&real_time_request(n)).context("Failed to request printer status")?;
+        match read_reply(link, is_real_time_reply, STATUS_TIMEOUT)? {
+            Some(byte) => *reply = byte,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(PrinterStatus::from_replies(replies)))
+}
+
+/// Send a job followed by GS r 1, wait for the printer to get through it and
+/// read its status afterwards. A printer that stops halfway never answers
+/// GS r, but still answers the real-time requests that follow.
+pub fn send_confirmed(link: &mut dyn StatusLink, data: &[u8]) -> Result<Option<PrinterStatus>> {
+    link.write(data)?;
+    link.write(&PAPER_SENSOR_REQUEST)?;
+    let paper = read_reply(link, is_paper_sensor_reply, COMPLETION_TIMEOUT)?;
+    if paper.is_none() {
+        println!("⚠️ Printer did not confirm the job within {}s", COMPLETION_TIMEOUT.as_secs());
+    }
+
+    let mut status = query(link)?;
+    if let (Some(status), Some(paper)) = (&mut status, paper) {
+        status.apply_paper_sensor(paper);
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Printer that answers each request from a script
+    struct Scripted {
+        written: Vec<u8>,
+        replies: VecDeque<Option<u8>>,
+    }
+
+    impl StatusLink for Scripted {
+        fn write(&mut self, data: &[u8]) -> Result<()> {
+            self.written.extend_from_slice(data);
+            Ok(())
+        }
+
+        fn read_byte(&mut self, _timeout: Duration) -> Result<Option<u8>> {
+            Ok(self.replies.pop_front().flatten())
+        }
+    }
+
+    #[test]
+    fn decodes_status_replies() {
+        let ready = PrinterStatus::from_replies([0x12, 0x12, 0x12, 0x12]);
+        assert!(ready.online && ready.is_ready());
+
+        let status = PrinterStatus::from_replies([0x1E, 0x36, 0x12, 0x7E]);
+        assert!(!status.online && status.drawer_open && status.cover_open);
+        assert!(status.paper_near_end && status.paper_out && !status.cutter_error);
+        assert_eq!(status.problems(), vec!["cover open", "paper out"]);
+
+        let error = status.ensure_ready("tcp://10.0.0.20:9100").unwrap_err();
+        assert!(error.downcast_ref::<NotReadyError>().is_some());
+        assert_eq!(error.to_string(), "Printer tcp://10.0.0.20:9100 is not ready: cover open, paper out");
+
+        let cutter = PrinterStatus::from_replies([0x1A, 0x52, 0x1A, 0x12]);
+        assert_eq!(cutter.problems(), vec!["cutter error"]);
+    }
+
+    #[test]
+    fn waits_for_the_job_then_queries() {
+        // A late DLE EOT reply and then the GS r reply for a near-end roll
+        let replies = [Some(0x16), Some(0x03), Some(0x12), Some(0x12), Some(0x12), Some(0x12)];
+        let mut link = Scripted {
+            written: Vec::new(),
+            replies: replies.into_iter().collect(),
+        };
+
+        let status = send_confirmed(&mut link, b"Hi\n").unwrap().unwrap();
+        assert!(status.is_ready() && status.paper_near_end);
+        assert_eq!(
+            link.written,
+            [&b"Hi\n"[..], &[0x1D, 0x72, 1], &[0x10, 4, 1], &[0x10, 4, 2], &[0x10, 4, 3], &[0x10, 4, 4]].concat()
+        );
+
+        // Printers without status support never answer
+        let mut silent = Scripted {
+            written: Vec::new(),
+            replies: VecDeque::new(),
+        };
+        assert_eq!(query(&mut silent).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_asb_reports_between_other_bytes() {
+        let mut parser = AsbParser::default();
+        // A DLE EOT reply, a cut-off report, then ready and paper out reports
+        // and a GS r reply
+        let stream = [0x12, 0x14, 0x00, 0x10, 0x00, 0x00, 0x00, 0x10, 0x00, 0x0C, 0x00, 0x0C];
+        let replies: Vec<_> = stream.iter().filter_map(|&b| parser.push(b)).collect();
+
+        assert_eq!(replies.len(), 3);
+        let [AsbReply::Status(ready), AsbReply::Status(paper_out), AsbReply::PaperSensor(0x0C)] = replies[..] else {
+            panic!("unexpected replies {:?}", replies);
+        };
+        assert!(ready.is_ready());
+        assert!(paper_out.paper_out && paper_out.online);
+        assert_eq!(paper_out.problems(), vec!["paper out"]);
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::{PrinterTransport, TransportStatus};
+use crate::emulator;
+
+/// Transport that runs print data through the ESC/POS emulator instead of a printer.
+///
+/// Every job is written to the directory as `job_<timestamp>.png`, the
+/// receipt as it would come out of the printer, next to `job_<timestamp>.json`
+/// listing cuts, drawer pulses and skipped commands.
+pub struct VirtualTransport {
+    dir: PathBuf,
+    dot_width: u32,
+}
+
+impl VirtualTransport {
+    pub fn new(dir: &Path, dot_width: u32) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            dot_width,
+        }
+    }
+}
+
+impl PrinterTransport for VirtualTransport {
+    fn send(&self, data: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create output directory {}", self.dir.display()))?;
+
+        let printout = emulator::render(data, self.dot_width);
+        let stem = format!("job_{}", chrono::Local::now().format("%Y%m%d_%H%M%S_%3f"));
+        let png = self.dir.join(format!("{}.png", stem));
+        let json = self.dir.join(format!("{}.json", stem));
+
+        fs::write(&png, printout.png()?)
+            .with_context(|| format!("Failed to write {}", png.display()))?;
+        fs::write(&json, serde_json::to_vec_pretty(&printout.effects)?)
+            .with_context(|| format!("Failed to write {}", json.display()))?;
+
+        println!(
+            "🖨️ Virtual printer: {} bytes, {} dots of paper, {} effects -> {}",
+            data.len(),
+            printout.image.height(),
+            printout.effects.len(),
+            png.display()
+        );
+        Ok(())
+    }
+
+    fn status(&self) -> Result<TransportStatus> {
+        match fs::metadata(&self.dir) {
+            Ok(meta) if meta.permissions().readonly() => Ok(TransportStatus::Unavailable(
+                format!("{} is read-only", self.dir.display()),
+            )),
+            _ => Ok(TransportStatus::Ready),
+        }
+    }
+
+    fn list_targets(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::{bail, Result};
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Seller and totals of a Saudi simplified tax invoice, printed as the ZATCA
+/// (Fatoora) phase-1 QR code.
+///
+/// ```json
+/// { "sellerName": "Bobs Records", "vatNumber": "310122393500003",
+///   "timestamp": "2022-04-25T15:30:00Z", "total": "1000.00", "vatTotal": "150.00" }
+/// ```
+///
+/// Amounts may also be sent as numbers; they are printed with two decimals.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZatcaInvoice {
+    pub seller_name: String,
+    /// 15-digit VAT registration number, starting and ending with 3
+    pub vat_number: String,
+    /// Invoice date and time, ISO 8601
+    pub timestamp: String,
+    /// Invoice total including VAT
+    #[serde(deserialize_with = "amount")]
+    pub total: String,
+    #[serde(deserialize_with = "amount")]
+    pub vat_total: String,
+}
+
+impl ZatcaInvoice {
+    /// The five fields as tag-length-value records, lengths in UTF-8 bytes
+    pub fn tlv(&self) -> Result<Vec<u8>> {
+        self.validate()?;
+
+        let fields = [&self.seller_name, &self.vat_number, &self.timestamp, &self.total, &self.vat_total];
+        let mut tlv = Vec::new();
+        for (tag, value) in (1u8..).zip(fields) {
+            let value = value.trim().as_bytes();
+            if value.len() > 255 {
+                bail!("ZATCA field {} is longer than 255 bytes", tag);
+            }
+            tlv.push(tag);
+            tlv.push(value.len() as u8);
+            tlv.extend_from_slice(value);
+        }
+        Ok(tlv)
+    }
+
+    /// Base64 of the TLV records, the text encoded in the QR code
+    pub fn qr_payload(&self) -> Result<String> {
+        Ok(base64::engine::general_purpose::STANDARD.encode(self.tlv()?))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.seller_name.trim().is_empty() {
+            bail!("ZATCA seller name is empty");
+        }
+
+        let vat = self.vat_number.trim();
+        if vat.len() != 15 || !vat.bytes().all(|b| b.is_ascii_digit()) || !vat.starts_with('3') || !vat.ends_with('3') {
+            bail!("ZATCA VAT number must be 15 digits starting and ending with 3, got {:?}", vat);
+        }
+
+        if chrono::DateTime::parse_from_rfc3339(self.timestamp.trim()).is_err()
+            && chrono::NaiveDateTime::parse_from_str(self.timestamp.trim(), "%Y-%m-%dT%H:%M:%S").is_err()
+        {
+            bail!("ZATCA timestamp must be ISO 8601, got {:?}", self.timestamp);
+        }
+
+        for (name, amount) in [("total", &self.total), ("VAT total", &self.vat_total)] {
+            if amount.trim().parse::<f64>().is_err() {
+                bail!("ZATCA {} is not a number: {:?}", name, amount);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Accept amounts as strings, kept as sent, or numbers, formatted with two decimals
+fn amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Text(String),
+        Number(f64),
+    }
+
+    Ok(match Amount::deserialize(deserializer)? {
+        Amount::Text(text) => text,
+        Amount::Number(n) => format!("{:.2}", n),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ZatcaInvoice {
+        ZatcaInvoice {
+            seller_name: "Bobs Records".to_string(),
+            vat_number: "310122393500003".to_string(),
+            timestamp: "2022-04-25T15:30:00Z".to_string(),
+            total: "1000.00".to_string(),
+            vat_total: "150.00".to_string(),
+        }
+    }
+
+    #[test]
+    fn matches_published_sample() {
+        assert_eq!(
+            sample().qr_payload().unwrap(),
+            "AQxCb2JzIFJlY29yZHMCDzMxMDEyMjM5MzUwMDAwMwMUMjAyMi0wNC0yNVQxNTozMDowMFoEBzEwMDAuMDAFBjE1MC4wMA=="
+        );
+    }
+
+    #[test]
+    fn numeric_amounts_and_arabic_names() {
+        let json = serde_json::json!({
+            "sellerName": "Bobs Records",
+            "vatNumber": "310122393500003",
+            "timestamp": "2022-04-25T15:30:00Z",
+            "total": 1000,
+            "vatTotal": 150.0
+        });
+        let invoice: ZatcaInvoice = serde_json::from_value(json).unwrap();
+        assert_eq!(invoice, sample());
+
+        // Lengths count UTF-8 bytes, not characters
+        let arabic = ZatcaInvoice {
+            seller_name: "شركة".to_string(),
+            ..sample()
+        };
+        assert_eq!(&arabic.tlv().unwrap()[..2], &[1, 8]);
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        let bad_vat = ZatcaInvoice {
+            vat_number: "123".to_string(),
+            ..sample()
+        };
+        assert!(bad_vat.qr_payload().is_err());
+
+        let bad_time = ZatcaInvoice {
+            timestamp: "yesterday".to_string(),
+            ..sample()
+        };
+        assert!(bad_time.qr_payload().is_err());
+
+        let bad_total = ZatcaInvoice {
+            total: "1,000".to_string(),
+            ..sample()
+        };
+        assert!(bad_total.qr_payload().is_err());
+    }
+}
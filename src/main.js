@@ -1,5 +1,6 @@
 // VopecsPrinter - Main JavaScript (TableTrack Clone)
 const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
 
 // State
 let config = {
@@ -12,7 +13,6 @@ let config = {
 };
 
 let isServiceRunning = false;
-let pollingTimer = null;
 let systemPrinters = [];
 let apiPrinters = [];
 
@@ -25,10 +25,14 @@ document.addEventListener('DOMContentLoaded', async () => {
   await loadSystemPrinters();
   await checkAutostart();
   setupEventListeners();
+  await setupServiceEvents();
+  await loadDeadLetters();
 
-  // Auto-start service if configured
-  if (config.domainUrl && config.key) {
-    startService();
+  // The print service runs in the backend and auto-starts when configured
+  try {
+    applyServiceStatus(await invoke('get_service_status'));
+  } catch (error) {
+    log('Failed to get service status: ' + error, 'error');
   }
 });
 
@@ -66,6 +70,9 @@ function setupEventListeners() {
   // Printers
   document.getElementById('refresh-printers').addEventListener('click', refreshPrinters);
 
+  // Failed jobs
+  document.getElementById('refresh-dead-letters').addEventListener('click', loadDeadLetters);
+
   // Autostart
   document.getElementById('toggle-autostart').addEventListener('click', toggleAutostart);
 
@@ -153,10 +160,18 @@ function closeConfigModal() {
   document.getElementById('config-modal').classList.add('hidden');
 }
 
+// Printer profiles are edited in the config file and by the logo commands,
+// so the copy loaded here is never written back
+function configToSave(data) {
+  const { printerProfiles, ...rest } = data;
+  return rest;
+}
+
 async function saveConfig(e) {
   e.preventDefault();
 
   const newConfig = {
+    ...config,
     domainUrl: document.getElementById('domain-url').value,
     key: document.getElementById('api-key').value,
     printerName: config.printerName,
@@ -168,7 +183,7 @@ async function saveConfig(e) {
   };
 
   try {
-    await invoke('save_config', { configData: newConfig });
+    await invoke('save_config', { configData: configToSave(newConfig) });
     config = newConfig;
     updateUI();
     closeConfigModal();
@@ -176,8 +191,8 @@ async function saveConfig(e) {
 
     // Restart service with new config
     if (isServiceRunning) {
-      stopService();
-      startService();
+      await stopService();
+      await startService();
     }
   } catch (error) {
     log('Failed to save config: ' + error, 'error');
@@ -236,30 +251,54 @@ function toggleService() {
   }
 }
 
-function startService() {
+async function startService() {
   if (!config.domainUrl || !config.key) {
     log('Please configure API settings first', 'warning');
     openConfigModal();
     return;
   }
 
-  isServiceRunning = true;
-  updateServiceUI();
-  log('Print service started - Polling every ' + (config.pollingInterval / 1000) + ' seconds');
+  try {
+    applyServiceStatus(await invoke('start_service'));
+  } catch (error) {
+    log('Failed to start print service: ' + error, 'error');
+  }
+}
 
-  // Start polling
-  pollJobs();
-  pollingTimer = setInterval(pollJobs, config.pollingInterval || 3000);
+async function stopService() {
+  try {
+    applyServiceStatus(await invoke('stop_service'));
+  } catch (error) {
+    log('Failed to stop print service: ' + error, 'error');
+  }
 }
 
-function stopService() {
-  isServiceRunning = false;
-  if (pollingTimer) {
-    clearInterval(pollingTimer);
-    pollingTimer = null;
+// Mirror backend service status in the UI
+function applyServiceStatus(status) {
+  if (status.lastPollAt) {
+    document.getElementById('last-check-time').textContent =
+      new Date(status.lastPollAt).toLocaleTimeString();
   }
+
+  isServiceRunning = status.running;
   updateServiceUI();
-  log('Print service stopped');
+}
+
+// Subscribe to events pushed by the backend print service
+async function setupServiceEvents() {
+  await listen('service-log', (event) => {
+    log(event.payload.message, event.payload.level);
+  });
+
+  await listen('service-status', (event) => {
+    applyServiceStatus(event.payload);
+  });
+
+  await listen('job-status', (event) => {
+    if (event.payload.status === 'failed') {
+      loadDeadLetters();
+    }
+  });
 }
 
 function updateServiceUI() {
@@ -287,171 +326,6 @@ function updateServiceUI() {
   }
 }
 
-// ============ Polling ============
-
-async function pollJobs() {
-  if (!isServiceRunning) return;
-
-  const now = new Date();
-  document.getElementById('last-check-time').textContent = now.toLocaleTimeString();
-
-  try {
-    log('Polling print jobs from: ' + config.domainUrl + '/api/print-jobs/pull-multiple');
-    const jobs = await invoke('poll_print_jobs');
-    log('Poll response status: 200, body length: ' + JSON.stringify(jobs).length);
-    log('Processing ' + jobs.length + ' print jobs');
-
-    for (const job of jobs) {
-      // Process all jobs returned from API (server marks them as 'printing' when pulled)
-      await processJob(job);
-    }
-  } catch (error) {
-    log('Poll failed: ' + error, 'error');
-  }
-}
-
-async function processJob(job) {
-  // Get printer name from various possible locations in the response
-  const printerApiName = job.printer_name || job.printerName ||
-                         (job.printer && job.printer.name) ||
-                         (job.station && job.station.name) ||
-                         job.printer;
-  const localPrinter = config.printerMappings[printerApiName];
-
-  if (!localPrinter) {
-    log('No printer mapping for: ' + printerApiName, 'warning');
-    try {
-      await invoke('update_job_status', {
-        jobId: job.id,
-        status: 'failed',
-        reason: 'No printer mapping configured'
-      });
-    } catch (e) {}
-    return;
-  }
-
-  const jobType = getJobType(job);
-  log('Processing job #' + job.id + ' (type: ' + jobType + ') for printer: ' + localPrinter);
-
-  try {
-    // Handle different job types
-    if (job.image) {
-      // Base64 image printing
-      await invoke('print_base64_to_thermal', {
-        printerName: localPrinter,
-        base64Image: job.image
-      });
-    } else if (job.image_path) {
-      // Image from URL (common format from VopecsPOS)
-      log('🖨️ Processing job for printer: ' + localPrinter);
-      log('🖨️ Printing image from URL: ' + job.image_path);
-      log('Downloading image from URL: ' + job.image_path);
-      const startTime = Date.now();
-      await invoke('print_image_from_url', {
-        printerName: localPrinter,
-        url: job.image_path
-      });
-      const elapsed = Date.now() - startTime;
-      log('✅ Print command executed successfully (' + elapsed + 'ms)');
-      log('✅ Image from URL printed successfully');
-    } else if (job.pdf) {
-      // PDF printing (URL or base64)
-      if (job.pdf.startsWith('http')) {
-        await invoke('print_pdf_to_thermal', {
-          printerName: localPrinter,
-          pdfUrl: job.pdf
-        });
-      } else {
-        // Base64 PDF - treat as text for now
-        log('Base64 PDF received, printing as document info', 'info');
-        await invoke('print_text_content', {
-          printerName: localPrinter,
-          content: 'PDF Document Received\nJob ID: ' + job.id
-        });
-      }
-    } else if (job.html) {
-      // HTML content printing
-      await invoke('print_html_content', {
-        printerName: localPrinter,
-        html: job.html
-      });
-    } else if (job.url) {
-      // Image from URL
-      await invoke('print_image_from_url', {
-        printerName: localPrinter,
-        url: job.url
-      });
-    } else if (job.content) {
-      // Plain text content
-      await invoke('print_text_content', {
-        printerName: localPrinter,
-        content: job.content
-      });
-    } else {
-      log('Unknown job type for job #' + job.id, 'warning');
-      throw new Error('Unknown job type');
-    }
-
-    // Print copies if specified (check job.copies, station.print_copies)
-    const copies = job.copies || (job.station && job.station.print_copies) || 1;
-    for (let i = 1; i < copies; i++) {
-      log('Printing copy ' + (i + 1) + ' of ' + copies);
-      if (job.image) {
-        await invoke('print_base64_to_thermal', {
-          printerName: localPrinter,
-          base64Image: job.image
-        });
-      } else if (job.image_path) {
-        await invoke('print_image_from_url', {
-          printerName: localPrinter,
-          url: job.image_path
-        });
-      }
-    }
-
-    await invoke('update_job_status', {
-      jobId: job.id,
-      status: 'done',
-      reason: null
-    });
-
-    log('✅ Print completed successfully!');
-    log('Updating job status to \'done\' at: ' + config.domainUrl + '/api/print-jobs/' + job.id);
-
-    if (config.openDrawerAfterPrint) {
-      const pin = config.drawerPin === 0 ? 1 : 2;
-      log('💰 Drawer decision - should_open: true, pin: ' + pin + ', config_value: ' + config.openDrawerAfterPrint);
-      log('💰 Opening cash drawer after successful print (pin: ' + pin + ')');
-      log('Opening cash drawer on printer: ' + localPrinter + ' (pin: ' + pin + ')');
-      await invoke('open_drawer', {
-        printerName: localPrinter,
-        pin: config.drawerPin
-      });
-      log('✅ Cash drawer opened successfully');
-    }
-  } catch (error) {
-    log('Failed to process job #' + job.id + ': ' + error, 'error');
-    try {
-      await invoke('update_job_status', {
-        jobId: job.id,
-        status: 'failed',
-        reason: error.toString()
-      });
-    } catch (e) {}
-  }
-}
-
-// Determine job type from job data
-function getJobType(job) {
-  if (job.image) return 'image';
-  if (job.image_path) return 'image_url';
-  if (job.pdf) return 'pdf';
-  if (job.html) return 'html';
-  if (job.url) return 'url';
-  if (job.content) return 'content';
-  return job.job_type || job.type || 'unknown';
-}
-
 // ============ Printers ============
 
 async function loadSystemPrinters() {
@@ -505,6 +379,10 @@ function renderPrinterMappings() {
             ${systemPrinters.map(sp =>
               `<option value="${sp}" ${sp === currentMapping ? 'selected' : ''}>${sp}</option>`
             ).join('')}
+            ${currentMapping && !systemPrinters.includes(currentMapping)
+              ? `<option value="${escapeHtml(currentMapping)}" selected>${escapeHtml(currentMapping)}</option>`
+              : ''}
+            <option value="__network__">+ Network printer (TCP 9100)...</option>
           </select>
         </td>
         <td>
@@ -529,10 +407,20 @@ function renderPrinterMappings() {
 }
 
 window.updatePrinterMapping = async function(apiName, localPrinter) {
+  if (localPrinter === '__network__') {
+    const address = prompt('Printer IP address or host (optionally host:port):', '');
+    if (!address) {
+      renderPrinterMappings();
+      return;
+    }
+    localPrinter = address.startsWith('tcp://') ? address : 'tcp://' + address.trim();
+  }
+
   config.printerMappings[apiName] = localPrinter;
+  renderPrinterMappings();
 
   try {
-    await invoke('save_config', { configData: config });
+    await invoke('save_config', { configData: configToSave(config) });
     log('Printer mapping updated: ' + apiName + ' -> ' + localPrinter);
   } catch (error) {
     log('Failed to save printer mapping: ' + error, 'error');
@@ -575,6 +463,79 @@ window.testDrawer = async function(apiName) {
   }
 };
 
+// ============ Failed Jobs ============
+
+// Printer replies and error text end up in the failed jobs table
+function escapeHtml(text) {
+  const div = document.createElement('div');
+  div.textContent = text;
+  // Quotes too, so the result is also safe inside attribute values
+  return div.innerHTML.replace(/"/g, '&quot;').replace(/'/g, '&#39;');
+}
+
+async function loadDeadLetters() {
+  const tbody = document.getElementById('dead-letters-body');
+  const count = document.getElementById('dead-letters-count');
+
+  let entries = [];
+  try {
+    entries = await invoke('get_dead_letters');
+  } catch (error) {
+    log('Failed to load failed jobs: ' + error, 'error');
+    return;
+  }
+
+  count.textContent = entries.length;
+
+  if (entries.length === 0) {
+    tbody.innerHTML = '<tr><td colspan="5" class="empty-row">No failed jobs.</td></tr>';
+    return;
+  }
+
+  tbody.innerHTML = entries.map(entry => `
+    <tr>
+      <td><strong>#${entry.job.id}</strong></td>
+      <td>${escapeHtml(entry.printer || '-')}</td>
+      <td>${escapeHtml(entry.reason || '-')}</td>
+      <td>${entry.attempts}</td>
+      <td>
+        <div class="test-buttons">
+          <button class="btn btn-success" onclick="reprintDeadLetter(${entry.job.id})">
+            <span class="icon">&#x1F5A8;</span> Reprint
+          </button>
+          <button class="btn btn-secondary" onclick="discardDeadLetter(${entry.job.id})">
+            Discard
+          </button>
+        </div>
+      </td>
+    </tr>
+  `).join('');
+}
+
+window.reprintDeadLetter = async function(jobId) {
+  log('Reprinting failed job #' + jobId);
+
+  try {
+    await invoke('reprint_dead_letter', { jobId, printerName: null });
+    log('Job #' + jobId + ' reprinted successfully');
+  } catch (error) {
+    log('Reprint of job #' + jobId + ' failed: ' + error, 'error');
+  }
+
+  loadDeadLetters();
+};
+
+window.discardDeadLetter = async function(jobId) {
+  try {
+    await invoke('discard_dead_letter', { jobId });
+    log('Discarded failed job #' + jobId);
+  } catch (error) {
+    log('Failed to discard job #' + jobId + ': ' + error, 'error');
+  }
+
+  loadDeadLetters();
+};
+
 // ============ Drawer Settings ============
 
 async function updateDrawerSettings() {
@@ -584,7 +545,7 @@ async function updateDrawerSettings() {
   updateDrawerInfo();
 
   try {
-    await invoke('save_config', { configData: config });
+    await invoke('save_config', { configData: configToSave(config) });
     log('Drawer settings updated');
   } catch (error) {
     log('Failed to save drawer settings: ' + error, 'error');